@@ -10,6 +10,8 @@ use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "pruntime")]
 pub mod pruntime;
+#[cfg(feature = "pruntime")]
+pub mod rpc;
 
 #[derive(Encode, Decode)]
 pub struct Transfer<AccountId, Balance> {
@@ -24,6 +26,22 @@ pub struct TransferData<AccountId, Balance> {
     pub signature: Vec<u8>,
 }
 
+/// A withdrawal of a bridged asset, signed by the Assets contract. `asset_id` is the id in
+/// pallet_assets.
+#[derive(Encode, Decode)]
+pub struct AssetTransfer<AccountId> {
+    pub dest: AccountId,
+    pub asset_id: u32,
+    pub amount: u128,
+    pub sequence: u64,
+}
+
+#[derive(Encode, Decode)]
+pub struct AssetTransferData<AccountId> {
+    pub data: AssetTransfer<AccountId>,
+    pub signature: Vec<u8>,
+}
+
 #[derive(Encode, Decode, Clone, Debug)]
 #[cfg_attr(feature = "enable_serde", derive(Serialize, Deserialize))]
 pub enum WorkerMessagePayload {
@@ -48,6 +66,92 @@ pub struct SignedWorkerMessage {
     pub signature: Vec<u8>,
 }
 
+/// The encoding version of the `ChainMessage`s in `Phala::WorkerMessages`
+pub const CHAIN_MESSAGE_VERSION: u8 = 1;
+
+/// A message from pallet_phala to the workers, queued in `Phala::WorkerMessages` of the block.
+///
+/// The encoding must stay stable across runtime upgrades: new messages are appended with new
+/// indexes, and any other change bumps `CHAIN_MESSAGE_VERSION`.
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub enum ChainMessage<AccountId, Balance> {
+    #[codec(index = 0)]
+    CommandPushed {
+        origin: AccountId,
+        contract_id: u32,
+        payload: Vec<u8>,
+        num: u64,
+    },
+    #[codec(index = 1)]
+    TransferToTee { who: AccountId, amount: Balance },
+    #[codec(index = 2)]
+    TransferToChain {
+        who: AccountId,
+        amount: Balance,
+        sequence: u64,
+    },
+    #[codec(index = 3)]
+    WorkerRegistered {
+        stash: AccountId,
+        pubkey: Vec<u8>,
+        machine_id: Vec<u8>,
+    },
+    #[codec(index = 4)]
+    WorkerRenewed { stash: AccountId, machine_id: Vec<u8> },
+    #[codec(index = 5)]
+    WorkerMessageReceived {
+        stash: AccountId,
+        pubkey: Vec<u8>,
+        seq: u64,
+    },
+    #[codec(index = 6)]
+    RewardSeed(BlockRewardInfo),
+    #[codec(index = 7)]
+    NewMiningRound(u32),
+    #[codec(index = 8)]
+    AssetTransferToTee {
+        who: AccountId,
+        asset_id: u32,
+        amount: u128,
+    },
+    #[codec(index = 9)]
+    AssetTransferToChain {
+        who: AccountId,
+        asset_id: u32,
+        amount: u128,
+        sequence: u64,
+    },
+}
+
+/// An encoded `ChainMessage` tagged with its encoding version, so that the workers can skip the
+/// messages they don't understand instead of failing the whole block
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct VersionedMessage {
+    pub version: u8,
+    pub payload: Vec<u8>,
+}
+
+impl VersionedMessage {
+    pub fn new<AccountId: Encode, Balance: Encode>(
+        message: &ChainMessage<AccountId, Balance>,
+    ) -> Self {
+        VersionedMessage {
+            version: CHAIN_MESSAGE_VERSION,
+            payload: message.encode(),
+        }
+    }
+
+    /// Decodes the message, or `None` if it's of another version or an unknown kind
+    pub fn decode_message<AccountId: Decode, Balance: Decode>(
+        &self,
+    ) -> Option<ChainMessage<AccountId, Balance>> {
+        if self.version != CHAIN_MESSAGE_VERSION {
+            return None;
+        }
+        Decode::decode(&mut self.payload.as_slice()).ok()
+    }
+}
+
 pub trait SignedDataType<T> {
     fn raw_data(&self) -> Vec<u8>;
     fn signature(&self) -> T;
@@ -64,6 +168,15 @@ impl<AccountId: Encode, Balance: Encode> SignedDataType<Vec<u8>>
     }
 }
 
+impl<AccountId: Encode> SignedDataType<Vec<u8>> for AssetTransferData<AccountId> {
+    fn raw_data(&self) -> Vec<u8> {
+        Encode::encode(&self.data)
+    }
+    fn signature(&self) -> Vec<u8> {
+        self.signature.clone()
+    }
+}
+
 impl SignedDataType<Vec<u8>> for SignedWorkerMessage {
     fn raw_data(&self) -> Vec<u8> {
         Encode::encode(&self.data)
@@ -33,6 +33,15 @@ where
     pub proof: StorageProof,
 }
 
+/// The storage items of a block requested by the contracts in pRuntime (`get_storage_keys`), with
+/// the proof of them against the state root of the block
+#[derive(Debug, Encode, Decode, Clone, Default)]
+pub struct StorageReadRequest {
+    /// The requested keys and their values, `None` for the absent ones
+    pub items: Vec<(RawStorageKey, Option<Vec<u8>>)>,
+    pub proof: StorageProof,
+}
+
 #[derive(Encode, Decode, Debug, Clone)]
 pub struct HeaderToSync<BlockNumber, Hash>
 where
@@ -51,7 +60,30 @@ where
     Balance: FullCodec + Clone,
 {
     pub block_header: Header<BlockNumber, Hash>,
-    pub events: Option<Vec<u8>>,
-    pub proof: Option<Vec<Vec<u8>>>,
+    /// The storage proof of `Phala::WorkerMessages` at the block, which pRuntime reads the chain
+    /// messages from (also proving there's none)
+    pub messages_proof: StorageProof,
     pub worker_snapshot: Option<OnlineWorkerSnapshot<BlockNumber, Balance>>,
+    /// Required when the contracts request any storage keys
+    pub storage_reads: Option<StorageReadRequest>,
+}
+
+/// The message pRuntime signs in every API response
+///
+/// The signature is made with the worker identity key (secp256k1) over the sha2_256 hash of the
+/// SCALE encoding of this message. For the JSON API, `request_hash` is the blake2_256 hash of the
+/// compact JSON encoding of the request (`{"input": .., "nonce": ..}`) with the object keys sorted,
+/// so the response is bound to the request it answers regardless of how the host re-encodes it.
+/// For the binary API (`rpc`), it's the blake2_256 hash of the encoded `RpcRequest`.
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct SignedResponseMessage {
+    pub request_hash: [u8; 32],
+    /// Whether the status is "ok" or "error"
+    pub ok: bool,
+    /// The JSON encoded payload, or the SCALE encoded `Reply` or `RpcError` of the binary API
+    pub payload: Vec<u8>,
+    /// The height of the synced header when the response was made
+    pub headernum: u32,
+    /// The height of the dispatched block when the response was made
+    pub blocknum: u32,
 }
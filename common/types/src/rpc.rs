@@ -0,0 +1,503 @@
+//! The binary RPC protocol of pRuntime
+//!
+//! A request is the SCALE encoding of `RpcRequest`, posted to `/rpc`. pRuntime answers with the
+//! SCALE encoding of `RpcResponse`, whose payload is the encoded `Reply` on success, or the encoded
+//! `RpcError` otherwise. The response is signed like the JSON API does (see
+//! `pruntime::SignedResponseMessage`), with the blake2_256 hash of the raw request as the
+//! `request_hash`.
+//!
+//! The variant indexes of `Call` and `Reply` are the action codes of the JSON API, which is kept
+//! for backward compatibility and translated to the same calls.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use codec::{Decode, Encode};
+
+use crate::pruntime::SignedResponseMessage;
+
+pub const ACTION_TEST: u8 = 0;
+pub const ACTION_INIT_RUNTIME: u8 = 1;
+pub const ACTION_GET_INFO: u8 = 2;
+pub const ACTION_DUMP_STATES: u8 = 3;
+pub const ACTION_LOAD_STATES: u8 = 4;
+pub const ACTION_SYNC_HEADER: u8 = 5;
+pub const ACTION_QUERY: u8 = 6;
+pub const ACTION_DISPATCH_BLOCK: u8 = 7;
+// Reserved: 8, 9
+pub const ACTION_GET_RUNTIME_INFO: u8 = 10;
+pub const ACTION_ADD_BRIDGE: u8 = 11;
+pub const ACTION_SYNC_BRIDGE_HEADER: u8 = 12;
+pub const ACTION_DISPATCH_BRIDGE_BLOCK: u8 = 13;
+pub const ACTION_GET_BRIDGES: u8 = 14;
+pub const ACTION_GET_STORAGE_KEYS: u8 = 15;
+pub const ACTION_SET: u8 = 21;
+pub const ACTION_GET: u8 = 22;
+pub const ACTION_TEST_INK: u8 = 100;
+
+/// The action code of the JSON API served at `/<name>`
+pub fn action_by_name(name: &str) -> Option<u8> {
+    let action = match name {
+        "test" => ACTION_TEST,
+        "init_runtime" => ACTION_INIT_RUNTIME,
+        "get_info" => ACTION_GET_INFO,
+        "dump_states" => ACTION_DUMP_STATES,
+        "load_states" => ACTION_LOAD_STATES,
+        "sync_header" => ACTION_SYNC_HEADER,
+        "query" => ACTION_QUERY,
+        "dispatch_block" => ACTION_DISPATCH_BLOCK,
+        "get_runtime_info" => ACTION_GET_RUNTIME_INFO,
+        "add_bridge" => ACTION_ADD_BRIDGE,
+        "sync_bridge_header" => ACTION_SYNC_BRIDGE_HEADER,
+        "dispatch_bridge_block" => ACTION_DISPATCH_BRIDGE_BLOCK,
+        "get_bridges" => ACTION_GET_BRIDGES,
+        "get_storage_keys" => ACTION_GET_STORAGE_KEYS,
+        "set" => ACTION_SET,
+        "get" => ACTION_GET,
+        "test_ink" => ACTION_TEST_INK,
+        _ => return None,
+    };
+    Some(action)
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct RpcRequest {
+    pub call: Call,
+    /// Makes the identical calls distinct, so are their response signatures
+    pub nonce: u64,
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub enum Call {
+    #[codec(index = 0)]
+    Test(TestArgs),
+    #[codec(index = 1)]
+    InitRuntime(InitRuntimeArgs),
+    #[codec(index = 2)]
+    GetInfo,
+    #[codec(index = 3)]
+    DumpStates,
+    #[codec(index = 4)]
+    LoadStates { data: Vec<u8> },
+    #[codec(index = 5)]
+    SyncHeader {
+        /// Encoded `HeaderToSync`s
+        headers: Vec<Vec<u8>>,
+        /// Encoded `AuthoritySetChange`
+        authority_set_change: Option<Vec<u8>>,
+    },
+    #[codec(index = 6)]
+    Query {
+        /// The JSON encoded `Payload` of the contract query
+        query_payload: String,
+        /// The account signing `query_payload`
+        origin: Option<QueryOrigin>,
+    },
+    #[codec(index = 7)]
+    DispatchBlock {
+        /// Encoded `BlockHeaderWithEvents`
+        blocks: Vec<Vec<u8>>,
+    },
+    #[codec(index = 10)]
+    GetRuntimeInfo,
+    /// Starts tracking another chain besides the main one
+    #[codec(index = 11)]
+    AddBridge(AddBridgeArgs),
+    /// `SyncHeader` of an added chain
+    #[codec(index = 12)]
+    SyncBridgeHeader {
+        bridge_id: u64,
+        /// Encoded `HeaderToSync`s. Only the last header of a GRANDPA chain has a justification,
+        /// and none of a parachain has.
+        headers: Vec<Vec<u8>>,
+        /// Encoded `AuthoritySetChange` of a GRANDPA chain
+        authority_set_change: Option<Vec<u8>>,
+        /// The storage proof of the last header of a parachain, as the head in the relay chain at
+        /// its last synced header
+        head_proof: Option<Vec<Vec<u8>>>,
+    },
+    /// `DispatchBlock` of an added chain
+    #[codec(index = 13)]
+    DispatchBridgeBlock {
+        bridge_id: u64,
+        /// Encoded `BlockHeaderWithEvents`
+        blocks: Vec<Vec<u8>>,
+    },
+    #[codec(index = 14)]
+    GetBridges,
+    /// The storage keys to read at the next blocks, for `BlockHeaderWithEvents::storage_reads`
+    #[codec(index = 15)]
+    GetStorageKeys,
+    /// Writes a DataPlaza dataset, only in dev mode
+    #[codec(index = 21)]
+    Set { path: String, data: Vec<u8> },
+    /// Reads a DataPlaza dataset or result, only in dev mode
+    #[codec(index = 22)]
+    Get { path: String },
+    #[codec(index = 100)]
+    TestInk,
+}
+
+impl Call {
+    /// The action code of the call in the JSON API
+    pub fn action(&self) -> u8 {
+        match self {
+            Call::Test(_) => ACTION_TEST,
+            Call::InitRuntime(_) => ACTION_INIT_RUNTIME,
+            Call::GetInfo => ACTION_GET_INFO,
+            Call::DumpStates => ACTION_DUMP_STATES,
+            Call::LoadStates { .. } => ACTION_LOAD_STATES,
+            Call::SyncHeader { .. } => ACTION_SYNC_HEADER,
+            Call::Query { .. } => ACTION_QUERY,
+            Call::DispatchBlock { .. } => ACTION_DISPATCH_BLOCK,
+            Call::GetRuntimeInfo => ACTION_GET_RUNTIME_INFO,
+            Call::AddBridge(_) => ACTION_ADD_BRIDGE,
+            Call::SyncBridgeHeader { .. } => ACTION_SYNC_BRIDGE_HEADER,
+            Call::DispatchBridgeBlock { .. } => ACTION_DISPATCH_BRIDGE_BLOCK,
+            Call::GetBridges => ACTION_GET_BRIDGES,
+            Call::GetStorageKeys => ACTION_GET_STORAGE_KEYS,
+            Call::Set { .. } => ACTION_SET,
+            Call::Get { .. } => ACTION_GET,
+            Call::TestInk => ACTION_TEST_INK,
+        }
+    }
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct TestArgs {
+    pub test_parse_block: bool,
+    pub test_bridge: bool,
+    pub test_ecdh: Option<TestEcdhArgs>,
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct TestEcdhArgs {
+    pub pubkey: Option<Vec<u8>>,
+    pub message: Option<Vec<u8>>,
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct InitRuntimeArgs {
+    pub skip_ra: bool,
+    /// Encoded `BridgeInitInfo`
+    pub bridge_genesis_info: Vec<u8>,
+    /// The raw identity key to set. Only allowed when `skip_ra` is set.
+    pub debug_set_key: Option<Vec<u8>>,
+    /// The initial states of the contracts
+    pub contracts_genesis: ContractsGenesis,
+}
+
+/// The initial states of the contracts, set up by `init_runtime`
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq, Default)]
+pub struct ContractsGenesis {
+    /// Loads the built-in test accounts. Only allowed in dev mode.
+    pub dev_fixtures: bool,
+    /// The initial free balances in `Balances`
+    pub balances: Vec<([u8; 32], u128)>,
+    /// The assets issued in `Assets`, by their owners
+    pub assets: Vec<AssetGenesis>,
+    /// The VASP account of `Diem` creating the child accounts
+    pub diem_master: Option<DiemMasterGenesis>,
+    /// The 32 byte secret of `Web3Analytics` deriving the keys of the encrypted sites, encrypted to
+    /// the worker
+    pub web3analytics_key: Option<EncryptedSecret>,
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct AssetGenesis {
+    pub owner: [u8; 32],
+    pub symbol: String,
+    pub name: String,
+    pub decimals: u8,
+    pub total: u128,
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct DiemMasterGenesis {
+    pub owner: [u8; 32],
+    /// The raw ed25519 private key
+    pub private_key: EncryptedSecret,
+}
+
+/// A secret for the enclave only, encrypted to the worker `ecdh_public_key` (see `get_info`) by
+/// AES-256-GCM under the raw secret agreed by ECDH with `pubkey`, so the host never sees it
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct EncryptedSecret {
+    /// The ephemeral ECDH public key of the sender
+    pub pubkey: Vec<u8>,
+    pub iv: Vec<u8>,
+    /// With the 128 bit auth tag appended
+    pub cipher: Vec<u8>,
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub enum AddBridgeArgs {
+    /// A chain finalized by GRANDPA
+    Grandpa {
+        /// Encoded `BridgeInitInfo`
+        genesis_info: Vec<u8>,
+    },
+    /// A parachain of a tracked GRANDPA chain, starting from its head at the last synced header of
+    /// the relay chain
+    Parachain {
+        relay_bridge: u64,
+        para_id: u32,
+        /// Encoded header
+        header: Vec<u8>,
+        /// The storage proof of `header` as the head in the relay chain
+        head_proof: Vec<Vec<u8>>,
+    },
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub enum SignatureType {
+    Ed25519,
+    Sr25519,
+    Ecdsa,
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct QueryOrigin {
+    pub pubkey: Vec<u8>,
+    pub signature: Vec<u8>,
+    pub sig_type: SignatureType,
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    #[codec(index = 0)]
+    Test,
+    #[codec(index = 1)]
+    InitRuntime(RuntimeInfo),
+    #[codec(index = 2)]
+    GetInfo(Info),
+    #[codec(index = 3)]
+    DumpStates {
+        /// The encrypted checkpoint
+        data: Vec<u8>,
+        checkpoint: CheckpointInfo,
+    },
+    #[codec(index = 4)]
+    LoadStates(CheckpointInfo),
+    #[codec(index = 5)]
+    SyncHeader { synced_to: u32 },
+    #[codec(index = 6)]
+    Query {
+        /// The JSON encoded `Payload` of the contract response
+        payload: String,
+    },
+    #[codec(index = 7)]
+    DispatchBlock {
+        dispatched_to: u32,
+        state_root: [u8; 32],
+    },
+    #[codec(index = 10)]
+    GetRuntimeInfo(RuntimeInfo),
+    #[codec(index = 11)]
+    AddBridge { bridge_id: u64 },
+    #[codec(index = 12)]
+    SyncBridgeHeader { bridge_id: u64, synced_to: u32 },
+    #[codec(index = 13)]
+    DispatchBridgeBlock {
+        bridge_id: u64,
+        dispatched_to: u32,
+        state_root: [u8; 32],
+    },
+    #[codec(index = 14)]
+    GetBridges(Vec<BridgeStatus>),
+    #[codec(index = 15)]
+    GetStorageKeys { keys: Vec<Vec<u8>> },
+    #[codec(index = 21)]
+    Set { path: String },
+    #[codec(index = 22)]
+    Get { path: String, data: Vec<u8> },
+    #[codec(index = 100)]
+    TestInk,
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeInfo {
+    /// Encoded `PRuntimeInfo`
+    pub encoded_runtime_info: Vec<u8>,
+    pub public_key: Vec<u8>,
+    pub ecdh_public_key: Vec<u8>,
+    pub attestation: Option<Attestation>,
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct Attestation {
+    pub version: i32,
+    pub provider: String,
+    /// The attestation report in JSON
+    pub report: String,
+    /// The base64 encoded signature of the report
+    pub signature: String,
+    /// The base64 encoded certificate signing the report
+    pub signing_cert: String,
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct Info {
+    pub initialized: bool,
+    pub public_key: Vec<u8>,
+    /// Empty if the ECDH key is not loaded
+    pub ecdh_public_key: Vec<u8>,
+    pub headernum: u32,
+    pub blocknum: u32,
+    pub machine_id: [u8; 16],
+    pub dev_mode: bool,
+    pub state_root: [u8; 32],
+    pub system_egress: EgressInfo,
+    /// The range of runtime `spec_version`s the blocks can be dispatched for, inclusive
+    pub min_spec_version: u32,
+    pub max_spec_version: u32,
+}
+
+/// The cursors of a chain added by `Call::AddBridge`
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct BridgeStatus {
+    pub bridge_id: u64,
+    /// The relay chain and the id of a parachain
+    pub parachain: Option<(u64, u32)>,
+    /// The next header to sync
+    pub headernum: u32,
+    /// The next block to dispatch
+    pub blocknum: u32,
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct EgressInfo {
+    pub sequence: u64,
+    pub len: u32,
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct CheckpointInfo {
+    pub headernum: u32,
+    pub blocknum: u32,
+    pub state_root: [u8; 32],
+    pub body_hash: [u8; 32],
+}
+
+#[derive(Encode, Decode, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// The request can't be decoded, or has invalid arguments
+    BadRequest = 1,
+    /// The action code is unknown
+    UnknownCall = 2,
+    NotInitialized = 3,
+    AlreadyInitialized = 4,
+    /// The call is not allowed in the current mode of pRuntime
+    Forbidden = 5,
+    /// The remote attestation report can't be produced
+    AttestationFailed = 6,
+    /// The headers don't pass the light validation
+    InvalidHeader = 7,
+    /// The blocks don't match the synced headers, or their events can't be verified
+    InvalidBlock = 8,
+    /// The checkpoint can't be taken, saved or restored
+    BadCheckpoint = 9,
+    /// The signature of the query doesn't match its origin
+    BadSignature = 10,
+    /// The queried contract or data doesn't exist
+    NotFound = 11,
+    Internal = 12,
+}
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct RpcError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl RpcError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl core::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:?}: {}", self.code, self.message)
+    }
+}
+
+/// The signed response to an `RpcRequest`
+///
+/// `signature` is made over `message` as in the JSON API. `message.payload` is the encoded `Reply`
+/// if `message.ok`, or the encoded `RpcError` otherwise.
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct RpcResponse {
+    pub message: SignedResponseMessage,
+    /// The secp256k1 signature (r, s) by the worker identity key
+    pub signature: Vec<u8>,
+}
+
+/// The maximum size of an encoded `IngestFrame`
+pub const MAX_INGEST_FRAME_LEN: u32 = 16 * 1024 * 1024;
+/// The size of an encoded `IngestAck`
+pub const INGEST_ACK_LEN: usize = 13;
+
+/// A frame of the streaming ingestion at `/ingest`
+///
+/// The request body is a sequence of frames, each one encoded and prefixed with its length as a
+/// little endian `u32` (see `encode_ingest_frame`). The frames are processed in order, as they
+/// arrive.
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub enum IngestFrame {
+    /// A header to sync. The headers are buffered until one with a justification arrives, then
+    /// submitted together as a `Call::SyncHeader`.
+    #[codec(index = 0)]
+    Header {
+        /// Encoded `HeaderToSync`
+        header: Vec<u8>,
+        /// Encoded `AuthoritySetChange`, submitted with the justified header carrying it
+        authority_set_change: Option<Vec<u8>>,
+    },
+    /// Encoded `BlockHeaderWithEvents` to dispatch
+    #[codec(index = 1)]
+    Block(Vec<u8>),
+}
+
+/// Encodes a frame with its length prefix
+pub fn encode_ingest_frame(frame: &IngestFrame) -> Vec<u8> {
+    let encoded = frame.encode();
+    let mut data = Vec::with_capacity(4 + encoded.len());
+    data.extend_from_slice(&(encoded.len() as u32).to_le_bytes());
+    data.extend_from_slice(&encoded);
+    data
+}
+
+/// The acknowledgement of an `IngestFrame`
+///
+/// The response body of `/ingest` is the sequence of the encoded acks, one per frame, sent as soon
+/// as the frame is processed. The stream ends after the first failed frame. The acks are not
+/// signed; `Call::GetInfo` returns the signed heights.
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct IngestAck {
+    /// The index of the frame in the stream
+    pub seq: u32,
+    /// The next header to sync
+    pub headernum: u32,
+    /// The next block to dispatch
+    pub blocknum: u32,
+    /// 0 if the frame was accepted, or the `ErrorCode` otherwise
+    pub code: u8,
+}
+
+impl IngestAck {
+    pub fn is_ok(&self) -> bool {
+        self.code == 0
+    }
+
+    /// The error of the frame, if failed
+    pub fn error(&self) -> Option<ErrorCode> {
+        if self.is_ok() {
+            None
+        } else {
+            Some(ErrorCode::decode(&mut &[self.code][..]).unwrap_or(ErrorCode::Internal))
+        }
+    }
+}
@@ -12,9 +12,11 @@ use alloc::{vec::Vec, borrow::ToOwned};
 use codec::Decode;
 use frame_support::{
 	traits::{
+		tokens::fungibles,
 		Currency, ExistenceRequirement::AllowDeath, Get, Imbalance, OnUnbalanced, Randomness,
 		UnixTime,
 	},
+	weights::Weight,
 	PalletId,
 };
 use sp_runtime::{
@@ -32,9 +34,9 @@ pub mod weights;
 // types
 extern crate phala_types as types;
 use types::{
-	BlockRewardInfo, MinerStatsDelta, PRuntimeInfo, PayoutPrefs, PayoutReason, RoundInfo,
+	AssetTransferData, BlockRewardInfo, ChainMessage, MinerStatsDelta, PRuntimeInfo, PayoutPrefs, PayoutReason, RoundInfo,
 	RoundStats, StashWorkerStats, Score, SignedDataType, SignedWorkerMessage, StashInfo, TransferData, WorkerInfo,
-	WorkerMessagePayload, WorkerStateEnum,
+	VersionedMessage, WorkerMessagePayload, WorkerStateEnum,
 };
 
 // constants
@@ -57,6 +59,8 @@ type BalanceOf<T> =
 	<<T as Config>::TEECurrency as Currency<<T as frame_system::Config>::AccountId>>::Balance;
 type NegativeImbalanceOf<T> =
 	<<T as Config>::TEECurrency as Currency<<T as frame_system::Config>::AccountId>>::NegativeImbalance;
+type AssetBalanceOf<T> =
+	<<T as Config>::Assets as fungibles::Inspect<<T as frame_system::Config>::AccountId>>::Balance;
 
 pub trait OnRoundEnd {
 	fn on_round_end(_round: u32) {}
@@ -68,6 +72,8 @@ pub trait Config: frame_system::Config {
 	type Event: From<Event<Self>> + Into<<Self as frame_system::Config>::Event>;
 	type Randomness: Randomness<Self::Hash, Self::BlockNumber>;
 	type TEECurrency: Currency<Self::AccountId>;
+	/// The on-chain assets bridged with the confidential Assets contract
+	type Assets: fungibles::Transfer<Self::AccountId, AssetId = u32>;
 	type UnixTime: UnixTime;
 	type Treasury: OnUnbalanced<NegativeImbalanceOf<Self>>;
 	type WeightInfo: WeightInfo;
@@ -171,6 +177,11 @@ decl_storage! {
 		RewardWindow get(fn reward_window): T::BlockNumber;
 		/// Miners could be slashed in `(now - slash_window, now - reward_window]`
 		SlashWindow get(fn slash_window): T::BlockNumber;
+
+		// Worker messages
+		/// The messages to the workers in this block, read by pRuntime with the storage proof
+		/// instead of decoding the runtime events. Cleared at the beginning of every block.
+		WorkerMessages get(fn worker_messages): Vec<VersionedMessage>;
 	}
 
 	add_extra_genesis {
@@ -226,6 +237,7 @@ decl_event!(
 	where
 		AccountId = <T as frame_system::Config>::AccountId,
 		Balance = BalanceOf<T>,
+		AssetBalance = AssetBalanceOf<T>,
 	{
 		// Chain events
 		CommandPushed(AccountId, u32, Vec<u8>, u64),
@@ -250,6 +262,8 @@ decl_event!(
 		PayoutMissed(AccountId, AccountId),             // stash, dest
 		WorkerRenewed(AccountId, Vec<u8>),              // stash, machine_id
 		PayoutReward(AccountId, Balance, Balance, PayoutReason), // dest, reward, treasury, reason
+		AssetTransferToTee(AccountId, u32, AssetBalance),        // who, asset_id, amount
+		AssetTransferToChain(AccountId, u32, AssetBalance, u64), // dest, asset_id, amount, sequence
 	}
 );
 
@@ -331,6 +345,11 @@ decl_module! {
 		type Error = Error<T>;
 		fn deposit_event() = default;
 
+		fn on_initialize() -> Weight {
+			WorkerMessages::kill();
+			T::DbWeight::get().writes(1)
+		}
+
 		fn on_finalize() {
 			let now = System::<T>::block_number();
 			let round = Round::<T>::get();
@@ -349,6 +368,9 @@ decl_module! {
 			let who = ensure_signed(origin)?;
 			let num = Self::command_number().unwrap_or(0);
 			CommandNumber::put(num + 1);
+			Self::push_message(ChainMessage::CommandPushed {
+				origin: who.clone(), contract_id, payload: payload.clone(), num,
+			});
 			Self::deposit_event(RawEvent::CommandPushed(who, contract_id, payload, num));
 			Ok(())
 		}
@@ -503,6 +525,9 @@ decl_module! {
 			let worker_info = WorkerState::<T>::get(&stash);
 			let machine_id = worker_info.machine_id;
 
+			Self::push_message(ChainMessage::WorkerRenewed {
+				stash: stash.clone(), machine_id: machine_id.clone(),
+			});
 			Self::deposit_event(RawEvent::WorkerRenewed(stash.clone(), machine_id.clone()));
 
 			WorkerIngress::<T>::insert(stash, 0);
@@ -567,6 +592,7 @@ decl_module! {
 			let who = ensure_signed(origin)?;
 			T::TEECurrency::transfer(&who, &Self::account_id(), amount, AllowDeath)
 				.map_err(|_| Error::<T>::CannotDeposit)?;
+			Self::push_message(ChainMessage::TransferToTee { who: who.clone(), amount });
 			Self::deposit_event(RawEvent::TransferToTee(who, amount));
 			Ok(())
 		}
@@ -596,10 +622,65 @@ decl_module! {
 				.map_err(|_| Error::<T>::CannotWithdraw)?;
 			// Announce the successful execution
 			IngressSequence::insert(CONTRACT_ID, sequence + 1);
+			Self::push_message(ChainMessage::TransferToChain {
+				who: transfer_data.data.dest.clone(),
+				amount: transfer_data.data.amount,
+				sequence: sequence + 1,
+			});
 			Self::deposit_event(RawEvent::TransferToChain(transfer_data.data.dest, transfer_data.data.amount, sequence + 1));
 			Ok(())
 		}
 
+		#[weight = T::WeightInfo::transfer_asset_to_tee()]
+		fn transfer_asset_to_tee(origin, #[compact] asset_id: u32, #[compact] amount: AssetBalanceOf<T>)
+		-> dispatch::DispatchResult {
+			let who = ensure_signed(origin)?;
+			<T::Assets as fungibles::Transfer<_>>::transfer(asset_id, &who, &Self::account_id(), amount, false)
+				.map_err(|_| Error::<T>::CannotDeposit)?;
+			Self::push_message(ChainMessage::AssetTransferToTee {
+				who: who.clone(),
+				asset_id,
+				amount: amount.saturated_into(),
+			});
+			Self::deposit_event(RawEvent::AssetTransferToTee(who, asset_id, amount));
+			Ok(())
+		}
+
+		#[weight = T::WeightInfo::transfer_asset_to_chain()]
+		fn transfer_asset_to_chain(origin, data: Vec<u8>) -> dispatch::DispatchResult {
+			// The same as `transfer_to_chain`, but the confidential contract is always Assets
+			// (id = 3), and the funds are released from `Assets`
+			const CONTRACT_ID: u32 = 3;
+			ensure_signed(origin)?;
+			let transfer_data: AssetTransferData<<T as frame_system::Config>::AccountId>
+				= Decode::decode(&mut &data[..]).map_err(|_| Error::<T>::InvalidInput)?;
+			// Check sequence
+			let sequence = IngressSequence::get(CONTRACT_ID);
+			ensure!(transfer_data.data.sequence == sequence + 1, Error::<T>::BadMessageSequence);
+			// Contract key
+			ensure!(ContractKey::contains_key(CONTRACT_ID), Error::<T>::InvalidContract);
+			let pubkey = ContractKey::get(CONTRACT_ID);
+			// Validate TEE signature
+			Self::verify_signature(&pubkey, &transfer_data)?;
+			// Release funds
+			let asset_id = transfer_data.data.asset_id;
+			let amount = AssetBalanceOf::<T>::try_from(transfer_data.data.amount)
+				.map_err(|_| Error::<T>::InvalidInput)?;
+			<T::Assets as fungibles::Transfer<_>>::transfer(
+				asset_id, &Self::account_id(), &transfer_data.data.dest, amount, false)
+				.map_err(|_| Error::<T>::CannotWithdraw)?;
+			// Announce the successful execution
+			IngressSequence::insert(CONTRACT_ID, sequence + 1);
+			Self::push_message(ChainMessage::AssetTransferToChain {
+				who: transfer_data.data.dest.clone(),
+				asset_id,
+				amount: transfer_data.data.amount,
+				sequence: sequence + 1,
+			});
+			Self::deposit_event(RawEvent::AssetTransferToChain(transfer_data.data.dest, asset_id, amount, sequence + 1));
+			Ok(())
+		}
+
 		// Messaging
 
 		#[weight = T::WeightInfo::sync_worker_message()]
@@ -632,6 +713,9 @@ decl_module! {
 						&stash, &stash_info.payout_prefs.target, claim_online, claim_compute,
 						score, block_num.into());
 					Self::deposit_event(RawEvent::Heartbeat(stash.clone(), block_num));
+					Self::push_message(ChainMessage::WorkerMessageReceived {
+						stash: stash.clone(), pubkey: id_pubkey.clone(), seq: expected_seq,
+					});
 					Self::deposit_event(RawEvent::WorkerMessageReceived(
 						stash.clone(), id_pubkey.clone(), expected_seq));
 				}
@@ -781,6 +865,11 @@ impl<T: Config> Module<T> {
 		PALLET_ID.into_account()
 	}
 
+	/// Queues a message to the workers in `WorkerMessages`
+	fn push_message(message: ChainMessage<T::AccountId, BalanceOf<T>>) {
+		WorkerMessages::append(VersionedMessage::new(&message));
+	}
+
 	pub fn is_controller(controller: T::AccountId) -> bool {
 		Stash::<T>::contains_key(&controller)
 	}
@@ -899,6 +988,9 @@ impl<T: Config> Module<T> {
 		// New WorkerInfo
 		let new_info = if renew_only {
 			// Just renewed
+			Self::push_message(ChainMessage::WorkerRenewed {
+				stash: stash.clone(), machine_id: machine_id.clone(),
+			});
 			Self::deposit_event(RawEvent::WorkerRenewed(stash.clone(), machine_id.clone()));
 			WorkerInfo {
 				machine_id: machine_id.clone(), // should not change, but we set it anyway
@@ -911,6 +1003,9 @@ impl<T: Config> Module<T> {
 			}
 		} else {
 			// Link a new worker
+			Self::push_message(ChainMessage::WorkerRegistered {
+				stash: stash.clone(), pubkey: pubkey.clone(), machine_id: machine_id.clone(),
+			});
 			Self::deposit_event(RawEvent::WorkerRegistered(
 				stash.clone(),
 				pubkey.clone(),
@@ -1159,6 +1254,7 @@ impl<T: Config> Module<T> {
 		});
 		Self::update_round_stats(new_round, new_online, compute_workers, new_total_power);
 		RoundWorkerStats::<T>::remove_all(); 
+		Self::push_message(ChainMessage::NewMiningRound(new_round));
 		Self::deposit_event(RawEvent::NewMiningRound(new_round));
 	}
 
@@ -1197,6 +1293,7 @@ impl<T: Config> Module<T> {
 		};
 		// Save
 		BlockRewardSeeds::<T>::insert(now, &seed_info);
+		Self::push_message(ChainMessage::RewardSeed(seed_info.clone()));
 		Self::deposit_event(RawEvent::RewardSeed(seed_info));
 	}
 
@@ -1,9 +1,9 @@
 use std::convert::TryFrom;
 
-use codec::Encode;
+use codec::{Decode, Encode};
 use frame_support::{
 	assert_noop, assert_ok, assert_err,
-	traits::{Currency, OnFinalize},
+	traits::{Currency, OnFinalize, OnInitialize},
 };
 use frame_system::RawOrigin;
 use hex_literal::hex;
@@ -13,7 +13,10 @@ use sp_runtime::traits::BadOrigin;
 
 use crate::{mock::*, Error};
 use crate::{
-	types::{BlockRewardInfo, RoundStats, Transfer, TransferData, WorkerStateEnum},
+	types::{
+		AssetTransfer, AssetTransferData, BlockRewardInfo, ChainMessage, RoundStats, Transfer,
+		TransferData, VersionedMessage, WorkerStateEnum,
+	},
 	RawEvent,
 };
 use phala_types::PayoutReason;
@@ -363,6 +366,114 @@ fn test_transfer() {
 	});
 }
 
+#[test]
+fn test_transfer_asset() {
+	new_test_ext().execute_with(|| {
+		// set contract key
+		let raw_sk = hex!["0000000000000000000000000000000000000000000000000000000000000001"];
+		let pubkey =
+			hex!["0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"].to_vec();
+		let sk = ecdsa_load_sk(&raw_sk);
+		assert_ok!(PhalaPallet::force_set_contract_key(
+			RawOrigin::Root.into(),
+			3,
+			pubkey
+		));
+		// Get some assets
+		assert_ok!(Assets::force_create(RawOrigin::Root.into(), 7, 1, true, 1));
+		assert_ok!(Assets::mint(Origin::signed(1), 7, 1, 100));
+		// transfer_asset_to_tee(some asset)
+		assert_ok!(PhalaPallet::transfer_asset_to_tee(Origin::signed(1), 7, 50));
+		assert_eq!(50, Assets::balance(7, 1));
+		assert_eq!(50, Assets::balance(7, PhalaPallet::account_id()));
+		let messages: Vec<_> = PhalaPallet::worker_messages()
+			.iter()
+			.map(|m| m.decode_message::<u64, Balance>().unwrap())
+			.collect();
+		assert_eq!(messages, vec![
+			ChainMessage::AssetTransferToTee { who: 1, asset_id: 7, amount: 50 },
+		]);
+		// Can't deposit more than owned
+		assert_noop!(
+			PhalaPallet::transfer_asset_to_tee(Origin::signed(1), 7, 51),
+			Error::<Test>::CannotDeposit
+		);
+		// transfer_asset_to_chain
+		let signed = |amount, sequence| {
+			let transfer = AssetTransfer::<u64> {
+				dest: 2u64,
+				asset_id: 7,
+				amount,
+				sequence,
+			};
+			let signature = ecdsa_sign(&sk, &transfer);
+			AssetTransferData {
+				data: transfer,
+				signature,
+			}
+			.encode()
+		};
+		assert_ok!(PhalaPallet::transfer_asset_to_chain(
+			Origin::signed(1),
+			signed(10, 1)
+		));
+		assert_eq!(10, Assets::balance(7, 2));
+		assert_eq!(40, Assets::balance(7, PhalaPallet::account_id()));
+		// Replayed or skipped sequences are rejected
+		assert_noop!(
+			PhalaPallet::transfer_asset_to_chain(Origin::signed(1), signed(10, 1)),
+			Error::<Test>::BadMessageSequence
+		);
+		assert_noop!(
+			PhalaPallet::transfer_asset_to_chain(Origin::signed(1), signed(10, 3)),
+			Error::<Test>::BadMessageSequence
+		);
+		// The Balances sequence is independent
+		assert_eq!(0, PhalaPallet::ingress_sequence(2));
+		// Not signed by the contract
+		let mut forged = AssetTransferData::<u64>::decode(&mut &signed(10, 2)[..]).unwrap();
+		forged.data.amount = 40;
+		assert_noop!(
+			PhalaPallet::transfer_asset_to_chain(Origin::signed(1), forged.encode()),
+			Error::<Test>::FailedToVerify
+		);
+		// Out of the range of the asset balance
+		assert_noop!(
+			PhalaPallet::transfer_asset_to_chain(Origin::signed(1), signed(u128::MAX, 2)),
+			Error::<Test>::InvalidInput
+		);
+		// More than the bridged assets
+		assert_noop!(
+			PhalaPallet::transfer_asset_to_chain(Origin::signed(1), signed(41, 2)),
+			Error::<Test>::CannotWithdraw
+		);
+	});
+}
+
+#[test]
+fn test_worker_messages() {
+	new_test_ext().execute_with(|| {
+		let imbalance = Balances::deposit_creating(&1, 100);
+		drop(imbalance);
+		assert_ok!(PhalaPallet::transfer_to_tee(Origin::signed(1), 50));
+		assert_ok!(PhalaPallet::push_command(Origin::signed(1), 2, vec![1, 2, 3]));
+		let messages: Vec<_> = PhalaPallet::worker_messages()
+			.iter()
+			.map(|m| m.decode_message::<u64, Balance>().unwrap())
+			.collect();
+		assert_eq!(messages, vec![
+			ChainMessage::TransferToTee { who: 1, amount: 50 },
+			ChainMessage::CommandPushed { origin: 1, contract_id: 2, payload: vec![1, 2, 3], num: 0 },
+		]);
+		// Unknown versions are skipped by the workers
+		let future = VersionedMessage { version: 2, payload: vec![] };
+		assert_eq!(future.decode_message::<u64, Balance>(), None);
+		// Cleared in the next block
+		PhalaPallet::on_initialize(2);
+		assert!(PhalaPallet::worker_messages().is_empty());
+	});
+}
+
 #[test]
 fn test_randomness() {
 	new_test_ext().execute_with(|| {
@@ -23,6 +23,8 @@ pub trait WeightInfo {
 	fn stop_mining_intention() -> Weight;
 	fn transfer_to_tee() -> Weight;
 	fn transfer_to_chain() -> Weight;
+	fn transfer_asset_to_tee() -> Weight;
+	fn transfer_asset_to_chain() -> Weight;
 	fn sync_worker_message() -> Weight;
 	fn force_next_round() -> Weight;
 	fn force_add_fire() -> Weight;
@@ -84,6 +86,16 @@ impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
 			.saturating_add(T::DbWeight::get().reads(4 as Weight))
 			.saturating_add(T::DbWeight::get().writes(3 as Weight))
 	}
+	fn transfer_asset_to_tee() -> Weight {
+		(150_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(3 as Weight))
+			.saturating_add(T::DbWeight::get().writes(4 as Weight))
+	}
+	fn transfer_asset_to_chain() -> Weight {
+		(380_000_000 as Weight)
+			.saturating_add(T::DbWeight::get().reads(6 as Weight))
+			.saturating_add(T::DbWeight::get().writes(5 as Weight))
+	}
 	fn sync_worker_message() -> Weight {
 		(345_000_000 as Weight)
 			.saturating_add(T::DbWeight::get().reads(5 as Weight))
@@ -176,6 +188,16 @@ impl WeightInfo for () {
 			.saturating_add(RocksDbWeight::get().reads(4 as Weight))
 			.saturating_add(RocksDbWeight::get().writes(3 as Weight))
 	}
+	fn transfer_asset_to_tee() -> Weight {
+		(150_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(3 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(4 as Weight))
+	}
+	fn transfer_asset_to_chain() -> Weight {
+		(380_000_000 as Weight)
+			.saturating_add(RocksDbWeight::get().reads(6 as Weight))
+			.saturating_add(RocksDbWeight::get().writes(5 as Weight))
+	}
 	fn sync_worker_message() -> Weight {
 		(345_000_000 as Weight)
 			.saturating_add(RocksDbWeight::get().reads(5 as Weight))
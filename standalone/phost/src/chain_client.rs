@@ -1,19 +1,20 @@
 use std::collections::HashSet;
 use anyhow::{anyhow, Result};
-use codec::FullCodec;
+use codec::{Decode, FullCodec};
 use log::{debug, error, info};
 use sp_core::{storage::StorageKey, twox_128};
 use phala_types::pruntime::{
     StorageKV, RawStorageKey, StorageProof,
-    OnlineWorkerSnapshot,
+    OnlineWorkerSnapshot, StorageReadRequest,
 };
-use subxt::{EventsDecoder, Store, RawEventWrapper as Raw};
+use phala_types::{ChainMessage, VersionedMessage};
+use subxt::Store;
 
 use super::XtClient;
 use crate::{
     runtimes,
     types::{
-        Runtime, Hash, BlockNumber, AccountId, RawEvents, Balance,
+        Runtime, Hash, BlockNumber, AccountId, Balance, LastRuntimeUpgradeInfo,
         utils::raw_proof,
     }
 };
@@ -36,19 +37,39 @@ pub async fn read_proof(client: &XtClient, hash: Option<Hash>, storage_key: Stor
         .map_err(Into::into)
 }
 
-/// Fetches the raw event at a certain block
-pub async fn fetch_events(client: &XtClient, hash: &Hash)
--> Result<Option<(RawEvents, StorageProof, RawStorageKey)>> {
-    let key = storage_value_key_vec("System", "Events");
-    let storage_key = StorageKey(key.clone());
-    let result = match get_storage(&client, Some(hash.clone()), storage_key.clone()).await? {
-        Some(value) => {
-            let proof = read_proof(&client, Some(hash.clone()), storage_key).await?;
-            Some((value, proof, key))
-        },
-        None => None,
-    };
-    Ok(result)
+/// Fetches the raw `Phala::WorkerMessages` at a certain block, with the proof of it (or of its
+/// absence)
+pub async fn fetch_messages(client: &XtClient, hash: &Hash)
+-> Result<(Option<Vec<u8>>, StorageProof)> {
+    let storage_key = StorageKey(storage_value_key_vec("Phala", "WorkerMessages"));
+    let messages = get_storage(&client, Some(hash.clone()), storage_key.clone()).await?;
+    let proof = read_proof(&client, Some(hash.clone()), storage_key).await?;
+    Ok((messages, proof))
+}
+
+/// Gets the runtime `spec_version` at a certain block from `System::LastRuntimeUpgrade`
+///
+/// Returns `None` if the chain has never been upgraded since the genesis.
+pub async fn spec_version_at(client: &XtClient, hash: Hash) -> Result<Option<u32>> {
+    let storage_key = StorageKey(storage_value_key_vec("System", "LastRuntimeUpgrade"));
+    let value = get_storage(client, Some(hash), storage_key).await?;
+    let info = value
+        .map(|v| LastRuntimeUpgradeInfo::decode(&mut v.as_slice()))
+        .transpose()?;
+    Ok(info.map(|info| info.spec_version))
+}
+
+/// Reads the storage items requested by pRuntime at a certain block, with the proof of them
+pub async fn read_storage_at(client: &XtClient, hash: Hash, keys: &[RawStorageKey])
+-> Result<StorageReadRequest> {
+    let mut items = Vec::new();
+    for key in keys {
+        let value = get_storage(client, Some(hash), StorageKey(key.clone())).await?;
+        items.push((key.clone(), value));
+    }
+    let storage_keys = keys.iter().cloned().map(StorageKey).collect();
+    let proof = raw_proof(client.read_proof(storage_keys, Some(hash)).await?);
+    Ok(StorageReadRequest { items, proof })
 }
 
 /// Takes a snapshot of the necessary information for calculating compute works at a certain block
@@ -122,19 +143,13 @@ pub async fn snapshot_online_worker_at(xt: &XtClient, hash: Option<Hash>)
     })
 }
 
-/// Check if the given raw event (in `Vec<u8>`) contains `Phala.NewMiningRound`
-pub fn check_round_end_event(
-    decoder: &EventsDecoder::<Runtime>, value: &Vec<u8>
-) -> Result<bool> {
-    let raw_events = decoder.decode_events(&mut value.as_slice())?;
-    for (_phase, raw) in &raw_events {
-        if let Raw::Event(event) = raw {
-            if event.module == "Phala" && event.variant == "NewMiningRound" {
-                return Ok(true)
-            }
-        }
-    }
-    Ok(false)
+/// Check if the given raw `Phala::WorkerMessages` contains `NewMiningRound`
+pub fn check_round_end_message(value: &[u8]) -> Result<bool> {
+    let messages: Vec<VersionedMessage> = codec::Decode::decode(&mut &value[..])?;
+    Ok(messages.iter().any(|m| matches!(
+        m.decode_message::<AccountId, Balance>(),
+        Some(ChainMessage::NewMiningRound(_))
+    )))
 }
 
 // Storage functions
@@ -11,6 +11,7 @@ pub enum Error {
     FailedToCallRegisterWorker,
     FailedToCallResetWorker,
     ComputeWorkerNotEnabled,
+    WorkerPubkeyMismatch,
 }
 
 impl fmt::Display for Error {
@@ -25,6 +26,7 @@ impl fmt::Display for Error {
             Error::FailedToCallRegisterWorker => write!(f, "failed to call register_worker"),
             Error::FailedToCallResetWorker => write!(f, "failed to call reset_worker"),
             Error::ComputeWorkerNotEnabled => write!(f, "compute worker not enabled"),
+            Error::WorkerPubkeyMismatch => write!(f, "worker public key doesn't match the pinned one"),
         }
     }
 }
@@ -10,11 +10,10 @@ use codec::{Encode, Decode};
 use core::marker::PhantomData;
 use sp_finality_grandpa::{AuthorityList, VersionedAuthorityList, GRANDPA_AUTHORITIES_KEY, SetId};
 use sp_core::{storage::StorageKey, sr25519, crypto::Pair};
-use subxt::{EventsDecoder, Signer, system::AccountStoreExt};
+use subxt::{Signer, system::AccountStoreExt};
 
 mod error;
 mod msg_sync;
-mod pruntime_client;
 mod notify_client;
 mod chain_client;
 mod runtimes;
@@ -27,9 +26,10 @@ use crate::types::{
     InitRuntimeReq, GenesisInfo, InitRuntimeResp, GetRuntimeInfoReq, InitRespAttestation,
     SyncHeaderReq, SyncHeaderResp, BlockWithEvents, HeaderToSync, AuthoritySet, AuthoritySetChange,
     OpaqueSignedBlock, DispatchBlockReq, DispatchBlockResp, BlockHeaderWithEvents,
-    NotifyReq,
+    GetStorageKeysReq, NotifyReq,
 };
 
+use phala_types::rpc::ContractsGenesis;
 use notify_client::NotifyClient;
 type XtClient = subxt::Client<Runtime>;
 type PrClient = pruntime_client::PRuntimeClient;
@@ -56,6 +56,13 @@ struct Args {
     #[structopt(default_value = "", long = "inject-key", help = "Inject key to pRuntime.")]
     inject_key: String,
 
+    #[structopt(
+        default_value = "", long = "contracts-genesis",
+        help = "The initial states of the contracts (SCALE encoded ContractsGenesis in base64). \
+                The private keys in it must be encrypted to the pRuntime ECDH key. \
+                The built-in test accounts are added with --use-dev-key.")]
+    contracts_genesis: String,
+
     #[structopt(
     short = "r", long = "remote-attestation",
     help = "Should enable Remote Attestation")]
@@ -76,6 +83,11 @@ struct Args {
     help = "pRuntime http endpoint")]
     pruntime_endpoint: String,
 
+    #[structopt(
+    default_value = "", long,
+    help = "The worker public key (hex) to verify pRuntime responses with. Taken from the runtime info if empty.")]
+    pruntime_pubkey: String,
+
     #[structopt(
     default_value = "", long,
     help = "notify endpoint")]
@@ -123,18 +135,12 @@ async fn get_block_with_events(client: &XtClient, h: Option<u32>)
     let block = get_block_at(&client, h).await?;
     let hash = block.block.header.hash();
 
-    let events_with_proof = chain_client::fetch_events(&client, &hash).await?;
-    if let Some((raw_events, proof, _key)) = events_with_proof {
-        info!("          ... with events {} bytes", raw_events.len());
-        let (events, proof) = (Some(raw_events), Some(proof));
-        return Ok(BlockWithEvents { block, events, proof });
-    }
-
-    if h == Some(0) {
-        return Ok(BlockWithEvents { block, events: None, proof: None });
+    let (messages, messages_proof) = chain_client::fetch_messages(&client, &hash).await?;
+    if let Some(messages) = &messages {
+        info!("          ... with messages {} bytes", messages.len());
     }
-
-    Err(anyhow!(Error::EventNotFound))
+    let spec_version = chain_client::spec_version_at(&client, hash).await?;
+    Ok(BlockWithEvents { block, messages, messages_proof, spec_version })
 }
 
 async fn get_authority_with_proof_at(client: &XtClient, hash: Hash) -> Result<AuthoritySetChange> {
@@ -241,32 +247,96 @@ where T: std::ops::Deref<Target = [BlockHeaderWithEvents]> {
     Ok(resp)
 }
 
-/// Attaches a worker snapshot for NewRound blocks for compute worker election
-async fn maybe_take_worker_snapshot(
+/// Converts the blocks to dispatch, attaching a worker snapshot for NewRound blocks for compute
+/// worker election
+async fn prepare_dispatch_batch(
     xt: &XtClient,
-    events_decoder: &EventsDecoder<Runtime>,
-    dispatch_batch: &mut Vec<BlockHeaderWithEvents>,
-) -> Result<()> {
-    for bwe in dispatch_batch {
-        let data = bwe.events.as_ref().unwrap();
-        if chain_client::check_round_end_event(events_decoder, data)? {
+    blocks: Vec<BlockWithEvents>,
+) -> Result<Vec<BlockHeaderWithEvents>> {
+    let mut dispatch_batch = Vec::with_capacity(blocks.len());
+    for bwe in blocks {
+        let round_end = match &bwe.messages {
+            Some(data) => chain_client::check_round_end_message(data)?,
+            None => false,
+        };
+        let block_header = bwe.block.block.header;
+        let mut worker_snapshot = None;
+        if round_end {
             let snapshot = chain_client::snapshot_online_worker_at(
-                xt, Some(bwe.block_header.hash())).await;
-            bwe.worker_snapshot = match snapshot {
+                xt, Some(block_header.hash())).await;
+            worker_snapshot = match snapshot {
                 Ok(snapshot) => Some(snapshot),
                 Err(e) if e.is::<Error>() && matches!(e.downcast_ref().unwrap(), Error::ComputeWorkerNotEnabled) => None,
                 Err(err) => return Err(err),
             };
         }
+        dispatch_batch.push(BlockHeaderWithEvents {
+            block_header,
+            messages_proof: bwe.messages_proof,
+            worker_snapshot,
+            storage_reads: None,
+        });
+    }
+    Ok(dispatch_batch)
+}
+
+/// The storage keys requested by the contracts in pRuntime at the next block to dispatch
+async fn get_storage_keys(pr: &PrClient) -> Result<Vec<Vec<u8>>> {
+    let resp = pr.req_decode("get_storage_keys", GetStorageKeysReq {}).await?;
+    let keys = resp.keys
+        .iter()
+        .map(hex::decode)
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(keys)
+}
+
+/// Attaches the values of the storage keys to the blocks
+async fn attach_storage_reads(
+    xt: &XtClient,
+    keys: &[Vec<u8>],
+    blocks: &mut [BlockHeaderWithEvents],
+) -> Result<()> {
+    for bwe in blocks {
+        bwe.storage_reads = if keys.is_empty() {
+            None
+        } else {
+            let hash = bwe.block_header.hash();
+            Some(chain_client::read_storage_at(xt, hash, keys).await?)
+        };
     }
     Ok(())
 }
 
+/// Dispatches the blocks with the storage items requested by the contracts in pRuntime attached
+///
+/// A contract may change its keys at a block in the batch. pRuntime then rejects the next block
+/// and keeps the ones before, so the blocks are sent again with the new keys. The dispatched ones
+/// are skipped by pRuntime.
+async fn dispatch_blocks(
+    xt: &XtClient,
+    pr: &PrClient,
+    mut blocks: Vec<BlockHeaderWithEvents>,
+) -> Result<DispatchBlockResp> {
+    let mut keys = get_storage_keys(pr).await?;
+    loop {
+        attach_storage_reads(xt, &keys, &mut blocks).await?;
+        let err = match req_dispatch_block(pr, &blocks).await {
+            Ok(resp) => return Ok(resp),
+            Err(err) => err,
+        };
+        let new_keys = get_storage_keys(pr).await?;
+        if new_keys == keys {
+            return Err(err);
+        }
+        info!("storage keys changed, dispatching the blocks again");
+        keys = new_keys;
+    }
+}
+
 /// Syncs only the events to pRuntime till `sync_to`
 async fn sync_events_only(
     xt: &XtClient,
     pr: &PrClient,
-    events_decoder: &EventsDecoder<Runtime>,
     sync_state: &mut BlockSyncState,
     sync_to: BlockNumber,
     batch_window: usize,
@@ -281,18 +351,10 @@ async fn sync_events_only(
             break;
         }
     }
-    let mut blocks: Vec<BlockHeaderWithEvents> = block_buf
-        .drain(..n)
-        .map(|bwe| BlockHeaderWithEvents {
-            block_header: bwe.block.block.header,
-            events: bwe.events,
-            proof: bwe.proof,
-            worker_snapshot: None,
-        })
-        .collect();
-    maybe_take_worker_snapshot(xt, events_decoder, &mut blocks).await?;
+    let blocks: Vec<BlockWithEvents> = block_buf.drain(..n).collect();
+    let blocks = prepare_dispatch_batch(xt, blocks).await?;
     for chunk in blocks.chunks(batch_window) {
-        let r = req_dispatch_block(pr, &chunk.to_vec()).await?;
+        let r = dispatch_blocks(xt, pr, chunk.to_vec()).await?;
         debug!("  ..dispatch_block: {:?}", r);
     }
     Ok(())
@@ -303,7 +365,6 @@ const GRANDPA_ENGINE_ID: sp_runtime::ConsensusEngineId = *b"FRNK";
 async fn batch_sync_block(
     client: &XtClient,
     pr: &PrClient,
-    events_decoder: &EventsDecoder<Runtime>,
     sync_state: &mut BlockSyncState,
     batch_window: usize
 ) -> Result<usize> {
@@ -407,21 +468,16 @@ async fn batch_sync_block(
             let end_batch = block_batch.len() as isize - 1;
             let batch_end = cmp::min(dispatch_window as isize, end_batch);
             if batch_end >= 0 {
-                let mut dispatch_batch: Vec<BlockHeaderWithEvents> = block_batch
+                let blocks: Vec<BlockWithEvents> = block_batch
                     .drain(..=(batch_end as usize))
-                    .map(|bwe| BlockHeaderWithEvents {
-                        block_header: bwe.block.block.header,
-                        events: bwe.events,
-                        proof: bwe.proof,
-                        worker_snapshot: None,
-                    })
                     .collect();
-                maybe_take_worker_snapshot(client, events_decoder, &mut dispatch_batch).await?;
-                let r = req_dispatch_block(pr, &dispatch_batch).await?;
+                let dispatch_batch = prepare_dispatch_batch(client, blocks).await?;
+                let batch_len = dispatch_batch.len();
+                let r = dispatch_blocks(client, pr, dispatch_batch).await?;
                 debug!("  ..dispatch_block: {:?}", r);
 
                 // Update sync state
-                synced_blocks += dispatch_batch.len();
+                synced_blocks += batch_len;
             }
         }
         sync_state.authory_set_state = Some(match set_id_change_at {
@@ -444,6 +500,10 @@ async fn get_balances_ingress_seq(client: &XtClient) -> Result<u64> {
     client.fetch_or_default(&runtimes::phala::IngressSequenceStore::new(2), None).await.or(Ok(0))
 }
 
+async fn get_assets_ingress_seq(client: &XtClient) -> Result<u64> {
+    client.fetch_or_default(&runtimes::phala::IngressSequenceStore::new(3), None).await.or(Ok(0))
+}
+
 async fn get_worker_ingress(client: &XtClient, stash: AccountId) -> Result<u64> {
     client.fetch_or_default(&runtimes::phala::WorkerIngressStore::new(stash), None).await
         .map_err(Into::into)
@@ -465,7 +525,7 @@ async fn update_signer_nonce(client: &XtClient, signer: &mut SrSigner) -> Result
 }
 
 async fn init_runtime(client: &XtClient, pr: &PrClient, skip_ra: bool, use_dev_key: bool,
-                       inject_key: &str) -> Result<InitRuntimeResp> {
+                       inject_key: &str, contracts_genesis: &str) -> Result<InitRuntimeResp> {
     let genesis_block = get_block_at(&client, Some(0)).await?.block;
     let hash = client.block_hash(Some(subxt::BlockNumber::from(NumberOrHex::Number(0)))).await?
         .expect("No genesis block?");
@@ -490,14 +550,38 @@ async fn init_runtime(client: &XtClient, pr: &PrClient, skip_ra: bool, use_dev_k
         debug_set_key = Some(String::from(DEV_KEY));
     }
 
+    let mut genesis = if contracts_genesis.is_empty() {
+        ContractsGenesis::default()
+    } else {
+        ContractsGenesis::decode(&mut &base64::decode(contracts_genesis)?[..])
+            .map_err(|_| Error::FailedToDecode)?
+    };
+    genesis.dev_fixtures |= use_dev_key;
+
     let resp = pr.req_decode("init_runtime", InitRuntimeReq {
         skip_ra,
         bridge_genesis_info_b64: info_b64,
-        debug_set_key
+        debug_set_key,
+        contracts_genesis_b64: Some(base64::encode(&genesis.encode())),
     }).await?;
     Ok(resp)
 }
 
+/// Verifies the following pRuntime responses with the worker key in the runtime info, unless it's
+/// pinned by `--pruntime-pubkey`
+fn pin_worker_pubkey(pr: &PrClient, runtime_info: &InitRuntimeResp, args: &Args) -> Result<()> {
+    let info = phala_types::PRuntimeInfo::decode(&mut &runtime_info.encoded_runtime_info[..])
+        .map_err(|_| Error::FailedToDecode)?;
+    if !args.pruntime_pubkey.is_empty() {
+        if hex::decode(&args.pruntime_pubkey)? != info.pubkey.to_vec() {
+            return Err(anyhow!(Error::WorkerPubkeyMismatch));
+        }
+        return Ok(());
+    }
+    info!("Verifying pRuntime responses with worker key {}", hex::encode(&info.pubkey[..]));
+    pr.set_worker_pubkey(&info.pubkey)
+}
+
 async fn register_worker(
     client: &XtClient, encoded_runtime_info: Vec<u8>,
     attestation: &InitRespAttestation, signer: &mut SrSigner
@@ -556,11 +640,15 @@ async fn bridge(args: Args) -> Result<()> {
         .set_url(args.substrate_ws_endpoint.clone())
         .skip_type_sizes_check()
         .build().await?;
-    let events_decoder = client.events_decoder();
     info!("Connected to substrate at: {}", args.substrate_ws_endpoint.clone());
 
     // Other initialization
     let pr = PrClient::new(&args.pruntime_endpoint);
+    if !args.pruntime_pubkey.is_empty() {
+        pr.set_worker_pubkey(&hex::decode(&args.pruntime_pubkey)?)?;
+    } else {
+        warn!("Worker public key not pinned. pRuntime responses unverified until the runtime info is fetched.");
+    }
     let pair = <sr25519::Pair as Pair>::from_string(&args.mnemonic, None)
         .expect("Bad privkey derive path");
     let mut signer: SrSigner = subxt::PairSigner::new(pair);
@@ -591,8 +679,10 @@ async fn bridge(args: Args) -> Result<()> {
         let mut runtime_info: Option<InitRuntimeResp> = None;
         if !info.initialized {
             warn!("pRuntime not initialized. Requesting init...");
-            runtime_info = Some(init_runtime(&client, &pr, !args.ra, args.use_dev_key,
-                                             &args.inject_key).await?);
+            let resp = init_runtime(&client, &pr, !args.ra, args.use_dev_key,
+                                    &args.inject_key, &args.contracts_genesis).await?;
+            pin_worker_pubkey(&pr, &resp, &args)?;
+            runtime_info = Some(resp);
             // STATUS: pruntime_initialized = true
             // STATUS: pruntime_new_init = true
             pruntime_initialized = true;
@@ -606,11 +696,12 @@ async fn bridge(args: Args) -> Result<()> {
             }).await.ok();
         } else {
             info!("pRuntime already initialized. Fetching runtime info...");
+            let resp = pr.req_decode("get_runtime_info", GetRuntimeInfoReq {}).await?;
+            pin_worker_pubkey(&pr, &resp, &args)?;
             let machine_owner = get_machine_owner(&client, info.machine_id).await?;
             if machine_owner == [0u8; 32] {
                 // Worker not registered
-                runtime_info = Some(
-                    pr.req_decode("get_runtime_info", GetRuntimeInfoReq {}).await?);
+                runtime_info = Some(resp);
             }
 
             // STATUS: pruntime_initialized = true
@@ -642,6 +733,7 @@ async fn bridge(args: Args) -> Result<()> {
     // Don't just sync message if we want to wait for some block
     let mut defer_block = wait_block_until.is_some();
     let mut balance_seq = get_balances_ingress_seq(&client).await?;
+    let mut asset_seq = get_assets_ingress_seq(&client).await?;
     let mut system_seq = get_worker_ingress(&client, stash).await?;
     let mut sync_state = BlockSyncState {
         blocks: Vec::new(),
@@ -682,8 +774,20 @@ async fn bridge(args: Args) -> Result<()> {
         };
         let batch_end =
             std::cmp::min(latest_block.header.number, next_block + args.fetch_blocks - 1);
+        let mut upgrade_blocked = false;
         for b in next_block ..= batch_end {
             let block = get_block_with_events(&client, Some(b)).await?;
+            // stop buffering at a runtime upgrade that pRuntime doesn't understand
+            if let Some(spec_version) = block.spec_version {
+                if spec_version < info.min_spec_version || spec_version > info.max_spec_version {
+                    error!(
+                        "Runtime upgraded to spec_version {} at block {}, but pRuntime only supports {}..={}. \
+                         Syncing paused; please upgrade pRuntime.",
+                        spec_version, b, info.min_spec_version, info.max_spec_version);
+                    upgrade_blocked = true;
+                    break;
+                }
+            }
             if block.block.justifications.is_some() {
                 debug!("block with justification at: {}", block.block.block.header.number);
             }
@@ -699,7 +803,7 @@ async fn bridge(args: Args) -> Result<()> {
         // if the header syncs faster than the event, let the events to catch up
         if info.headernum > info.blocknum {
             sync_events_only(
-                &client, &pr, events_decoder, &mut sync_state,
+                &client, &pr, &mut sync_state,
                 // info.headernum is the next unknown header. So we sync to headernum - 1
                 info.headernum - 1,
                 args.sync_blocks
@@ -708,7 +812,7 @@ async fn bridge(args: Args) -> Result<()> {
 
         // send the blocks to pRuntime in batch
         let synced_blocks = batch_sync_block(
-            &client, &pr, events_decoder, &mut sync_state, args.sync_blocks).await?;
+            &client, &pr, &mut sync_state, args.sync_blocks).await?;
 
         // check if pRuntime has already reached the chain tip.
         if !defer_block && synced_blocks == 0 {
@@ -727,10 +831,15 @@ async fn bridge(args: Args) -> Result<()> {
                 let mut msg_sync = msg_sync::MsgSync::new(&client, &pr, &mut signer);
                 msg_sync.maybe_sync_worker_egress(&mut system_seq).await?;
                 msg_sync.maybe_sync_balances_egress(&mut balance_seq).await?;
+                msg_sync.maybe_sync_assets_egress(&mut asset_seq).await?;
             }
         }
         if synced_blocks == 0 {
-            info!("Waiting for new blocks");
+            if upgrade_blocked {
+                warn!("Waiting for a pRuntime supporting the new runtime");
+            } else {
+                info!("Waiting for new blocks");
+            }
             delay_for(Duration::from_millis(5000)).await;
             continue;
         }
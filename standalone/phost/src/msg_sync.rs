@@ -3,11 +3,16 @@ use std::cmp;
 use codec::{Encode, Decode};
 use core::marker::PhantomData;
 use log::{error, info};
+use pruntime_client::contracts::{
+    assets::{self, Assets},
+    balances::{self, Balances},
+    system::{self, System},
+};
 
 use super::{
     update_signer_nonce,
     error::Error,
-    types::{ReqData, QueryRespData, TransferData},
+    types::{AssetTransferData, TransferData},
     runtimes,
     XtClient, PrClient, SrSigner
 };
@@ -36,10 +41,11 @@ impl<'a> MsgSync<'a> {
     /// Syncs the worker egress messages when available
     pub async fn maybe_sync_worker_egress(&mut self, sequence: &mut u64) -> Result<()> {
         // Check pending messages in worker egress queue
-        let query_resp = self.pr.query(
-            0, ReqData::GetWorkerEgress { start_sequence: *sequence }).await?;
+        let query_resp = self.pr.contract::<System>()
+            .query(system::Request::GetWorkerEgress { start_sequence: *sequence })
+            .send().await?;
         let msg_data = match query_resp {
-            QueryRespData::GetWorkerEgress { length, encoded_egress_b64 } => {
+            system::Response::GetWorkerEgress { length, encoded_egress_b64 } => {
                 info!("maybe_sync_worker_egress: got {} messages", length);
                 base64::decode(&encoded_egress_b64)
                     .map_err(|_| Error::FailedToDecode)?
@@ -81,9 +87,11 @@ impl<'a> MsgSync<'a> {
     /// Syncs the Balances egress messages when available
     pub async fn maybe_sync_balances_egress(&mut self, sequence: &mut u64) -> Result<()> {
         // Check pending messages in Balances' egress queue
-        let query_resp = self.pr.query(2, ReqData::PendingChainTransfer {sequence: *sequence}).await?;
+        let query_resp = self.pr.contract::<Balances>()
+            .query(balances::Request::PendingChainTransfer { sequence: *sequence })
+            .send().await?;
         let transfer_data = match query_resp {
-            QueryRespData::PendingChainTransfer { transfer_queue_b64 } =>
+            balances::Response::PendingChainTransfer { transfer_queue_b64 } =>
                 base64::decode(&transfer_queue_b64)
                     .map_err(|_| Error::FailedToDecode)?,
             _ => return Err(anyhow!(Error::FailedToDecode))
@@ -118,6 +126,46 @@ impl<'a> MsgSync<'a> {
         Ok(())
     }
 
+    /// Syncs the Assets egress messages (the withdrawals to pallet_assets) when available
+    pub async fn maybe_sync_assets_egress(&mut self, sequence: &mut u64) -> Result<()> {
+        let query_resp = self.pr.contract::<Assets>()
+            .query(assets::Request::PendingChainTransfer { sequence: *sequence })
+            .send().await?;
+        let transfer_data = match query_resp {
+            assets::Response::PendingChainTransfer { transfer_queue_b64 } =>
+                base64::decode(&transfer_queue_b64)
+                    .map_err(|_| Error::FailedToDecode)?,
+            _ => return Err(anyhow!(Error::FailedToDecode))
+        };
+        let transfer_queue: Vec<AssetTransferData> = Decode::decode(&mut &transfer_data[..])
+            .map_err(|_|Error::FailedToDecode)?;
+        // No pending message. We are done.
+        if transfer_queue.is_empty() {
+            return Ok(());
+        }
+        // Send messages
+        self.maybe_update_signer_nonce().await?;
+        let mut next_seq = *sequence;
+        for transfer_data in &transfer_queue {
+            let msg_seq = transfer_data.data.sequence;
+            if msg_seq <= *sequence {   // This seq is 1-based
+                info!("Asset msg {} has been submitted. Skipping...", msg_seq);
+                continue;
+            }
+            next_seq = cmp::max(next_seq, msg_seq);
+            let ret = self.client.submit(runtimes::phala::TransferAssetToChainCall {
+                _runtime: PhantomData,
+                data: transfer_data.encode()
+            }, self.signer).await;
+            if let Err(err) = ret {
+                error!("Failed to submit tx: {:?}", err);
+            }
+            self.signer.increment_nonce();
+        }
+        *sequence = next_seq;
+        Ok(())
+    }
+
     /// Updates the nonce if it's not updated.
     ///
     /// The nonce will only be updated once during the lifetime of MsgSync struct.
@@ -195,6 +195,15 @@ pub mod phala {
         pub data: Vec<u8>,
     }
 
+    /// The call to transfer_asset_to_chain
+    #[derive(Clone, Debug, PartialEq, Call, Encode)]
+    pub struct TransferAssetToChainCall<T: Phala> {
+        /// Runtime marker
+        pub _runtime: PhantomData<T>,
+        /// The asset transfer transaction data, SCALE encoded
+        pub data: Vec<u8>,
+    }
+
     /// The call to register_worker
     #[derive(Clone, Debug, PartialEq, Call, Encode)]
     pub struct RegisterWorkerCall<T: Phala> {
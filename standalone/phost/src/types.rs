@@ -1,4 +1,4 @@
-use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use serde::{Serialize, Deserialize};
 
 use codec::{Encode, Decode};
 use sp_finality_grandpa::{AuthorityList, SetId};
@@ -23,117 +23,43 @@ pub type BlockNumber = <Runtime as subxt::system::System>::BlockNumber;
 pub type AccountId = <Runtime as subxt::system::System>::AccountId;
 pub type Balance = <Runtime as subxt::balances::Balances>::Balance;
 
-pub type RawEvents = Vec<u8>;
 pub type HeaderToSync = pruntime::HeaderToSync<BlockNumber, Hashing>;
 pub type BlockHeaderWithEvents = pruntime::BlockHeaderWithEvents<BlockNumber, Hashing, Balance>;
 
 
 // pRuntime APIs
 
-pub trait Resp {
-    type Resp: DeserializeOwned;
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct SignedResp {
-    pub payload: String,
-    pub status: String,
-    pub signature: String
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Nonce {
-    value: u32,
-}
-
-impl Nonce {
-    pub fn new() -> Nonce {
-        Nonce { value: rand::random::<u32>() }
-    }
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct RuntimeReq<T: Serialize> {
-    pub input: T,
-    pub nonce: Nonce,
-}
-impl<T: Serialize> RuntimeReq<T> {
-    pub fn new(input: T) -> Self {
-        Self { input: input, nonce: Nonce::new() }
-    }
-}
+pub use pruntime_client::Resp;
 
 // API: get_info
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct GetInfoReq {}
-#[derive(Serialize, Deserialize, Debug)]
-pub struct GetInfoResp {
-    pub headernum: BlockNumber,
-    pub blocknum: BlockNumber,
-    pub initialized: bool,
-    pub public_key: String,
-    pub ecdh_public_key: String,
-    pub machine_id: Vec<u8>,
-    pub system_egress: EgressInfo,
-}
-#[derive(Serialize, Deserialize, Debug)]
-pub struct EgressInfo {
-    sequence: u64,
-    len: u64,
-}
-impl Resp for GetInfoReq {
-    type Resp = GetInfoResp;
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub enum Payload {
-    Plain(String),
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Query {
-    pub contract_id: u32,
-    pub nonce: u32,
-    pub request: ReqData,
-}
+pub use pruntime_client::{GetInfoReq, GetInfoResp};
 
 #[derive(Serialize, Deserialize, Debug)]
-pub enum ReqData {
-    PendingChainTransfer { sequence: u64 },     // Balances
-    GetWorkerEgress { start_sequence: u64 },    // System
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub enum QueryRespData {
-    PendingChainTransfer {
-        transfer_queue_b64: String,
-    },
-    GetWorkerEgress {
-        length: usize,
-        encoded_egress_b64: String,
-    }
+#[derive(Encode, Decode)]
+pub struct Transfer {
+    pub dest: [u8; 32],
+    pub amount: u128,
+    pub sequence: u64,
 }
-
 #[derive(Serialize, Deserialize, Debug)]
-pub struct QueryReq {
-    pub query_payload: String,
-}
-impl Resp for QueryReq {
-    type Resp = Payload;
+#[derive(Encode, Decode)]
+pub struct TransferData {
+    pub data: Transfer,
+    pub signature: Vec<u8>,
 }
-
 #[derive(Serialize, Deserialize, Debug)]
 #[derive(Encode, Decode)]
-pub struct Transfer {
+pub struct AssetTransfer {
     pub dest: [u8; 32],
+    pub asset_id: u32,
     pub amount: u128,
     pub sequence: u64,
 }
 #[derive(Serialize, Deserialize, Debug)]
 #[derive(Encode, Decode)]
-pub struct TransferData {
-    pub data: Transfer,
+pub struct AssetTransferData {
+    pub data: AssetTransfer,
     pub signature: Vec<u8>,
 }
 
@@ -145,6 +71,8 @@ pub struct InitRuntimeReq {
   pub skip_ra: bool,
   pub bridge_genesis_info_b64: String,
   pub debug_set_key: Option<String>,
+  /// SCALE encoded `phala_types::rpc::ContractsGenesis`
+  pub contracts_genesis_b64: Option<String>,
 }
 #[derive(Serialize, Deserialize, Debug)]
 pub struct InitRuntimeResp {
@@ -199,8 +127,19 @@ impl Resp for SyncHeaderReq {
 #[derive(Clone, Debug)]
 pub struct BlockWithEvents {
     pub block: OpaqueSignedBlock,
-    pub events: Option<Vec<u8>>,
-    pub proof: Option<StorageProof>,
+    /// The encoded `Phala::WorkerMessages`, `None` if there's no message in the block
+    pub messages: Option<Vec<u8>>,
+    pub messages_proof: StorageProof,
+    /// The runtime `spec_version` of the block, `None` if the chain was never upgraded
+    pub spec_version: Option<u32>,
+}
+
+/// `frame_system::LastRuntimeUpgradeInfo`
+#[derive(Decode, Clone, Debug)]
+pub struct LastRuntimeUpgradeInfo {
+    #[codec(compact)]
+    pub spec_version: u32,
+    pub spec_name: String,
 }
 
 #[derive(Encode, Decode, Clone, PartialEq, Debug)]
@@ -228,6 +167,18 @@ impl Resp for DispatchBlockReq {
     type Resp = DispatchBlockResp;
 }
 
+// API: get_storage_keys
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetStorageKeysReq {}
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetStorageKeysResp {
+    pub keys: Vec<String>
+}
+impl Resp for GetStorageKeysReq {
+    type Resp = GetStorageKeysResp;
+}
+
 // API: notify
 
 #[derive(Serialize, Deserialize, Debug)]
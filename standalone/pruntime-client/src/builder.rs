@@ -0,0 +1,136 @@
+//! Typed builders of the contract queries and commands
+
+use std::marker::PhantomData;
+
+use anyhow::Result;
+use log::info;
+
+use crate::contracts::Contract;
+use crate::crypto::{EcdhSession, Signer};
+use crate::error::Error;
+use crate::types::{Payload, Query, SignedQuery};
+use crate::PRuntimeClient;
+
+/// The entry to the queries and commands of the contract `C`
+pub struct ContractClient<'a, C> {
+    client: &'a PRuntimeClient,
+    contract: PhantomData<C>,
+}
+
+impl<'a, C: Contract> ContractClient<'a, C> {
+    pub(crate) fn new(client: &'a PRuntimeClient) -> Self {
+        ContractClient {
+            client,
+            contract: PhantomData,
+        }
+    }
+
+    pub fn query(&self, request: C::Request) -> QueryBuilder<'a, C> {
+        QueryBuilder {
+            client: self.client,
+            request,
+            signer: None,
+            encrypted: false,
+        }
+    }
+
+    pub fn command(&self, command: C::Command) -> CommandBuilder<'a, C> {
+        CommandBuilder {
+            client: self.client,
+            command,
+            encrypted: false,
+        }
+    }
+}
+
+/// A query sent to pRuntime directly
+pub struct QueryBuilder<'a, C: Contract> {
+    client: &'a PRuntimeClient,
+    request: C::Request,
+    signer: Option<&'a Signer>,
+    encrypted: bool,
+}
+
+impl<'a, C: Contract> QueryBuilder<'a, C> {
+    /// Signs the query, so the contract sees the signer as the origin
+    pub fn signed_by(mut self, signer: &'a Signer) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Encrypts the query and its response with a secret agreed with the worker
+    pub fn encrypted(mut self) -> Self {
+        self.encrypted = true;
+        self
+    }
+
+    pub async fn send(self) -> Result<C::Response> {
+        let query = Query {
+            contract_id: C::ID,
+            nonce: rand::random(),
+            request: self.request,
+        };
+        let query_json = serde_json::to_string(&query)?;
+        let (payload, session) = if self.encrypted {
+            let session = EcdhSession::new(&self.client.worker_ecdh_pubkey().await?)?;
+            let cipher = session.encrypt(query_json.as_bytes())?;
+            (Payload::Cipher(cipher), Some(session))
+        } else {
+            (Payload::Plain(query_json), None)
+        };
+        let query_payload = serde_json::to_string(&payload)?;
+        let origin = self
+            .signer
+            .map(|signer| signer.sign(query_payload.as_bytes()));
+        info!("Query contract: {}, payload: {}", C::ID, query_payload);
+
+        let resp = self
+            .client
+            .req_decode(
+                "query",
+                SignedQuery {
+                    query_payload,
+                    origin,
+                },
+            )
+            .await?;
+        // The response is encrypted if and only if the query is
+        let resp_json = match (resp, session) {
+            (Payload::Plain(json), None) => json.into_bytes(),
+            (Payload::Cipher(cipher), Some(session)) => session.decrypt(&cipher)?,
+            _ => return Err(Error::FailedToDecode.into()),
+        };
+        info!("Query response: {}", String::from_utf8_lossy(&resp_json));
+        let resp = serde_json::from_slice(&resp_json).map_err(|_| Error::FailedToDecode)?;
+        Ok(resp)
+    }
+}
+
+/// A command sent to the contract through the chain
+///
+/// pRuntime takes the signer of the `Phala::push_command` extrinsic as the origin.
+pub struct CommandBuilder<'a, C: Contract> {
+    client: &'a PRuntimeClient,
+    command: C::Command,
+    encrypted: bool,
+}
+
+impl<'a, C: Contract> CommandBuilder<'a, C> {
+    /// Encrypts the command to the worker, so it's not revealed on the chain
+    pub fn encrypted(mut self) -> Self {
+        self.encrypted = true;
+        self
+    }
+
+    /// Builds the payload for `Phala::push_command(C::ID, payload)`
+    pub async fn build(self) -> Result<Vec<u8>> {
+        let command_json = serde_json::to_string(&self.command)?;
+        let payload = if self.encrypted {
+            let session = EcdhSession::new(&self.client.worker_ecdh_pubkey().await?)?;
+            Payload::Cipher(session.encrypt(command_json.as_bytes())?)
+        } else {
+            Payload::Plain(command_json)
+        };
+        Ok(serde_json::to_vec(&payload)?)
+    }
+}
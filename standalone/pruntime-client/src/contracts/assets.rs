@@ -0,0 +1,159 @@
+//! The Assets contract: confidential tokens issued by the users
+
+use serde::{Deserialize, Serialize};
+
+use super::{serde_balance, AccountId, Balance, TxRef, ASSETS};
+
+contract!(Assets, ASSETS);
+
+pub type AssetId = u32;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Command {
+    Issue {
+        symbol: String,
+        #[serde(with = "serde_balance")]
+        total: Balance,
+        name: String,
+        /// At most 18
+        decimals: u8,
+    },
+    /// Destroys an asset. Only allowed when no one but the owner holds it.
+    Destroy {
+        id: AssetId,
+    },
+    /// Issues more of an asset to `dest`. Owner only.
+    Mint {
+        id: AssetId,
+        dest: AccountId,
+        #[serde(with = "serde_balance")]
+        value: Balance,
+    },
+    /// Burns the asset held by `who`. Owner only.
+    Burn {
+        id: AssetId,
+        who: AccountId,
+        #[serde(with = "serde_balance")]
+        value: Balance,
+    },
+    /// Stops `who` from transferring the asset, and from receiving it in transfers. The owner can
+    /// still mint to and burn from a frozen account. Owner only.
+    Freeze {
+        id: AssetId,
+        who: AccountId,
+    },
+    Thaw {
+        id: AssetId,
+        who: AccountId,
+    },
+    /// Hands the admin rights of an asset over. Owner only.
+    TransferOwnership {
+        id: AssetId,
+        owner: AccountId,
+    },
+    Transfer {
+        id: AssetId,
+        dest: AccountId,
+        #[serde(with = "serde_balance")]
+        value: Balance,
+    },
+    /// Withdraws a bridged asset to `dest` in pallet_assets
+    TransferToChain {
+        id: AssetId,
+        dest: AccountId,
+        #[serde(with = "serde_balance")]
+        value: Balance,
+    },
+    /// Starts or stops recording the history of the origin. Stopping drops the recorded history.
+    SetTracking { enabled: bool },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Request {
+    Balance { id: AssetId, account: AccountId },
+    TotalSupply { id: AssetId },
+    /// The supply held by the accounts other than the owner
+    CirculatingSupply { id: AssetId },
+    Metadata,
+    /// The history of `account` from the transaction `from` on, the newest first. Only the owner
+    /// of the account can query it.
+    History {
+        account: AccountId,
+        from: Option<u64>,
+        limit: u32,
+    },
+    ListAssets { available_only: bool },
+    PendingChainTransfer { sequence: u64 },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Response {
+    Balance {
+        #[serde(with = "serde_balance")]
+        balance: Balance,
+    },
+    TotalSupply {
+        #[serde(with = "serde_balance")]
+        total_issuance: Balance,
+    },
+    CirculatingSupply {
+        #[serde(with = "serde_balance")]
+        circulating_supply: Balance,
+    },
+    Metadata {
+        metadata: Vec<AssetMetadata>,
+    },
+    History {
+        history: Vec<AssetsTx>,
+        /// The `from` of the next page, if any
+        next: Option<u64>,
+    },
+    ListAssets {
+        assets: Vec<AssetMetadataBalance>,
+    },
+    PendingChainTransfer {
+        /// The SCALE encoded `Vec<phala_types::AssetTransferData>` in base64
+        transfer_queue_b64: String,
+    },
+    Error(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AssetMetadata {
+    pub owner: AccountId,
+    #[serde(with = "serde_balance")]
+    pub total_supply: Balance,
+    pub symbol: String,
+    pub name: String,
+    pub decimals: u8,
+    pub id: AssetId,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AssetMetadataBalance {
+    pub metadata: AssetMetadata,
+    #[serde(with = "serde_balance")]
+    pub balance: Balance,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AssetsTxKind {
+    Issue,
+    Destroy,
+    Mint,
+    Burn,
+    Transfer,
+    TransferToChain,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AssetsTx {
+    pub id: u64,
+    pub txref: TxRef,
+    pub kind: AssetsTxKind,
+    pub asset_id: AssetId,
+    pub from: AccountId,
+    pub to: AccountId,
+    #[serde(with = "serde_balance")]
+    pub amount: Balance,
+}
@@ -0,0 +1,88 @@
+//! The Balances contract: the confidential native token
+
+use serde::{Deserialize, Serialize};
+
+use super::{serde_balance, AccountId, Balance, TxRef, BALANCES};
+
+contract!(Balances, BALANCES);
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Command {
+    Transfer {
+        dest: AccountId,
+        #[serde(with = "serde_balance")]
+        value: Balance,
+    },
+    /// Allows `spender` to transfer up to `value` from the origin, replacing the former allowance
+    Approve {
+        spender: AccountId,
+        #[serde(with = "serde_balance")]
+        value: Balance,
+    },
+    /// Transfers from `owner` to `dest` within the allowance of the origin
+    TransferFrom {
+        owner: AccountId,
+        dest: AccountId,
+        #[serde(with = "serde_balance")]
+        value: Balance,
+    },
+    TransferToChain {
+        dest: AccountId,
+        #[serde(with = "serde_balance")]
+        value: Balance,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Request {
+    FreeBalance { account: AccountId },
+    TotalIssuance,
+    PendingChainTransfer { sequence: u64 },
+    /// Only the owner or the spender can query it
+    Allowance { owner: AccountId, spender: AccountId },
+    /// The transfers of `account` older than `before`, the newest first. Only the owner of the
+    /// account can query it.
+    History {
+        account: AccountId,
+        before: Option<u64>,
+        limit: u32,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Response {
+    FreeBalance {
+        #[serde(with = "serde_balance")]
+        balance: Balance,
+    },
+    TotalIssuance {
+        #[serde(with = "serde_balance")]
+        total_issuance: Balance,
+    },
+    PendingChainTransfer {
+        /// The SCALE encoded `Vec<phala_types::TransferData>` in base64
+        transfer_queue_b64: String,
+    },
+    Allowance {
+        #[serde(with = "serde_balance")]
+        allowance: Balance,
+    },
+    History {
+        history: Vec<BalancesTx>,
+        /// The `before` of the next page, if any
+        next: Option<u64>,
+    },
+    Error(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BalancesTx {
+    pub id: u64,
+    pub txref: TxRef,
+    pub from: AccountId,
+    pub to: AccountId,
+    /// The spender of a `TransferFrom`
+    pub spender: Option<AccountId>,
+    #[serde(with = "serde_balance")]
+    pub amount: Balance,
+}
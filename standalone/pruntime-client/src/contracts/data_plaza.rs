@@ -0,0 +1,162 @@
+//! The DataPlaza contract: a marketplace of datasets
+
+use serde::{Deserialize, Serialize};
+
+use super::{serde_balance, Balance, TxRef, DATA_PLAZA};
+
+contract!(DataPlaza, DATA_PLAZA);
+
+pub type ItemId = u32;
+pub type OrderId = u32;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Command {
+    List(ItemDetails),
+    OpenOrder(OrderDetails),
+    /// Uploads the dataset of an item by its seller, or the query file of an order by its buyer.
+    /// Must be sent `encrypted`.
+    Upload { path: String, data_b64: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Request {
+    GetItems,
+    GetOrders,
+    /// The result of an order, only for its buyer. Must be sent `encrypted`.
+    GetResult { order_id: OrderId },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Response {
+    GetItems { items: Vec<Item> },
+    GetOrders { orders: Vec<Order> },
+    GetResult { data_b64: String },
+    Error(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Item {
+    pub id: ItemId,
+    pub txref: TxRef,
+    pub seller: String,
+    pub details: ItemDetails,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ItemDetails {
+    pub name: String,
+    pub category: String,
+    pub description: String,
+    pub price: PricePolicy,
+    pub dataset_link: String,
+    pub dataset_preview: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum PricePolicy {
+    PerRow {
+        #[serde(with = "serde_balance")]
+        price: Balance,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Order {
+    pub id: OrderId,
+    pub txref: TxRef,
+    pub buyer: String,
+    pub details: OrderDetails,
+    pub state: OrderState,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OrderDetails {
+    pub item_id: ItemId,
+    /// The dataset of the buyer to join with
+    pub query_link: String,
+    /// Without a query, the dataset is matched against the keys listed in `query_link`, named by
+    /// its header
+    pub query: Option<Query>,
+    /// The most the buyer pays, escrowed when the order is opened
+    #[serde(with = "serde_balance")]
+    pub budget: Balance,
+}
+
+/// A SQL-like query over the CSV dataset of an item
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Query {
+    /// The columns to output, all of them if empty. Ignored by the aggregations.
+    pub select: Vec<String>,
+    /// Only the rows matching all the filters are selected
+    pub filters: Vec<Filter>,
+    pub join: Option<Join>,
+    pub aggregate: Option<Aggregate>,
+}
+
+/// Compares the field of a column with `value`, as integers if both are, or as bytes otherwise
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Filter {
+    pub column: String,
+    pub op: Op,
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// An inner join with the rows of the `query_link` dataset having the same `key`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Join {
+    pub key: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Aggregate {
+    Count,
+    /// The sum of an integer column
+    Sum { column: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OrderState {
+    pub data_ready: bool,
+    pub query_ready: bool,
+    pub result_ready: bool,
+    pub matched_rows: u32,
+    /// The price of the matched rows
+    #[serde(with = "serde_balance")]
+    pub cost: Balance,
+    pub result_path: String,
+    /// Why the query failed
+    pub error: Option<String>,
+    pub settlement: Settlement,
+}
+
+/// Where the budget of an order goes
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Settlement {
+    /// Opened before the payments were added, so the result is free
+    Unfunded,
+    /// Waiting for the budget to be escrowed
+    Pending,
+    /// The budget couldn't be escrowed, so the order is dropped
+    Rejected,
+    /// The budget is held until the result is delivered
+    Escrowed,
+    /// The cost is paid to the seller and the rest of the budget refunded to the buyer
+    Settled {
+        #[serde(with = "serde_balance")]
+        paid: Balance,
+        #[serde(with = "serde_balance")]
+        refunded: Balance,
+    },
+    /// No result is delivered, so the whole budget is refunded
+    Refunded,
+}
@@ -0,0 +1,85 @@
+//! The Diem contract: a light client of Diem with custodial accounts
+
+use serde::{Deserialize, Serialize};
+
+use super::{AccountId, DIEM};
+
+contract!(Diem, DIEM);
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Command {
+    /// Sets the whitelisted accounts, in bcs encoded base64
+    AccountInfo {
+        account_info_b64: String,
+    },
+    /// Verifies a transactions
+    VerifyTransaction {
+        account_address: String,
+        transaction_with_proof_b64: String,
+    },
+    /// Sets the trusted state. The owner can only initialize the bridge with the genesis state
+    /// once.
+    SetTrustedState {
+        trusted_state_b64: String,
+        chain_id: u8,
+    },
+    VerifyEpochProof {
+        ledger_info_with_signatures_b64: String,
+        epoch_change_proof_b64: String,
+    },
+    NewAccount {
+        seq_number: u64,
+    },
+    TransferXUS {
+        to: String,
+        amount: u64,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Request {
+    /// Gets all the verified transactions, in hex hash string
+    VerifiedTransactions,
+    /// Gets signed transaction, from start
+    GetSignedTransactions {
+        start: u64,
+    },
+    CurrentState,
+    AccountData,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Response {
+    /// The response with all the the transaction hash verified successfully by the light client
+    VerifiedTransactions {
+        hash: Vec<String>,
+    },
+    GetSignedTransactions {
+        queue_b64: String,
+    },
+    CurrentState {
+        state: State,
+    },
+    AccountData {
+        data: Vec<AccountData>,
+    },
+    /// Some other errors
+    Error(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct State {
+    pub queue_seq: u64,
+    pub account_address: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccountData {
+    pub is_vasp: bool,
+    /// The Diem account address in hex
+    pub address: String,
+    pub phala_address: AccountId,
+    pub sequence: u64,
+    pub free: u64,
+    pub locked: u64,
+}
@@ -0,0 +1,163 @@
+//! The messages of the confidential contracts in pRuntime
+//!
+//! Each contract is a marker type implementing [`Contract`], which binds its id to the types of its
+//! commands, queries and query responses. They mirror the JSON encoding in pRuntime.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{de, de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Declares the marker type of a contract
+macro_rules! contract {
+    ($name: ident, $id: expr) => {
+        #[derive(Debug, Clone, Copy)]
+        pub struct $name;
+
+        impl super::Contract for $name {
+            const ID: super::ContractId = $id;
+            type Command = Command;
+            type Request = Request;
+            type Response = Response;
+        }
+    };
+}
+
+pub mod assets;
+pub mod balances;
+pub mod data_plaza;
+pub mod diem;
+pub mod system;
+pub mod web3analytics;
+
+pub type ContractId = u32;
+pub type Balance = u128;
+pub type BlockNumber = u32;
+
+pub const SYSTEM: ContractId = 0;
+pub const DATA_PLAZA: ContractId = 1;
+pub const BALANCES: ContractId = 2;
+pub const ASSETS: ContractId = 3;
+pub const WEB3_ANALYTICS: ContractId = 4;
+pub const DIEM: ContractId = 5;
+
+pub trait Contract {
+    const ID: ContractId;
+    /// The commands sent to the contract through the chain (`Phala::push_command`)
+    type Command: Serialize;
+    /// The queries sent to the contract directly
+    type Request: Serialize;
+    type Response: DeserializeOwned;
+}
+
+/// The position of a transaction in the chain
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TxRef {
+    pub blocknum: BlockNumber,
+    pub index: u64,
+}
+
+/// An account id, encoded in hex in the messages
+#[derive(Default, Debug, Ord, PartialOrd, Eq, PartialEq, Clone, Copy, Hash)]
+pub struct AccountId(pub [u8; 32]);
+
+impl From<[u8; 32]> for AccountId {
+    fn from(raw: [u8; 32]) -> Self {
+        AccountId(raw)
+    }
+}
+
+impl fmt::Display for AccountId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(&self.0))
+    }
+}
+
+impl FromStr for AccountId {
+    type Err = hex::FromHexError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut raw = [0u8; 32];
+        hex::decode_to_slice(s, &mut raw)?;
+        Ok(AccountId(raw))
+    }
+}
+
+impl Serialize for AccountId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for AccountId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Serde module to encode the balances in decimal strings, as pRuntime does
+pub mod serde_balance {
+    use super::Balance;
+    use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S>(value: &Balance, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        value.to_string().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Balance, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wire_format() {
+        let account = AccountId([0xab; 32]);
+        let req = balances::Request::FreeBalance { account };
+        assert_eq!(
+            serde_json::to_value(&req).unwrap(),
+            serde_json::json!({"FreeBalance": {"account": "ab".repeat(32)}})
+        );
+        let cmd = assets::Command::Transfer {
+            id: 1,
+            dest: account,
+            value: u128::max_value(),
+        };
+        assert_eq!(
+            serde_json::to_value(&cmd).unwrap(),
+            serde_json::json!({"Transfer": {
+                "id": 1,
+                "dest": "ab".repeat(32),
+                "value": u128::max_value().to_string(),
+            }})
+        );
+        let resp: balances::Response = serde_json::from_str(
+            r#"{"FreeBalance": {"balance": "340282366920938463463374607431768211455"}}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            resp,
+            balances::Response::FreeBalance { balance } if balance == u128::max_value()
+        ));
+        let resp: system::Response =
+            serde_json::from_str(r#"{"Error": "not authorized"}"#).unwrap();
+        assert!(matches!(resp, system::Response::Error(msg) if msg == "not authorized"));
+    }
+}
@@ -0,0 +1,121 @@
+//! The System contract: the worker identity, the transaction receipts and the egress queue
+
+use serde::{Deserialize, Serialize};
+
+use super::{AccountId, BlockNumber, SYSTEM};
+
+contract!(System, SYSTEM);
+
+/// System takes no commands. It reacts to the chain events instead.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Command {}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Request {
+    QueryReceipt {
+        command_index: u64,
+    },
+    GetWorkerEgress {
+        start_sequence: u64,
+    },
+    /// The receipts of the origin older than `before`, the newest first
+    QueryReceiptsByAccount {
+        before: Option<u64>,
+        limit: u32,
+    },
+    /// The receipts of the origin in the block
+    QueryReceiptsByBlock {
+        block_num: BlockNumber,
+    },
+    /// The receipts of the origin newer than `after`, the oldest first. Poll it with the returned
+    /// `cursor` to watch the commands complete.
+    WatchReceipts {
+        after: Option<u64>,
+        limit: u32,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Response {
+    QueryReceipt {
+        receipt: TransactionReceipt,
+    },
+    GetWorkerEgress {
+        length: usize,
+        /// The SCALE encoded `Vec<phala_types::SignedWorkerMessage>` in base64
+        encoded_egress_b64: String,
+    },
+    Receipts {
+        receipts: Vec<IndexedReceipt>,
+        /// The `before` of the next page, if any
+        next: Option<u64>,
+    },
+    ReceiptFeed {
+        receipts: Vec<IndexedReceipt>,
+        /// The `after` of the next poll
+        cursor: Option<u64>,
+        /// Some receipts after `after` were dropped by the retention
+        missed: bool,
+    },
+    Error(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum TransactionStatus {
+    Ok,
+    InsufficientBalance,
+    NoBalance,
+    UnknownError,
+    BadContractId,
+    BadCommand,
+    SymbolExist,
+    AssetIdNotFound,
+    NotAssetOwner,
+    BadSecret,
+    BadMachineId,
+    FailedToSign,
+    BadDecimal,
+    DestroyNotAllowed,
+    // for pdiem
+    BadAccountInfo,
+    BadLedgerInfo,
+    BadTrustedStateData,
+    BadEpochChangedProofData,
+    BadTrustedState,
+    InvalidAccount,
+    BadTransactionWithProof,
+    FailedToVerify,
+    FailedToGetTransaction,
+    FailedToCalculateBalance,
+    BadChainId,
+    TransferringNotAllowed,
+    InsufficientAllowance,
+    MemoTooLong,
+    AccountFrozen,
+    Overflow,
+    AssetNotBridged,
+    ItemNotFound,
+    PathNotAllowed,
+    SiteExist,
+    SiteNotFound,
+    NotSiteOwner,
+    NoContractKey,
+    NotEncrypted,
+    DecryptFailed,
+    DatasetTooLarge,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransactionReceipt {
+    pub account: AccountId,
+    pub block_num: BlockNumber,
+    pub contract_id: u32,
+    pub command: String,
+    pub status: TransactionStatus,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexedReceipt {
+    pub command_index: u64,
+    pub receipt: TransactionReceipt,
+}
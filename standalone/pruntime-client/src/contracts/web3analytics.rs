@@ -0,0 +1,199 @@
+//! The Web3Analytics contract: privacy preserving website analytics
+
+use serde::{Deserialize, Serialize};
+
+use super::{AccountId, WEB3_ANALYTICS};
+
+contract!(Web3Analytics, WEB3_ANALYTICS);
+
+pub type Sid = String;
+pub type Timestamp = u32;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Command {
+    SetConfiguration { skip_stat: bool },
+    RegisterSite { sid: Sid, encrypted: bool },
+    AddApiKey { sid: Sid, api_key: AccountId },
+    RemoveApiKey { sid: Sid, api_key: AccountId },
+    SetRetention { sid: Sid, retention: Timestamp },
+    IngestPageViews { sid: Sid, page_views: Vec<PageView> },
+    RotateKey { sid: Sid },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Request {
+    GetSite {
+        sid: Sid,
+    },
+    /// Must be encrypted to the worker
+    GetSiteKey {
+        sid: Sid,
+    },
+    GetOnlineUsers {
+        sid: Sid,
+        start: Timestamp,
+        end: Timestamp,
+    },
+    GetHourlyStats {
+        sid: Sid,
+        start: Timestamp,
+        end: Timestamp,
+        start_of_week: Timestamp,
+    },
+    GetDailyStats {
+        sid: Sid,
+        daily_stat: DailyStat,
+    },
+    GetWeeklySites {
+        sid: Sid,
+        weekly_sites_in_db: Vec<WeeklySite>,
+        weekly_sites_new: Vec<WeeklySite>,
+    },
+    GetWeeklyDevices {
+        sid: Sid,
+        weekly_devices_in_db: Vec<WeeklyDevice>,
+        weekly_devices_new: Vec<WeeklyDevice>,
+    },
+    GetTotalStat {
+        sid: Sid,
+        total_stat: HourlyPageViewStat,
+        count: Option<Field>,
+    },
+    GetConfiguration {
+        account: AccountId,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Response {
+    GetSite {
+        owner: AccountId,
+        api_keys: Vec<AccountId>,
+        retention: Timestamp,
+        page_view_count: u32,
+        encrypted: bool,
+        key_version: u32,
+    },
+    GetSiteKey {
+        key_version: u32,
+        key_b64: String,
+    },
+    GetOnlineUsers {
+        online_users: Vec<OnlineUser>,
+    },
+    GetHourlyStats {
+        hourly_stat: HourlyStat,
+    },
+    GetDailyStats {
+        daily_stat: DailyStat,
+    },
+    GetWeeklySites {
+        weekly_sites: Vec<WeeklySite>,
+    },
+    GetWeeklyDevices {
+        weekly_devices: Vec<WeeklyDevice>,
+    },
+    GetTotalStat {
+        total_stat: HourlyPageViewStat,
+    },
+    GetConfiguration {
+        skip_stat: bool,
+    },
+    Error(String),
+}
+
+/// A field of the page views and the statistics, sealed with the site key if the site is
+/// encrypted
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum Field {
+    Plain(String),
+    Sealed(Cipher),
+}
+
+impl Default for Field {
+    fn default() -> Self {
+        Field::Plain(String::new())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Cipher {
+    pub key_version: u32,
+    pub iv_b64: String,
+    pub data_b64: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PageView {
+    pub id: String,
+    pub sid: Sid,
+    pub cid: String,
+    pub uid: String,
+    pub host: String,
+    pub path: Field,
+    pub referrer: String,
+    pub ip: Field,
+    pub user_agent: Field,
+    pub created_at: Timestamp,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OnlineUser {
+    pub sid: Sid,
+    pub cid_count: Field,
+    pub ip_count: Field,
+    pub timestamp: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct HourlyPageViewStat {
+    pub sid: Sid,
+    pub pv_count: Field,
+    pub cid_count: Field,
+    pub avg_duration: Field,
+    pub timestamp: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WeeklySite {
+    pub sid: Sid,
+    pub path: Field,
+    pub count: Field,
+    pub timestamp: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WeeklyDevice {
+    pub sid: Sid,
+    pub device: Field,
+    pub count: Field,
+    pub timestamp: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WeeklyClient {
+    pub sid: Sid,
+    pub cids: Vec<String>,
+    pub timestamp: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SiteClient {
+    pub sid: Sid,
+    pub cids: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HourlyStat {
+    pub hourly_page_view_stats: Vec<HourlyPageViewStat>,
+    pub site_clients: Vec<SiteClient>,
+    pub weekly_clients: Vec<WeeklyClient>,
+    pub weekly_sites: Vec<WeeklySite>,
+    pub weekly_devices: Vec<WeeklyDevice>,
+    pub total_stats: Vec<HourlyPageViewStat>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DailyStat {
+    pub stats: Vec<HourlyPageViewStat>,
+}
@@ -0,0 +1,303 @@
+//! End-to-end encryption and signing of the messages to the contracts
+//!
+//! The wire formats match `cryptography` in pRuntime: the messages are encrypted by AES-256-GCM
+//! under the raw secret agreed by ECDH (P-256) with the worker `ecdh_public_key`, and the queries
+//! are signed by the origin account.
+
+use anyhow::Result;
+use codec::Encode;
+use phala_types::rpc::EncryptedSecret;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
+use ring::agreement::{self, EphemeralPrivateKey, UnparsedPublicKey, ECDH_P256};
+use ring::rand::{SecureRandom, SystemRandom};
+use sp_core::crypto::Pair;
+use sp_core::{ecdsa, ed25519, sr25519};
+
+use crate::contracts::web3analytics::{Cipher, Field};
+use crate::error::Error;
+use crate::types::{AeadCipher, Origin, SignatureType};
+
+const IV_BYTES: usize = 12;
+
+/// A secret agreed with a worker, to encrypt the messages to it and decrypt its replies
+pub struct EcdhSession {
+    secret: Vec<u8>,
+    pubkey: Vec<u8>,
+}
+
+impl EcdhSession {
+    /// Agrees a secret with the worker ECDH public key by a new ephemeral key
+    pub fn new(worker_ecdh_pubkey: &[u8]) -> Result<Self> {
+        let rng = SystemRandom::new();
+        let sk = EphemeralPrivateKey::generate(&ECDH_P256, &rng).map_err(|_| Error::CryptoError)?;
+        let pubkey = sk.compute_public_key().map_err(|_| Error::CryptoError)?;
+        let secret = agreement::agree_ephemeral(
+            sk,
+            &UnparsedPublicKey::new(&ECDH_P256, worker_ecdh_pubkey),
+            Error::InvalidPubkey,
+            |key_material| Ok(key_material.to_vec()),
+        )?;
+        Ok(EcdhSession {
+            secret,
+            pubkey: pubkey.as_ref().to_vec(),
+        })
+    }
+
+    /// The ephemeral public key sent along with the ciphers
+    pub fn pubkey(&self) -> &[u8] {
+        &self.pubkey
+    }
+
+    pub fn encrypt(&self, msg: &[u8]) -> Result<AeadCipher> {
+        let mut iv = [0u8; IV_BYTES];
+        SystemRandom::new()
+            .fill(&mut iv)
+            .map_err(|_| Error::CryptoError)?;
+        let mut data = msg.to_vec();
+        self.key()?
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(iv), Aad::empty(), &mut data)
+            .map_err(|_| Error::CryptoError)?;
+        Ok(AeadCipher {
+            iv_b64: base64::encode(&iv),
+            cipher_b64: base64::encode(&data),
+            pubkey_b64: base64::encode(&self.pubkey),
+        })
+    }
+
+    /// Encrypts a secret of the contract genesis (e.g. a private key in
+    /// `phala_types::rpc::ContractsGenesis`), so only the worker can read it
+    pub fn encrypt_secret(&self, secret: &[u8]) -> Result<EncryptedSecret> {
+        let mut iv = [0u8; IV_BYTES];
+        SystemRandom::new()
+            .fill(&mut iv)
+            .map_err(|_| Error::CryptoError)?;
+        let mut cipher = secret.to_vec();
+        self.key()?
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(iv), Aad::empty(), &mut cipher)
+            .map_err(|_| Error::CryptoError)?;
+        Ok(EncryptedSecret {
+            pubkey: self.pubkey.clone(),
+            iv: iv.to_vec(),
+            cipher,
+        })
+    }
+
+    pub fn decrypt(&self, cipher: &AeadCipher) -> Result<Vec<u8>> {
+        let iv = base64::decode(&cipher.iv_b64).map_err(|_| Error::FailedToDecode)?;
+        let mut data = base64::decode(&cipher.cipher_b64).map_err(|_| Error::FailedToDecode)?;
+        if iv.len() != IV_BYTES {
+            return Err(Error::FailedToDecode.into());
+        }
+        let mut iv_arr = [0u8; IV_BYTES];
+        iv_arr.copy_from_slice(&iv);
+        let msg = self
+            .key()?
+            .open_in_place(
+                Nonce::assume_unique_for_key(iv_arr),
+                Aad::empty(),
+                &mut data,
+            )
+            .map_err(|_| Error::CryptoError)?;
+        Ok(msg.to_vec())
+    }
+
+    fn key(&self) -> Result<LessSafeKey> {
+        let key = UnboundKey::new(&AES_256_GCM, &self.secret).map_err(|_| Error::CryptoError)?;
+        Ok(LessSafeKey::new(key))
+    }
+}
+
+/// The key of an encrypted Web3Analytics site, returned by the `GetSiteKey` query
+///
+/// It seals the fields of the page views before the ingestion, and opens the statistics sealed
+/// with the same key version. The sid and the key version are authenticated along with the values.
+pub struct SiteKey {
+    sid: String,
+    key_version: u32,
+    key: Vec<u8>,
+}
+
+impl SiteKey {
+    pub fn new(sid: &str, key_version: u32, key_b64: &str) -> Result<Self> {
+        let key = base64::decode(key_b64).map_err(|_| Error::FailedToDecode)?;
+        Ok(SiteKey {
+            sid: sid.to_string(),
+            key_version,
+            key,
+        })
+    }
+
+    pub fn seal(&self, value: &str) -> Result<Field> {
+        let mut iv = [0u8; IV_BYTES];
+        SystemRandom::new()
+            .fill(&mut iv)
+            .map_err(|_| Error::CryptoError)?;
+        let mut data = value.as_bytes().to_vec();
+        self.key()?
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(iv),
+                Aad::from(self.aad()),
+                &mut data,
+            )
+            .map_err(|_| Error::CryptoError)?;
+        Ok(Field::Sealed(Cipher {
+            key_version: self.key_version,
+            iv_b64: base64::encode(&iv),
+            data_b64: base64::encode(&data),
+        }))
+    }
+
+    pub fn open(&self, field: &Field) -> Result<String> {
+        let cipher = match field {
+            Field::Sealed(cipher) if cipher.key_version == self.key_version => cipher,
+            _ => return Err(Error::FailedToDecode.into()),
+        };
+        let iv = base64::decode(&cipher.iv_b64).map_err(|_| Error::FailedToDecode)?;
+        let mut data = base64::decode(&cipher.data_b64).map_err(|_| Error::FailedToDecode)?;
+        if iv.len() != IV_BYTES {
+            return Err(Error::FailedToDecode.into());
+        }
+        let mut iv_arr = [0u8; IV_BYTES];
+        iv_arr.copy_from_slice(&iv);
+        let value = self
+            .key()?
+            .open_in_place(
+                Nonce::assume_unique_for_key(iv_arr),
+                Aad::from(self.aad()),
+                &mut data,
+            )
+            .map_err(|_| Error::CryptoError)?;
+        String::from_utf8(value.to_vec()).map_err(|_| Error::FailedToDecode.into())
+    }
+
+    fn aad(&self) -> Vec<u8> {
+        let mut aad = self.sid.encode();
+        aad.extend(self.key_version.encode());
+        aad
+    }
+
+    fn key(&self) -> Result<LessSafeKey> {
+        let key = UnboundKey::new(&AES_256_GCM, &self.key).map_err(|_| Error::CryptoError)?;
+        Ok(LessSafeKey::new(key))
+    }
+}
+
+/// The key pair of the account signing the queries
+pub enum Signer {
+    Sr25519(sr25519::Pair),
+    Ed25519(ed25519::Pair),
+    Ecdsa(ecdsa::Pair),
+}
+
+impl Signer {
+    /// Signs `msg` and returns the `Origin` to send along with it
+    pub fn sign(&self, msg: &[u8]) -> Origin {
+        let (pubkey, signature, sig_type) = match self {
+            Signer::Sr25519(pair) => (
+                pair.public().as_ref().to_vec(),
+                pair.sign(msg).as_ref().to_vec(),
+                SignatureType::Sr25519,
+            ),
+            Signer::Ed25519(pair) => (
+                pair.public().as_ref().to_vec(),
+                pair.sign(msg).as_ref().to_vec(),
+                SignatureType::Ed25519,
+            ),
+            Signer::Ecdsa(pair) => (
+                pair.public().as_ref().to_vec(),
+                pair.sign(msg).as_ref().to_vec(),
+                SignatureType::Ecdsa,
+            ),
+        };
+        Origin {
+            origin: hex::encode(pubkey),
+            sig_b64: base64::encode(&signature),
+            sig_type,
+        }
+    }
+
+    /// The account id pRuntime sees as the origin
+    ///
+    /// It's the public key itself, except for ECDSA where it's the blake2_256 hash of the key.
+    pub fn account_id(&self) -> [u8; 32] {
+        match self {
+            Signer::Sr25519(pair) => pair.public().0,
+            Signer::Ed25519(pair) => pair.public().0,
+            Signer::Ecdsa(pair) => sp_core::hashing::blake2_256(pair.public().as_ref()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ecdh_session() {
+        let rng = SystemRandom::new();
+        let worker_sk = EphemeralPrivateKey::generate(&ECDH_P256, &rng).unwrap();
+        let worker_pk = worker_sk.compute_public_key().unwrap();
+        let session = EcdhSession::new(worker_pk.as_ref()).unwrap();
+
+        let cipher = session.encrypt(b"hello").unwrap();
+        assert_eq!(session.decrypt(&cipher).unwrap(), b"hello".to_vec());
+        // The worker agrees the same secret from the public key in the cipher
+        let client_pk = base64::decode(&cipher.pubkey_b64).unwrap();
+        let secret = agreement::agree_ephemeral(
+            worker_sk,
+            &UnparsedPublicKey::new(&ECDH_P256, client_pk),
+            (),
+            |key_material| Ok(key_material.to_vec()),
+        )
+        .unwrap();
+        assert_eq!(secret, session.secret);
+
+        let mut tampered = cipher.clone();
+        tampered.cipher_b64 = base64::encode(b"not the cipher at all");
+        assert!(session.decrypt(&tampered).is_err());
+        assert!(EcdhSession::new(b"bad key").is_err());
+    }
+
+    #[test]
+    fn test_site_key() {
+        let key_b64 = base64::encode(&[7u8; 32]);
+        let key = SiteKey::new("1", 0, &key_b64).unwrap();
+        let field = key.seal("/index.html").unwrap();
+        assert_eq!(key.open(&field).unwrap(), "/index.html");
+        // Only opens on the same site and key version
+        let other_site = SiteKey::new("2", 0, &key_b64).unwrap();
+        assert!(other_site.open(&field).is_err());
+        let mut rotated = field.clone();
+        if let Field::Sealed(cipher) = &mut rotated {
+            cipher.key_version = 1;
+        }
+        let new_key = SiteKey::new("1", 1, &key_b64).unwrap();
+        assert!(new_key.open(&rotated).is_err());
+        assert!(key.open(&Field::Plain("/index.html".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_sign_origin() {
+        let signers = vec![
+            Signer::Sr25519(sr25519::Pair::from_seed(&[1; 32])),
+            Signer::Ed25519(ed25519::Pair::from_seed(&[1; 32])),
+            Signer::Ecdsa(ecdsa::Pair::from_seed(&[1; 32])),
+        ];
+        for signer in signers {
+            let origin = signer.sign(b"payload");
+            let pubkey = hex::decode(&origin.origin).unwrap();
+            let sig = base64::decode(&origin.sig_b64).unwrap();
+            let valid = match origin.sig_type {
+                SignatureType::Sr25519 => sr25519::Pair::verify_weak(&sig, b"payload", &pubkey),
+                SignatureType::Ed25519 => ed25519::Pair::verify_weak(&sig, b"payload", &pubkey),
+                SignatureType::Ecdsa => ecdsa::Pair::verify_weak(&sig, b"payload", &pubkey),
+            };
+            assert!(valid);
+            let account = signer.account_id();
+            match signer {
+                Signer::Ecdsa(_) => assert_eq!(account, sp_core::hashing::blake2_256(&pubkey)),
+                _ => assert_eq!(account.to_vec(), pubkey),
+            }
+        }
+    }
+}
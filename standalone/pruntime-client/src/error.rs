@@ -0,0 +1,49 @@
+use std::{error, fmt};
+
+use phala_types::rpc::RpcError;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The worker public key is not a compressed secp256k1 public key
+    InvalidPubkey,
+    /// The signature in the response can't be parsed
+    MalformedSignature,
+    /// The signature doesn't match the response and the request
+    BadSignature,
+    /// The response status is neither "ok" nor "error"
+    UnknownStatus(String),
+    /// pRuntime rejected the request with the error payload
+    ErrorResponse(String),
+    /// pRuntime rejected the binary request with the error
+    Rpc(RpcError),
+    /// The HTTP request failed with the status code
+    HttpStatus(u16),
+    /// The encryption or decryption failed
+    CryptoError,
+    /// The worker ECDH public key is unknown, so the message can't be encrypted
+    EcdhKeyUnknown,
+    /// Neither the worker public key nor its ECDH public key is set, so the ECDH public key can't
+    /// be trusted
+    WorkerKeyNotPinned,
+    FailedToDecode,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidPubkey => write!(f, "invalid worker public key"),
+            Error::MalformedSignature => write!(f, "malformed response signature"),
+            Error::BadSignature => write!(f, "bad response signature"),
+            Error::UnknownStatus(status) => write!(f, "unknown response status: {}", status),
+            Error::ErrorResponse(payload) => write!(f, "pRuntime error: {}", payload),
+            Error::Rpc(err) => write!(f, "pRuntime error: {}", err),
+            Error::HttpStatus(status) => write!(f, "HTTP status {}", status),
+            Error::CryptoError => write!(f, "encryption or decryption failed"),
+            Error::EcdhKeyUnknown => write!(f, "worker ECDH public key unknown"),
+            Error::WorkerKeyNotPinned => write!(f, "worker public key not pinned"),
+            Error::FailedToDecode => write!(f, "failed to decode"),
+        }
+    }
+}
+
+impl error::Error for Error {}
@@ -0,0 +1,265 @@
+//! A client of the pRuntime HTTP API
+//!
+//! Every pRuntime response is signed by the worker identity key over the request hash, the
+//! payload and the chain heights (see `phala_types::pruntime::SignedResponseMessage`). Once the
+//! worker public key is set, the client rejects any response whose signature doesn't match, so
+//! the host relaying the requests can't forge or replay the results.
+//!
+//! The contracts are queried with typed builders, optionally signed by the origin account and
+//! encrypted end to end:
+//!
+//! ```ignore
+//! let balance = client
+//!     .contract::<Balances>()
+//!     .query(balances::Request::FreeBalance { account })
+//!     .signed_by(&signer)
+//!     .encrypted()
+//!     .send()
+//!     .await?;
+//! ```
+//!
+//! The calls to pRuntime itself can be made in the binary API (`phala_types::rpc`) by `rpc`:
+//!
+//! ```ignore
+//! if let Reply::GetInfo(info) = client.rpc(Call::GetInfo).await? {
+//!     println!("synced to {}", info.headernum);
+//! }
+//! ```
+//!
+//! The headers and blocks can be streamed to pRuntime by `ingest`, which yields an ack per frame
+//! as soon as pRuntime has processed it.
+
+use std::sync::RwLock;
+
+use anyhow::Result;
+use bytes::buf::BufExt as _;
+use codec::{Decode, Encode};
+use futures::{Stream, StreamExt};
+use hyper::body::HttpBody as _;
+use hyper::Client as HttpClient;
+use hyper::{Body, Method, Request};
+use log::{debug, info};
+use phala_types::rpc::{
+    encode_ingest_frame, Call, IngestAck, IngestFrame, Reply, RpcError, RpcRequest, RpcResponse,
+    INGEST_ACK_LEN,
+};
+use secp256k1::PublicKey;
+use serde::Serialize;
+use sp_core::hashing::blake2_256;
+
+mod builder;
+pub mod contracts;
+pub mod crypto;
+mod error;
+mod types;
+pub mod verify;
+
+pub use crate::builder::{CommandBuilder, ContractClient, QueryBuilder};
+pub use crate::crypto::{EcdhSession, Signer, SiteKey};
+pub use crate::error::Error;
+pub use crate::types::*;
+
+use crate::contracts::Contract;
+
+pub struct PRuntimeClient {
+    base_url: String,
+    worker_pubkey: RwLock<Option<PublicKey>>,
+    worker_ecdh_pubkey: RwLock<Option<Vec<u8>>>,
+}
+
+impl PRuntimeClient {
+    pub fn new(base_url: &str) -> Self {
+        PRuntimeClient {
+            base_url: base_url.to_string(),
+            worker_pubkey: RwLock::new(None),
+            worker_ecdh_pubkey: RwLock::new(None),
+        }
+    }
+
+    /// The queries and commands of the contract `C`
+    pub fn contract<C: Contract>(&self) -> ContractClient<'_, C> {
+        ContractClient::new(self)
+    }
+
+    /// Sets the worker identity public key (compressed secp256k1) to verify the responses with
+    pub fn set_worker_pubkey(&self, pubkey: &[u8]) -> Result<()> {
+        let pubkey = verify::parse_pubkey(pubkey)?;
+        *self.worker_pubkey.write().unwrap() = Some(pubkey);
+        Ok(())
+    }
+
+    /// Sets the worker ECDH public key (P-256) to encrypt the messages to
+    pub fn set_worker_ecdh_pubkey(&self, pubkey: &[u8]) {
+        *self.worker_ecdh_pubkey.write().unwrap() = Some(pubkey.to_vec());
+    }
+
+    /// The worker ECDH public key, fetched by `get_info` if not set yet
+    ///
+    /// The key is only fetched once the worker public key is pinned, so `get_info` is verified and
+    /// the host can't substitute a key of its own.
+    pub async fn worker_ecdh_pubkey(&self) -> Result<Vec<u8>> {
+        let cached = self.worker_ecdh_pubkey.read().unwrap().clone();
+        if let Some(pubkey) = cached {
+            return Ok(pubkey);
+        }
+        if self.worker_pubkey.read().unwrap().is_none() {
+            return Err(Error::WorkerKeyNotPinned.into());
+        }
+        let info = self.req_decode("get_info", GetInfoReq {}).await?;
+        if info.ecdh_public_key.is_empty() {
+            return Err(Error::EcdhKeyUnknown.into());
+        }
+        let pubkey = hex::decode(&info.ecdh_public_key).map_err(|_| Error::InvalidPubkey)?;
+        self.set_worker_ecdh_pubkey(&pubkey);
+        Ok(pubkey)
+    }
+
+    /// Sends a request and returns the verified response
+    ///
+    /// Responses are not verified until the worker public key is set.
+    pub async fn req<T>(&self, command: &str, param: &T) -> Result<SignedResp>
+    where
+        T: Serialize,
+    {
+        let client = HttpClient::new();
+        let endpoint = format!("{}/{}", self.base_url, command);
+
+        // Send the canonical encoding, which is also what pRuntime hashes
+        let request = serde_json::to_value(param)?;
+        let body_json = serde_json::to_vec(&request)?;
+        let request_hash = verify::request_hash(&request);
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(endpoint)
+            .header("content-type", "application/json")
+            .body(Body::from(body_json))?;
+
+        let res = client.request(req).await?;
+
+        info!("Response: {}", res.status());
+
+        let body = hyper::body::aggregate(res.into_body()).await?;
+        let signed_resp: SignedResp = serde_json::from_reader(body.reader())?;
+
+        match &*self.worker_pubkey.read().unwrap() {
+            Some(pubkey) => verify::verify_response(pubkey, request_hash, &signed_resp)?,
+            None => debug!(
+                "Worker public key unknown. Response to {} not verified",
+                command
+            ),
+        }
+
+        Ok(signed_resp)
+    }
+
+    /// Makes a call in the binary API and returns the verified reply
+    ///
+    /// Like `req`, the reply is not verified until the worker public key is set.
+    pub async fn rpc(&self, call: Call) -> Result<Reply> {
+        let client = HttpClient::new();
+        let endpoint = format!("{}/rpc", self.base_url);
+
+        let action = call.action();
+        let request = RpcRequest {
+            call,
+            nonce: rand::random(),
+        }
+        .encode();
+        let request_hash = blake2_256(&request);
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(endpoint)
+            .header("content-type", "application/octet-stream")
+            .body(Body::from(request))?;
+
+        let res = client.request(req).await?;
+
+        info!("Response: {}", res.status());
+        if !res.status().is_success() {
+            return Err(Error::HttpStatus(res.status().as_u16()).into());
+        }
+
+        let body = hyper::body::to_bytes(res.into_body()).await?;
+        let resp = RpcResponse::decode(&mut &body[..]).map_err(|_| Error::FailedToDecode)?;
+
+        match &*self.worker_pubkey.read().unwrap() {
+            Some(pubkey) => verify::verify_rpc_response(pubkey, request_hash, &resp)?,
+            None => debug!(
+                "Worker public key unknown. Response to action {} not verified",
+                action
+            ),
+        }
+
+        let payload = &mut &resp.message.payload[..];
+        if resp.message.ok {
+            Ok(Reply::decode(payload).map_err(|_| Error::FailedToDecode)?)
+        } else {
+            let err = RpcError::decode(payload).map_err(|_| Error::FailedToDecode)?;
+            Err(Error::Rpc(err).into())
+        }
+    }
+
+    /// Streams the frames to `/ingest` and returns the stream of their acks
+    ///
+    /// pRuntime reads the next frame only after acking the previous one, so `frames` is polled no
+    /// faster than pRuntime processes them. The acks are not signed; call `GetInfo` to get the
+    /// verified heights. The ack stream ends after the first failed frame.
+    pub async fn ingest<S>(&self, frames: S) -> Result<impl Stream<Item = Result<IngestAck>>>
+    where
+        S: Stream<Item = IngestFrame> + Send + 'static,
+    {
+        let client = HttpClient::new();
+        let endpoint = format!("{}/ingest", self.base_url);
+
+        let body = Body::wrap_stream(
+            frames.map(|frame| Ok::<_, std::io::Error>(encode_ingest_frame(&frame))),
+        );
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(endpoint)
+            .header("content-type", "application/octet-stream")
+            .body(body)?;
+
+        let res = client.request(req).await?;
+
+        info!("Response: {}", res.status());
+        if !res.status().is_success() {
+            return Err(Error::HttpStatus(res.status().as_u16()).into());
+        }
+
+        let acks = futures::stream::try_unfold(
+            (res.into_body(), Vec::new()),
+            |(mut body, mut buf)| async move {
+                loop {
+                    if buf.len() >= INGEST_ACK_LEN {
+                        let rest = buf.split_off(INGEST_ACK_LEN);
+                        let ack = IngestAck::decode(&mut &buf[..])
+                            .map_err(|_| Error::FailedToDecode)?;
+                        return Ok(Some((ack, (body, rest))));
+                    }
+                    match body.data().await {
+                        Some(chunk) => buf.extend_from_slice(&chunk?),
+                        None if buf.is_empty() => return Ok(None),
+                        None => return Err(Error::FailedToDecode.into()),
+                    }
+                }
+            },
+        );
+        Ok(acks)
+    }
+
+    pub async fn req_decode<Req>(&self, command: &str, request: Req) -> Result<Req::Resp>
+    where
+        Req: Serialize + Resp,
+    {
+        let payload = RuntimeReq::new(request);
+        let resp = self.req(command, &payload).await?;
+        if resp.status != "ok" {
+            return Err(Error::ErrorResponse(resp.payload).into());
+        }
+        let result: Req::Resp = serde_json::from_str(&resp.payload)?;
+        Ok(result)
+    }
+}
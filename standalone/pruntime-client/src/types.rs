@@ -0,0 +1,126 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Binds a request type to the type of its response payload
+pub trait Resp {
+    type Resp: DeserializeOwned;
+}
+
+/// A response of pRuntime as sent over the wire
+///
+/// The signature covers a `phala_types::pruntime::SignedResponseMessage` built from the other
+/// fields and the hash of the request.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignedResp {
+    pub payload: String,
+    pub status: String,
+    pub signature: String,
+    pub headernum: u32,
+    pub blocknum: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Nonce {
+    value: u32,
+}
+
+impl Nonce {
+    pub fn new() -> Nonce {
+        Nonce {
+            value: rand::random::<u32>(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RuntimeReq<T: Serialize> {
+    pub input: T,
+    pub nonce: Nonce,
+}
+
+impl<T: Serialize> RuntimeReq<T> {
+    pub fn new(input: T) -> Self {
+        Self {
+            input,
+            nonce: Nonce::new(),
+        }
+    }
+}
+
+// API: get_info
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetInfoReq {}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetInfoResp {
+    pub headernum: u32,
+    pub blocknum: u32,
+    pub initialized: bool,
+    pub public_key: String,
+    pub ecdh_public_key: String,
+    pub machine_id: Vec<u8>,
+    pub system_egress: EgressInfo,
+    pub min_spec_version: u32,
+    pub max_spec_version: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EgressInfo {
+    pub sequence: u64,
+    pub len: u64,
+}
+
+impl Resp for GetInfoReq {
+    type Resp = GetInfoResp;
+}
+
+// API: query
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AeadCipher {
+    pub iv_b64: String,
+    pub cipher_b64: String,
+    pub pubkey_b64: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Payload {
+    Plain(String),
+    Cipher(AeadCipher),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum SignatureType {
+    #[serde(rename = "ed25519")]
+    Ed25519,
+    #[serde(rename = "sr25519")]
+    Sr25519,
+    #[serde(rename = "ecdsa")]
+    Ecdsa,
+}
+
+/// The account signing a query: the hex public key and the base64 signature of `query_payload`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Origin {
+    pub origin: String,
+    pub sig_b64: String,
+    pub sig_type: SignatureType,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Query<T> {
+    pub contract_id: u32,
+    pub nonce: u32,
+    pub request: T,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SignedQuery {
+    /// The JSON encoded `Payload` wrapping the `Query`
+    pub query_payload: String,
+    pub origin: Option<Origin>,
+}
+
+impl Resp for SignedQuery {
+    type Resp = Payload;
+}
@@ -0,0 +1,183 @@
+//! Verification of the signed pRuntime responses
+
+use codec::Encode;
+use phala_types::pruntime::SignedResponseMessage;
+use phala_types::rpc::RpcResponse;
+use secp256k1::{Message, PublicKey, Signature};
+use serde_json::Value;
+use sp_core::hashing::{blake2_256, sha2_256};
+
+use crate::error::Error;
+use crate::types::SignedResp;
+
+/// Parses the worker identity public key, as reported in `public_key` by pRuntime
+pub fn parse_pubkey(pubkey: &[u8]) -> Result<PublicKey, Error> {
+    PublicKey::parse_slice(pubkey, None).map_err(|_| Error::InvalidPubkey)
+}
+
+/// Hashes a request the same way pRuntime does when signing the response
+///
+/// `request` is the whole `{input, nonce}` object. `Value` keeps the object keys sorted, so the
+/// hash matches the one in pRuntime no matter how the host re-encoded the request.
+pub fn request_hash(request: &Value) -> [u8; 32] {
+    blake2_256(&serde_json::to_vec(request).expect("Value is always serializable; qed."))
+}
+
+/// Checks that `resp` is signed by `pubkey` as the response to the request hashed to
+/// `request_hash`
+pub fn verify_response(
+    pubkey: &PublicKey,
+    request_hash: [u8; 32],
+    resp: &SignedResp,
+) -> Result<(), Error> {
+    let ok = match resp.status.as_str() {
+        "ok" => true,
+        "error" => false,
+        _ => return Err(Error::UnknownStatus(resp.status.clone())),
+    };
+    let signed = SignedResponseMessage {
+        request_hash,
+        ok,
+        payload: resp.payload.as_bytes().to_vec(),
+        headernum: resp.headernum,
+        blocknum: resp.blocknum,
+    };
+    let signature = hex::decode(&resp.signature).map_err(|_| Error::MalformedSignature)?;
+    verify_message(pubkey, &signed, &signature)
+}
+
+/// Checks that the binary `resp` is signed by `pubkey` as the response to the encoded
+/// `RpcRequest` hashed to `request_hash`
+pub fn verify_rpc_response(
+    pubkey: &PublicKey,
+    request_hash: [u8; 32],
+    resp: &RpcResponse,
+) -> Result<(), Error> {
+    if resp.message.request_hash != request_hash {
+        return Err(Error::BadSignature);
+    }
+    verify_message(pubkey, &resp.message, &resp.signature)
+}
+
+fn verify_message(
+    pubkey: &PublicKey,
+    signed: &SignedResponseMessage,
+    signature: &[u8],
+) -> Result<(), Error> {
+    let signature = Signature::parse_slice(signature).map_err(|_| Error::MalformedSignature)?;
+    let message = Message::parse(&sha2_256(&signed.encode()));
+    if secp256k1::verify(&message, &signature, pubkey) {
+        Ok(())
+    } else {
+        Err(Error::BadSignature)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use secp256k1::SecretKey;
+    use serde_json::json;
+
+    fn worker_key() -> (SecretKey, PublicKey) {
+        let sk = SecretKey::parse(&[1u8; 32]).unwrap();
+        let pk = PublicKey::from_secret_key(&sk);
+        (sk, pk)
+    }
+
+    fn sign(sk: &SecretKey, request_hash: [u8; 32], payload: &str, blocknum: u32) -> SignedResp {
+        let signed = SignedResponseMessage {
+            request_hash,
+            ok: true,
+            payload: payload.as_bytes().to_vec(),
+            headernum: 10,
+            blocknum,
+        };
+        let message = Message::parse(&sha2_256(&signed.encode()));
+        let (signature, _) = secp256k1::sign(&message, sk);
+        SignedResp {
+            payload: payload.to_string(),
+            status: "ok".to_string(),
+            signature: hex::encode(&signature.serialize()[..]),
+            headernum: 10,
+            blocknum,
+        }
+    }
+
+    #[test]
+    fn test_verify_response() {
+        let (sk, pk) = worker_key();
+        let request = json!({"input": {"query_payload": "{}"}, "nonce": {"value": 1}});
+        let hash = request_hash(&request);
+        let resp = sign(&sk, hash, r#"{"Plain":"{}"}"#, 5);
+        assert!(verify_response(&pk, hash, &resp).is_ok());
+        assert_eq!(parse_pubkey(&pk.serialize_compressed()).unwrap(), pk);
+
+        // Forged payload
+        let mut forged = resp.clone();
+        forged.payload = r#"{"Plain":"{\"forged\":true}"}"#.to_string();
+        assert!(matches!(
+            verify_response(&pk, hash, &forged),
+            Err(Error::BadSignature)
+        ));
+        // Replayed to another request
+        let other = request_hash(&json!({"input": {"query_payload": "{}"}, "nonce": {"value": 2}}));
+        assert!(matches!(
+            verify_response(&pk, other, &resp),
+            Err(Error::BadSignature)
+        ));
+        // Stale heights
+        let mut stale = resp.clone();
+        stale.blocknum = 4;
+        assert!(matches!(
+            verify_response(&pk, hash, &stale),
+            Err(Error::BadSignature)
+        ));
+        // Signed by another key
+        let other_sk = SecretKey::parse(&[2u8; 32]).unwrap();
+        let resp = sign(&other_sk, hash, r#"{"Plain":"{}"}"#, 5);
+        assert!(matches!(
+            verify_response(&pk, hash, &resp),
+            Err(Error::BadSignature)
+        ));
+    }
+
+    #[test]
+    fn test_verify_rpc_response() {
+        let (sk, pk) = worker_key();
+        let hash = [7u8; 32];
+        let message = SignedResponseMessage {
+            request_hash: hash,
+            ok: true,
+            payload: vec![2, 0],
+            headernum: 10,
+            blocknum: 5,
+        };
+        let (signature, _) = secp256k1::sign(&Message::parse(&sha2_256(&message.encode())), &sk);
+        let resp = RpcResponse {
+            message,
+            signature: signature.serialize().to_vec(),
+        };
+        assert!(verify_rpc_response(&pk, hash, &resp).is_ok());
+
+        // Replayed to another request
+        assert!(matches!(
+            verify_rpc_response(&pk, [8u8; 32], &resp),
+            Err(Error::BadSignature)
+        ));
+        // Forged payload
+        let mut forged = resp.clone();
+        forged.message.payload = vec![2, 1];
+        assert!(matches!(
+            verify_rpc_response(&pk, hash, &forged),
+            Err(Error::BadSignature)
+        ));
+        // Truncated signature
+        let mut truncated = resp;
+        truncated.signature.pop();
+        assert!(matches!(
+            verify_rpc_response(&pk, hash, &truncated),
+            Err(Error::MalformedSignature)
+        ));
+    }
+}
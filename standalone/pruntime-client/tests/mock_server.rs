@@ -0,0 +1,537 @@
+//! Tests the client against a mock pRuntime
+//!
+//! The mock speaks the wire protocol of pRuntime: it signs the responses with its identity key,
+//! verifies the origins and decrypts the queries. The contracts answer with canned responses.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+
+use codec::{Decode, Encode};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
+use ring::agreement::{self, EphemeralPrivateKey, UnparsedPublicKey, ECDH_P256};
+use ring::rand::SystemRandom;
+use serde_json::{json, Value};
+use sp_core::crypto::Pair;
+use sp_core::hashing::{blake2_256, sha2_256};
+use sp_core::{ecdsa, ed25519, sr25519};
+
+use phala_types::pruntime::SignedResponseMessage;
+use phala_types::rpc::{
+    Call, EgressInfo, ErrorCode, Info, IngestAck, IngestFrame, Reply, RpcError, RpcRequest,
+    RpcResponse,
+};
+use pruntime_client::contracts::{assets, balances, system, AccountId, Assets, Balances, System};
+use pruntime_client::{AeadCipher, Error, PRuntimeClient, Payload, SignatureType, Signer};
+
+/// A query as seen by the contract
+#[derive(Debug)]
+struct SeenQuery {
+    origin: Option<[u8; 32]>,
+    contract_id: u32,
+    request: Value,
+    encrypted: bool,
+}
+
+struct Mock {
+    sk: secp256k1::SecretKey,
+    /// ring only allows an ECDH key to be used once, so a mock can decrypt only one message
+    ecdh_sk: Mutex<Option<EphemeralPrivateKey>>,
+    ecdh_pk: Vec<u8>,
+    /// The canned responses by contract id
+    responses: HashMap<u32, Value>,
+    /// Modifies the payloads after signing them
+    tamper: bool,
+    seen: Mutex<Vec<SeenQuery>>,
+}
+
+impl Mock {
+    fn new(responses: HashMap<u32, Value>) -> Self {
+        let ecdh_sk = EphemeralPrivateKey::generate(&ECDH_P256, &SystemRandom::new()).unwrap();
+        let ecdh_pk = ecdh_sk.compute_public_key().unwrap().as_ref().to_vec();
+        Mock {
+            sk: secp256k1::SecretKey::parse(&[7u8; 32]).unwrap(),
+            ecdh_sk: Mutex::new(Some(ecdh_sk)),
+            ecdh_pk,
+            responses,
+            tamper: false,
+            seen: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn pubkey(&self) -> Vec<u8> {
+        secp256k1::PublicKey::from_secret_key(&self.sk)
+            .serialize_compressed()
+            .to_vec()
+    }
+
+    fn agree(&self, cipher: &AeadCipher) -> Vec<u8> {
+        let sk = self.ecdh_sk.lock().unwrap().take().expect("ECDH key used");
+        let pk = base64::decode(&cipher.pubkey_b64).unwrap();
+        agreement::agree_ephemeral(sk, &UnparsedPublicKey::new(&ECDH_P256, pk), (), |km| {
+            Ok(km.to_vec())
+        })
+        .unwrap()
+    }
+
+    /// Decrypts a cipher to the mock, returning the message and the agreed secret
+    fn decrypt(&self, cipher: &AeadCipher) -> (Vec<u8>, Vec<u8>) {
+        let secret = self.agree(cipher);
+        let mut iv = [0u8; 12];
+        iv.copy_from_slice(&base64::decode(&cipher.iv_b64).unwrap());
+        let mut data = base64::decode(&cipher.cipher_b64).unwrap();
+        let msg = key(&secret)
+            .open_in_place(Nonce::assume_unique_for_key(iv), Aad::empty(), &mut data)
+            .unwrap()
+            .to_vec();
+        (msg, secret)
+    }
+
+    fn get_info(&self) -> Result<Value, Value> {
+        Ok(json!({
+            "headernum": 1,
+            "blocknum": 1,
+            "initialized": true,
+            "public_key": hex::encode(self.pubkey()),
+            "ecdh_public_key": hex::encode(&self.ecdh_pk),
+            "machine_id": [0u8; 16],
+            "system_egress": {"sequence": 0, "len": 0},
+            "min_spec_version": 33,
+            "max_spec_version": 33,
+        }))
+    }
+
+    fn query(&self, input: &Value) -> Result<Value, Value> {
+        let query_payload = input["query_payload"].as_str().unwrap();
+        let origin = match input.get("origin") {
+            Some(Value::Null) | None => None,
+            Some(origin) => {
+                let pubkey = hex::decode(origin["origin"].as_str().unwrap()).unwrap();
+                let sig = base64::decode(origin["sig_b64"].as_str().unwrap()).unwrap();
+                let sig_type: SignatureType = serde_json::from_value(origin["sig_type"].clone())
+                    .map_err(|_| json!("Bad origin"))?;
+                let msg = query_payload.as_bytes();
+                let (valid, account) = match sig_type {
+                    SignatureType::Sr25519 => (
+                        sr25519::Pair::verify_weak(&sig, msg, &pubkey),
+                        raw_account(&pubkey),
+                    ),
+                    SignatureType::Ed25519 => (
+                        ed25519::Pair::verify_weak(&sig, msg, &pubkey),
+                        raw_account(&pubkey),
+                    ),
+                    SignatureType::Ecdsa => (
+                        ecdsa::Pair::verify_weak(&sig, msg, &pubkey),
+                        blake2_256(&pubkey),
+                    ),
+                };
+                if !valid {
+                    return Err(json!("Verifying signature failed"));
+                }
+                Some(account)
+            }
+        };
+        let payload: Payload = serde_json::from_str(query_payload).unwrap();
+        let (msg, secret) = match &payload {
+            Payload::Plain(data) => (data.as_bytes().to_vec(), None),
+            Payload::Cipher(cipher) => {
+                let (msg, secret) = self.decrypt(cipher);
+                (msg, Some(secret))
+            }
+        };
+        let query: Value = serde_json::from_slice(&msg).unwrap();
+        let contract_id = query["contract_id"].as_u64().unwrap() as u32;
+        self.seen.lock().unwrap().push(SeenQuery {
+            origin,
+            contract_id,
+            request: query["request"].clone(),
+            encrypted: secret.is_some(),
+        });
+
+        let res_json = self.responses[&contract_id].to_string();
+        let res_payload = match secret {
+            Some(secret) => {
+                let iv = [1u8; 12];
+                let mut data = res_json.into_bytes();
+                key(&secret)
+                    .seal_in_place_append_tag(
+                        Nonce::assume_unique_for_key(iv),
+                        Aad::empty(),
+                        &mut data,
+                    )
+                    .unwrap();
+                Payload::Cipher(AeadCipher {
+                    iv_b64: base64::encode(&iv),
+                    cipher_b64: base64::encode(&data),
+                    pubkey_b64: base64::encode(&self.ecdh_pk),
+                })
+            }
+            None => Payload::Plain(res_json),
+        };
+        Ok(serde_json::to_value(res_payload).unwrap())
+    }
+
+    /// Handles a binary request and signs the response, as pRuntime does
+    fn handle_rpc(&self, body: &[u8]) -> Vec<u8> {
+        let request = RpcRequest::decode(&mut &body[..]).unwrap();
+        let (ok, payload) = match request.call {
+            Call::GetInfo => (
+                true,
+                Reply::GetInfo(Info {
+                    initialized: true,
+                    public_key: self.pubkey(),
+                    ecdh_public_key: self.ecdh_pk.clone(),
+                    headernum: 1,
+                    blocknum: 1,
+                    machine_id: [0u8; 16],
+                    dev_mode: false,
+                    state_root: [0u8; 32],
+                    system_egress: EgressInfo {
+                        sequence: 0,
+                        len: 0,
+                    },
+                    min_spec_version: 33,
+                    max_spec_version: 33,
+                })
+                .encode(),
+            ),
+            _ => (
+                false,
+                RpcError::new(ErrorCode::UnknownCall, "Unknown action").encode(),
+            ),
+        };
+        let mut message = SignedResponseMessage {
+            request_hash: blake2_256(body),
+            ok,
+            payload,
+            headernum: 1,
+            blocknum: 1,
+        };
+        let digest = secp256k1::Message::parse(&sha2_256(&message.encode()));
+        let (signature, _) = secp256k1::sign(&digest, &self.sk);
+        if self.tamper {
+            message.headernum = 2;
+        }
+        RpcResponse {
+            message,
+            signature: signature.serialize().to_vec(),
+        }
+        .encode()
+    }
+
+    /// Acks the frames of an ingestion stream. A block `[0xff]` fails.
+    fn handle_ingest(&self, body: &[u8]) -> Vec<u8> {
+        let mut body = body;
+        let mut acks = Vec::new();
+        let mut seq = 0;
+        while !body.is_empty() {
+            let len = u32::from_le_bytes([body[0], body[1], body[2], body[3]]) as usize;
+            let frame = IngestFrame::decode(&mut &body[4..4 + len]).unwrap();
+            body = &body[4 + len..];
+            let code = match frame {
+                IngestFrame::Block(block) if block == [0xff] => ErrorCode::InvalidBlock as u8,
+                _ => 0,
+            };
+            acks.extend(
+                IngestAck {
+                    seq,
+                    headernum: 10,
+                    blocknum: if code == 0 { seq + 1 } else { seq },
+                    code,
+                }
+                .encode(),
+            );
+            if code != 0 {
+                break;
+            }
+            seq += 1;
+        }
+        acks
+    }
+
+    /// Handles a request and signs the response, as pRuntime does
+    fn handle(&self, path: &str, body: &[u8]) -> Value {
+        let request: Value = serde_json::from_slice(body).unwrap();
+        let result = match path {
+            "/get_info" => self.get_info(),
+            "/query" => self.query(&request["input"]),
+            _ => Err(json!("Unknown action")),
+        };
+        let (ok, payload) = match result {
+            Ok(payload) => (true, payload),
+            Err(payload) => (false, payload),
+        };
+        let s_payload = payload.to_string();
+        let signed = SignedResponseMessage {
+            request_hash: blake2_256(&serde_json::to_vec(&request).unwrap()),
+            ok,
+            payload: s_payload.as_bytes().to_vec(),
+            headernum: 1,
+            blocknum: 1,
+        };
+        let message = secp256k1::Message::parse(&sha2_256(&signed.encode()));
+        let (signature, _) = secp256k1::sign(&message, &self.sk);
+        let s_payload = if self.tamper {
+            s_payload.replace("100", "999")
+        } else {
+            s_payload
+        };
+        json!({
+            "status": if ok { "ok" } else { "error" },
+            "payload": s_payload,
+            "signature": hex::encode(&signature.serialize()[..]),
+            "headernum": 1,
+            "blocknum": 1,
+        })
+    }
+}
+
+fn raw_account(pubkey: &[u8]) -> [u8; 32] {
+    let mut raw = [0u8; 32];
+    raw.copy_from_slice(pubkey);
+    raw
+}
+
+fn key(secret: &[u8]) -> LessSafeKey {
+    LessSafeKey::new(UnboundKey::new(&AES_256_GCM, secret).unwrap())
+}
+
+/// Serves the mock on a random port and returns a client pinned to its identity key
+async fn serve(mock: Arc<Mock>) -> PRuntimeClient {
+    let service_mock = mock.clone();
+    let make_svc = make_service_fn(move |_| {
+        let mock = service_mock.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let mock = mock.clone();
+                async move {
+                    let path = req.uri().path().to_string();
+                    let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                    let resp = match path.as_str() {
+                        "/rpc" => mock.handle_rpc(&body),
+                        "/ingest" => mock.handle_ingest(&body),
+                        _ => mock.handle(&path, &body).to_string().into_bytes(),
+                    };
+                    Ok::<_, Infallible>(Response::new(Body::from(resp)))
+                }
+            }))
+        }
+    });
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+    let client = PRuntimeClient::new(&format!("http://{}", server.local_addr()));
+    tokio::spawn(server);
+    client.set_worker_pubkey(&mock.pubkey()).unwrap();
+    client
+}
+
+fn canned_responses() -> HashMap<u32, Value> {
+    let mut responses = HashMap::new();
+    responses.insert(
+        0,
+        json!({"GetWorkerEgress": {"length": 0, "encoded_egress_b64": ""}}),
+    );
+    responses.insert(2, json!({"FreeBalance": {"balance": "100"}}));
+    responses
+}
+
+#[tokio::test]
+async fn test_plain_query() {
+    let mock = Arc::new(Mock::new(canned_responses()));
+    let client = serve(mock.clone()).await;
+
+    let resp = client
+        .contract::<System>()
+        .query(system::Request::GetWorkerEgress { start_sequence: 3 })
+        .send()
+        .await
+        .unwrap();
+    assert!(matches!(
+        resp,
+        system::Response::GetWorkerEgress { length: 0, .. }
+    ));
+
+    let seen = mock.seen.lock().unwrap();
+    assert_eq!(seen.len(), 1);
+    assert_eq!(seen[0].contract_id, 0);
+    assert_eq!(seen[0].origin, None);
+    assert!(!seen[0].encrypted);
+    assert_eq!(
+        seen[0].request,
+        json!({"GetWorkerEgress": {"start_sequence": 3}})
+    );
+}
+
+#[tokio::test]
+async fn test_signed_encrypted_query() {
+    let signers = vec![
+        Signer::Sr25519(sr25519::Pair::from_seed(&[1; 32])),
+        Signer::Ed25519(ed25519::Pair::from_seed(&[2; 32])),
+        Signer::Ecdsa(ecdsa::Pair::from_seed(&[3; 32])),
+    ];
+    for signer in signers {
+        // A new mock for each query, since it can decrypt only once
+        let mock = Arc::new(Mock::new(canned_responses()));
+        let client = serve(mock.clone()).await;
+        let account = AccountId(signer.account_id());
+
+        let resp = client
+            .contract::<Balances>()
+            .query(balances::Request::FreeBalance { account })
+            .signed_by(&signer)
+            .encrypted()
+            .send()
+            .await
+            .unwrap();
+        assert!(matches!(
+            resp,
+            balances::Response::FreeBalance { balance: 100 }
+        ));
+
+        let seen = mock.seen.lock().unwrap();
+        assert_eq!(seen[0].contract_id, 2);
+        assert_eq!(seen[0].origin, Some(account.0));
+        assert!(seen[0].encrypted);
+        assert_eq!(
+            seen[0].request,
+            json!({"FreeBalance": {"account": account.to_string()}})
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_encrypted_command() {
+    let mock = Arc::new(Mock::new(canned_responses()));
+    let client = serve(mock.clone()).await;
+    let command = assets::Command::Transfer {
+        id: 1,
+        dest: AccountId([2; 32]),
+        value: 10,
+    };
+
+    // The ECDH key is fetched from get_info
+    let payload = client
+        .contract::<Assets>()
+        .command(command)
+        .encrypted()
+        .build()
+        .await
+        .unwrap();
+    let cipher = match serde_json::from_slice::<Payload>(&payload).unwrap() {
+        Payload::Cipher(cipher) => cipher,
+        Payload::Plain(_) => panic!("command not encrypted"),
+    };
+    let (msg, _) = mock.decrypt(&cipher);
+    let command: Value = serde_json::from_slice(&msg).unwrap();
+    assert_eq!(
+        command,
+        json!({"Transfer": {"id": 1, "dest": hex::encode([2u8; 32]), "value": "10"}})
+    );
+
+    let payload = client
+        .contract::<Assets>()
+        .command(assets::Command::Destroy { id: 1 })
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(
+        serde_json::from_slice::<Value>(&payload).unwrap(),
+        json!({"Plain": r#"{"Destroy":{"id":1}}"#})
+    );
+}
+
+#[tokio::test]
+async fn test_forged_response() {
+    let mut mock = Mock::new(canned_responses());
+    mock.tamper = true;
+    let client = serve(Arc::new(mock)).await;
+
+    let err = client
+        .contract::<Balances>()
+        .query(balances::Request::FreeBalance {
+            account: AccountId([1; 32]),
+        })
+        .send()
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::BadSignature)
+    ));
+}
+
+#[tokio::test]
+async fn test_encrypted_query_unpinned() {
+    // Nothing is sent, since the worker ECDH public key can't be trusted without the identity key
+    let client = PRuntimeClient::new("http://127.0.0.1:1");
+    let err = client
+        .contract::<Balances>()
+        .query(balances::Request::FreeBalance {
+            account: AccountId([1; 32]),
+        })
+        .encrypted()
+        .send()
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::WorkerKeyNotPinned)
+    ));
+}
+
+#[tokio::test]
+async fn test_binary_rpc() {
+    let mock = Arc::new(Mock::new(canned_responses()));
+    let client = serve(mock.clone()).await;
+
+    match client.rpc(Call::GetInfo).await.unwrap() {
+        Reply::GetInfo(info) => {
+            assert!(info.initialized);
+            assert_eq!(info.public_key, mock.pubkey());
+        }
+        reply => panic!("unexpected reply {:?}", reply),
+    }
+
+    let err = client.rpc(Call::DumpStates).await.unwrap_err();
+    match err.downcast_ref::<Error>() {
+        Some(Error::Rpc(err)) => assert_eq!(err.code, ErrorCode::UnknownCall),
+        err => panic!("unexpected error {:?}", err),
+    }
+
+    let mut mock = Mock::new(canned_responses());
+    mock.tamper = true;
+    let client = serve(Arc::new(mock)).await;
+    let err = client.rpc(Call::GetInfo).await.unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::BadSignature)
+    ));
+}
+
+#[tokio::test]
+async fn test_ingest() {
+    use futures::TryStreamExt;
+
+    let mock = Arc::new(Mock::new(canned_responses()));
+    let client = serve(mock).await;
+    let frames = vec![
+        IngestFrame::Block(vec![1]),
+        IngestFrame::Block(vec![2]),
+        IngestFrame::Block(vec![0xff]),
+        IngestFrame::Block(vec![3]),
+    ];
+
+    let acks: Vec<IngestAck> = client
+        .ingest(futures::stream::iter(frames))
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(acks.len(), 3);
+    assert!(acks[1].is_ok());
+    assert_eq!(acks[1].blocknum, 2);
+    assert_eq!(acks[2].seq, 2);
+    assert_eq!(acks[2].error(), Some(ErrorCode::InvalidBlock));
+}
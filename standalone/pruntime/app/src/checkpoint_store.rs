@@ -0,0 +1,123 @@
+//! A rotating set of checkpoint files
+//!
+//! The checkpoints are opaque to the host. They are named after the block height they were taken
+//! at, so the newest one can be picked without looking into them. Also used by `pruntime-sim`.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const FILE_PREFIX: &str = "pruntime-";
+const FILE_SUFFIX: &str = ".checkpoint";
+
+pub struct CheckpointFile {
+    pub blocknum: u32,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+pub struct CheckpointStore {
+    dir: PathBuf,
+    keep: usize,
+}
+
+impl CheckpointStore {
+    /// Creates a store in `dir`, keeping the newest `keep` checkpoints
+    pub fn new(dir: impl Into<PathBuf>, keep: usize) -> Self {
+        CheckpointStore {
+            dir: dir.into(),
+            keep: keep.max(1),
+        }
+    }
+
+    /// Saves a checkpoint and removes the outdated ones
+    pub fn save(&self, blocknum: u32, data: &[u8]) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self
+            .dir
+            .join(format!("{}{}{}", FILE_PREFIX, blocknum, FILE_SUFFIX));
+        // Write to a temporary file and rename it, so a crash never leaves a truncated checkpoint
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, data)?;
+        fs::rename(&tmp_path, &path)?;
+
+        for outdated in self.list()?.iter().skip(self.keep) {
+            fs::remove_file(&outdated.path)?;
+        }
+        Ok(path)
+    }
+
+    /// Lists the checkpoints, the newest first
+    pub fn list(&self) -> io::Result<Vec<CheckpointFile>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut files = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if let Some(blocknum) = parse_blocknum(&entry.path()) {
+                files.push(CheckpointFile {
+                    blocknum,
+                    path: entry.path(),
+                    size: entry.metadata()?.len(),
+                });
+            }
+        }
+        files.sort_by(|a, b| b.blocknum.cmp(&a.blocknum));
+        Ok(files)
+    }
+
+    /// Reads the `index`-th newest checkpoint. Returns `None` if there are not so many.
+    pub fn load(&self, index: usize) -> io::Result<Option<Vec<u8>>> {
+        match self.list()?.get(index) {
+            Some(file) => fs::read(&file.path).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+fn parse_blocknum(path: &Path) -> Option<u32> {
+    let name = path.file_name()?.to_str()?;
+    name.strip_prefix(FILE_PREFIX)?
+        .strip_suffix(FILE_SUFFIX)?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "pruntime-checkpoint-store-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = test_dir("rotation");
+        let store = CheckpointStore::new(&dir, 2);
+        assert!(store.load(0).unwrap().is_none());
+
+        store.save(10, b"10").unwrap();
+        store.save(100, b"100").unwrap();
+        store.save(20, b"20").unwrap();
+        // Unrelated files are ignored
+        fs::write(dir.join("enclave.token"), b"").unwrap();
+
+        let blocks: Vec<_> = store.list().unwrap().iter().map(|f| f.blocknum).collect();
+        assert_eq!(blocks, vec![100, 20]);
+        assert_eq!(store.load(0).unwrap(), Some(b"100".to_vec()));
+        assert_eq!(store.load(1).unwrap(), Some(b"20".to_vec()));
+        assert_eq!(store.load(2).unwrap(), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,188 @@
+//! The host side of the streaming ingestion at `/ingest`
+//!
+//! The request body is a sequence of frames, each prefixed with its length as a little endian
+//! `u32` (`phala_types::rpc::IngestFrame`). The stream reads the next frame only when the ack of the
+//! previous one has been sent, so a client can't get further ahead of pRuntime than the socket
+//! buffers allow. Also used by `pruntime-sim`.
+
+use std::cmp;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The maximum size of a frame, as `phala_types::rpc::MAX_INGEST_FRAME_LEN`
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+/// The size of an ack, as `phala_types::rpc::INGEST_ACK_LEN`. The last byte is the error code.
+pub const ACK_LEN: usize = 13;
+
+/// Reads the acks of the frames read from `frames`
+///
+/// `process` handles a frame and returns its ack. The stream ends after a failed frame, or with an
+/// error if the framing is broken.
+pub struct IngestStream<R, F> {
+    frames: R,
+    process: F,
+    active: &'static AtomicBool,
+    seq: u32,
+    ack: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R, F> IngestStream<R, F>
+where
+    R: Read,
+    F: FnMut(u32, &[u8]) -> io::Result<Vec<u8>>,
+{
+    /// Starts a stream, or returns `None` if another one holding `active` is running
+    ///
+    /// Only one stream can run at a time, because pRuntime buffers the pending headers of the stream.
+    pub fn start(active: &'static AtomicBool, frames: R, process: F) -> Option<Self> {
+        if active
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return None;
+        }
+        Some(IngestStream {
+            frames,
+            process,
+            active,
+            seq: 0,
+            ack: Vec::new(),
+            pos: 0,
+            done: false,
+        })
+    }
+
+    /// Reads the next frame, or `None` at the end of the body
+    fn read_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut len = [0u8; 4];
+        let mut read = 0;
+        while read < len.len() {
+            match self.frames.read(&mut len[read..])? {
+                0 if read == 0 => return Ok(None),
+                0 => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "Truncated frame",
+                    ))
+                }
+                n => read += n,
+            }
+        }
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_FRAME_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Frame too large",
+            ));
+        }
+        let mut frame = vec![0; len];
+        self.frames.read_exact(&mut frame)?;
+        Ok(Some(frame))
+    }
+}
+
+impl<R, F> Read for IngestStream<R, F>
+where
+    R: Read,
+    F: FnMut(u32, &[u8]) -> io::Result<Vec<u8>>,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.ack.len() {
+            if self.done {
+                return Ok(0);
+            }
+            let frame = match self.read_frame()? {
+                Some(frame) => frame,
+                None => {
+                    self.done = true;
+                    return Ok(0);
+                }
+            };
+            let ack = (self.process)(self.seq, &frame)?;
+            if ack.len() != ACK_LEN {
+                return Err(io::Error::new(io::ErrorKind::Other, "Bad ack"));
+            }
+            self.done = ack[ACK_LEN - 1] != 0;
+            self.seq += 1;
+            self.ack = ack;
+            self.pos = 0;
+        }
+        let n = cmp::min(buf.len(), self.ack.len() - self.pos);
+        buf[..n].copy_from_slice(&self.ack[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl<R, F> Drop for IngestStream<R, F> {
+    fn drop(&mut self) {
+        self.active.store(false, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::io::Cursor;
+
+    fn frames(frames: &[&[u8]]) -> Cursor<Vec<u8>> {
+        let mut data = Vec::new();
+        for frame in frames {
+            data.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            data.extend_from_slice(frame);
+        }
+        Cursor::new(data)
+    }
+
+    /// Acks with the first byte of the frame as the error code
+    fn ack(seq: u32, frame: &[u8]) -> io::Result<Vec<u8>> {
+        let mut ack = vec![0; ACK_LEN];
+        ack[0] = seq as u8;
+        ack[ACK_LEN - 1] = frame[0];
+        Ok(ack)
+    }
+
+    #[test]
+    fn test_ingest_stream() {
+        static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+        // The frames are processed as the acks are read
+        let processed = RefCell::new(Vec::new());
+        let mut stream = IngestStream::start(
+            &ACTIVE,
+            frames(&[&[0, 1], &[0], &[1], &[0]]),
+            |seq, frame: &[u8]| {
+                processed.borrow_mut().push(frame.to_vec());
+                ack(seq, frame)
+            },
+        )
+        .unwrap();
+        assert!(IngestStream::start(&ACTIVE, frames(&[]), ack).is_none());
+        let mut buf = [0u8; ACK_LEN];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(buf[0], 0);
+        assert_eq!(processed.borrow().len(), 1);
+        // Ends after the failed frame
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest.len(), ACK_LEN * 2);
+        assert_eq!(rest[ACK_LEN], 2);
+        assert_eq!(processed.borrow().len(), 3);
+        drop(stream);
+
+        // Broken framing
+        let mut truncated = frames(&[&[0], &[0, 0]]).into_inner();
+        truncated.pop();
+        let mut stream = IngestStream::start(&ACTIVE, Cursor::new(truncated), ack).unwrap();
+        stream.read_exact(&mut buf).unwrap();
+        assert!(stream.read(&mut buf).is_err());
+        drop(stream);
+
+        let oversized = ((MAX_FRAME_LEN + 1) as u32).to_le_bytes().to_vec();
+        let mut stream = IngestStream::start(&ACTIVE, Cursor::new(oversized), ack).unwrap();
+        assert!(stream.read(&mut buf).is_err());
+    }
+}
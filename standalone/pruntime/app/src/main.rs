@@ -20,16 +20,9 @@ extern crate serde_json;
 #[macro_use]
 extern crate serde_derive;
 
-#[cfg(test)]
-mod tests;
-#[cfg(test)]
-extern crate ring_compat;
-#[cfg(test)]
-extern crate base64;
-#[cfg(test)]
-extern crate hex_literal;
-
 mod attestation;
+mod checkpoint_store;
+mod ingest_stream;
 mod contract_input;
 mod contract_output;
 
@@ -42,20 +35,29 @@ use std::net::SocketAddr;
 use std::str;
 use std::io::Read;
 use std::sync::{Arc, RwLock};
+use std::sync::atomic::AtomicBool;
 use std::env;
 
-use rocket::http::Method;
+use rocket::Data;
+use rocket::http::{ContentType, Method, Status};
+use rocket::response::Stream;
+use rocket::response::content::Content;
 use rocket_contrib::json::{Json, JsonValue};
 use rocket_cors::{AllowedHeaders, AllowedOrigins, AllowedMethods, Cors, CorsOptions};
 
 use contract_input::ContractInput;
 use contract_output::ContractOutput;
 use attestation::Attestation;
+use checkpoint_store::CheckpointStore;
+use ingest_stream::IngestStream;
 
 static ENCLAVE_FILE: &'static str = "enclave.signed.so";
 static ENCLAVE_STATE_FILE: &'static str = "enclave.token";
+static CHECKPOINT_DIR: &'static str = "checkpoints";
 
 const ENCLAVE_OUTPUT_BUF_MAX_LEN: usize = 2*2048*1024 as usize;
+/// The maximum size of a request to the binary API
+const RPC_REQUEST_LIMIT: u64 = 64*1024*1024;
 
 lazy_static! {
     static ref ENCLAVE: RwLock<Option<SgxEnclave>> = RwLock::new(None);
@@ -70,6 +72,21 @@ lazy_static! {
     static ref ENABLE_KICK_API: bool = {
         env::var("ENABLE_KICK_API").unwrap_or_else(|_| "".to_string()) != ""
     };
+    /// Take a checkpoint every N dispatched blocks. 0 to disable.
+    static ref CHECKPOINT_INTERVAL: u32 = {
+        env::var("CHECKPOINT_INTERVAL").ok().and_then(|v| v.parse().ok()).unwrap_or(1000)
+    };
+    /// Keep the transaction receipts of the last N blocks (about a week by default). 0 to keep
+    /// them until the store is full.
+    static ref RECEIPT_RETENTION: u32 = {
+        env::var("RECEIPT_RETENTION").ok().and_then(|v| v.parse().ok()).unwrap_or(100_000)
+    };
+    static ref CHECKPOINT_STORE: CheckpointStore = {
+        let keep = env::var("CHECKPOINT_KEEP").ok().and_then(|v| v.parse().ok()).unwrap_or(3);
+        let executable = env::current_exe().unwrap();
+        let path = executable.parent().unwrap();
+        CheckpointStore::new(path.join(*ENCLAVE_STATE_FILE_PATH).join(CHECKPOINT_DIR), keep)
+    };
 }
 
 fn destroy_enclave() {
@@ -89,8 +106,22 @@ extern {
         output_ptr : *mut u8, output_len_ptr: *mut usize, output_buf_len: usize
     ) -> sgx_status_t;
 
+    fn ecall_rpc(
+        eid: sgx_enclave_id_t, retval: *mut sgx_status_t,
+        input_ptr: *const u8, input_len: usize,
+        output_ptr : *mut u8, output_len_ptr: *mut usize, output_buf_len: usize
+    ) -> sgx_status_t;
+
+    fn ecall_ingest(
+        eid: sgx_enclave_id_t, retval: *mut sgx_status_t,
+        seq: u32,
+        frame_ptr: *const u8, frame_len: usize,
+        ack_ptr: *mut u8, ack_len: usize
+    ) -> sgx_status_t;
+
     fn ecall_init(
-        eid: sgx_enclave_id_t, retval: *mut sgx_status_t
+        eid: sgx_enclave_id_t, retval: *mut sgx_status_t,
+        checkpoint_interval: u32, receipt_retention: u32
     ) -> sgx_status_t;
 }
 
@@ -219,11 +250,52 @@ fn ocall_get_update_info(
 #[no_mangle]
 pub extern "C"
 fn ocall_dump_state(
-    _output_ptr : *mut u8,
-    _output_len_ptr: *mut usize,
-    _output_buf_len: usize
+    input_ptr: *const u8,
+    input_len: usize,
+    blocknum: u32
 ) -> sgx_status_t {
-    // TODO:
+    let input_slice = unsafe { std::slice::from_raw_parts(input_ptr, input_len) };
+    match CHECKPOINT_STORE.save(blocknum, input_slice) {
+        Ok(path) => {
+            info!("Saved checkpoint ({} bytes) to {}", input_len, path.display());
+            sgx_status_t::SGX_SUCCESS
+        },
+        Err(err) => {
+            error!("Failed to save checkpoint: {}", err);
+            sgx_status_t::SGX_ERROR_UNEXPECTED
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C"
+fn ocall_load_state(
+    index: usize,
+    output_ptr : *mut u8,
+    output_len_ptr: *mut usize,
+    output_buf_len: usize
+) -> sgx_status_t {
+    let state = match CHECKPOINT_STORE.load(index) {
+        Ok(data) => data.unwrap_or_default(),
+        Err(err) => {
+            error!("Failed to load checkpoint: {}", err);
+            return sgx_status_t::SGX_ERROR_UNEXPECTED;
+        }
+    };
+    let state_len = state.len();
+    info!("Loaded checkpoint #{} ({} bytes)", index, state_len);
+
+    // Always report the length, so the enclave can retry with a larger buffer if it's too short
+    unsafe {
+        if state_len <= output_buf_len {
+            std::ptr::copy_nonoverlapping(state.as_ptr(),
+                                          output_ptr,
+                                          state_len);
+        }
+        std::ptr::copy_nonoverlapping(&state_len as *const usize,
+                                      output_len_ptr,
+                                      std::mem::size_of_val(&state_len));
+    }
 
     sgx_status_t::SGX_SUCCESS
 }
@@ -302,475 +374,161 @@ fn init_enclave() -> SgxResult<SgxEnclave> {
                        &mut misc_attr)
 }
 
-#[post("/test", format = "json", data = "<contract_input>")]
-fn test(contract_input: Json<ContractInput>) -> JsonValue {
-    debug!("{}", ::serde_json::to_string_pretty(&*contract_input).unwrap());
-
-    let eid = get_eid();
-
-    let input_string = serde_json::to_string(&*contract_input).unwrap();
-    let mut return_output_buf = vec![0; ENCLAVE_OUTPUT_BUF_MAX_LEN].into_boxed_slice();
-    let mut output_len : usize = 0;
-    let output_slice = &mut return_output_buf;
-    let output_ptr = output_slice.as_mut_ptr();
-    let output_len_ptr = &mut output_len as *mut usize;
-
-    let mut retval = sgx_status_t::SGX_SUCCESS;
-    let result = unsafe {
-        ecall_handle(
-            eid, &mut retval,
-            0,
-            input_string.as_ptr(), input_string.len(),
-            output_ptr, output_len_ptr, ENCLAVE_OUTPUT_BUF_MAX_LEN
-        )
+/// The action codes of the JSON API, by the route name
+fn action_by_name(name: &str) -> Option<u8> {
+    let action = match name {
+        "test" => 0,
+        "init_runtime" => 1,
+        "get_info" => 2,
+        "dump_states" => 3,
+        "load_states" => 4,
+        "sync_header" => 5,
+        "query" => 6,
+        "dispatch_block" => 7,
+        "get_runtime_info" => 10,
+        "add_bridge" => 11,
+        "sync_bridge_header" => 12,
+        "dispatch_bridge_block" => 13,
+        "get_bridges" => 14,
+        "get_storage_keys" => 15,
+        "set" => 21,
+        "get" => 22,
+        "test_ink" => 100,
+        _ => return None,
     };
-
-    match result {
-        sgx_status_t::SGX_SUCCESS => {
-            let output_slice = unsafe { std::slice::from_raw_parts(output_ptr, output_len) };
-            let output_value: serde_json::value::Value = serde_json::from_slice(output_slice).unwrap();
-            json!(output_value)
-        },
-        _ => {
-            error!("[-] ECALL Enclave Failed {}!", result.as_str());
-            json!({
-                "status": "error",
-                "payload": format!("[-] ECALL Enclave Failed {}!", result.as_str())
-            })
-        }
-    }
+    Some(action)
 }
 
-#[post("/init_runtime", format = "json", data = "<contract_input>")]
-fn init_runtime(contract_input: Json<ContractInput>) -> JsonValue {
-    debug!("{}", ::serde_json::to_string_pretty(&*contract_input).unwrap());
-
+/// Makes an ECALL writing its output to a buffer, and returns the output
+fn ecall_with_output<F>(ecall: F) -> Result<Vec<u8>, sgx_status_t>
+    where F: FnOnce(sgx_enclave_id_t, *mut sgx_status_t, *mut u8, *mut usize, usize) -> sgx_status_t
+{
     let eid = get_eid();
-
-    let input_string = serde_json::to_string(&*contract_input).unwrap();
-    let mut return_output_buf = vec![0; ENCLAVE_OUTPUT_BUF_MAX_LEN].into_boxed_slice();
+    let mut output_buf = vec![0; ENCLAVE_OUTPUT_BUF_MAX_LEN];
     let mut output_len : usize = 0;
-    let output_slice = &mut return_output_buf;
-    let output_ptr = output_slice.as_mut_ptr();
-    let output_len_ptr = &mut output_len as *mut usize;
-
     let mut retval = sgx_status_t::SGX_SUCCESS;
-    let result = unsafe {
-        ecall_handle(
-            eid, &mut retval,
-            1,
-            input_string.as_ptr(), input_string.len(),
-            output_ptr, output_len_ptr, ENCLAVE_OUTPUT_BUF_MAX_LEN
-        )
-    };
+    let result = ecall(eid, &mut retval, output_buf.as_mut_ptr(), &mut output_len, ENCLAVE_OUTPUT_BUF_MAX_LEN);
 
-    match result {
-        sgx_status_t::SGX_SUCCESS => {
-            let output_slice = unsafe { std::slice::from_raw_parts(output_ptr, output_len) };
-            let output_value: serde_json::value::Value = serde_json::from_slice(output_slice).unwrap();
-            json!(output_value)
-        },
-        _ => {
-            error!("[-] ECALL Enclave Failed {}!", result.as_str());
-            json!({
-                "status": "error",
-                "payload": format!("[-] ECALL Enclave Failed {}!", result.as_str())
-            })
-        }
+    if result != sgx_status_t::SGX_SUCCESS {
+        return Err(result);
     }
-}
-
-#[post("/get_info", format = "json", data = "<contract_input>")]
-fn get_info(contract_input: Json<ContractInput>) -> JsonValue {
-    debug!("{}", ::serde_json::to_string_pretty(&*contract_input).unwrap());
-
-    let eid = get_eid();
-
-    let input_string = serde_json::to_string(&*contract_input).unwrap();
-    let mut return_output_buf = vec![0; ENCLAVE_OUTPUT_BUF_MAX_LEN].into_boxed_slice();
-    let mut output_len : usize = 0;
-    let output_slice = &mut return_output_buf;
-    let output_ptr = output_slice.as_mut_ptr();
-    let output_len_ptr = &mut output_len as *mut usize;
-
-    let mut retval = sgx_status_t::SGX_SUCCESS;
-    let result = unsafe {
-        ecall_handle(
-            eid, &mut retval,
-            2,
-            input_string.as_ptr(), input_string.len(),
-            output_ptr, output_len_ptr, ENCLAVE_OUTPUT_BUF_MAX_LEN
-        )
-    };
-
-    match result {
-        sgx_status_t::SGX_SUCCESS => {
-            let output_slice = unsafe { std::slice::from_raw_parts(output_ptr, output_len) };
-            let output_value: serde_json::value::Value = serde_json::from_slice(output_slice).unwrap();
-            json!(output_value)
-        },
-        _ => {
-            error!("[-] ECALL Enclave Failed {}!", result.as_str());
-            json!({
-                "status": "error",
-                "payload": format!("[-] ECALL Enclave Failed {}!", result.as_str())
-            })
-        }
+    if retval != sgx_status_t::SGX_SUCCESS {
+        return Err(retval);
     }
-}
-
-#[post("/get_runtime_info", format = "json", data = "<contract_input>")]
-fn get_runtime_info(contract_input: Json<ContractInput>) -> JsonValue {
-    debug!("{}", ::serde_json::to_string_pretty(&*contract_input).unwrap());
-
-    let eid = get_eid();
-
-    let input_string = serde_json::to_string(&*contract_input).unwrap();
-    let mut return_output_buf = vec![0; ENCLAVE_OUTPUT_BUF_MAX_LEN].into_boxed_slice();
-    let mut output_len : usize = 0;
-    let output_slice = &mut return_output_buf;
-    let output_ptr = output_slice.as_mut_ptr();
-    let output_len_ptr = &mut output_len as *mut usize;
-
-    let mut retval = sgx_status_t::SGX_SUCCESS;
-    let result = unsafe {
-        ecall_handle(
-            eid, &mut retval,
-            10,
-            input_string.as_ptr(), input_string.len(),
-            output_ptr, output_len_ptr, ENCLAVE_OUTPUT_BUF_MAX_LEN
-        )
-    };
-
-    match result {
-        sgx_status_t::SGX_SUCCESS => {
-            let output_slice = unsafe { std::slice::from_raw_parts(output_ptr, output_len) };
-            let output_value: serde_json::value::Value = serde_json::from_slice(output_slice).unwrap();
-            json!(output_value)
-        },
-        _ => {
-            error!("[-] ECALL Enclave Failed {}!", result.as_str());
-            json!({
-                "status": "error",
-                "payload": format!("[-] ECALL Enclave Failed {}!", result.as_str())
-            })
-        }
+    if output_len > ENCLAVE_OUTPUT_BUF_MAX_LEN {
+        error!("[-] Enclave output too large ({} bytes)", output_len);
+        return Err(sgx_status_t::SGX_ERROR_UNEXPECTED);
     }
+    output_buf.truncate(output_len);
+    Ok(output_buf)
 }
 
-#[post("/dump_states", format = "json", data = "<contract_input>")]
-fn dump_states(contract_input: Json<ContractInput>) -> JsonValue {
-    debug!("{}", ::serde_json::to_string_pretty(&*contract_input).unwrap());
-
-    let eid = get_eid();
-
-    let input_string = serde_json::to_string(&*contract_input).unwrap();
-    let mut return_output_buf = vec![0; ENCLAVE_OUTPUT_BUF_MAX_LEN].into_boxed_slice();
-    let mut output_len : usize = 0;
-    let output_slice = &mut return_output_buf;
-    let output_ptr = output_slice.as_mut_ptr();
-    let output_len_ptr = &mut output_len as *mut usize;
-
-    let mut retval = sgx_status_t::SGX_SUCCESS;
-    let result = unsafe {
+/// Calls the JSON API in the enclave with the `{input, nonce}` request
+fn call_json_api(action: u8, input_string: &str) -> Result<serde_json::Value, sgx_status_t> {
+    let output = ecall_with_output(|eid, retval, output_ptr, output_len_ptr, output_buf_len| unsafe {
         ecall_handle(
-            eid, &mut retval,
-            3,
+            eid, retval,
+            action,
             input_string.as_ptr(), input_string.len(),
-            output_ptr, output_len_ptr, ENCLAVE_OUTPUT_BUF_MAX_LEN
+            output_ptr, output_len_ptr, output_buf_len
         )
-    };
-
-    match result {
-        sgx_status_t::SGX_SUCCESS => {
-            let output_slice = unsafe { std::slice::from_raw_parts(output_ptr, output_len) };
-            let output_value: serde_json::value::Value = serde_json::from_slice(output_slice).unwrap();
-            json!(output_value)
-        },
-        _ => {
-            error!("[-] ECALL Enclave Failed {}!", result.as_str());
-            json!({
-                "status": "error",
-                "payload": format!("[-] ECALL Enclave Failed {}!", result.as_str())
-            })
-        }
-    }
+    })?;
+    Ok(serde_json::from_slice(&output).unwrap())
 }
 
-#[post("/load_states", format = "json", data = "<contract_input>")]
-fn load_states(contract_input: Json<ContractInput>) -> JsonValue {
+/// The JSON API. `action` is the name of the call, e.g. `/get_info`.
+#[post("/<action>", format = "json", data = "<contract_input>")]
+fn json_rpc(action: String, contract_input: Json<ContractInput>) -> Option<JsonValue> {
+    let action = action_by_name(&action)?;
     debug!("{}", ::serde_json::to_string_pretty(&*contract_input).unwrap());
 
-    let eid = get_eid();
-
     let input_string = serde_json::to_string(&*contract_input).unwrap();
-    let mut return_output_buf = vec![0; ENCLAVE_OUTPUT_BUF_MAX_LEN].into_boxed_slice();
-    let mut output_len : usize = 0;
-    let output_slice = &mut return_output_buf;
-    let output_ptr = output_slice.as_mut_ptr();
-    let output_len_ptr = &mut output_len as *mut usize;
-
-    let mut retval = sgx_status_t::SGX_SUCCESS;
-    let result = unsafe {
-        ecall_handle(
-            eid, &mut retval,
-            4,
-            input_string.as_ptr(), input_string.len(),
-            output_ptr, output_len_ptr, ENCLAVE_OUTPUT_BUF_MAX_LEN
-        )
-    };
-
-    match result {
-        sgx_status_t::SGX_SUCCESS => {
-            let output_slice = unsafe { std::slice::from_raw_parts(output_ptr, output_len) };
-            let output_value: serde_json::value::Value = serde_json::from_slice(output_slice).unwrap();
-            json!(output_value)
-        },
-        _ => {
+    let output = match call_json_api(action, &input_string) {
+        Ok(output_value) => json!(output_value),
+        Err(result) => {
             error!("[-] ECALL Enclave Failed {}!", result.as_str());
             json!({
                 "status": "error",
                 "payload": format!("[-] ECALL Enclave Failed {}!", result.as_str())
             })
         }
-    }
-}
-
-#[post("/sync_header", format = "json", data = "<contract_input>")]
-fn sync_header(contract_input: Json<ContractInput>) -> JsonValue {
-    debug!("{}", ::serde_json::to_string_pretty(&*contract_input).unwrap());
-
-    let eid = get_eid();
-
-    let input_string = serde_json::to_string(&*contract_input).unwrap();
-    let mut return_output_buf = vec![0; ENCLAVE_OUTPUT_BUF_MAX_LEN].into_boxed_slice();
-    let mut output_len : usize = 0;
-    let output_slice = &mut return_output_buf;
-    let output_ptr = output_slice.as_mut_ptr();
-    let output_len_ptr = &mut output_len as *mut usize;
-
-    let mut retval = sgx_status_t::SGX_SUCCESS;
-    let result = unsafe {
-        ecall_handle(
-            eid, &mut retval,
-            5,
-            input_string.as_ptr(), input_string.len(),
-            output_ptr, output_len_ptr, ENCLAVE_OUTPUT_BUF_MAX_LEN
-        )
     };
-
-    match result {
-        sgx_status_t::SGX_SUCCESS => {
-            let output_slice = unsafe { std::slice::from_raw_parts(output_ptr, output_len) };
-            let output_value: serde_json::value::Value = serde_json::from_slice(output_slice).unwrap();
-            json!(output_value)
-        },
-        _ => {
-            error!("[-] ECALL Enclave Failed {}!", result.as_str());
-            json!({
-                "status": "error",
-                "payload": format!("[-] ECALL Enclave Failed {}!", result.as_str())
-            })
-        }
-    }
+    Some(output)
 }
 
-#[post("/query", format = "json", data = "<contract_input>")]
-fn query(contract_input: Json<ContractInput>) -> JsonValue {
-    debug!("{}", ::serde_json::to_string_pretty(&*contract_input).unwrap());
-
-    let eid = get_eid();
-
-    let input_string = serde_json::to_string(&*contract_input).unwrap();
-
-    let mut return_output_buf = vec![0; ENCLAVE_OUTPUT_BUF_MAX_LEN].into_boxed_slice();
-    let mut output_len : usize = 0;
-    let output_slice = &mut return_output_buf;
-    let output_ptr = output_slice.as_mut_ptr();
-    let output_len_ptr = &mut output_len as *mut usize;
-
-    let mut retval = sgx_status_t::SGX_SUCCESS;
-    let result = unsafe {
-        ecall_handle(
-            eid, &mut retval,
-            6,
-            input_string.as_ptr(), input_string.len(),
-            output_ptr, output_len_ptr, ENCLAVE_OUTPUT_BUF_MAX_LEN
+/// The binary API. The body is an encoded `RpcRequest`, and so is the response an `RpcResponse`.
+#[post("/rpc", data = "<data>")]
+fn rpc(data: Data) -> Result<Content<Vec<u8>>, Status> {
+    let mut request = Vec::new();
+    data.open().take(RPC_REQUEST_LIMIT).read_to_end(&mut request).map_err(|_| Status::BadRequest)?;
+
+    let output = ecall_with_output(|eid, retval, output_ptr, output_len_ptr, output_buf_len| unsafe {
+        ecall_rpc(
+            eid, retval,
+            request.as_ptr(), request.len(),
+            output_ptr, output_len_ptr, output_buf_len
         )
-    };
-
-    match result {
-        sgx_status_t::SGX_SUCCESS => {
-            let output_slice = unsafe { std::slice::from_raw_parts(output_ptr, output_len) };
-            let output_value: serde_json::value::Value = serde_json::from_slice(output_slice).unwrap();
-            json!(output_value)
-        },
-        _ => {
+    });
+    match output {
+        Ok(output) => Ok(Content(ContentType::Binary, output)),
+        Err(result) => {
             error!("[-] ECALL Enclave Failed {}!", result.as_str());
-            json!({
-                "status": "error",
-                "payload": format!("[-] ECALL Enclave Failed {}!", result.as_str())
-            })
+            Err(Status::InternalServerError)
         }
     }
 }
 
-#[post("/dispatch_block", format = "json", data = "<contract_input>")]
-fn dispatch_block(contract_input: Json<ContractInput>) -> JsonValue {
-    debug!("{}", ::serde_json::to_string_pretty(&*contract_input).unwrap());
+/// Set while an ingestion stream is running
+static INGESTING: AtomicBool = AtomicBool::new(false);
 
+/// Hands a frame of the ingestion stream to the enclave and returns its ack
+fn ingest_frame(seq: u32, frame: &[u8]) -> std::io::Result<Vec<u8>> {
     let eid = get_eid();
-
-    let input_string = serde_json::to_string(&*contract_input).unwrap();
-    let mut return_output_buf = vec![0; ENCLAVE_OUTPUT_BUF_MAX_LEN].into_boxed_slice();
-    let mut output_len : usize = 0;
-    let output_slice = &mut return_output_buf;
-    let output_ptr = output_slice.as_mut_ptr();
-    let output_len_ptr = &mut output_len as *mut usize;
-
+    let mut ack = vec![0; ingest_stream::ACK_LEN];
     let mut retval = sgx_status_t::SGX_SUCCESS;
     let result = unsafe {
-        ecall_handle(
+        ecall_ingest(
             eid, &mut retval,
-            7,
-            input_string.as_ptr(), input_string.len(),
-            output_ptr, output_len_ptr, ENCLAVE_OUTPUT_BUF_MAX_LEN
+            seq,
+            frame.as_ptr(), frame.len(),
+            ack.as_mut_ptr(), ack.len()
         )
     };
-
-    match result {
-        sgx_status_t::SGX_SUCCESS => {
-            let output_slice = unsafe { std::slice::from_raw_parts(output_ptr, output_len) };
-            let output_value: serde_json::value::Value = serde_json::from_slice(output_slice).unwrap();
-            json!(output_value)
-        },
-        _ => {
-            error!("[-] ECALL Enclave Failed {}!", result.as_str());
-            json!({
-                "status": "error",
-                "payload": format!("[-] ECALL Enclave Failed {}!", result.as_str())
-            })
-        }
+    if result != sgx_status_t::SGX_SUCCESS || retval != sgx_status_t::SGX_SUCCESS {
+        error!("[-] ECALL Enclave Failed {} {}!", result.as_str(), retval.as_str());
+        return Err(std::io::Error::new(std::io::ErrorKind::Other, "ECALL failed"));
     }
+    Ok(ack)
 }
 
-#[post("/set", format = "json", data = "<contract_input>")]
-fn set(contract_input: Json<ContractInput>) -> JsonValue {
-    debug!("{}", ::serde_json::to_string_pretty(&*contract_input).unwrap());
-
-    let eid = get_eid();
-
-    let input_string = serde_json::to_string(&*contract_input).unwrap();
-
-    let mut return_output_buf = vec![0; ENCLAVE_OUTPUT_BUF_MAX_LEN].into_boxed_slice();
-    let mut output_len : usize = 0;
-    let output_slice = &mut return_output_buf;
-    let output_ptr = output_slice.as_mut_ptr();
-    let output_len_ptr = &mut output_len as *mut usize;
-
-    let mut retval = sgx_status_t::SGX_SUCCESS;
-    let result = unsafe {
-        ecall_handle(
-            eid, &mut retval,
-            21,
-            input_string.as_ptr(), input_string.len(),
-            output_ptr, output_len_ptr, ENCLAVE_OUTPUT_BUF_MAX_LEN
-        )
-    };
-
-    match result {
-        sgx_status_t::SGX_SUCCESS => {
-            let output_slice = unsafe { std::slice::from_raw_parts(output_ptr, output_len) };
-            let output_value: serde_json::value::Value = serde_json::from_slice(output_slice).unwrap();
-            json!(output_value)
-        },
-        _ => {
-            error!("[-] ECALL Enclave Failed {}!", result.as_str());
-            json!({
-                "status": "error",
-                "payload": format!("[-] ECALL Enclave Failed {}!", result.as_str())
-            })
-        }
-    }
+/// The streaming ingestion. The body is a stream of `IngestFrame`s, and the response streams
+/// back an `IngestAck` per frame.
+#[post("/ingest", data = "<data>")]
+fn ingest(data: Data) -> Result<Content<Stream<impl Read>>, Status> {
+    let stream = IngestStream::start(&INGESTING, data.open(), ingest_frame)
+        .ok_or(Status::Conflict)?;
+    Ok(Content(ContentType::Binary, Stream::chunked(stream, ingest_stream::ACK_LEN as u64)))
 }
 
-#[post("/get", format = "json", data = "<contract_input>")]
-fn get(contract_input: Json<ContractInput>) -> JsonValue {
-    debug!("{}", ::serde_json::to_string_pretty(&*contract_input).unwrap());
-
-    let eid = get_eid();
-
-    let input_string = serde_json::to_string(&*contract_input).unwrap();
-
-    let mut return_output_buf = vec![0; ENCLAVE_OUTPUT_BUF_MAX_LEN].into_boxed_slice();
-    let mut output_len : usize = 0;
-    let output_slice = &mut return_output_buf;
-    let output_ptr = output_slice.as_mut_ptr();
-    let output_len_ptr = &mut output_len as *mut usize;
-
-    let mut retval = sgx_status_t::SGX_SUCCESS;
-    let result = unsafe {
-        ecall_handle(
-            eid, &mut retval,
-            22,
-            input_string.as_ptr(), input_string.len(),
-            output_ptr, output_len_ptr, ENCLAVE_OUTPUT_BUF_MAX_LEN
-        )
-    };
-
-    match result {
-        sgx_status_t::SGX_SUCCESS => {
-            let output_slice = unsafe { std::slice::from_raw_parts(output_ptr, output_len) };
-            let output_value: serde_json::value::Value = serde_json::from_slice(output_slice).unwrap();
-            json!(output_value)
-        },
-        _ => {
-            error!("[-] ECALL Enclave Failed {}!", result.as_str());
+/// Lists the checkpoints kept on the disk, the newest first
+#[get("/checkpoints")]
+fn checkpoints() -> JsonValue {
+    match CHECKPOINT_STORE.list() {
+        Ok(files) => {
+            let checkpoints: Vec<serde_json::Value> = files.iter().map(|f| json!({
+                "blocknum": f.blocknum,
+                "file": f.path.display().to_string(),
+                "size": f.size,
+            }).0).collect();
             json!({
-                "status": "error",
-                "payload": format!("[-] ECALL Enclave Failed {}!", result.as_str())
+                "interval": *CHECKPOINT_INTERVAL,
+                "checkpoints": checkpoints
             })
-        }
-    }
-}
-
-#[post("/test_ink", format = "json", data = "<contract_input>")]
-fn test_ink(contract_input: Json<ContractInput>) -> JsonValue {
-    debug!("{}", ::serde_json::to_string_pretty(&*contract_input).unwrap());
-
-    let eid = get_eid();
-
-    let input_string = serde_json::to_string(&*contract_input).unwrap();
-
-    let mut return_output_buf = vec![0; ENCLAVE_OUTPUT_BUF_MAX_LEN].into_boxed_slice();
-    let mut output_len : usize = 0;
-    let output_slice = &mut return_output_buf;
-    let output_ptr = output_slice.as_mut_ptr();
-    let output_len_ptr = &mut output_len as *mut usize;
-
-    let mut retval = sgx_status_t::SGX_SUCCESS;
-    let result = unsafe {
-        ecall_handle(
-            eid, &mut retval,
-            100,
-            input_string.as_ptr(), input_string.len(),
-            output_ptr, output_len_ptr, ENCLAVE_OUTPUT_BUF_MAX_LEN
-        )
-    };
-
-    match result {
-        sgx_status_t::SGX_SUCCESS => {
-            let output_slice = unsafe { std::slice::from_raw_parts(output_ptr, output_len) };
-            let output_value: serde_json::value::Value = serde_json::from_slice(output_slice).unwrap();
-            json!(output_value)
         },
-        _ => {
-            error!("[-] ECALL Enclave Failed {}!", result.as_str());
-            json!({
-                "status": "error",
-                "payload": format!("[-] ECALL Enclave Failed {}!", result.as_str())
-            })
-        }
+        Err(err) => json!({
+            "status": "error",
+            "payload": format!("Failed to list checkpoints: {}", err)
+        })
     }
 }
 
@@ -800,10 +558,7 @@ fn cors_options() -> CorsOptions {
 fn rocket() -> rocket::Rocket {
     let mut server = rocket::ignite()
         .mount("/", routes![
-            test, init_runtime, get_info,
-            dump_states, load_states,
-            sync_header, dispatch_block, query,
-            set, get, get_runtime_info, test_ink]);
+            json_rpc, rpc, ingest, checkpoints]);
 
     if *ENABLE_KICK_API {
         info!("ENABLE `kick` API");
@@ -847,7 +602,7 @@ fn main() {
     let eid = get_eid();
     let mut retval = sgx_status_t::SGX_SUCCESS;
     let result = unsafe {
-        ecall_init(eid, &mut retval)
+        ecall_init(eid, &mut retval, *CHECKPOINT_INTERVAL, *RECEIPT_RETENTION)
     };
 
     if result != sgx_status_t::SGX_SUCCESS {
@@ -0,0 +1,28 @@
+//! The primitive types of the Phala chain.
+//!
+//! pRuntime doesn't link the runtime, so a runtime upgrade can't break a deployed enclave. The
+//! chain is only read through the headers and the storage proofs of the items pRuntime knows
+//! about, and the messages to the workers come from `Phala::WorkerMessages` in a stable encoding
+//! (`phala_types::ChainMessage`).
+
+use sp_runtime::{generic, traits::BlakeTwo256, OpaqueExtrinsic};
+
+pub type AccountId = sp_runtime::AccountId32;
+pub type Balance = u128;
+pub type BlockNumber = u32;
+pub type Hash = sp_core::H256;
+pub type Hashing = BlakeTwo256;
+pub type Header = generic::Header<BlockNumber, Hashing>;
+pub type Block = generic::Block<Header, OpaqueExtrinsic>;
+
+/// The oldest runtime `spec_version` this pRuntime understands
+pub const MIN_SPEC_VERSION: u32 = 33;
+/// The newest runtime `spec_version` this pRuntime understands. Bump it once a runtime upgrade
+/// is verified to keep the storage items and the messages pRuntime reads compatible.
+pub const MAX_SPEC_VERSION: u32 = 34;
+
+pub type ChainMessage = phala_types::ChainMessage<AccountId, Balance>;
+
+/// The chain tracked by the light client
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Runtime;
@@ -0,0 +1,185 @@
+//! Encrypted and authenticated state checkpoints
+//!
+//! A checkpoint is the SCALE encoded `CheckpointHeader` followed by the AES-GCM encrypted body.
+//! The header is not encrypted so that the host can tell what a checkpoint is about, but it's
+//! authenticated as the additional data of the cipher, so neither part can be tampered. The key is
+//! derived from the worker identity key, which is sealed by SGX, so a checkpoint can only be
+//! loaded by the same worker. The header also carries the state root of the contracts in the body,
+//! so the restored states are checked against the root committed when the checkpoint was taken.
+
+use crate::std::fmt;
+use crate::std::vec::Vec;
+use parity_scale_codec::{Decode, Encode};
+use secp256k1::SecretKey;
+use sp_core::hashing::blake2_256;
+use sp_core::H256;
+
+use crate::cryptography::aead;
+
+pub const MAGIC: [u8; 4] = *b"PRCK";
+/// Bump it when the layout of the header or the body changes
+pub const FORMAT_VERSION: u32 = 6;
+
+const KEY_DERIVATION_CONTEXT: &[u8] = b"pruntime_checkpoint_key";
+
+#[derive(Encode, Decode, Debug, Clone, PartialEq)]
+pub struct CheckpointHeader {
+    pub magic: [u8; 4],
+    pub format_version: u32,
+    /// The next header to sync
+    pub headernum: u32,
+    /// The next block to dispatch
+    pub blocknum: u32,
+    /// The state root of the contracts at `blocknum`
+    pub state_root: H256,
+    /// The hash of the plain body
+    pub body_hash: H256,
+    pub iv: aead::IV,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    BadMagic,
+    UnsupportedVersion(u32),
+    DecodeError,
+    /// The cipher or the header was tampered, or it's sealed by another worker
+    AuthFailed,
+    BodyHashMismatch,
+    /// The restored states don't match the committed state root
+    StateRootMismatch,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::BadMagic => write!(f, "not a checkpoint"),
+            Error::UnsupportedVersion(v) => write!(f, "unsupported checkpoint format {}", v),
+            Error::DecodeError => write!(f, "decode error"),
+            Error::AuthFailed => write!(f, "checkpoint authentication failed"),
+            Error::BodyHashMismatch => write!(f, "body hash mismatch"),
+            Error::StateRootMismatch => write!(f, "state root mismatch"),
+        }
+    }
+}
+
+/// Derives the checkpoint encryption key of the worker
+pub fn derive_key(identity_key: &SecretKey) -> [u8; 32] {
+    let mut material = KEY_DERIVATION_CONTEXT.to_vec();
+    material.extend_from_slice(&identity_key.serialize());
+    blake2_256(&material)
+}
+
+/// Encrypts `body` into a checkpoint
+pub fn seal(
+    key: &[u8; 32],
+    headernum: u32,
+    blocknum: u32,
+    state_root: H256,
+    body: &[u8],
+) -> Vec<u8> {
+    let header = CheckpointHeader {
+        magic: MAGIC,
+        format_version: FORMAT_VERSION,
+        headernum,
+        blocknum,
+        state_root,
+        body_hash: H256(blake2_256(body)),
+        iv: aead::generate_iv(),
+    };
+    let encoded_header = header.encode();
+    let mut cipher = body.to_vec();
+    aead::encrypt_with_aad(&header.iv, key, &encoded_header, &mut cipher);
+
+    let mut data = encoded_header;
+    data.extend(cipher);
+    data
+}
+
+/// Reads the header without decrypting the checkpoint. The result is not authenticated.
+pub fn peek_header(data: &[u8]) -> Result<CheckpointHeader, Error> {
+    let header = CheckpointHeader::decode(&mut &data[..]).map_err(|_| Error::DecodeError)?;
+    if header.magic != MAGIC {
+        return Err(Error::BadMagic);
+    }
+    if header.format_version != FORMAT_VERSION {
+        return Err(Error::UnsupportedVersion(header.format_version));
+    }
+    Ok(header)
+}
+
+/// Authenticates and decrypts a checkpoint, returning the header and the plain body
+pub fn open(key: &[u8; 32], data: &[u8]) -> Result<(CheckpointHeader, Vec<u8>), Error> {
+    let header = peek_header(data)?;
+    let header_len = header.encoded_size();
+    let (encoded_header, cipher) = data.split_at(header_len);
+
+    let mut in_out = cipher.to_vec();
+    let body = aead::decrypt_with_aad(&header.iv, key, encoded_header, &mut in_out)
+        .map_err(|_| Error::AuthFailed)?
+        .to_vec();
+    if H256(blake2_256(&body)) != header.body_hash {
+        return Err(Error::BodyHashMismatch);
+    }
+    Ok((header, body))
+}
+
+/// Checks the state root recomputed from the restored states against the opened header
+pub fn check_state_root(header: &CheckpointHeader, state_root: H256) -> Result<(), Error> {
+    if state_root != header.state_root {
+        return Err(Error::StateRootMismatch);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_key(seed: u8) -> [u8; 32] {
+        let sk = SecretKey::parse(&[seed; 32]).unwrap();
+        derive_key(&sk)
+    }
+
+    #[test]
+    fn test_seal_and_open() {
+        let key = test_key(1);
+        let data = seal(&key, 10, 8, H256::repeat_byte(1), b"state");
+        let (header, body) = open(&key, &data).unwrap();
+        assert_eq!(header.headernum, 10);
+        assert_eq!(header.blocknum, 8);
+        assert_eq!(body, b"state".to_vec());
+        assert_eq!(peek_header(&data).unwrap(), header);
+        assert_eq!(check_state_root(&header, H256::repeat_byte(1)), Ok(()));
+        assert_eq!(
+            check_state_root(&header, H256::repeat_byte(2)),
+            Err(Error::StateRootMismatch)
+        );
+    }
+
+    #[test]
+    fn test_tampered_checkpoint() {
+        let key = test_key(1);
+        let data = seal(&key, 10, 8, H256::repeat_byte(1), b"state");
+        // Tamper the block height in the header
+        let mut tampered = data.clone();
+        tampered[8] ^= 1;
+        assert_eq!(open(&key, &tampered), Err(Error::AuthFailed));
+        // Tamper the cipher
+        let mut tampered = data.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert_eq!(open(&key, &tampered), Err(Error::AuthFailed));
+        // Sealed by another worker
+        assert_eq!(open(&test_key(2), &data), Err(Error::AuthFailed));
+    }
+
+    #[test]
+    fn test_bad_header() {
+        let key = test_key(1);
+        let mut data = seal(&key, 10, 8, H256::repeat_byte(1), b"state");
+        data[4] = 0xff;
+        assert_eq!(peek_header(&data), Err(Error::UnsupportedVersion(0xff)));
+        data[0] = 0;
+        assert_eq!(peek_header(&data), Err(Error::BadMagic));
+    }
+}
@@ -1,26 +1,46 @@
 use anyhow::Result;
 use log::info;
-use std::collections::{BTreeMap};
+use std::collections::{BTreeMap, BTreeSet};
 use serde::{Serialize, Deserialize};
 use crate::std::string::String;
 use crate::std::vec::Vec;
 use core::{fmt, str};
+use parity_scale_codec::{Decode, Encode};
+use phala_types::ChainMessage;
+use sp_core::crypto::Pair;
+use sp_core::ecdsa;
 
 use crate::contracts;
 use crate::types::TxRef;
 use crate::contracts::{AccountIdWrapper};
 use super::TransactionStatus;
-
-extern crate runtime as chain;
+use crate::chain;
 
 pub type AssetId = u32;
+/// The id of an asset in pallet_assets
+pub type ChainAssetId = u32;
+type SequenceType = u64;
+type TxId = u64;
+
+/// The most decimals of an asset
+pub const MAX_DECIMALS: u8 = 18;
+/// The symbols of the assets bridged from pallet_assets, followed by their chain ids. Can't be
+/// issued by the users.
+pub const BRIDGED_SYMBOL_PREFIX: &str = "chain#";
+/// The most transactions kept in the history of an account, dropping the oldest first
+pub const MAX_HISTORY_LEN: usize = 1_000;
+/// The most transactions returned in a page of the history
+pub const MAX_PAGE_SIZE: u32 = 100;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AssetMetadata {
+    /// The admin who can mint, burn, freeze and destroy the asset
     owner: AccountIdWrapper,
     #[serde(with = "super::serde_balance")]
     total_supply: u128,
     symbol: String,
+    name: String,
+    decimals: u8,
     id: u32
 }
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -30,16 +50,78 @@ pub struct AssetMetadataBalance {
     balance: chain::Balance,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Assets {
     next_id: u32,
     assets: BTreeMap<u32, BTreeMap<AccountIdWrapper, chain::Balance>>,
     metadata: BTreeMap<u32, AssetMetadata>,
     history: BTreeMap<AccountIdWrapper, Vec<AssetsTx>>,
+    /// The accounts not allowed to transfer out of an asset
+    frozen: BTreeMap<AssetId, BTreeSet<AccountIdWrapper>>,
+    /// The accounts opted in to record their history
+    #[serde(default)]
+    tracked: BTreeSet<AccountIdWrapper>,
+    #[serde(default)]
+    next_tx_id: TxId,
+    /// The assets created for the deposits from pallet_assets
+    #[serde(default)]
+    bridged: BTreeMap<ChainAssetId, AssetId>,
+    #[serde(default)]
+    sequence: SequenceType,
+    /// The signed withdrawals waiting to be relayed to the chain
+    #[serde(default)]
+    queue: Vec<TransferData>,
+    #[serde(skip)]
+    id: Option<ecdsa::Pair>,
+}
+
+/// The state before the names, decimals and freezing were added
+#[derive(Deserialize)]
+struct AssetsV1 {
+    next_id: u32,
+    assets: BTreeMap<u32, BTreeMap<AccountIdWrapper, chain::Balance>>,
+    metadata: BTreeMap<u32, AssetMetadataV1>,
+}
+
+#[derive(Deserialize)]
+struct AssetMetadataV1 {
+    owner: AccountIdWrapper,
+    #[serde(with = "super::serde_balance")]
+    total_supply: u128,
+    symbol: String,
+    id: u32
+}
+/// A withdrawal to pallet_assets, encoded as `phala_types::AssetTransfer`
+#[derive(Serialize, Deserialize, Debug, Clone, Encode, Decode)]
+pub struct Transfer {
+    dest: AccountIdWrapper,
+    asset_id: ChainAssetId,
+    amount: chain::Balance,
+    sequence: SequenceType,
+}
+#[derive(Serialize, Deserialize, Debug, Clone, Encode, Decode)]
+pub struct TransferData {
+    data: Transfer,
+    signature: Vec<u8>,
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum AssetsTxKind {
+    Issue,
+    Destroy,
+    Mint,
+    Burn,
+    Transfer,
+    TransferToChain,
+}
+
+/// A transaction in the history of the tracked accounts. `from` is the owner for `Issue`, `Destroy`
+/// and `Mint`, and `to` is the owner for `Burn`.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AssetsTx {
+    id: TxId,
     txref: TxRef,
+    kind: AssetsTxKind,
     asset_id: u32,
     from: AccountIdWrapper,
     to: AccountIdWrapper,
@@ -67,17 +149,62 @@ pub enum Command {
     Issue {
         symbol: String,
         #[serde(with = "super::serde_balance")]
-        total: chain::Balance
+        total: chain::Balance,
+        #[serde(default)]
+        name: String,
+        #[serde(default)]
+        decimals: u8,
     },
+    /// Destroys an asset. Only allowed when no one but the owner holds it.
     Destroy {
         id: AssetId,
     },
+    /// Issues more of an asset to `dest`. Owner only.
+    Mint {
+        id: AssetId,
+        dest: AccountIdWrapper,
+        #[serde(with = "super::serde_balance")]
+        value: chain::Balance,
+    },
+    /// Burns the asset held by `who`. Owner only.
+    Burn {
+        id: AssetId,
+        who: AccountIdWrapper,
+        #[serde(with = "super::serde_balance")]
+        value: chain::Balance,
+    },
+    /// Stops `who` from transferring the asset, and from receiving it in transfers. The owner can
+    /// still mint to and burn from a frozen account. Owner only.
+    Freeze {
+        id: AssetId,
+        who: AccountIdWrapper,
+    },
+    Thaw {
+        id: AssetId,
+        who: AccountIdWrapper,
+    },
+    /// Hands the admin rights of an asset over. Owner only.
+    TransferOwnership {
+        id: AssetId,
+        owner: AccountIdWrapper,
+    },
     Transfer {
         id: AssetId,
         dest: AccountIdWrapper,
         #[serde(with = "super::serde_balance")]
         value: chain::Balance,
     },
+    /// Withdraws a bridged asset to `dest` in pallet_assets
+    TransferToChain {
+        id: AssetId,
+        dest: AccountIdWrapper,
+        #[serde(with = "super::serde_balance")]
+        value: chain::Balance,
+    },
+    /// Starts or stops recording the history of the origin. Stopping drops the recorded history.
+    SetTracking {
+        enabled: bool,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -89,13 +216,24 @@ pub enum Request {
     TotalSupply {
         id: AssetId
     },
+    /// The supply held by the accounts other than the owner
+    CirculatingSupply {
+        id: AssetId
+    },
     Metadata,
+    /// The history of `account` from the transaction `from` on, the newest first. Only the owner
+    /// of the account can query it.
     History {
-        account: AccountIdWrapper
+        account: AccountIdWrapper,
+        from: Option<TxId>,
+        limit: u32,
     },
     ListAssets {
         available_only :bool
     },
+    PendingChainTransfer {
+        sequence: SequenceType,
+    },
 }
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Response {
@@ -107,43 +245,305 @@ pub enum Response {
         #[serde(with = "super::serde_balance")]
         total_issuance: chain::Balance
     },
+    CirculatingSupply {
+        #[serde(with = "super::serde_balance")]
+        circulating_supply: chain::Balance
+    },
     Metadata {
         metadata: Vec<AssetMetadata>
     },
     History {
-        history: Vec<AssetsTx>
+        history: Vec<AssetsTx>,
+        /// The `from` of the next page, if any
+        next: Option<TxId>,
     },
     ListAssets {
         assets: Vec<AssetMetadataBalance>
     },
+    PendingChainTransfer {
+        transfer_queue_b64: String,
+    },
     Error(#[serde(with = "super::serde_anyhow")] anyhow::Error)
 }
 
-const ALICE: &'static str = "d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d";
-const SUPPLY: u128 = 1_024_000_000_000_000;
-const SYMBOL: &'static str = "TTT";
-
 impl Assets {
     pub fn new() -> Self{
-        let mut assets = BTreeMap::<u32, BTreeMap::<AccountIdWrapper, chain::Balance>>::new();
-        let mut metadata = BTreeMap::<u32, AssetMetadata>::new();
+        Assets {
+            next_id: 0,
+            assets: BTreeMap::new(),
+            metadata: BTreeMap::new(),
+            history: Default::default(),
+            frozen: BTreeMap::new(),
+            tracked: BTreeSet::new(),
+            next_tx_id: 0,
+            bridged: BTreeMap::new(),
+            sequence: 0,
+            queue: Vec::new(),
+            id: None,
+        }
+    }
 
-        let owner = AccountIdWrapper::from_hex(ALICE);
-        let symbol = String::from(SYMBOL);
+    /// Sets the identity key, which is not serialized with the states
+    pub fn set_id(&mut self, id: ecdsa::Pair) {
+        self.id = Some(id);
+    }
+
+    /// Issues an asset with all the supply held by the owner
+    pub fn issue(
+        &mut self,
+        owner: AccountIdWrapper,
+        symbol: String,
+        name: String,
+        decimals: u8,
+        total: chain::Balance
+    ) -> Result<AssetId, TransactionStatus> {
+        if decimals > MAX_DECIMALS {
+            return Err(TransactionStatus::BadDecimal);
+        }
+        if symbol.starts_with(BRIDGED_SYMBOL_PREFIX) || self.metadata.values().any(|metadatum| metadatum.symbol == symbol) {
+            return Err(TransactionStatus::SymbolExist);
+        }
         let mut accounts = BTreeMap::<AccountIdWrapper, chain::Balance>::new();
-        accounts.insert(owner.clone(), SUPPLY);
+        accounts.insert(owner.clone(), total);
+
+        let id = self.next_id;
+        let metadatum = AssetMetadata {
+            owner,
+            total_supply: total,
+            symbol,
+            name,
+            decimals,
+            id
+        };
+
+        self.metadata.insert(id, metadatum);
+        self.assets.insert(id, accounts);
+        self.next_id += 1;
+        Ok(id)
+    }
+
+    /// The metadata of an asset for its owner to change
+    fn owned_mut(&mut self, id: AssetId, origin: &AccountIdWrapper) -> Result<&mut AssetMetadata, TransactionStatus> {
+        let metadatum = self.metadata.get_mut(&id).ok_or(TransactionStatus::AssetIdNotFound)?;
+        if &metadatum.owner != origin {
+            return Err(TransactionStatus::NotAssetOwner);
+        }
+        Ok(metadatum)
+    }
+
+    fn is_frozen(&self, id: AssetId, who: &AccountIdWrapper) -> bool {
+        self.frozen.get(&id).map_or(false, |accounts| accounts.contains(who))
+    }
 
+    fn mint(&mut self, origin: &AccountIdWrapper, id: AssetId, dest: AccountIdWrapper, value: chain::Balance) -> Result<(), TransactionStatus> {
+        let metadatum = self.owned_mut(id, origin)?;
+        let total_supply = metadatum.total_supply.checked_add(value).ok_or(TransactionStatus::Overflow)?;
+        let accounts = self.assets.get_mut(&id).unwrap();
+        let dest_amount = accounts.entry(dest).or_default();
+        // Never overflows since the balance is within the total supply
+        *dest_amount += value;
+        self.metadata.get_mut(&id).unwrap().total_supply = total_supply;
+        Ok(())
+    }
+
+    /// The asset of a pallet_assets asset, created at its first deposit. It's owned by no one,
+    /// so that it can only be minted by deposits and burned by withdrawals.
+    fn bridged_asset(&mut self, chain_id: ChainAssetId) -> AssetId {
+        if let Some(id) = self.bridged.get(&chain_id) {
+            return *id;
+        }
+        let id = self.next_id;
+        let symbol = format!("{}{}", BRIDGED_SYMBOL_PREFIX, chain_id);
         let metadatum = AssetMetadata {
-            owner: owner.clone(),
-            total_supply: SUPPLY,
+            owner: AccountIdWrapper(chain::AccountId::new([0u8; 32])),
+            total_supply: 0,
+            name: symbol.clone(),
             symbol,
-            id: 0
+            decimals: 0,
+            id
+        };
+        self.metadata.insert(id, metadatum);
+        self.assets.insert(id, BTreeMap::new());
+        self.bridged.insert(chain_id, id);
+        self.next_id += 1;
+        id
+    }
+
+    fn transfer_to_chain(&mut self, origin: &AccountIdWrapper, id: AssetId, dest: AccountIdWrapper, value: chain::Balance) -> Result<(), TransactionStatus> {
+        let chain_id = self.bridged
+            .iter()
+            .find(|(_, asset_id)| **asset_id == id)
+            .map(|(chain_id, _)| *chain_id)
+            .ok_or(TransactionStatus::AssetNotBridged)?;
+        if self.is_frozen(id, origin) {
+            return Err(TransactionStatus::AccountFrozen);
+        }
+        let accounts = self.assets.get_mut(&id).unwrap();
+        let src_amount = accounts.get_mut(origin).ok_or(TransactionStatus::NoBalance)?;
+        if *src_amount < value {
+            return Err(TransactionStatus::InsufficientBalance);
+        }
+        let key = self.id.as_ref().ok_or(TransactionStatus::BadSecret)?;
+
+        let sequence = self.sequence + 1;
+        let data = Transfer {
+            dest,
+            asset_id: chain_id,
+            amount: value,
+            sequence,
         };
+        let sig = key.sign(&Encode::encode(&data));
+        *src_amount -= value;
+        self.metadata.get_mut(&id).unwrap().total_supply -= value;
+        self.queue.push(TransferData {
+            data,
+            signature: sig.0.to_vec(),
+        });
+        self.sequence = sequence;
+        Ok(())
+    }
+
+    /// Moves `value` of the asset from `origin` to `dest`. Both sides must not be frozen.
+    fn transfer(&mut self, origin: &AccountIdWrapper, id: AssetId, dest: &AccountIdWrapper, value: chain::Balance) -> Result<(), TransactionStatus> {
+        if !self.metadata.contains_key(&id) {
+            return Err(TransactionStatus::AssetIdNotFound);
+        }
+        if self.is_frozen(id, origin) || self.is_frozen(id, dest) {
+            return Err(TransactionStatus::AccountFrozen);
+        }
+        let accounts = self.assets.get_mut(&id).unwrap();
+        let src_amount = accounts.get_mut(origin).ok_or(TransactionStatus::NoBalance)?;
+        if *src_amount < value {
+            return Err(TransactionStatus::InsufficientBalance);
+        }
+        let src0 = *src_amount;
+        *src_amount -= value;
+        let dest_amount = accounts.entry(dest.clone()).or_default();
+        let dest0 = *dest_amount;
+        // Never overflows since the balance is within the total supply
+        *dest_amount += value;
+
+        info!("   src: {:>20} -> {:>20}", src0, src0 - value);
+        info!("  dest: {:>20} -> {:>20}", dest0, dest0 + value);
+        Ok(())
+    }
+
+    /// Appends a transaction to the history of the both sides if they are tracked
+    fn record(&mut self, txref: &TxRef, kind: AssetsTxKind, asset_id: AssetId, from: AccountIdWrapper, to: AccountIdWrapper, amount: chain::Balance) {
+        let tx = AssetsTx {
+            id: self.next_tx_id,
+            txref: txref.clone(),
+            kind,
+            asset_id,
+            from: from.clone(),
+            to: to.clone(),
+            amount
+        };
+        self.next_tx_id += 1;
+        let tracked = &self.tracked;
+        let history = &mut self.history;
+        let mut push = |account: AccountIdWrapper, tx: AssetsTx| {
+            if !tracked.contains(&account) {
+                return;
+            }
+            let slot = history.entry(account).or_default();
+            slot.push(tx);
+            if slot.len() > MAX_HISTORY_LEN {
+                slot.remove(0);
+            }
+        };
+        if from != to {
+            push(from, tx.clone());
+        }
+        push(to, tx);
+    }
+
+    /// The history of `account` from the transaction `from` on, the newest first. Returns the page
+    /// and the cursor of the next one.
+    fn history(&self, account: &AccountIdWrapper, from: Option<TxId>, limit: u32) -> (Vec<AssetsTx>, Option<TxId>) {
+        let limit = limit.min(MAX_PAGE_SIZE) as usize;
+        let history = match self.history.get(account) {
+            Some(history) => history,
+            None => return (Vec::new(), None),
+        };
+        let mut txs = history
+            .iter()
+            .rev()
+            .filter(|tx| from.map_or(true, |from| tx.id <= from));
+        let page: Vec<AssetsTx> = txs.by_ref().take(limit).cloned().collect();
+        let next = txs.next().map(|tx| tx.id);
+        (page, next)
+    }
 
-        metadata.insert(0, metadatum);
-        assets.insert(0, accounts);
+    fn burn(&mut self, origin: &AccountIdWrapper, id: AssetId, who: &AccountIdWrapper, value: chain::Balance) -> Result<(), TransactionStatus> {
+        self.owned_mut(id, origin)?;
+        let accounts = self.assets.get_mut(&id).unwrap();
+        let amount = accounts.get_mut(who).ok_or(TransactionStatus::NoBalance)?;
+        if *amount < value {
+            return Err(TransactionStatus::InsufficientBalance);
+        }
+        *amount -= value;
+        self.metadata.get_mut(&id).unwrap().total_supply -= value;
+        Ok(())
+    }
+}
 
-        Assets { next_id: 1, assets, metadata, history: Default::default() }
+impl fmt::Debug for Assets {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Assets")
+            .field("next_id", &self.next_id)
+            .field("assets", &self.assets)
+            .field("metadata", &self.metadata)
+            .field("history", &self.history)
+            .field("frozen", &self.frozen)
+            .field("tracked", &self.tracked)
+            .field("next_tx_id", &self.next_tx_id)
+            .field("bridged", &self.bridged)
+            .field("sequence", &self.sequence)
+            .field("queue", &self.queue)
+            .finish()
+    }
+}
+
+impl contracts::ContractState for Assets {
+    const SCHEMA_VERSION: u32 = 4;
+
+    fn migrate(version: u32, data: &[u8]) -> Result<Self> {
+        match version {
+            1 => {
+                let old: AssetsV1 = serde_cbor::from_slice(data)
+                    .map_err(|e| anyhow::Error::msg(e.to_string()))?;
+                let metadata = old.metadata
+                    .into_iter()
+                    .map(|(id, m)| (id, AssetMetadata {
+                        owner: m.owner,
+                        total_supply: m.total_supply,
+                        name: m.symbol.clone(),
+                        symbol: m.symbol,
+                        decimals: 0,
+                        id: m.id,
+                    }))
+                    .collect();
+                Ok(Assets {
+                    next_id: old.next_id,
+                    assets: old.assets,
+                    metadata,
+                    // No account could be tracked before
+                    history: BTreeMap::new(),
+                    frozen: BTreeMap::new(),
+                    tracked: BTreeSet::new(),
+                    next_tx_id: 0,
+                    bridged: BTreeMap::new(),
+                    sequence: 0,
+                    queue: Vec::new(),
+                    id: None,
+                })
+            }
+            // The bridge and the tracking states are added with their defaults. The history is
+            // always empty since no account could be tracked before.
+            2 | 3 => serde_cbor::from_slice(data).map_err(|e| anyhow::Error::msg(e.to_string())),
+            _ => Err(anyhow::Error::msg(format!("Can't migrate from schema version {}", version))),
+        }
     }
 }
 
@@ -152,98 +552,116 @@ impl contracts::Contract<Command, Request, Response> for Assets {
 
     fn handle_command(&mut self, origin: &chain::AccountId, txref: &TxRef, cmd: Command) -> TransactionStatus {
         match cmd {
-            Command::Issue {symbol, total} => {
+            Command::Issue {symbol, total, name, decimals} => {
                 let o = AccountIdWrapper(origin.clone());
                 info!("Issue: [{}] -> [{}]: {}", o.to_string(), symbol, total);
 
-                if let None = self.metadata.iter().find(|(_, metadatum)| metadatum.symbol == symbol) {
-                    let mut accounts = BTreeMap::<AccountIdWrapper, chain::Balance>::new();
-                    accounts.insert(o.clone(), total);
-
-                    let id = self.next_id;
-                    let metadatum = AssetMetadata {
-                        owner: o.clone(),
-                        total_supply: total,
-                        symbol,
-                        id
-                    };
-
-                    self.metadata.insert(id, metadatum);
-                    self.assets.insert(id.clone(), accounts);
-                    self.next_id += 1;
-
-                    TransactionStatus::Ok
-                } else {
-                    TransactionStatus::SymbolExist
+                match self.issue(o.clone(), symbol, name, decimals, total) {
+                    Ok(id) => {
+                        self.record(txref, AssetsTxKind::Issue, id, o.clone(), o, total);
+                        TransactionStatus::Ok
+                    }
+                    Err(status) => status,
                 }
             },
             Command::Destroy {id} => {
                 let o = AccountIdWrapper(origin.clone());
 
-                if let Some(metadatum) = self.metadata.get(&id) {
-                    if metadatum.owner.to_string() == o.to_string() {
-                        self.metadata.remove(&id);
-                        self.assets.remove(&id);
+                if let Err(status) = self.owned_mut(id, &o) {
+                    return status;
+                }
+                let accounts = self.assets.get(&id).unwrap();
+                if accounts.iter().any(|(account, balance)| account != &o && *balance > 0) {
+                    return TransactionStatus::DestroyNotAllowed;
+                }
+                let owned = accounts.get(&o).cloned().unwrap_or(0);
+                self.metadata.remove(&id);
+                self.assets.remove(&id);
+                self.frozen.remove(&id);
+                self.record(txref, AssetsTxKind::Destroy, id, o.clone(), o, owned);
 
+                TransactionStatus::Ok
+            },
+            Command::Mint {id, dest, value} => {
+                let o = AccountIdWrapper(origin.clone());
+                info!("Mint: [{}] -> [{}]: {}", id, dest.to_string(), value);
+                let result = self.mint(&o, id, dest.clone(), value);
+                if result.is_ok() {
+                    self.record(txref, AssetsTxKind::Mint, id, o, dest, value);
+                }
+                into_status(result)
+            },
+            Command::Burn {id, who, value} => {
+                let o = AccountIdWrapper(origin.clone());
+                info!("Burn: [{}] <- [{}]: {}", id, who.to_string(), value);
+                let result = self.burn(&o, id, &who, value);
+                if result.is_ok() {
+                    self.record(txref, AssetsTxKind::Burn, id, who, o, value);
+                }
+                into_status(result)
+            },
+            Command::Freeze {id, who} => {
+                let o = AccountIdWrapper(origin.clone());
+                info!("Freeze: [{}] [{}]", id, who.to_string());
+                if let Err(status) = self.owned_mut(id, &o) {
+                    return status;
+                }
+                self.frozen.entry(id).or_default().insert(who);
+                TransactionStatus::Ok
+            },
+            Command::Thaw {id, who} => {
+                let o = AccountIdWrapper(origin.clone());
+                info!("Thaw: [{}] [{}]", id, who.to_string());
+                if let Err(status) = self.owned_mut(id, &o) {
+                    return status;
+                }
+                if let Some(accounts) = self.frozen.get_mut(&id) {
+                    accounts.remove(&who);
+                    if accounts.is_empty() {
+                        self.frozen.remove(&id);
+                    }
+                }
+                TransactionStatus::Ok
+            },
+            Command::TransferOwnership {id, owner} => {
+                let o = AccountIdWrapper(origin.clone());
+                info!("TransferOwnership: [{}] [{}] -> [{}]", id, o.to_string(), owner.to_string());
+                match self.owned_mut(id, &o) {
+                    Ok(metadatum) => {
+                        metadatum.owner = owner;
                         TransactionStatus::Ok
-                    } else {
-                        TransactionStatus::NotAssetOwner
                     }
-                } else {
-                    TransactionStatus::AssetIdNotFound
+                    Err(status) => status,
                 }
             },
             Command::Transfer {id, dest, value} => {
                 let o = AccountIdWrapper(origin.clone());
-
-                if let Some(metadatum) = self.metadata.get(&id) {
-                    let accounts = self.assets.get_mut(&metadatum.id).unwrap();
-
-                    info!("Transfer: [{}] -> [{}]: {}", o.to_string(), dest.to_string(), value);
-                    if let Some(src_amount) = accounts.get_mut(&o) {
-                        if *src_amount >= value {
-                            let src0 = *src_amount;
-                            let mut dest0 = 0;
-
-                            *src_amount -= value;
-                            if let Some(dest_amount) = accounts.get_mut(&dest) {
-                                dest0 = *dest_amount;
-                                *dest_amount += value;
-                            } else {
-                                accounts.insert(dest.clone(), value);
-                            }
-
-                            info!("   src: {:>20} -> {:>20}", src0, src0 - value);
-                            info!("  dest: {:>20} -> {:>20}", dest0, dest0 + value);
-
-                            let tx = AssetsTx {
-                                txref: txref.clone(),
-                                asset_id: id,
-                                from: o.clone(),
-                                to: dest.clone(),
-                                amount: value
-                            };
-                            if is_tracked(&o) {
-                                let slot = self.history.entry(o).or_default();
-                                slot.push(tx.clone());
-                                info!(" pushed history (src)");
-                            }
-                            if is_tracked(&dest) {
-                                let slot = self.history.entry(dest).or_default();
-                                slot.push(tx.clone());
-                                info!(" pushed history (dest)");
-                            }
-
-                            TransactionStatus::Ok
-                        } else {
-                            TransactionStatus::InsufficientBalance
-                        }
-                    } else {
-                        TransactionStatus::NoBalance
-                    }
+                info!("Transfer: [{}] [{}] -> [{}]: {}", id, o.to_string(), dest.to_string(), value);
+                let result = self.transfer(&o, id, &dest, value);
+                if result.is_ok() {
+                    self.record(txref, AssetsTxKind::Transfer, id, o, dest, value);
+                }
+                into_status(result)
+            },
+            Command::TransferToChain {id, dest, value} => {
+                let o = AccountIdWrapper(origin.clone());
+                info!("Transfer to chain: [{}] [{}] -> [{}]: {}", id, o.to_string(), dest.to_string(), value);
+                let result = self.transfer_to_chain(&o, id, dest.clone(), value);
+                if result.is_ok() {
+                    self.record(txref, AssetsTxKind::TransferToChain, id, o, dest, value);
+                }
+                into_status(result)
+            },
+            Command::SetTracking {enabled} => {
+                let o = AccountIdWrapper(origin.clone());
+                info!("SetTracking: [{}] {}", o.to_string(), enabled);
+                if enabled {
+                    self.tracked.insert(o);
                 } else {
-                    TransactionStatus::AssetIdNotFound
+                    self.tracked.remove(&o);
+                    self.history.remove(&o);
                 }
+                TransactionStatus::Ok
             }
         }
     }
@@ -274,12 +692,24 @@ impl contracts::Contract<Command, Request, Response> for Assets {
                         Err(anyhow::Error::msg(Error::Other(String::from("Asset not found"))))
                     }
                 },
+                Request::CirculatingSupply { id } => {
+                    if let Some(metadatum) = self.metadata.get(&id) {
+                        let accounts = self.assets.get(&id).unwrap();
+                        let owned = accounts.get(&metadatum.owner).cloned().unwrap_or(0);
+                        Ok(Response::CirculatingSupply { circulating_supply: metadatum.total_supply - owned })
+                    } else {
+                        Err(anyhow::Error::msg(Error::Other(String::from("Asset not found"))))
+                    }
+                },
                 Request::Metadata => {
                     Ok(Response::Metadata { metadata: self.metadata.values().cloned().collect() })
                 },
-                Request::History { account } => {
-                    let tx_list = self.history.get(&account).cloned().unwrap_or(Default::default());
-                    Ok(Response::History { history: tx_list })
+                Request::History { account, from, limit } => {
+                    if origin == None || origin.unwrap() != &account.0 {
+                        return Err(anyhow::Error::msg(Error::NotAuthorized));
+                    }
+                    let (history, next) = self.history(&account, from, limit);
+                    Ok(Response::History { history, next })
                 },
                 Request::ListAssets { available_only } => {
                     let raw_origin = origin.ok_or_else(|| anyhow::Error::msg(Error::NotAuthorized))?;
@@ -313,6 +743,15 @@ impl contracts::Contract<Command, Request, Response> for Assets {
                             .collect()
                     };
                     Ok(Response::ListAssets {assets})
+                },
+                Request::PendingChainTransfer { sequence } => {
+                    let transfer_queue: Vec<&TransferData> = self.queue
+                        .iter()
+                        .filter(|x| x.data.sequence > sequence)
+                        .collect();
+                    Ok(Response::PendingChainTransfer {
+                        transfer_queue_b64: base64::encode(&transfer_queue.encode()),
+                    })
                 }
             }
         };
@@ -321,8 +760,315 @@ impl contracts::Contract<Command, Request, Response> for Assets {
             Ok(resp) => resp
         }
     }
+
+    fn handle_event(&mut self, message: &chain::ChainMessage) {
+        match message {
+            ChainMessage::AssetTransferToTee { who, asset_id, amount } => {
+                info!("AssetTransferToTee from: {:?}, [{}]: {}", who, asset_id, amount);
+                let id = self.bridged_asset(*asset_id);
+                let dest_amount = self.assets.get_mut(&id).unwrap().entry(AccountIdWrapper(who.clone())).or_default();
+                *dest_amount += *amount;
+                self.metadata.get_mut(&id).unwrap().total_supply += *amount;
+            },
+            ChainMessage::AssetTransferToChain { who, asset_id, amount, sequence } => {
+                info!("AssetTransferToChain who: {:?}, [{}]: {}", who, asset_id, amount);
+                self.queue.retain(|x| x.data.sequence > *sequence);
+                info!("queue len: {:}", self.queue.len());
+            },
+            _ => (),
+        }
+    }
 }
 
-fn is_tracked(_id: &AccountIdWrapper) -> bool {
-    false
+fn into_status(result: Result<(), TransactionStatus>) -> TransactionStatus {
+    match result {
+        Ok(()) => TransactionStatus::Ok,
+        Err(status) => status,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::contracts::test_helpers::{account, txref};
+    use crate::contracts::{Contract, ContractState};
+
+    /// Issues asset 0 with 1000 held by account 1
+    fn issued() -> Assets {
+        let mut assets = Assets::new();
+        let cmd = Command::Issue {
+            symbol: String::from("PHA"),
+            total: 1000,
+            name: String::from("Phala"),
+            decimals: 12,
+        };
+        assert!(matches!(assets.handle_command(&account(1), &txref(0), cmd), TransactionStatus::Ok));
+        assets
+    }
+
+    fn balance(assets: &mut Assets, who: &chain::AccountId) -> chain::Balance {
+        let req = Request::Balance { id: 0, account: AccountIdWrapper(who.clone()) };
+        match assets.handle_query(Some(who), req) {
+            Response::Balance { balance } => balance,
+            _ => panic!("Unexpected response"),
+        }
+    }
+
+    fn supply(assets: &mut Assets) -> (chain::Balance, chain::Balance) {
+        let total = match assets.handle_query(None, Request::TotalSupply { id: 0 }) {
+            Response::TotalSupply { total_issuance } => total_issuance,
+            _ => panic!("Unexpected response"),
+        };
+        let circulating = match assets.handle_query(None, Request::CirculatingSupply { id: 0 }) {
+            Response::CirculatingSupply { circulating_supply } => circulating_supply,
+            _ => panic!("Unexpected response"),
+        };
+        (total, circulating)
+    }
+
+    fn transfer(dest: &chain::AccountId, value: chain::Balance) -> Command {
+        Command::Transfer { id: 0, dest: AccountIdWrapper(dest.clone()), value }
+    }
+
+    #[test]
+    fn test_issue_decimals() {
+        let mut assets = issued();
+        let cmd = Command::Issue {
+            symbol: String::from("BAD"),
+            total: 1,
+            name: String::new(),
+            decimals: MAX_DECIMALS + 1,
+        };
+        let status = assets.handle_command(&account(1), &txref(0), cmd);
+        assert!(matches!(status, TransactionStatus::BadDecimal));
+        let metadatum = &assets.metadata[&0];
+        assert_eq!((metadatum.name.as_str(), metadatum.decimals), ("Phala", 12));
+    }
+
+    #[test]
+    fn test_mint_burn() {
+        let mut assets = issued();
+        let (owner, user) = (account(1), account(2));
+        let mint = Command::Mint { id: 0, dest: AccountIdWrapper(user.clone()), value: 500 };
+        let status = assets.handle_command(&user, &txref(0), mint);
+        assert!(matches!(status, TransactionStatus::NotAssetOwner));
+
+        let mint = Command::Mint { id: 0, dest: AccountIdWrapper(user.clone()), value: 500 };
+        let status = assets.handle_command(&owner, &txref(0), mint);
+        assert!(matches!(status, TransactionStatus::Ok));
+        assert_eq!(balance(&mut assets, &user), 500);
+        assert_eq!(supply(&mut assets), (1500, 500));
+
+        let burn = Command::Burn { id: 0, who: AccountIdWrapper(user.clone()), value: 600 };
+        let status = assets.handle_command(&owner, &txref(0), burn);
+        assert!(matches!(status, TransactionStatus::InsufficientBalance));
+        let burn = Command::Burn { id: 0, who: AccountIdWrapper(user.clone()), value: 200 };
+        let status = assets.handle_command(&owner, &txref(0), burn);
+        assert!(matches!(status, TransactionStatus::Ok));
+        assert_eq!(balance(&mut assets, &user), 300);
+        assert_eq!(supply(&mut assets), (1300, 300));
+
+        let mint = Command::Mint { id: 0, dest: AccountIdWrapper(user.clone()), value: u128::max_value() };
+        let status = assets.handle_command(&owner, &txref(0), mint);
+        assert!(matches!(status, TransactionStatus::Overflow));
+        assert_eq!(supply(&mut assets), (1300, 300));
+    }
+
+    #[test]
+    fn test_freeze_thaw() {
+        let mut assets = issued();
+        let (owner, user) = (account(1), account(2));
+        assets.handle_command(&owner, &txref(0), transfer(&user, 100));
+
+        let freeze = Command::Freeze { id: 0, who: AccountIdWrapper(user.clone()) };
+        let status = assets.handle_command(&user, &txref(0), freeze);
+        assert!(matches!(status, TransactionStatus::NotAssetOwner));
+        let freeze = Command::Freeze { id: 0, who: AccountIdWrapper(user.clone()) };
+        assets.handle_command(&owner, &txref(0), freeze);
+        let status = assets.handle_command(&user, &txref(0), transfer(&owner, 10));
+        assert!(matches!(status, TransactionStatus::AccountFrozen));
+        // Nor receive it, but the owner can still mint to and burn from them
+        let status = assets.handle_command(&owner, &txref(0), transfer(&user, 10));
+        assert!(matches!(status, TransactionStatus::AccountFrozen));
+        assert_eq!(balance(&mut assets, &owner), 900);
+        let mint = Command::Mint { id: 0, dest: AccountIdWrapper(user.clone()), value: 10 };
+        let status = assets.handle_command(&owner, &txref(0), mint);
+        assert!(matches!(status, TransactionStatus::Ok));
+        let burn = Command::Burn { id: 0, who: AccountIdWrapper(user.clone()), value: 10 };
+        let status = assets.handle_command(&owner, &txref(0), burn);
+        assert!(matches!(status, TransactionStatus::Ok));
+
+        let thaw = Command::Thaw { id: 0, who: AccountIdWrapper(user.clone()) };
+        assets.handle_command(&owner, &txref(0), thaw);
+        assert!(assets.frozen.is_empty());
+        let status = assets.handle_command(&user, &txref(0), transfer(&owner, 10));
+        assert!(matches!(status, TransactionStatus::Ok));
+        assert_eq!(balance(&mut assets, &user), 90);
+        let status = assets.handle_command(&owner, &txref(0), transfer(&user, 10));
+        assert!(matches!(status, TransactionStatus::Ok));
+    }
+
+    #[test]
+    fn test_transfer_ownership_and_destroy() {
+        let mut assets = issued();
+        let (owner, user) = (account(1), account(2));
+        assets.handle_command(&owner, &txref(0), transfer(&user, 100));
+
+        let handover = Command::TransferOwnership { id: 0, owner: AccountIdWrapper(user.clone()) };
+        let status = assets.handle_command(&owner, &txref(0), handover);
+        assert!(matches!(status, TransactionStatus::Ok));
+        assert_eq!(supply(&mut assets), (1000, 900));
+        let mint = Command::Mint { id: 0, dest: AccountIdWrapper(owner.clone()), value: 1 };
+        let status = assets.handle_command(&owner, &txref(0), mint);
+        assert!(matches!(status, TransactionStatus::NotAssetOwner));
+
+        // The former owner still holds the asset
+        let status = assets.handle_command(&user, &txref(0), Command::Destroy { id: 0 });
+        assert!(matches!(status, TransactionStatus::DestroyNotAllowed));
+        let burn = Command::Burn { id: 0, who: AccountIdWrapper(owner.clone()), value: 900 };
+        assets.handle_command(&user, &txref(0), burn);
+        let status = assets.handle_command(&user, &txref(0), Command::Destroy { id: 0 });
+        assert!(matches!(status, TransactionStatus::Ok));
+        assert!(assets.metadata.is_empty());
+    }
+
+    #[test]
+    fn test_bridge() {
+        let mut assets = Assets::new();
+        let key = ecdsa::Pair::from_seed(&[1u8; 32]);
+        let user = account(2);
+        let deposit = ChainMessage::AssetTransferToTee { who: user.clone(), asset_id: 7, amount: 100 };
+        assets.handle_event(&deposit);
+        assets.handle_event(&deposit);
+        assert_eq!(balance(&mut assets, &user), 200);
+        assert_eq!(supply(&mut assets), (200, 200));
+        assert_eq!(assets.metadata[&0].symbol, "chain#7");
+        // The bridged symbols are reserved
+        let issue = Command::Issue { symbol: String::from("chain#8"), total: 1, name: String::new(), decimals: 0 };
+        let status = assets.handle_command(&user, &txref(0), issue);
+        assert!(matches!(status, TransactionStatus::SymbolExist));
+        // And no one can mint them
+        let mint = Command::Mint { id: 0, dest: AccountIdWrapper(user.clone()), value: 1 };
+        let status = assets.handle_command(&user, &txref(0), mint);
+        assert!(matches!(status, TransactionStatus::NotAssetOwner));
+
+        let withdraw = |value| Command::TransferToChain { id: 0, dest: AccountIdWrapper(account(3)), value };
+        let status = assets.handle_command(&user, &txref(0), withdraw(30));
+        assert!(matches!(status, TransactionStatus::BadSecret));
+        assets.set_id(key.clone());
+        let status = assets.handle_command(&user, &txref(0), withdraw(300));
+        assert!(matches!(status, TransactionStatus::InsufficientBalance));
+        let status = assets.handle_command(&user, &txref(0), withdraw(30));
+        assert!(matches!(status, TransactionStatus::Ok));
+        assert_eq!(balance(&mut assets, &user), 170);
+        assert_eq!(supply(&mut assets), (170, 170));
+
+        // The queue is signed in the format of pallet_phala
+        let queue = match assets.handle_query(None, Request::PendingChainTransfer { sequence: 0 }) {
+            Response::PendingChainTransfer { transfer_queue_b64 } => base64::decode(&transfer_queue_b64).unwrap(),
+            _ => panic!("Unexpected response"),
+        };
+        let queue: Vec<phala_types::AssetTransferData<chain::AccountId>> = Decode::decode(&mut &queue[..]).unwrap();
+        assert_eq!(queue.len(), 1);
+        let transfer = &queue[0];
+        assert_eq!((transfer.data.asset_id, transfer.data.amount, transfer.data.sequence), (7, 30, 1));
+        let mut raw_sig = [0u8; 65];
+        raw_sig.copy_from_slice(&transfer.signature);
+        let sig = ecdsa::Signature::from_raw(raw_sig);
+        assert!(ecdsa::Pair::verify(&sig, &transfer.data.encode(), &key.public()));
+
+        // Removed from the queue once executed on the chain
+        let executed = ChainMessage::AssetTransferToChain { who: account(3), asset_id: 7, amount: 30, sequence: 1 };
+        assets.handle_event(&executed);
+        assert!(assets.queue.is_empty());
+
+        // Only the bridged assets can be withdrawn
+        let issue = Command::Issue { symbol: String::from("PHA"), total: 1000, name: String::new(), decimals: 0 };
+        assets.handle_command(&user, &txref(0), issue);
+        let withdraw = Command::TransferToChain { id: 1, dest: AccountIdWrapper(user.clone()), value: 1 };
+        let status = assets.handle_command(&user, &txref(0), withdraw);
+        assert!(matches!(status, TransactionStatus::AssetNotBridged));
+    }
+
+    #[test]
+    fn test_history() {
+        let (owner, user) = (account(1), account(2));
+        let mut assets = Assets::new();
+        for who in [&owner, &user].iter() {
+            assets.handle_command(who, &txref(0), Command::SetTracking { enabled: true });
+        }
+        let issue = Command::Issue { symbol: String::from("PHA"), total: 1000, name: String::new(), decimals: 0 };
+        assets.handle_command(&owner, &txref(0), issue);
+        assets.handle_command(&owner, &txref(0), transfer(&user, 100));
+        let mint = Command::Mint { id: 0, dest: AccountIdWrapper(user.clone()), value: 10 };
+        assets.handle_command(&owner, &txref(0), mint);
+        // Failed transactions are not recorded
+        assets.handle_command(&user, &txref(0), transfer(&owner, 1000));
+
+        let o = AccountIdWrapper(owner.clone());
+        let kinds = |page: &[AssetsTx]| -> Vec<AssetsTxKind> { page.iter().map(|tx| tx.kind.clone()).collect() };
+        let (page, next) = assets.history(&o, None, 2);
+        assert_eq!(kinds(&page), vec![AssetsTxKind::Mint, AssetsTxKind::Transfer]);
+        let (page, next) = assets.history(&o, next, 2);
+        assert_eq!(kinds(&page), vec![AssetsTxKind::Issue]);
+        assert_eq!(next, None);
+        let (page, _) = assets.history(&AccountIdWrapper(user.clone()), None, 10);
+        assert_eq!(kinds(&page), vec![AssetsTxKind::Mint, AssetsTxKind::Transfer]);
+
+        // Only the owner can query the history
+        let req = Request::History { account: o.clone(), from: None, limit: 10 };
+        let resp = assets.handle_query(Some(&user), req.clone());
+        assert!(matches!(resp, Response::Error(_)));
+        let resp = assets.handle_query(Some(&owner), req);
+        assert!(matches!(resp, Response::History { .. }));
+
+        // Opting out drops the history and stops recording
+        assets.handle_command(&user, &txref(0), Command::SetTracking { enabled: false });
+        assets.handle_command(&owner, &txref(0), transfer(&user, 1));
+        assert!(assets.history(&AccountIdWrapper(user.clone()), None, 10).0.is_empty());
+        assert_eq!(assets.history(&o, None, 10).0.len(), 4);
+
+        // Only the latest transactions are kept
+        for _ in 0..MAX_HISTORY_LEN {
+            assets.handle_command(&owner, &txref(0), transfer(&user, 1));
+        }
+        assert_eq!(assets.history[&o].len(), MAX_HISTORY_LEN);
+        assert_eq!(assets.history[&o][0].kind, AssetsTxKind::Transfer);
+    }
+
+    #[test]
+    fn test_migrate_v1() {
+        #[derive(Serialize)]
+        struct MetadataV1 {
+            owner: AccountIdWrapper,
+            #[serde(with = "crate::contracts::serde_balance")]
+            total_supply: u128,
+            symbol: String,
+            id: u32,
+        }
+        #[derive(Serialize)]
+        struct StateV1 {
+            next_id: u32,
+            assets: BTreeMap<u32, BTreeMap<AccountIdWrapper, chain::Balance>>,
+            metadata: BTreeMap<u32, MetadataV1>,
+            history: BTreeMap<AccountIdWrapper, Vec<AssetsTx>>,
+        }
+        let owner = AccountIdWrapper(account(1));
+        let mut balances = BTreeMap::new();
+        balances.insert(owner.clone(), 1000);
+        let mut state = StateV1 {
+            next_id: 1,
+            assets: BTreeMap::new(),
+            metadata: BTreeMap::new(),
+            history: BTreeMap::new(),
+        };
+        state.assets.insert(0, balances);
+        state.metadata.insert(0, MetadataV1 { owner, total_supply: 1000, symbol: String::from("TTT"), id: 0 });
+        let data = serde_cbor::to_vec(&state).unwrap();
+
+        let assets = Assets::migrate(1, &data).unwrap();
+        let metadatum = &assets.metadata[&0];
+        assert_eq!((metadatum.name.as_str(), metadatum.decimals), ("TTT", 0));
+        assert_eq!(assets.next_id, 1);
+    }
 }
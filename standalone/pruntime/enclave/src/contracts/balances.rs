@@ -6,19 +6,24 @@ use anyhow::Result;
 use core::{fmt, str};
 use log::{debug, info};
 use parity_scale_codec::{Decode, Encode};
+use phala_types::ChainMessage;
 use serde::{Deserialize, Serialize};
 use sp_core::crypto::Pair;
 use sp_core::ecdsa;
 
+use crate::chain;
 use crate::contracts;
 use crate::contracts::AccountIdWrapper;
 use crate::types::TxRef;
 use crate::TransactionStatus;
-extern crate runtime as chain;
-
-const ALICE: &'static str = "d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d";
 
 type SequenceType = u64;
+type TxId = u64;
+
+/// The most transfers kept in the history of an account, dropping the oldest first
+pub const MAX_HISTORY_LEN: usize = 1_000;
+/// The most transfers returned in a page of the history
+pub const MAX_PAGE_SIZE: u32 = 100;
 
 #[derive(Serialize, Deserialize)]
 pub struct Balances {
@@ -26,10 +31,36 @@ pub struct Balances {
     accounts: BTreeMap<AccountIdWrapper, chain::Balance>,
     sequence: SequenceType,
     queue: Vec<TransferData>,
+    /// owner -> spender -> the amount the spender can still transfer from the owner
+    allowances: BTreeMap<AccountIdWrapper, BTreeMap<AccountIdWrapper, chain::Balance>>,
+    /// The transfers of each account, the oldest first
+    history: BTreeMap<AccountIdWrapper, Vec<BalancesTx>>,
+    next_tx_id: TxId,
     #[serde(skip)]
     id: Option<ecdsa::Pair>,
 }
 
+/// The state before the allowances and the history were added
+#[derive(Deserialize)]
+struct BalancesV1 {
+    total_issuance: chain::Balance,
+    accounts: BTreeMap<AccountIdWrapper, chain::Balance>,
+    sequence: SequenceType,
+    queue: Vec<TransferData>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BalancesTx {
+    id: TxId,
+    txref: TxRef,
+    from: AccountIdWrapper,
+    to: AccountIdWrapper,
+    /// The spender of a `TransferFrom`
+    spender: Option<AccountIdWrapper>,
+    #[serde(with = "super::serde_balance")]
+    amount: chain::Balance,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Error {
     NotAuthorized,
@@ -52,6 +83,19 @@ pub enum Command {
         #[serde(with = "super::serde_balance")]
         value: chain::Balance,
     },
+    /// Allows `spender` to transfer up to `value` from the origin, replacing the former allowance
+    Approve {
+        spender: AccountIdWrapper,
+        #[serde(with = "super::serde_balance")]
+        value: chain::Balance,
+    },
+    /// Transfers from `owner` to `dest` within the allowance of the origin
+    TransferFrom {
+        owner: AccountIdWrapper,
+        dest: AccountIdWrapper,
+        #[serde(with = "super::serde_balance")]
+        value: chain::Balance,
+    },
     TransferToChain {
         dest: AccountIdWrapper,
         #[serde(with = "super::serde_balance")]
@@ -61,9 +105,25 @@ pub enum Command {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Request {
-    FreeBalance { account: AccountIdWrapper },
+    FreeBalance {
+        account: AccountIdWrapper,
+    },
     TotalIssuance,
-    PendingChainTransfer { sequence: SequenceType },
+    PendingChainTransfer {
+        sequence: SequenceType,
+    },
+    /// Only the owner or the spender can query it
+    Allowance {
+        owner: AccountIdWrapper,
+        spender: AccountIdWrapper,
+    },
+    /// The transfers of `account` older than `before`, the newest first. Only the owner of the
+    /// account can query it.
+    History {
+        account: AccountIdWrapper,
+        before: Option<TxId>,
+        limit: u32,
+    },
 }
 #[derive(Serialize, Deserialize, Debug, Clone, Encode, Decode)]
 pub struct Transfer {
@@ -89,23 +149,182 @@ pub enum Response {
     PendingChainTransfer {
         transfer_queue_b64: String,
     },
+    Allowance {
+        #[serde(with = "super::serde_balance")]
+        allowance: chain::Balance,
+    },
+    History {
+        history: Vec<BalancesTx>,
+        /// The `before` of the next page, if any
+        next: Option<TxId>,
+    },
     Error(#[serde(with = "super::serde_anyhow")] anyhow::Error),
 }
 
-const SUPPLY: u128 = 0;
-
 impl Balances {
     pub fn new(id: Option<ecdsa::Pair>) -> Self {
-        let mut accounts = BTreeMap::<AccountIdWrapper, chain::Balance>::new();
-        accounts.insert(AccountIdWrapper::from_hex(ALICE), SUPPLY);
         Balances {
             total_issuance: 0,
-            accounts,
+            accounts: BTreeMap::new(),
             sequence: 0,
             queue: Vec::new(),
+            allowances: BTreeMap::new(),
+            history: BTreeMap::new(),
+            next_tx_id: 0,
             id,
         }
     }
+
+    /// Credits an account at genesis, counted in the total issuance
+    pub fn deposit_genesis(&mut self, account: AccountIdWrapper, amount: chain::Balance) {
+        *self.accounts.entry(account).or_default() += amount;
+        self.total_issuance += amount;
+    }
+
+    fn transfer(
+        &mut self,
+        from: &AccountIdWrapper,
+        dest: &AccountIdWrapper,
+        value: chain::Balance,
+    ) -> TransactionStatus {
+        if let Some(src_amount) = self.accounts.get_mut(from) {
+            if *src_amount >= value {
+                let src0 = *src_amount;
+                let mut dest0 = 0;
+
+                *src_amount -= value;
+                if let Some(dest_amount) = self.accounts.get_mut(dest) {
+                    dest0 = *dest_amount;
+                    *dest_amount += value;
+                } else {
+                    self.accounts.insert(dest.clone(), value);
+                }
+
+                info!("   src: {:>20} -> {:>20}", src0, src0 - value);
+                info!("  dest: {:>20} -> {:>20}", dest0, dest0 + value);
+
+                TransactionStatus::Ok
+            } else {
+                TransactionStatus::InsufficientBalance
+            }
+        } else {
+            TransactionStatus::NoBalance
+        }
+    }
+
+    /// Transfers on behalf of another contract, recorded in the history like a `Transfer`
+    pub fn pay(
+        &mut self,
+        txref: &TxRef,
+        from: &AccountIdWrapper,
+        dest: &AccountIdWrapper,
+        value: chain::Balance,
+    ) -> TransactionStatus {
+        let status = self.transfer(from, dest, value);
+        if let TransactionStatus::Ok = status {
+            self.record(txref, from.clone(), dest.clone(), None, value);
+        }
+        status
+    }
+
+    fn allowance(&self, owner: &AccountIdWrapper, spender: &AccountIdWrapper) -> chain::Balance {
+        self.allowances
+            .get(owner)
+            .and_then(|spenders| spenders.get(spender))
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    /// Appends a transfer to the history of the both sides
+    fn record(
+        &mut self,
+        txref: &TxRef,
+        from: AccountIdWrapper,
+        to: AccountIdWrapper,
+        spender: Option<AccountIdWrapper>,
+        amount: chain::Balance,
+    ) {
+        let tx = BalancesTx {
+            id: self.next_tx_id,
+            txref: txref.clone(),
+            from: from.clone(),
+            to: to.clone(),
+            spender,
+            amount,
+        };
+        self.next_tx_id += 1;
+        let mut push = |account: AccountIdWrapper, tx: BalancesTx| {
+            let history = self.history.entry(account).or_default();
+            history.push(tx);
+            if history.len() > MAX_HISTORY_LEN {
+                history.remove(0);
+            }
+        };
+        if from != to {
+            push(from, tx.clone());
+        }
+        push(to, tx);
+    }
+
+    /// The transfers of `account` older than `before`, the newest first. Returns the page and the
+    /// cursor of the next one.
+    fn history(
+        &self,
+        account: &AccountIdWrapper,
+        before: Option<TxId>,
+        limit: u32,
+    ) -> (Vec<BalancesTx>, Option<TxId>) {
+        let limit = limit.min(MAX_PAGE_SIZE) as usize;
+        let page: Vec<BalancesTx> = match self.history.get(account) {
+            Some(history) => history
+                .iter()
+                .rev()
+                .filter(|tx| before.map_or(true, |before| tx.id < before))
+                .take(limit)
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+        let next = match page.last() {
+            Some(last) if page.len() == limit => Some(last.id),
+            _ => None,
+        };
+        (page, next)
+    }
+
+    /// Sets the identity key, which is not serialized with the states
+    pub fn set_id(&mut self, id: ecdsa::Pair) {
+        self.id = Some(id);
+    }
+}
+
+impl contracts::ContractState for Balances {
+    const SCHEMA_VERSION: u32 = 3;
+
+    // The memos of the transfers in the history are dropped from version 2
+    fn migrate(version: u32, data: &[u8]) -> Result<Self> {
+        match version {
+            1 => {
+                let old: BalancesV1 =
+                    serde_cbor::from_slice(data).map_err(|e| anyhow::Error::msg(e.to_string()))?;
+                Ok(Balances {
+                    total_issuance: old.total_issuance,
+                    accounts: old.accounts,
+                    sequence: old.sequence,
+                    queue: old.queue,
+                    allowances: BTreeMap::new(),
+                    history: BTreeMap::new(),
+                    next_tx_id: 0,
+                    id: None,
+                })
+            }
+            2 => serde_cbor::from_slice(data).map_err(|e| anyhow::Error::msg(e.to_string())),
+            _ => Err(anyhow::Error::msg(format!(
+                "Can't migrate from schema version {}",
+                version
+            ))),
+        }
+    }
 }
 
 impl contracts::Contract<Command, Request, Response> for Balances {
@@ -116,7 +335,7 @@ impl contracts::Contract<Command, Request, Response> for Balances {
     fn handle_command(
         &mut self,
         origin: &chain::AccountId,
-        _txref: &TxRef,
+        txref: &TxRef,
         cmd: Command,
     ) -> TransactionStatus {
         let status = match cmd {
@@ -128,29 +347,59 @@ impl contracts::Contract<Command, Request, Response> for Balances {
                     dest.to_string(),
                     value
                 );
-                if let Some(src_amount) = self.accounts.get_mut(&o) {
-                    if *src_amount >= value {
-                        let src0 = *src_amount;
-                        let mut dest0 = 0;
-
-                        *src_amount -= value;
-                        if let Some(dest_amount) = self.accounts.get_mut(&dest) {
-                            dest0 = *dest_amount;
-                            *dest_amount += value;
+                let status = self.transfer(&o, &dest, value);
+                if let TransactionStatus::Ok = status {
+                    self.record(txref, o, dest, None, value);
+                }
+                status
+            }
+            Command::Approve { spender, value } => {
+                let o = AccountIdWrapper(origin.clone());
+                info!(
+                    "Approve: [{}] -> [{}]: {}",
+                    o.to_string(),
+                    spender.to_string(),
+                    value
+                );
+                let spenders = self.allowances.entry(o.clone()).or_default();
+                if value == 0 {
+                    spenders.remove(&spender);
+                    if spenders.is_empty() {
+                        self.allowances.remove(&o);
+                    }
+                } else {
+                    spenders.insert(spender, value);
+                }
+                TransactionStatus::Ok
+            }
+            Command::TransferFrom { owner, dest, value } => {
+                let o = AccountIdWrapper(origin.clone());
+                info!(
+                    "TransferFrom: [{}] -> [{}] by [{}]: {}",
+                    owner.to_string(),
+                    dest.to_string(),
+                    o.to_string(),
+                    value
+                );
+                let allowance = self.allowance(&owner, &o);
+                if allowance < value {
+                    return TransactionStatus::InsufficientAllowance;
+                }
+                let status = self.transfer(&owner, &dest, value);
+                if let TransactionStatus::Ok = status {
+                    if let Some(spenders) = self.allowances.get_mut(&owner) {
+                        if allowance == value {
+                            spenders.remove(&o);
+                            if spenders.is_empty() {
+                                self.allowances.remove(&owner);
+                            }
                         } else {
-                            self.accounts.insert(dest, value);
+                            spenders.insert(o.clone(), allowance - value);
                         }
-
-                        info!("   src: {:>20} -> {:>20}", src0, src0 - value);
-                        info!("  dest: {:>20} -> {:>20}", dest0, dest0 + value);
-
-                        TransactionStatus::Ok
-                    } else {
-                        TransactionStatus::InsufficientBalance
                     }
-                } else {
-                    TransactionStatus::NoBalance
+                    self.record(txref, owner, dest, Some(o), value);
                 }
+                status
             }
             Command::TransferToChain { dest, value } => {
                 let o = AccountIdWrapper(origin.clone());
@@ -228,6 +477,26 @@ impl contracts::Contract<Command, Request, Response> for Balances {
                 Request::TotalIssuance => Ok(Response::TotalIssuance {
                     total_issuance: self.total_issuance,
                 }),
+                Request::Allowance { owner, spender } => {
+                    match origin {
+                        Some(o) if o == &owner.0 || o == &spender.0 => (),
+                        _ => return Err(anyhow::Error::msg(Error::NotAuthorized)),
+                    }
+                    Ok(Response::Allowance {
+                        allowance: self.allowance(&owner, &spender),
+                    })
+                }
+                Request::History {
+                    account,
+                    before,
+                    limit,
+                } => {
+                    if origin == None || origin.unwrap() != &account.0 {
+                        return Err(anyhow::Error::msg(Error::NotAuthorized));
+                    }
+                    let (history, next) = self.history(&account, before, limit);
+                    Ok(Response::History { history, next })
+                }
             }
         };
         match inner() {
@@ -236,11 +505,12 @@ impl contracts::Contract<Command, Request, Response> for Balances {
         }
     }
 
-    fn handle_event(&mut self, ce: chain::Event) {
-        if let chain::Event::pallet_phala(pe) = ce {
-            if let phala::RawEvent::TransferToTee(who, amount) = pe {
+    fn handle_event(&mut self, message: &chain::ChainMessage) {
+        match message {
+            ChainMessage::TransferToTee { who, amount } => {
+                let amount = *amount;
                 info!("TransferToTee from :{:?}, {:}", who, amount);
-                let dest = AccountIdWrapper(who);
+                let dest = AccountIdWrapper(who.clone());
                 info!("   dest: {}", dest.to_string());
                 if let Some(dest_amount) = self.accounts.get_mut(&dest) {
                     let dest_amount0 = *dest_amount;
@@ -251,13 +521,18 @@ impl contracts::Contract<Command, Request, Response> for Balances {
                     info!("   value: {:>20} -> {:>20}", 0, amount);
                 }
                 self.total_issuance += amount;
-            } else if let phala::RawEvent::TransferToChain(who, amount, sequence) = pe {
+            }
+            ChainMessage::TransferToChain {
+                who,
+                amount,
+                sequence,
+            } => {
                 info!("TransferToChain who: {:?}, amount: {:}", who, amount);
                 let transfer_data = TransferData {
                     data: Transfer {
-                        dest: AccountIdWrapper(who),
-                        amount,
-                        sequence,
+                        dest: AccountIdWrapper(who.clone()),
+                        amount: *amount,
+                        sequence: *sequence,
                     },
                     signature: Vec::new(),
                 };
@@ -266,6 +541,7 @@ impl contracts::Contract<Command, Request, Response> for Balances {
                     .retain(|x| x.data.sequence > transfer_data.data.sequence);
                 info!("queue len: {:}", self.queue.len());
             }
+            _ => (),
         }
     }
 }
@@ -279,8 +555,96 @@ impl core::fmt::Debug for Balances {
     accounts: {:?},
     sequence: {:?},
     queue: {:?},
+    allowances: {:?},
+    next_tx_id: {:?},
 }}"#,
-            self.total_issuance, self.accounts, self.sequence, self.queue
+            self.total_issuance,
+            self.accounts,
+            self.sequence,
+            self.queue,
+            self.allowances,
+            self.next_tx_id
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::contracts::test_helpers::{account, txref};
+    use crate::contracts::Contract;
+
+    fn balances_with(owner: &chain::AccountId, amount: chain::Balance) -> Balances {
+        let mut balances = Balances::new(None);
+        balances
+            .accounts
+            .insert(AccountIdWrapper(owner.clone()), amount);
+        balances
+    }
+
+    #[test]
+    fn test_transfer_from() {
+        let (owner, spender, dest) = (account(1), account(2), account(3));
+        let mut balances = balances_with(&owner, 100);
+        let transfer_from = |value| Command::TransferFrom {
+            owner: AccountIdWrapper(owner.clone()),
+            dest: AccountIdWrapper(dest.clone()),
+            value,
+        };
+
+        let status = balances.handle_command(&spender, &txref(0), transfer_from(10));
+        assert!(matches!(status, TransactionStatus::InsufficientAllowance));
+
+        let approve = Command::Approve {
+            spender: AccountIdWrapper(spender.clone()),
+            value: 30,
+        };
+        balances.handle_command(&owner, &txref(1), approve);
+        let status = balances.handle_command(&spender, &txref(2), transfer_from(20));
+        assert!(matches!(status, TransactionStatus::Ok));
+        let o = AccountIdWrapper(owner.clone());
+        let s = AccountIdWrapper(spender.clone());
+        assert_eq!(balances.allowance(&o, &s), 10);
+        assert_eq!(balances.accounts[&AccountIdWrapper(dest.clone())], 20);
+        assert_eq!(balances.accounts[&o], 80);
+
+        let status = balances.handle_command(&spender, &txref(3), transfer_from(20));
+        assert!(matches!(status, TransactionStatus::InsufficientAllowance));
+        let status = balances.handle_command(&spender, &txref(4), transfer_from(10));
+        assert!(matches!(status, TransactionStatus::Ok));
+        assert!(balances.allowances.is_empty());
+    }
+
+    #[test]
+    fn test_history() {
+        let (alice, bob) = (account(1), account(2));
+        let mut balances = balances_with(&alice, 100);
+        for i in 0..5 {
+            let transfer = Command::Transfer {
+                dest: AccountIdWrapper(bob.clone()),
+                value: 1,
+            };
+            balances.handle_command(&alice, &txref(i), transfer);
+        }
+
+        let b = AccountIdWrapper(bob.clone());
+        let (page, next) = balances.history(&b, None, 3);
+        let ids: Vec<_> = page.iter().map(|tx| tx.id).collect();
+        assert_eq!(ids, vec![4, 3, 2]);
+        let (page, next) = balances.history(&b, next, 3);
+        let ids: Vec<_> = page.iter().map(|tx| tx.id).collect();
+        assert_eq!(ids, vec![1, 0]);
+        assert_eq!(next, None);
+
+        // Only the owner can query the history
+        let req = Request::History {
+            account: b.clone(),
+            before: None,
+            limit: 10,
+        };
+        let resp = balances.handle_query(Some(&alice), req.clone());
+        assert!(matches!(resp, Response::Error(_)));
+        let resp = balances.handle_query(Some(&bob), req);
+        assert!(matches!(resp, Response::History { .. }));
+    }
+}
@@ -0,0 +1,797 @@
+use super::TransactionStatus;
+use crate::std::collections::BTreeMap;
+use crate::std::prelude::v1::*;
+use crate::std::vec::Vec;
+use anyhow::Result;
+use core::fmt;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::chain;
+use crate::contracts;
+use crate::contracts::balances::Balances;
+use crate::contracts::AccountIdWrapper;
+use crate::types::TxRef;
+
+pub mod query;
+
+pub type ItemId = u32;
+pub type OrderId = u32;
+
+/// The paths of the results, which can't be used by the datasets
+pub const RESULT_PREFIX: &str = "/order/";
+/// The max size of an uploaded dataset in bytes
+pub const MAX_DATASET_LEN: usize = 1024 * 1024;
+/// The max size of all the uploaded datasets in bytes, since every worker keeps them in the state
+pub const MAX_TOTAL_DATASET_LEN: usize = 16 * 1024 * 1024;
+
+// item
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Item {
+    id: ItemId,
+    txref: TxRef,
+    seller: String,
+    details: ItemDetails,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ItemDetails {
+    pub name: String,
+    pub category: String,
+    pub description: String,
+    pub price: PricePolicy,
+    pub dataset_link: String,
+    pub dataset_preview: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum PricePolicy {
+    PerRow {
+        #[serde(with = "super::serde_balance")]
+        price: chain::Balance,
+    },
+}
+
+// order
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Order {
+    id: OrderId,
+    txref: TxRef,
+    buyer: String,
+    details: OrderDetails,
+    state: OrderState, // maybe shouldn't serialize this
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OrderDetails {
+    item_id: ItemId,
+    /// The dataset of the buyer to join with
+    #[serde(default)]
+    query_link: String,
+    /// Without a query, the dataset is matched against the keys listed in `query_link`, named by
+    /// its header
+    #[serde(default)]
+    query: Option<query::Query>,
+    /// The most the buyer pays, escrowed when the order is opened
+    #[serde(default, with = "super::serde_balance")]
+    budget: chain::Balance,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OrderState {
+    data_ready: bool,
+    query_ready: bool,
+    result_ready: bool,
+    matched_rows: u32,
+    /// The price of the matched rows
+    #[serde(default, with = "super::serde_balance")]
+    cost: chain::Balance,
+    result_path: String,
+    /// Why the query failed, which is not retried
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    settlement: Settlement,
+}
+
+/// Where the budget of an order goes
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Settlement {
+    /// Opened before the payments were added, so the result is free
+    Unfunded,
+    /// Waiting for the budget to be escrowed
+    Pending,
+    /// The budget couldn't be escrowed, so the order is dropped
+    Rejected,
+    /// The budget is held until the result is delivered
+    Escrowed,
+    /// The cost is paid to the seller and the rest of the budget refunded to the buyer
+    Settled {
+        #[serde(with = "super::serde_balance")]
+        paid: chain::Balance,
+        #[serde(with = "super::serde_balance")]
+        refunded: chain::Balance,
+    },
+    /// No result is delivered, so the whole budget is refunded
+    Refunded,
+}
+
+impl Default for Settlement {
+    fn default() -> Self {
+        Settlement::Unfunded
+    }
+}
+
+// contract
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Command {
+    List(ItemDetails),
+    OpenOrder(OrderDetails),
+    /// Uploads the dataset of an item by its seller, or the query file of an order by its buyer
+    Upload {
+        path: String,
+        data_b64: String,
+    },
+}
+
+impl Command {
+    /// Whether the command carries a dataset, so it must be encrypted to the worker
+    pub fn is_confidential(&self) -> bool {
+        matches!(self, Command::Upload { .. })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Request {
+    GetItems,
+    GetOrders,
+    /// The result of an order, only for its buyer
+    GetResult {
+        order_id: OrderId,
+    },
+}
+
+impl Request {
+    /// Whether the response carries a dataset, so it must be encrypted to the worker
+    pub fn is_confidential(&self) -> bool {
+        matches!(self, Request::GetResult { .. })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Response {
+    GetItems { items: Vec<Item> },
+    GetOrders { orders: Vec<Order> },
+    GetResult { data_b64: String },
+    Error(#[serde(with = "super::serde_anyhow")] anyhow::Error),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Error {
+    NotAuthorized,
+    OrderNotFound,
+    ResultNotReady,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotAuthorized => write!(f, "not authorized"),
+            Error::OrderNotFound => write!(f, "order not found"),
+            Error::ResultNotReady => write!(f, "result not ready"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DataPlaza {
+    items: Vec<Item>,
+    orders: Vec<Order>,
+    /// The uploaded datasets by their paths
+    #[serde(default)]
+    dataset: BTreeMap<String, Vec<u8>>,
+    /// The results of the orders by their paths
+    #[serde(default)]
+    results: BTreeMap<String, Vec<u8>>,
+}
+
+impl DataPlaza {
+    pub fn new() -> Self {
+        Self {
+            items: Vec::<Item>::new(),
+            orders: Vec::<Order>::new(),
+            dataset: BTreeMap::new(),
+            results: BTreeMap::new(),
+        }
+    }
+
+    pub fn set(&mut self, key: String, value: Vec<u8>) {
+        self.dataset.insert(key, value);
+    }
+
+    pub fn get(&self, key: &String) -> Option<&Vec<u8>> {
+        self.dataset.get(key).or_else(|| self.results.get(key))
+    }
+
+    /// The size of all the datasets but the one at `path`, which is about to be replaced
+    fn dataset_len_except(&self, path: &str) -> usize {
+        self.dataset
+            .iter()
+            .filter(|(key, _)| key.as_str() != path)
+            .map(|(_, data)| data.len())
+            .sum()
+    }
+
+    /// The account uploading the dataset at `path`: the seller of the item or the buyer of the
+    /// order using it
+    fn path_owner(&self, path: &str) -> Option<&String> {
+        let sellers = self
+            .items
+            .iter()
+            .filter(|item| item.details.dataset_link == path)
+            .map(|item| &item.seller);
+        let buyers = self
+            .orders
+            .iter()
+            .filter(|order| order.details.query_link == path)
+            .map(|order| &order.buyer);
+        sellers.chain(buyers).next()
+    }
+
+    /// Whether `account` can upload to `path`, which is not a result path or already used by
+    /// others
+    fn can_use_path(&self, path: &str, account: &String) -> bool {
+        !path.starts_with(RESULT_PREFIX)
+            && self.path_owner(path).map_or(true, |owner| owner == account)
+    }
+
+    /// Escrows the budgets of the new orders, then computes the orders whose datasets are ready
+    /// and settles them
+    pub fn settle(&mut self, balances: &mut Balances) {
+        let escrow = escrow_account();
+        for order in &mut self.orders {
+            if order.state.settlement != Settlement::Pending {
+                continue;
+            }
+            let buyer = AccountIdWrapper::from_hex(&order.buyer);
+            let budget = order.details.budget;
+            order.state.settlement = match pay(balances, order, &buyer, &escrow, budget) {
+                TransactionStatus::Ok => Settlement::Escrowed,
+                status => {
+                    info!("Order {} rejected: {:?}", order.id, status);
+                    order.state.error = Some(format!("{:?}", status));
+                    Settlement::Rejected
+                }
+            };
+        }
+        self.update_order_state(balances);
+    }
+
+    fn update_order_state(&mut self, balances: &mut Balances) {
+        for order in &mut self.orders {
+            match order.state.settlement {
+                Settlement::Unfunded | Settlement::Escrowed => (),
+                _ => continue,
+            }
+            if order.state.result_ready || order.state.error.is_some() {
+                continue;
+            }
+            // The item is checked when the order is opened
+            let item = &self.items[order.details.item_id as usize];
+            // check data available
+            let data_link = &item.details.dataset_link;
+            order.state.data_ready = self.dataset.contains_key(data_link);
+            // check query available, which is only needed by a join
+            let query_link = &order.details.query_link;
+            let needs_query = order
+                .details
+                .query
+                .as_ref()
+                .map_or(true, |query| query.join.is_some());
+            order.state.query_ready = !needs_query || self.dataset.contains_key(query_link);
+            // compute if possible
+            if !order.state.data_ready || !order.state.query_ready {
+                continue;
+            }
+            let dataset = &self.dataset[data_link];
+            let joined = self.dataset.get(query_link).map(|data| data.as_slice());
+            let funded = order.state.settlement == Settlement::Escrowed;
+            let budget = order.details.budget;
+            let result = Self::compute(item, order, dataset, joined).and_then(|(output, cost)| {
+                if funded && cost > budget {
+                    Err(format!("cost {} exceeds the budget {}", cost, budget))
+                } else {
+                    Ok((output, cost))
+                }
+            });
+            match result {
+                Ok((output, cost)) => {
+                    order.state.result_ready = true;
+                    order.state.matched_rows = output.matched_rows;
+                    order.state.cost = cost;
+                    order.state.result_path = format!("/order/{}", order.id);
+                    self.results
+                        .insert(order.state.result_path.clone(), output.data);
+                    if funded {
+                        let seller = AccountIdWrapper::from_hex(&item.seller);
+                        order.state.settlement = release(balances, order, &seller, cost);
+                    }
+                }
+                Err(err) => {
+                    info!("Order {} failed: {}", order.id, err);
+                    order.state.error = Some(err);
+                    if funded {
+                        let buyer = AccountIdWrapper::from_hex(&order.buyer);
+                        release(balances, order, &buyer, 0);
+                        order.state.settlement = Settlement::Refunded;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs the query of an order and prices its result
+    fn compute(
+        item: &Item,
+        order: &Order,
+        dataset: &[u8],
+        joined: Option<&[u8]>,
+    ) -> Result<(query::Output, chain::Balance), String> {
+        let run = || -> Result<(query::Output, chain::Balance), query::Error> {
+            let query = match &order.details.query {
+                Some(query) => query.clone(),
+                // Matches the dataset against the keys listed in the query file
+                None => {
+                    let key = query::first_column(joined.ok_or(query::Error::NoJoinedDataset)?)?;
+                    query::Query {
+                        join: Some(query::Join { key }),
+                        ..Default::default()
+                    }
+                }
+            };
+            let output = query::run(&query, dataset, joined)?;
+            let cost = match item.details.price {
+                PricePolicy::PerRow { price } => price
+                    .checked_mul(output.matched_rows as chain::Balance)
+                    .ok_or(query::Error::Overflow)?,
+            };
+            Ok((output, cost))
+        };
+        run().map_err(|err| err.to_string())
+    }
+}
+
+/// The account holding the budgets of the open orders. It's derived from a hash, so no one has
+/// its key.
+pub fn escrow_account() -> AccountIdWrapper {
+    let hash = sp_core::hashing::blake2_256(b"phala/data_plaza/escrow");
+    AccountIdWrapper(chain::AccountId::new(hash))
+}
+
+/// Transfers for an order, where nothing to transfer always succeeds
+fn pay(
+    balances: &mut Balances,
+    order: &Order,
+    from: &AccountIdWrapper,
+    dest: &AccountIdWrapper,
+    value: chain::Balance,
+) -> TransactionStatus {
+    if value == 0 {
+        return TransactionStatus::Ok;
+    }
+    balances.pay(&order.txref, from, dest, value)
+}
+
+/// Pays `paid` of the escrowed budget of an order to `dest` and refunds the rest to the buyer
+fn release(
+    balances: &mut Balances,
+    order: &Order,
+    dest: &AccountIdWrapper,
+    paid: chain::Balance,
+) -> Settlement {
+    let escrow = escrow_account();
+    let buyer = AccountIdWrapper::from_hex(&order.buyer);
+    let refunded = order.details.budget - paid;
+    for (to, value) in [(dest, paid), (&buyer, refunded)].iter() {
+        let status = pay(balances, order, &escrow, to, *value);
+        // The escrow holds the budgets of all the open orders
+        if !matches!(status, TransactionStatus::Ok) {
+            warn!("Order {} can't release {}: {:?}", order.id, value, status);
+        }
+    }
+    Settlement::Settled { paid, refunded }
+}
+
+impl contracts::ContractState for DataPlaza {
+    const SCHEMA_VERSION: u32 = 4;
+
+    fn migrate(version: u32, data: &[u8]) -> Result<Self> {
+        match version {
+            // The results, the order costs, the settlements and the datasets are added with their
+            // defaults. The datasets were not kept before, so they have to be uploaded again.
+            1 | 2 | 3 => {
+                serde_cbor::from_slice(data).map_err(|e| anyhow::Error::msg(e.to_string()))
+            }
+            _ => Err(anyhow::Error::msg(format!(
+                "Can't migrate from schema version {}",
+                version
+            ))),
+        }
+    }
+}
+
+impl contracts::Contract<Command, Request, Response> for DataPlaza {
+    fn id(&self) -> contracts::ContractId {
+        contracts::DATA_PLAZA
+    }
+
+    fn handle_command(
+        &mut self,
+        origin: &chain::AccountId,
+        txref: &TxRef,
+        cmd: Command,
+    ) -> TransactionStatus {
+        let address_hex = crate::hex::encode_hex_compact(origin.as_ref());
+        let status = match cmd {
+            Command::List(details) => {
+                if !self.can_use_path(&details.dataset_link, &address_hex) {
+                    return TransactionStatus::PathNotAllowed;
+                }
+                self.items.push(Item {
+                    id: self.items.len() as ItemId,
+                    txref: txref.clone(),
+                    seller: address_hex,
+                    details,
+                });
+                TransactionStatus::Ok
+            }
+            Command::OpenOrder(details) => {
+                if details.item_id as usize >= self.items.len() {
+                    return TransactionStatus::ItemNotFound;
+                }
+                let query_link = &details.query_link;
+                if !query_link.is_empty() && !self.can_use_path(query_link, &address_hex) {
+                    return TransactionStatus::PathNotAllowed;
+                }
+                self.orders.push(Order {
+                    id: self.orders.len() as OrderId,
+                    txref: txref.clone(),
+                    buyer: address_hex,
+                    details,
+                    state: OrderState {
+                        // TODO
+                        data_ready: false,
+                        query_ready: false,
+                        result_ready: false,
+                        matched_rows: 0,
+                        cost: 0,
+                        result_path: String::new(),
+                        error: None,
+                        // Escrowed by `settle` right after the command
+                        settlement: Settlement::Pending,
+                    },
+                });
+                TransactionStatus::Ok
+            }
+            Command::Upload { path, data_b64 } => {
+                if self.path_owner(&path) != Some(&address_hex) {
+                    return TransactionStatus::PathNotAllowed;
+                }
+                // Refuse the oversized ones before decoding them
+                if data_b64.len() > (MAX_DATASET_LEN + 2) / 3 * 4 {
+                    return TransactionStatus::DatasetTooLarge;
+                }
+                let data = match base64::decode(&data_b64) {
+                    Ok(data) => data,
+                    Err(_) => return TransactionStatus::BadCommand,
+                };
+                if data.len() > MAX_DATASET_LEN
+                    || self.dataset_len_except(&path) + data.len() > MAX_TOTAL_DATASET_LEN
+                {
+                    return TransactionStatus::DatasetTooLarge;
+                }
+                info!("Uploaded {} bytes to {}", data.len(), path);
+                self.dataset.insert(path, data);
+                TransactionStatus::Ok
+            }
+        };
+
+        status
+    }
+
+    fn handle_query(&mut self, origin: Option<&chain::AccountId>, req: Request) -> Response {
+        let origin = origin.map(|origin| crate::hex::encode_hex_compact(origin.as_ref()));
+        let inner = || -> Result<Response> {
+            match req {
+                Request::GetItems => Ok(Response::GetItems {
+                    items: self.items.clone(),
+                }),
+                Request::GetOrders => Ok(Response::GetOrders {
+                    orders: self.orders.clone(),
+                }),
+                Request::GetResult { order_id } => {
+                    let order = self
+                        .orders
+                        .get(order_id as usize)
+                        .ok_or_else(|| anyhow::Error::msg(Error::OrderNotFound))?;
+                    if origin.as_ref() != Some(&order.buyer) {
+                        return Err(anyhow::Error::msg(Error::NotAuthorized));
+                    }
+                    let data = self
+                        .results
+                        .get(&order.state.result_path)
+                        .ok_or_else(|| anyhow::Error::msg(Error::ResultNotReady))?;
+                    Ok(Response::GetResult {
+                        data_b64: base64::encode(data),
+                    })
+                }
+            }
+        };
+        match inner() {
+            Err(error) => Response::Error(error),
+            Ok(resp) => resp,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::contracts::balances;
+    use crate::contracts::test_helpers::{account, txref};
+    use crate::contracts::Contract;
+
+    const SELLER: u8 = 1;
+    const BUYER: u8 = 2;
+
+    fn list(dataset_link: &str) -> Command {
+        Command::List(ItemDetails {
+            name: String::from("people"),
+            category: String::new(),
+            description: String::new(),
+            price: PricePolicy::PerRow { price: 10 },
+            dataset_link: String::from(dataset_link),
+            dataset_preview: String::new(),
+        })
+    }
+
+    fn listed() -> (DataPlaza, Balances) {
+        let mut plaza = DataPlaza::new();
+        plaza.handle_command(&account(SELLER), &txref(0), list("/data/people"));
+        plaza.set(
+            String::from("/data/people"),
+            b"name,phone\nalice,123\nbob,456\ncarol,789\n".to_vec(),
+        );
+        let mut balances = Balances::new(None);
+        balances.deposit_genesis(AccountIdWrapper(account(BUYER)), 100);
+        (plaza, balances)
+    }
+
+    fn open(
+        (plaza, balances): &mut (DataPlaza, Balances),
+        item_id: ItemId,
+        query: Option<query::Query>,
+        budget: chain::Balance,
+    ) -> TransactionStatus {
+        let details = OrderDetails {
+            item_id,
+            query_link: String::from("/query/0"),
+            query,
+            budget,
+        };
+        let status = plaza.handle_command(&account(BUYER), &txref(0), Command::OpenOrder(details));
+        plaza.settle(balances);
+        status
+    }
+
+    fn orders((plaza, balances): &mut (DataPlaza, Balances)) -> Vec<Order> {
+        plaza.settle(balances);
+        match plaza.handle_query(None, Request::GetOrders) {
+            Response::GetOrders { orders } => orders,
+            _ => panic!("Unexpected response"),
+        }
+    }
+
+    fn balance(balances: &mut Balances, account: AccountIdWrapper) -> chain::Balance {
+        let request = balances::Request::FreeBalance {
+            account: account.clone(),
+        };
+        match balances.handle_query(Some(&account.0), request) {
+            balances::Response::FreeBalance { balance } => balance,
+            _ => panic!("Unexpected response"),
+        }
+    }
+
+    fn balances_of(balances: &mut Balances) -> [chain::Balance; 3] {
+        [
+            balance(balances, AccountIdWrapper(account(SELLER))),
+            balance(balances, AccountIdWrapper(account(BUYER))),
+            balance(balances, escrow_account()),
+        ]
+    }
+
+    #[test]
+    fn test_match_query_file() {
+        let mut ctx = listed();
+        assert!(matches!(
+            open(&mut ctx, 1, None, 50),
+            TransactionStatus::ItemNotFound
+        ));
+        assert!(matches!(open(&mut ctx, 0, None, 50), TransactionStatus::Ok));
+        // Waiting for the query file, with the budget in escrow
+        let state = orders(&mut ctx).remove(0).state;
+        assert!(!state.result_ready);
+        assert_eq!(state.settlement, Settlement::Escrowed);
+        assert_eq!(balances_of(&mut ctx.1), [0, 50, 50]);
+
+        ctx.0
+            .set(String::from("/query/0"), b"phone\n123\n789\n".to_vec());
+        let state = orders(&mut ctx).remove(0).state;
+        assert!(state.result_ready);
+        assert_eq!((state.matched_rows, state.cost), (2, 20));
+        assert_eq!(
+            state.settlement,
+            Settlement::Settled {
+                paid: 20,
+                refunded: 30
+            }
+        );
+        assert_eq!(balances_of(&mut ctx.1), [20, 80, 0]);
+        let result = ctx.0.get(&state.result_path).unwrap();
+        assert_eq!(&result[..], &b"name,phone\nalice,123\ncarol,789\n"[..]);
+    }
+
+    #[test]
+    fn test_query() {
+        let mut ctx = listed();
+        let query = query::Query {
+            aggregate: Some(query::Aggregate::Count),
+            ..Default::default()
+        };
+        open(&mut ctx, 0, Some(query), 30);
+        // No query file is needed without a join
+        let state = orders(&mut ctx).remove(0).state;
+        assert_eq!((state.matched_rows, state.cost), (3, 30));
+        assert_eq!(balances_of(&mut ctx.1), [30, 70, 0]);
+
+        let query = query::Query {
+            select: vec![String::from("email")],
+            ..Default::default()
+        };
+        open(&mut ctx, 0, Some(query), 10);
+        let state = orders(&mut ctx).remove(1).state;
+        assert!(!state.result_ready);
+        assert_eq!(state.error.as_deref(), Some("unknown column email"));
+        assert_eq!(state.settlement, Settlement::Refunded);
+        assert_eq!(balances_of(&mut ctx.1), [30, 70, 0]);
+    }
+
+    #[test]
+    fn test_settlement() {
+        let mut ctx = listed();
+        ctx.0
+            .set(String::from("/query/0"), b"phone\n000\n".to_vec());
+        // Insufficient balance
+        open(&mut ctx, 0, None, 101);
+        let state = orders(&mut ctx).remove(0).state;
+        assert_eq!(state.settlement, Settlement::Rejected);
+        assert!(!state.result_ready);
+        assert_eq!(balances_of(&mut ctx.1), [0, 100, 0]);
+
+        // Nothing matched, so nothing is paid
+        open(&mut ctx, 0, None, 40);
+        let state = orders(&mut ctx).remove(1).state;
+        assert!(state.result_ready);
+        assert_eq!((state.matched_rows, state.cost), (0, 0));
+        assert_eq!(
+            state.settlement,
+            Settlement::Settled {
+                paid: 0,
+                refunded: 40
+            }
+        );
+        assert_eq!(balances_of(&mut ctx.1), [0, 100, 0]);
+
+        // Over the budget, so the result is withheld
+        let query = query::Query {
+            filters: vec![query::Filter {
+                column: String::from("phone"),
+                op: query::Op::Gt,
+                value: String::from("200"),
+            }],
+            ..Default::default()
+        };
+        open(&mut ctx, 0, Some(query), 15);
+        let state = orders(&mut ctx).remove(2).state;
+        assert!(!state.result_ready);
+        assert_eq!(
+            state.error.as_deref(),
+            Some("cost 20 exceeds the budget 15")
+        );
+        assert_eq!(state.settlement, Settlement::Refunded);
+        assert_eq!(balances_of(&mut ctx.1), [0, 100, 0]);
+    }
+
+    #[test]
+    fn test_access() {
+        let mut ctx = listed();
+        let upload = |path: &str| Command::Upload {
+            path: String::from(path),
+            data_b64: base64::encode(b"phone\n123\n"),
+        };
+        let get_result = Request::GetResult { order_id: 0 };
+        let is_error = |resp: Response| matches!(resp, Response::Error(_));
+
+        // The paths of the others and the results can't be used
+        for (origin, path) in [(BUYER, "/data/people"), (SELLER, "/order/0")].iter() {
+            assert!(matches!(
+                ctx.0
+                    .handle_command(&account(*origin), &txref(0), list(path)),
+                TransactionStatus::PathNotAllowed
+            ));
+        }
+
+        open(&mut ctx, 0, None, 20);
+        let (plaza, balances) = &mut ctx;
+        // Only the buyer uploads the query file
+        assert!(matches!(
+            plaza.handle_command(&account(SELLER), &txref(0), upload("/query/0")),
+            TransactionStatus::PathNotAllowed
+        ));
+        assert!(is_error(
+            plaza.handle_query(Some(&account(BUYER)), get_result.clone())
+        ));
+        assert!(matches!(
+            plaza.handle_command(&account(BUYER), &txref(0), upload("/query/0")),
+            TransactionStatus::Ok
+        ));
+        plaza.settle(balances);
+
+        // Only the buyer reads the result
+        assert!(is_error(
+            plaza.handle_query(Some(&account(SELLER)), get_result.clone())
+        ));
+        match plaza.handle_query(Some(&account(BUYER)), get_result) {
+            Response::GetResult { data_b64 } => {
+                assert_eq!(
+                    base64::decode(&data_b64).unwrap(),
+                    b"name,phone\nalice,123\n"
+                )
+            }
+            _ => panic!("Unexpected response"),
+        }
+    }
+
+    #[test]
+    fn test_upload_limits() {
+        let mut ctx = listed();
+        open(&mut ctx, 0, None, 20);
+        let plaza = &mut ctx.0;
+        let upload = |len: usize| Command::Upload {
+            path: String::from("/query/0"),
+            data_b64: base64::encode(&vec![b'a'; len]),
+        };
+        let status = plaza.handle_command(&account(BUYER), &txref(0), upload(MAX_DATASET_LEN + 1));
+        assert!(matches!(status, TransactionStatus::DatasetTooLarge));
+        let status = plaza.handle_command(&account(BUYER), &txref(0), upload(MAX_DATASET_LEN));
+        assert!(matches!(status, TransactionStatus::Ok));
+
+        // The datasets in the state are capped as a whole. Replacing one doesn't count it twice.
+        let len = plaza.dataset_len_except("/query/0");
+        plaza.set(
+            String::from("/data/other"),
+            vec![0u8; MAX_TOTAL_DATASET_LEN - len - MAX_DATASET_LEN],
+        );
+        let status = plaza.handle_command(&account(BUYER), &txref(0), upload(MAX_DATASET_LEN));
+        assert!(matches!(status, TransactionStatus::Ok));
+        plaza.set(
+            String::from("/data/other"),
+            vec![0u8; MAX_TOTAL_DATASET_LEN - len],
+        );
+        let status = plaza.handle_command(&account(BUYER), &txref(0), upload(1));
+        assert!(matches!(status, TransactionStatus::DatasetTooLarge));
+    }
+}
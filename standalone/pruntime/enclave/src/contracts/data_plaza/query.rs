@@ -0,0 +1,544 @@
+//! A small SQL-like engine over CSV datasets
+//!
+//! A query selects the rows of a dataset matching all of its filters, optionally joined with a
+//! second dataset on a key column, and outputs the selected columns or a single aggregated row.
+//! The first record of a dataset is its header. The datasets are parsed as a stream of records,
+//! so only the joined dataset and the output are held in memory.
+
+use crate::std::collections::BTreeMap;
+use crate::std::prelude::v1::*;
+use crate::std::vec::Vec;
+use core::cmp::Ordering;
+use core::{fmt, str};
+use csv_core::{ReadRecordResult, Reader};
+use serde::{Deserialize, Serialize};
+
+/// The longest record in bytes
+pub const MAX_RECORD_LEN: usize = 64 * 1024;
+/// The most fields in a record
+pub const MAX_FIELDS: usize = 1024;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Query {
+    /// The columns to output, all of them if empty. Ignored by the aggregations.
+    #[serde(default)]
+    pub select: Vec<String>,
+    /// Only the rows matching all the filters are selected
+    #[serde(default)]
+    pub filters: Vec<Filter>,
+    #[serde(default)]
+    pub join: Option<Join>,
+    #[serde(default)]
+    pub aggregate: Option<Aggregate>,
+}
+
+/// Compares the field of a column with `value`, as integers if both are, or as bytes otherwise
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Filter {
+    pub column: String,
+    pub op: Op,
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// An inner join with the rows of the second dataset having the same `key`. The columns of the
+/// second dataset are appended to the ones of the first, except for the key.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Join {
+    pub key: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Aggregate {
+    Count,
+    /// The sum of an integer column
+    Sum {
+        column: String,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    RecordTooLong,
+    TooManyFields,
+    /// A dataset without the header
+    NoHeader,
+    UnknownColumn(String),
+    /// A field summed up which isn't an integer
+    NotAnInteger(String),
+    /// The query joins datasets but the second one is missing
+    NoJoinedDataset,
+    Overflow,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::RecordTooLong => write!(f, "record longer than {} bytes", MAX_RECORD_LEN),
+            Error::TooManyFields => write!(f, "record with more than {} fields", MAX_FIELDS),
+            Error::NoHeader => write!(f, "dataset without the header"),
+            Error::UnknownColumn(column) => write!(f, "unknown column {}", column),
+            Error::NotAnInteger(column) => write!(f, "non-integer field in column {}", column),
+            Error::NoJoinedDataset => write!(f, "no dataset to join"),
+            Error::Overflow => write!(f, "overflow"),
+        }
+    }
+}
+
+/// The result of a query
+#[derive(Debug)]
+pub struct Output {
+    /// The selected rows or the aggregation in CSV, with the header
+    pub data: Vec<u8>,
+    /// The rows matching the query, which are charged for
+    pub matched_rows: u32,
+}
+
+/// A parsed record
+#[derive(Debug, Clone)]
+struct Record {
+    data: Vec<u8>,
+    ends: Vec<usize>,
+}
+
+impl Record {
+    fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    fn field(&self, i: usize) -> &[u8] {
+        let start = if i == 0 { 0 } else { self.ends[i - 1] };
+        &self.data[start..self.ends[i]]
+    }
+
+    fn fields(&self) -> impl Iterator<Item = &[u8]> {
+        (0..self.len()).map(move |i| self.field(i))
+    }
+}
+
+/// Reads the records of a dataset one by one, growing the buffers up to the limits
+struct Records<'a> {
+    reader: Reader,
+    input: &'a [u8],
+    outbuf: Vec<u8>,
+    ends: Vec<usize>,
+}
+
+impl<'a> Records<'a> {
+    fn new(input: &'a [u8]) -> Self {
+        Records {
+            reader: Reader::new(),
+            input,
+            outbuf: vec![0; 1024],
+            ends: vec![0; 16],
+        }
+    }
+
+    fn next(&mut self) -> Result<Option<Record>, Error> {
+        let (mut nout, mut nend) = (0, 0);
+        loop {
+            // The ends are positions in the whole record, since the reader tracks them across
+            // the calls
+            let (result, nin, out, end) = self.reader.read_record(
+                self.input,
+                &mut self.outbuf[nout..],
+                &mut self.ends[nend..],
+            );
+            self.input = &self.input[nin..];
+            nout += out;
+            nend += end;
+            match result {
+                // The input is complete. An empty input ends the last record.
+                ReadRecordResult::InputEmpty => {}
+                ReadRecordResult::OutputFull => {
+                    if self.outbuf.len() >= MAX_RECORD_LEN {
+                        return Err(Error::RecordTooLong);
+                    }
+                    let len = (self.outbuf.len() * 2).min(MAX_RECORD_LEN);
+                    self.outbuf.resize(len, 0);
+                }
+                ReadRecordResult::OutputEndsFull => {
+                    if self.ends.len() >= MAX_FIELDS {
+                        return Err(Error::TooManyFields);
+                    }
+                    let len = (self.ends.len() * 2).min(MAX_FIELDS);
+                    self.ends.resize(len, 0);
+                }
+                ReadRecordResult::Record => {
+                    return Ok(Some(Record {
+                        data: self.outbuf[..nout].to_vec(),
+                        ends: self.ends[..nend].to_vec(),
+                    }))
+                }
+                ReadRecordResult::End => return Ok(None),
+            }
+        }
+    }
+
+    fn header(&mut self) -> Result<Vec<String>, Error> {
+        let header = self.next()?.ok_or(Error::NoHeader)?;
+        Ok(header
+            .fields()
+            .map(|field| String::from_utf8_lossy(field).into_owned())
+            .collect())
+    }
+}
+
+/// The name of the first column of a dataset
+pub fn first_column(dataset: &[u8]) -> Result<String, Error> {
+    Records::new(dataset)
+        .header()?
+        .into_iter()
+        .next()
+        .ok_or(Error::NoHeader)
+}
+
+/// A column of the dataset or of the joined one
+#[derive(Clone, Copy)]
+enum Column {
+    Left(usize),
+    Right(usize),
+}
+
+struct Schema {
+    left: Vec<String>,
+    right: Vec<String>,
+    right_key: Option<usize>,
+}
+
+impl Schema {
+    /// Looks up a column in the dataset first
+    fn column(&self, name: &str) -> Result<Column, Error> {
+        if let Some(i) = self.left.iter().position(|c| c == name) {
+            return Ok(Column::Left(i));
+        }
+        match self.right.iter().position(|c| c == name) {
+            Some(i) if Some(i) != self.right_key => Ok(Column::Right(i)),
+            _ => Err(Error::UnknownColumn(String::from(name))),
+        }
+    }
+
+    fn all(&self) -> Vec<Column> {
+        let left = (0..self.left.len()).map(Column::Left);
+        let right = (0..self.right.len())
+            .filter(|i| Some(*i) != self.right_key)
+            .map(Column::Right);
+        left.chain(right).collect()
+    }
+
+    fn name(&self, column: Column) -> &str {
+        match column {
+            Column::Left(i) => &self.left[i],
+            Column::Right(i) => &self.right[i],
+        }
+    }
+}
+
+/// A row of the dataset, with the matched row of the joined dataset if any
+struct Row<'r> {
+    left: &'r Record,
+    right: Option<&'r Record>,
+}
+
+impl<'r> Row<'r> {
+    /// The field of a column, empty if the record is short of it
+    fn get(&self, column: Column) -> &'r [u8] {
+        let (record, i) = match column {
+            Column::Left(i) => (Some(self.left), i),
+            Column::Right(i) => (self.right, i),
+        };
+        match record {
+            Some(record) if i < record.len() => record.field(i),
+            _ => &[],
+        }
+    }
+}
+
+fn parse_int(field: &[u8]) -> Option<i128> {
+    str::from_utf8(field).ok()?.trim().parse().ok()
+}
+
+fn compare(field: &[u8], value: &str) -> Ordering {
+    match (parse_int(field), value.trim().parse::<i128>()) {
+        (Some(a), Ok(b)) => a.cmp(&b),
+        _ => field.cmp(value.as_bytes()),
+    }
+}
+
+impl Op {
+    fn matches(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Ne => ordering != Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// Writes a CSV record, quoting the fields when needed
+fn write_record<'f>(out: &mut Vec<u8>, fields: impl Iterator<Item = &'f [u8]>) {
+    for (i, field) in fields.enumerate() {
+        if i > 0 {
+            out.push(b',');
+        }
+        if field
+            .iter()
+            .any(|b| matches!(b, b',' | b'"' | b'\n' | b'\r'))
+        {
+            out.push(b'"');
+            for b in field {
+                if *b == b'"' {
+                    out.push(b'"');
+                }
+                out.push(*b);
+            }
+            out.push(b'"');
+        } else {
+            out.extend_from_slice(field);
+        }
+    }
+    out.push(b'\n');
+}
+
+/// Runs a query over `dataset`, joined with `joined` if the query has a join
+pub fn run(query: &Query, dataset: &[u8], joined: Option<&[u8]>) -> Result<Output, Error> {
+    let mut records = Records::new(dataset);
+    let left = records.header()?;
+
+    // Load the joined dataset, indexed by the key
+    let mut schema = Schema {
+        left,
+        right: Vec::new(),
+        right_key: None,
+    };
+    let mut join_index = BTreeMap::<Vec<u8>, Vec<Record>>::new();
+    let mut left_key = None;
+    if let Some(join) = &query.join {
+        let joined = joined.ok_or(Error::NoJoinedDataset)?;
+        let mut right_records = Records::new(joined);
+        schema.right = right_records.header()?;
+        let right_key = schema
+            .right
+            .iter()
+            .position(|c| c == &join.key)
+            .ok_or_else(|| Error::UnknownColumn(join.key.clone()))?;
+        schema.right_key = Some(right_key);
+        left_key = Some(
+            schema
+                .left
+                .iter()
+                .position(|c| c == &join.key)
+                .ok_or_else(|| Error::UnknownColumn(join.key.clone()))?,
+        );
+        while let Some(record) = right_records.next()? {
+            if right_key < record.len() {
+                join_index
+                    .entry(record.field(right_key).to_vec())
+                    .or_default()
+                    .push(record);
+            }
+        }
+    }
+
+    let filters = query
+        .filters
+        .iter()
+        .map(|filter| Ok((schema.column(&filter.column)?, filter)))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let selected = if query.select.is_empty() {
+        schema.all()
+    } else {
+        query
+            .select
+            .iter()
+            .map(|name| schema.column(name))
+            .collect::<Result<Vec<_>, Error>>()?
+    };
+    let summed = match &query.aggregate {
+        Some(Aggregate::Sum { column }) => Some((schema.column(column)?, column)),
+        _ => None,
+    };
+
+    let mut out = Vec::new();
+    match &query.aggregate {
+        Some(Aggregate::Count) => write_record(&mut out, [&b"count"[..]].iter().cloned()),
+        Some(Aggregate::Sum { column }) => {
+            let name = format!("sum({})", column);
+            write_record(&mut out, [name.as_bytes()].iter().cloned())
+        }
+        None => write_record(
+            &mut out,
+            selected.iter().map(|c| schema.name(*c).as_bytes()),
+        ),
+    }
+
+    let mut matched_rows: u32 = 0;
+    let mut sum: i128 = 0;
+    let no_match = Vec::new();
+    while let Some(record) = records.next()? {
+        let rights: Vec<Option<&Record>> = match left_key {
+            Some(key) if key < record.len() => join_index
+                .get(record.field(key))
+                .unwrap_or(&no_match)
+                .iter()
+                .map(Some)
+                .collect(),
+            Some(_) => Vec::new(),
+            None => vec![None],
+        };
+        for right in rights {
+            let row = Row {
+                left: &record,
+                right,
+            };
+            let matched = filters.iter().all(|(column, filter)| {
+                filter.op.matches(compare(row.get(*column), &filter.value))
+            });
+            if !matched {
+                continue;
+            }
+            matched_rows = matched_rows.checked_add(1).ok_or(Error::Overflow)?;
+            match summed {
+                Some((column, name)) => {
+                    let value = parse_int(row.get(column))
+                        .ok_or_else(|| Error::NotAnInteger(name.clone()))?;
+                    sum = sum.checked_add(value).ok_or(Error::Overflow)?;
+                }
+                None if query.aggregate.is_none() => {
+                    write_record(&mut out, selected.iter().map(|c| row.get(*c)))
+                }
+                None => (),
+            }
+        }
+    }
+
+    match &query.aggregate {
+        Some(Aggregate::Count) => {
+            let count = matched_rows.to_string();
+            write_record(&mut out, [count.as_bytes()].iter().cloned())
+        }
+        Some(Aggregate::Sum { .. }) => {
+            let sum = sum.to_string();
+            write_record(&mut out, [sum.as_bytes()].iter().cloned())
+        }
+        None => (),
+    }
+    Ok(Output {
+        data: out,
+        matched_rows,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PEOPLE: &[u8] = b"name,phone,age\n\
+        alice,123,30\n\
+        bob,456,25\n\
+        \"carol, jr\",789,41\n";
+
+    fn query(json: &str) -> Query {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn run_str(q: &str, joined: Option<&[u8]>) -> (String, u32) {
+        let output = run(&query(q), PEOPLE, joined).unwrap();
+        (String::from_utf8(output.data).unwrap(), output.matched_rows)
+    }
+
+    #[test]
+    fn test_select_filter() {
+        let (out, rows) = run_str(
+            r#"{"select": ["name"], "filters": [{"column": "age", "op": "Ge", "value": "30"}]}"#,
+            None,
+        );
+        assert_eq!(out, "name\nalice\n\"carol, jr\"\n");
+        assert_eq!(rows, 2);
+        // Compared as integers, not as bytes
+        let (_, rows) = run_str(
+            r#"{"filters": [{"column": "age", "op": "Lt", "value": "100"}]}"#,
+            None,
+        );
+        assert_eq!(rows, 3);
+        let (out, _) = run_str(
+            r#"{"filters": [{"column": "name", "op": "Eq", "value": "bob"}]}"#,
+            None,
+        );
+        assert_eq!(out, "name,phone,age\nbob,456,25\n");
+    }
+
+    #[test]
+    fn test_join_aggregate() {
+        let orders: &[u8] = b"phone,amount\n123,10\n123,5\n789,7\n000,1\n";
+        let (out, rows) = run_str(r#"{"join": {"key": "phone"}}"#, Some(orders));
+        assert_eq!(
+            out,
+            "name,phone,age,amount\nalice,123,30,10\nalice,123,30,5\n\"carol, jr\",789,41,7\n"
+        );
+        assert_eq!(rows, 3);
+        let (out, _) = run_str(
+            r#"{"join": {"key": "phone"}, "aggregate": {"Sum": {"column": "amount"}}}"#,
+            Some(orders),
+        );
+        assert_eq!(out, "sum(amount)\n22\n");
+        let (out, rows) = run_str(
+            r#"{"join": {"key": "phone"}, "filters": [{"column": "amount", "op": "Gt", "value": "6"}], "aggregate": "Count"}"#,
+            Some(orders),
+        );
+        assert_eq!((out.as_str(), rows), ("count\n2\n", 2));
+        assert_eq!(first_column(orders), Ok(String::from("phone")));
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = |q: &str, joined: Option<&[u8]>| run(&query(q), PEOPLE, joined).unwrap_err();
+        assert_eq!(
+            err(r#"{"select": ["email"]}"#, None),
+            Error::UnknownColumn(String::from("email"))
+        );
+        assert_eq!(
+            err(r#"{"join": {"key": "phone"}}"#, None),
+            Error::NoJoinedDataset
+        );
+        assert_eq!(
+            err(r#"{"aggregate": {"Sum": {"column": "name"}}}"#, None),
+            Error::NotAnInteger(String::from("name"))
+        );
+        assert_eq!(
+            run(&Query::default(), b"", None).unwrap_err(),
+            Error::NoHeader
+        );
+
+        // Large records are parsed up to the limits
+        let long = vec![b'x'; 4096];
+        let mut dataset = b"a\n".to_vec();
+        dataset.extend_from_slice(&long);
+        assert_eq!(
+            run(&Query::default(), &dataset, None).unwrap().matched_rows,
+            1
+        );
+        let too_long = vec![b'x'; MAX_RECORD_LEN + 1];
+        assert_eq!(
+            run(&Query::default(), &too_long, None).unwrap_err(),
+            Error::RecordTooLong
+        );
+        let too_wide = vec![b','; MAX_FIELDS];
+        assert_eq!(
+            run(&Query::default(), &too_wide, None).unwrap_err(),
+            Error::TooManyFields
+        );
+    }
+}
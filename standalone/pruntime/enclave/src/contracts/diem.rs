@@ -8,6 +8,7 @@ use core::{fmt, str};
 use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
 
+use crate::chain;
 use crate::contracts;
 use crate::contracts::AccountIdWrapper;
 use crate::types::TxRef;
@@ -29,7 +30,6 @@ use diem_types::transaction::{SignedTransaction, Transaction, TransactionPayload
 use diem_types::trusted_state::{TrustedState, TrustedStateChange};
 use move_core_types::transaction_argument::TransactionArgument;
 
-use crate::hex;
 use crate::std::borrow::ToOwned;
 use diem_crypto::{
     ed25519::{Ed25519PrivateKey, Ed25519PublicKey},
@@ -44,15 +44,10 @@ use diem_types::{
     transaction::helpers::create_user_txn,
 };
 use parity_scale_codec::{Decode, Encode};
-use rand::{rngs::OsRng, Rng, SeedableRng};
 const GAS_UNIT_PRICE: u64 = 0;
 const MAX_GAS_AMOUNT: u64 = 1_000_000;
 const TX_EXPIRATION: i64 = 180;
 const CHAIN_ID_UNINITIALIZED: u8 = 0;
-const ALICE_PRIVATE_KEY: &str = "818ad9a64e3d1bbc388f8bf1e43c78d125237b875a1b70a18f412f7d18efbeea";
-const ALICE_ADDRESS: &str = "D4F0C053205BA934BB2AC0C4E8479E77";
-
-const ALICE_PHALA: &str = "d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d";
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct Amount {
@@ -233,35 +228,6 @@ pub struct Diem {
 
 impl Diem {
     pub fn new() -> Self {
-        let alice_priv_key =
-            Ed25519PrivateKey::from_bytes_unchecked(&hex::decode_hex(ALICE_PRIVATE_KEY)).expect("Bad private key");
-        let alice_key_pair: KeyPair<Ed25519PrivateKey, Ed25519PublicKey> =
-            KeyPair::from(alice_priv_key);
-        let alice_account_address =
-            AuthenticationKey::ed25519(&alice_key_pair.public_key).derived_address();
-
-        let alice_account = Account {
-            address: alice_account_address,
-            key: alice_key_pair,
-            sequence: 0,
-            event_id: 0,
-            free: 0,
-            locked: 0,
-            is_child: false,
-        };
-
-        let mut accounts = BTreeMap::<AccountIdWrapper, Account>::new();
-        accounts.insert(AccountIdWrapper::from_hex(ALICE_PHALA), alice_account);
-
-        let mut address = BTreeMap::<String, AccountIdWrapper>::new();
-        address.insert(
-            ALICE_ADDRESS.to_string(),
-            AccountIdWrapper::from_hex(ALICE_PHALA),
-        );
-
-        let mut account_address: Vec<String> = Vec::new();
-        account_address.push(ALICE_ADDRESS.to_string());
-
         Diem {
             chain_id: CHAIN_ID_UNINITIALIZED,
             account_info: Vec::new(),
@@ -270,9 +236,9 @@ impl Diem {
             seq_number: BTreeMap::<String, u64>::new(),
             init_trusted_state: None,
             trusted_state: None,
-            accounts,
-            address,
-            account_address,
+            accounts: BTreeMap::new(),
+            address: BTreeMap::new(),
+            account_address: Vec::new(),
             pending_transactions: BTreeMap::<String, Vec<PendingTransaction>>::new(),
             queue_seq: 1,
             tx_queue: Vec::new(),
@@ -280,6 +246,38 @@ impl Diem {
         }
     }
 
+    /// Sets up the VASP account creating the child accounts, owned by `owner`
+    pub fn set_master(&mut self, owner: AccountIdWrapper, private_key: &[u8]) -> Result<()> {
+        if self.master().is_some() {
+            return Err(anyhow::Error::msg(Error::Other(String::from(
+                "Master account already set",
+            ))));
+        }
+        let priv_key = Ed25519PrivateKey::try_from(private_key)
+            .map_err(|_| anyhow::Error::msg(Error::Other(String::from("Bad private key"))))?;
+        let key_pair: KeyPair<Ed25519PrivateKey, Ed25519PublicKey> = KeyPair::from(priv_key);
+        let account_address = AuthenticationKey::ed25519(&key_pair.public_key).derived_address();
+
+        let account = Account {
+            address: account_address,
+            key: key_pair,
+            sequence: 0,
+            event_id: 0,
+            free: 0,
+            locked: 0,
+            is_child: false,
+        };
+        self.accounts.insert(owner.clone(), account);
+        self.address.insert(account_address.to_string(), owner);
+        self.account_address.push(account_address.to_string());
+        Ok(())
+    }
+
+    /// The VASP account and its owner
+    fn master(&self) -> Option<(&AccountIdWrapper, &Account)> {
+        self.accounts.iter().find(|(_, account)| !account.is_child)
+    }
+
     pub fn get_transaction(
         &mut self,
         transaction_with_proof: TransactionWithProof,
@@ -545,6 +543,10 @@ fn auth_key_prefix(auth_key: Vec<u8>) -> Vec<u8> {
     auth_key[0..16].to_vec()
 }
 
+impl contracts::ContractState for Diem {
+    const SCHEMA_VERSION: u32 = 1;
+}
+
 impl contracts::Contract<Command, Request, Response> for Diem {
     fn id(&self) -> contracts::ContractId {
         contracts::DIEM
@@ -553,7 +555,7 @@ impl contracts::Contract<Command, Request, Response> for Diem {
     fn handle_command(
         &mut self,
         origin: &chain::AccountId,
-        _txref: &TxRef,
+        txref: &TxRef,
         cmd: Command,
     ) -> TransactionStatus {
         match cmd {
@@ -743,19 +745,27 @@ impl contracts::Contract<Command, Request, Response> for Diem {
                 let o = AccountIdWrapper(origin.clone());
                 info!("NewAccount {:}, seq_number:{:}", o.to_string(), seq_number);
 
-                let alice = AccountIdWrapper::from_hex(ALICE_PHALA);
-                if o == alice {
-                    error!("Alice can't execute NewAccount command");
+                let (master, master_account) = match self.master() {
+                    Some(master) => master,
+                    None => {
+                        error!("Master account not set");
+                        return TransactionStatus::BadSecret;
+                    }
+                };
+                if &o == master {
+                    error!("The master can't execute NewAccount command");
                     return TransactionStatus::InvalidAccount;
                 }
 
-                let alice_account = self.accounts.get(&alice)
-                    .expect("Alice account was required");
-
-                let alice_key_pair = &alice_account.key;
-                // TODO: make deterministic privkey generation
-                let mut seed_rng = OsRng;
-                let mut rng = rand::rngs::StdRng::from_seed(seed_rng.gen());
+                let master_key_pair = &master_account.key;
+                // The contract has no key of its own yet. Seed with the master key, which only the
+                // enclaves know (the genesis passes it encrypted to the worker), so every pRuntime
+                // derives the same child account while nobody else can.
+                let mut rng = contracts::deterministic_rng(
+                    &master_key_pair.private_key.to_bytes(),
+                    txref,
+                    &(origin, seq_number).encode(),
+                );
                 let keypair: KeyPair<Ed25519PrivateKey, Ed25519PublicKey> =
                     Ed25519PrivateKey::generate(&mut rng).into();
                 let auth_key = AuthenticationKey::ed25519(&keypair.public_key).to_vec();
@@ -773,9 +783,9 @@ impl contracts::Contract<Command, Request, Response> for Diem {
                 );
 
                 let txn = create_user_txn(
-                    alice_key_pair,
+                    master_key_pair,
                     TransactionPayload::Script(script),
-                    alice_account.address,
+                    master_account.address,
                     seq_number,
                     MAX_GAS_AMOUNT,
                     GAS_UNIT_PRICE,
@@ -988,7 +998,7 @@ impl contracts::Contract<Command, Request, Response> for Diem {
         }
     }
 
-    fn handle_event(&mut self, _ce: chain::Event) {}
+    fn handle_event(&mut self, _message: &chain::ChainMessage) {}
 }
 
 /// Parses a TrustedState from a bcs encoded LedgerInfoWithSignature in base64
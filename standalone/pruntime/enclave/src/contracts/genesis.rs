@@ -0,0 +1,256 @@
+//! The initial states of the contracts
+//!
+//! The contracts start empty. `init_runtime` seeds them with a `ContractsGenesis`, and the built-in
+//! test accounts (Alice) are only loaded in dev mode. The private keys in the genesis are encrypted
+//! to the worker ECDH key, so they never pass through the host in plaintext.
+
+use crate::std::string::String;
+
+use anyhow::Result;
+use phala_types::rpc::ContractsGenesis;
+
+use super::assets::Assets;
+use super::balances::Balances;
+use super::diem::Diem;
+use super::web3analytics::Web3Analytics;
+use super::AccountIdWrapper;
+use crate::cryptography;
+use crate::hex;
+
+/// `//Alice`
+const ALICE: &str = "d43593c715fdd31c61141abd04a99fd6822c8558854ccde39a5684e7a56da27d";
+/// The ed25519 key of the Diem VASP account on the test network
+const DIEM_TEST_KEY: &str = "818ad9a64e3d1bbc388f8bf1e43c78d125237b875a1b70a18f412f7d18efbeea";
+const TEST_ASSET_SYMBOL: &str = "TTT";
+const TEST_ASSET_SUPPLY: u128 = 1_024_000_000_000_000;
+
+/// Loads the built-in test accounts. Web3Analytics has no test key, so the encrypted sites need the
+/// contract key from the genesis even in dev mode.
+fn apply_dev_fixtures(assets: &mut Assets, diem: &mut Diem) -> Result<()> {
+    let alice = AccountIdWrapper::from_hex(ALICE);
+    let symbol = String::from(TEST_ASSET_SYMBOL);
+    assets
+        .issue(alice.clone(), symbol.clone(), symbol, 0, TEST_ASSET_SUPPLY)
+        .map_err(|status| {
+            anyhow::Error::msg(format!("Can't issue the test asset: {:?}", status))
+        })?;
+    diem.set_master(alice, &hex::decode_hex(DIEM_TEST_KEY))
+}
+
+/// Checks that the test accounts are only loaded in dev mode
+pub fn check(genesis: &ContractsGenesis, dev_mode: bool) -> Result<()> {
+    if genesis.dev_fixtures && !dev_mode {
+        return Err(anyhow::Error::msg(
+            "The test accounts are only allowed in dev mode",
+        ));
+    }
+    Ok(())
+}
+
+/// Seeds the empty contracts. The test accounts go first if requested. The secrets are decrypted
+/// by the worker ECDH key.
+pub fn apply(
+    genesis: &ContractsGenesis,
+    ecdh_key: &ring::agreement::EphemeralPrivateKey,
+    balances: &mut Balances,
+    assets: &mut Assets,
+    diem: &mut Diem,
+    web3analytics: &mut Web3Analytics,
+) -> Result<()> {
+    if genesis.dev_fixtures {
+        apply_dev_fixtures(assets, diem)?;
+    }
+    for (account, amount) in &genesis.balances {
+        balances.deposit_genesis(AccountIdWrapper::from(&account[..]), *amount);
+    }
+    for asset in &genesis.assets {
+        let owner = AccountIdWrapper::from(&asset.owner[..]);
+        assets
+            .issue(
+                owner,
+                asset.symbol.clone(),
+                asset.name.clone(),
+                asset.decimals,
+                asset.total,
+            )
+            .map_err(|status| {
+                anyhow::Error::msg(format!("Can't issue {}: {:?}", asset.symbol, status))
+            })?;
+    }
+    if let Some(master) = &genesis.diem_master {
+        let private_key = cryptography::decrypt_secret(&master.private_key, ecdh_key)
+            .map_err(|_| anyhow::Error::msg("Can't decrypt the Diem master key"))?;
+        diem.set_master(AccountIdWrapper::from(&master.owner[..]), &private_key)?;
+    }
+    if let Some(key) = &genesis.web3analytics_key {
+        let key = cryptography::decrypt_secret(key, ecdh_key)
+            .map_err(|_| anyhow::Error::msg("Can't decrypt the Web3Analytics contract key"))?;
+        web3analytics.set_key(&key)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chain;
+    use crate::contracts::{assets, balances, Contract};
+    use crate::cryptography::ecdh;
+    use phala_types::rpc::{AssetGenesis, DiemMasterGenesis, EncryptedSecret};
+
+    fn apply_new(
+        genesis: &ContractsGenesis,
+        ecdh_key: &ring::agreement::EphemeralPrivateKey,
+    ) -> Result<(Balances, Assets, Diem, Web3Analytics)> {
+        let (mut balances, mut assets, mut diem, mut web3analytics) = (
+            Balances::new(None),
+            Assets::new(),
+            Diem::new(),
+            Web3Analytics::new(),
+        );
+        apply(
+            genesis,
+            ecdh_key,
+            &mut balances,
+            &mut assets,
+            &mut diem,
+            &mut web3analytics,
+        )?;
+        Ok((balances, assets, diem, web3analytics))
+    }
+
+    fn asset_balance(assets: &mut Assets, id: u32, owner: [u8; 32]) -> Option<chain::Balance> {
+        let owner = chain::AccountId::from(owner);
+        let req = assets::Request::Balance {
+            id,
+            account: AccountIdWrapper(owner.clone()),
+        };
+        match assets.handle_query(Some(&owner), req) {
+            assets::Response::Balance { balance } => Some(balance),
+            _ => None,
+        }
+    }
+
+    fn encrypt_to(
+        ecdh_key: &ring::agreement::EphemeralPrivateKey,
+        secret: &[u8],
+    ) -> EncryptedSecret {
+        let worker_pubkey = ecdh_key.compute_public_key().unwrap();
+        cryptography::encrypt_secret(worker_pubkey.as_ref(), secret)
+    }
+
+    fn diem_master(ecdh_key: &ring::agreement::EphemeralPrivateKey) -> DiemMasterGenesis {
+        DiemMasterGenesis {
+            owner: [1u8; 32],
+            private_key: encrypt_to(ecdh_key, &[2u8; 32]),
+        }
+    }
+
+    #[test]
+    fn test_production_genesis() {
+        let ecdh_key = ecdh::generate_key();
+        let genesis = ContractsGenesis {
+            balances: vec![([1u8; 32], 100)],
+            assets: vec![AssetGenesis {
+                owner: [1u8; 32],
+                symbol: String::from("PHA"),
+                name: String::from("Phala"),
+                decimals: 12,
+                total: 1000,
+            }],
+            diem_master: Some(diem_master(&ecdh_key)),
+            web3analytics_key: Some(encrypt_to(&ecdh_key, &[3u8; 32])),
+            ..Default::default()
+        };
+        assert!(check(&genesis, false).is_ok());
+        let (mut balances, mut assets, mut diem, mut web3analytics) =
+            apply_new(&genesis, &ecdh_key).unwrap();
+        match balances.handle_query(None, balances::Request::TotalIssuance) {
+            balances::Response::TotalIssuance { total_issuance } => assert_eq!(total_issuance, 100),
+            _ => panic!("Unexpected response"),
+        }
+        assert_eq!(asset_balance(&mut assets, 0, [1u8; 32]), Some(1000));
+        // The Diem master account is set from the decrypted key
+        assert!(diem
+            .set_master(AccountIdWrapper::from(&[1u8; 32][..]), &[2u8; 32])
+            .is_err());
+        // So is the Web3Analytics key
+        assert!(web3analytics.set_key(&[3u8; 32]).is_err());
+        // No test account is seeded
+        let alice: [u8; 32] = AccountIdWrapper::from_hex(ALICE).0.into();
+        let symbol = String::from(TEST_ASSET_SYMBOL);
+        assert!(assets
+            .issue(
+                AccountIdWrapper::from(&alice[..]),
+                symbol.clone(),
+                symbol,
+                0,
+                1
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn test_secret_of_another_worker() {
+        let ecdh_key = ecdh::generate_key();
+        let other_key = ecdh::generate_key();
+        let genesis = ContractsGenesis {
+            diem_master: Some(diem_master(&other_key)),
+            ..Default::default()
+        };
+        assert!(apply_new(&genesis, &ecdh_key).is_err());
+        let genesis = ContractsGenesis {
+            web3analytics_key: Some(encrypt_to(&other_key, &[3u8; 32])),
+            ..Default::default()
+        };
+        assert!(apply_new(&genesis, &ecdh_key).is_err());
+    }
+
+    #[test]
+    fn test_test_accounts_refused_without_dev_mode() {
+        let fixtures = ContractsGenesis {
+            dev_fixtures: true,
+            ..Default::default()
+        };
+        assert!(check(&fixtures, false).is_err());
+        assert!(check(&fixtures, true).is_ok());
+    }
+
+    #[test]
+    fn test_dev_fixtures() {
+        let ecdh_key = ecdh::generate_key();
+        let genesis = ContractsGenesis {
+            dev_fixtures: true,
+            assets: vec![AssetGenesis {
+                owner: [1u8; 32],
+                symbol: String::from("PHA"),
+                name: String::from("Phala"),
+                decimals: 12,
+                total: 1000,
+            }],
+            ..Default::default()
+        };
+        let (_, mut assets, mut diem, mut web3analytics) = apply_new(&genesis, &ecdh_key).unwrap();
+        // The test asset is issued first
+        let alice: [u8; 32] = AccountIdWrapper::from_hex(ALICE).0.into();
+        assert_eq!(
+            asset_balance(&mut assets, 0, alice),
+            Some(TEST_ASSET_SUPPLY)
+        );
+        assert_eq!(asset_balance(&mut assets, 1, [1u8; 32]), Some(1000));
+        // The Diem master account is taken
+        assert!(diem
+            .set_master(AccountIdWrapper::from(&alice[..]), &[1u8; 32])
+            .is_err());
+        // But not the Web3Analytics key
+        assert!(web3analytics.set_key(&[1u8; 32]).is_ok());
+
+        // The fixtures can't be applied twice
+        let duplicated = ContractsGenesis {
+            dev_fixtures: true,
+            diem_master: Some(diem_master(&ecdh_key)),
+            ..Default::default()
+        };
+        assert!(apply_new(&duplicated, &ecdh_key).is_err());
+    }
+}
@@ -1,11 +1,14 @@
 use crate::std::fmt::Debug;
 use crate::std::string::String;
+use crate::std::vec::Vec;
 
 use super::TransactionStatus;
+use crate::chain;
 use crate::types::TxRef;
 use anyhow::Result;
 use core::{fmt, str};
 use parity_scale_codec::{Decode, Encode};
+use rand::{rngs::StdRng, SeedableRng};
 use serde::{
     de::{self, DeserializeOwned, Visitor},
     Deserialize, Deserializer, Serialize, Serializer,
@@ -15,6 +18,9 @@ pub mod assets;
 pub mod balances;
 pub mod data_plaza;
 pub mod diem;
+pub mod genesis;
+#[cfg(test)]
+pub(crate) mod test_helpers;
 pub mod web3analytics;
 pub mod woothee;
 
@@ -41,13 +47,91 @@ where
         cmd: Cmd,
     ) -> TransactionStatus;
     fn handle_query(&mut self, origin: Option<&chain::AccountId>, req: QReq) -> QResp;
-    fn handle_event(&mut self, _re: runtime::Event) {}
+    fn handle_event(&mut self, _message: &chain::ChainMessage) {}
+    /// Handles a message of a chain added by `add_bridge`. Returns whether the state is changed.
+    fn handle_bridge_event(&mut self, _bridge_id: u64, _message: &chain::ChainMessage) -> bool {
+        false
+    }
+    /// The storage keys of the main chain to read at every block, handed to
+    /// `handle_storage_reads` with their proven values
+    fn storage_keys(&self) -> Vec<Vec<u8>> {
+        Vec::new()
+    }
+    /// Handles the values of `storage_keys` at a block, `None` for the absent ones. Returns
+    /// whether the state is changed.
+    fn handle_storage_reads(
+        &mut self,
+        _blocknum: chain::BlockNumber,
+        _reads: &[(Vec<u8>, Option<Vec<u8>>)],
+    ) -> bool {
+        false
+    }
+}
+
+/// A contract state in its binary encoding, tagged with the schema version
+#[derive(Encode, Decode, Debug, Clone, PartialEq)]
+pub struct EncodedState {
+    pub id: ContractId,
+    pub version: u32,
+    pub data: Vec<u8>,
+}
+
+/// The persistent state of a contract, encoded in CBOR in the checkpoints
+pub trait ContractState: Serialize + DeserializeOwned {
+    /// Bump it when the layout of the state changes, and convert the old states in `migrate`
+    const SCHEMA_VERSION: u32;
+
+    /// Converts a state encoded with an older schema version
+    fn migrate(version: u32, _data: &[u8]) -> Result<Self> {
+        Err(anyhow::Error::msg(format!(
+            "Can't migrate from schema version {}",
+            version
+        )))
+    }
+
+    fn encode_state(&self, id: ContractId) -> Result<EncodedState> {
+        let data = serde_cbor::to_vec(self).map_err(|e| anyhow::Error::msg(e.to_string()))?;
+        Ok(EncodedState {
+            id,
+            version: Self::SCHEMA_VERSION,
+            data,
+        })
+    }
+
+    fn decode_state(encoded: &EncodedState) -> Result<Self> {
+        if encoded.version == Self::SCHEMA_VERSION {
+            serde_cbor::from_slice(&encoded.data).map_err(|e| anyhow::Error::msg(e.to_string()))
+        } else if encoded.version < Self::SCHEMA_VERSION {
+            info!(
+                "Migrating contract {} from schema version {} to {}",
+                encoded.id,
+                encoded.version,
+                Self::SCHEMA_VERSION
+            );
+            Self::migrate(encoded.version, &encoded.data)
+        } else {
+            Err(anyhow::Error::msg(format!(
+                "Unknown schema version {} of contract {}",
+                encoded.version, encoded.id
+            )))
+        }
+    }
 }
 
-pub fn account_id_from_hex(accid_hex: &String) -> Result<chain::AccountId> {
-    use core::convert::TryFrom;
-    let bytes = crate::hex::decode_hex(accid_hex);
-    chain::AccountId::try_from(bytes.as_slice()).map_err(|_| anyhow::Error::msg(""))
+/// Deterministic randomness for the contracts
+///
+/// The seed is derived from the contract key, the position of the transaction in the chain and
+/// the `context` of the command (e.g. the origin and its arguments), so every pRuntime processing
+/// the same blocks gets the same random numbers, while they stay unpredictable to anyone without
+/// the contract key. The contract key must be a secret only known to the enclaves. Never use
+/// `OsRng` or random IVs to produce contract states, or the pRuntimes would diverge.
+pub fn deterministic_rng(contract_key: &[u8], txref: &TxRef, context: &[u8]) -> StdRng {
+    let mut material = b"phala_contract_rng".to_vec();
+    material.extend_from_slice(contract_key);
+    material.extend(txref.blocknum.encode());
+    material.extend(txref.index.encode());
+    material.extend(context.encode());
+    StdRng::from_seed(sp_core::hashing::blake2_256(&material))
 }
 
 /// Serde module to serialize or deserialize parity scale codec types
@@ -142,8 +226,13 @@ impl Serialize for AccountIdWrapper {
     where
         S: Serializer,
     {
-        let data_hex = self.to_string();
-        serializer.serialize_str(&data_hex)
+        // Raw bytes in the binary formats, to keep the checkpoints compact
+        if serializer.is_human_readable() {
+            let data_hex = self.to_string();
+            serializer.serialize_str(&data_hex)
+        } else {
+            serializer.serialize_bytes(self.0.as_ref())
+        }
     }
 }
 
@@ -152,7 +241,11 @@ impl<'de> Deserialize<'de> for AccountIdWrapper {
     where
         D: Deserializer<'de>,
     {
-        deserializer.deserialize_str(AcidVisitor)
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(AcidVisitor)
+        } else {
+            deserializer.deserialize_bytes(AcidVisitor)
+        }
     }
 }
 
@@ -176,4 +269,85 @@ impl<'de> Visitor<'de> for AcidVisitor {
             Err(E::custom(format!("AccountId hex length wrong: {}", v)))
         }
     }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        if v.len() == 32 {
+            Ok(AccountIdWrapper::from(v))
+        } else {
+            Err(E::custom(format!("AccountId length wrong: {}", v.len())))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct StateV1 {
+        owner: AccountIdWrapper,
+        total: u128,
+    }
+    impl ContractState for StateV1 {
+        const SCHEMA_VERSION: u32 = 1;
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct StateV2 {
+        owner: AccountIdWrapper,
+        total: u128,
+        frozen: bool,
+    }
+    impl ContractState for StateV2 {
+        const SCHEMA_VERSION: u32 = 2;
+
+        fn migrate(version: u32, data: &[u8]) -> Result<Self> {
+            match version {
+                1 => {
+                    let old: StateV1 = serde_cbor::from_slice(data)
+                        .map_err(|e| anyhow::Error::msg(e.to_string()))?;
+                    Ok(StateV2 {
+                        owner: old.owner,
+                        total: old.total,
+                        frozen: false,
+                    })
+                }
+                _ => Err(anyhow::Error::msg("Unknown version")),
+            }
+        }
+    }
+
+    fn state_v1() -> StateV1 {
+        StateV1 {
+            owner: AccountIdWrapper::from(&[1u8; 32]),
+            // Larger than u64
+            total: u128::max_value() - 1,
+        }
+    }
+
+    #[test]
+    fn test_encode_decode_state() {
+        let state = state_v1();
+        let encoded = state.encode_state(1).unwrap();
+        assert_eq!(encoded.version, 1);
+        // The account id is encoded as raw bytes rather than hex
+        assert!(encoded.data.len() < 64);
+        assert_eq!(StateV1::decode_state(&encoded).unwrap(), state);
+    }
+
+    #[test]
+    fn test_migrate_state() {
+        let state = state_v1();
+        let encoded = state.encode_state(1).unwrap();
+        let migrated = StateV2::decode_state(&encoded).unwrap();
+        assert_eq!(migrated.owner, state.owner);
+        assert_eq!(migrated.total, state.total);
+        assert!(!migrated.frozen);
+        // Newer states can't be downgraded
+        let encoded = migrated.encode_state(1).unwrap();
+        assert!(StateV1::decode_state(&encoded).is_err());
+    }
 }
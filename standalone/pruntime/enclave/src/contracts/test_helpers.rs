@@ -0,0 +1,14 @@
+//! Fixtures shared by the tests of the contracts
+
+use crate::chain;
+use crate::types::TxRef;
+
+/// The account whose public key is filled with `n`
+pub fn account(n: u8) -> chain::AccountId {
+    chain::AccountId::new([n; 32])
+}
+
+/// The position of the `index`th command in block 1
+pub fn txref(index: u64) -> TxRef {
+    TxRef { blocknum: 1, index }
+}
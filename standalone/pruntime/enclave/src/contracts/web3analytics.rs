@@ -1,14 +1,16 @@
 use super::TransactionStatus;
+use crate::chain;
 use crate::contracts::AccountIdWrapper;
 use crate::cryptography::aead;
-use crate::hex;
-use crate::std::collections::BTreeMap;
 use crate::std::collections::HashMap;
+use crate::std::collections::{BTreeMap, BTreeSet};
 use crate::std::prelude::v1::*;
 use crate::std::vec::Vec;
 use anyhow::Result;
 use core::fmt;
+use parity_scale_codec::{Decode, Encode};
 use serde::{Deserialize, Serialize};
+use sp_core::hashing::blake2_256;
 
 use crate::contracts;
 use crate::types::TxRef;
@@ -23,7 +25,39 @@ const HOUR_IN_SECONDS: u32 = 60 * MINUTE_IN_SECONDS;
 const DAY_IN_SECONDS: u32 = 24 * HOUR_IN_SECONDS;
 const WEEK_IN_SECONDS: u32 = 7 * DAY_IN_SECONDS;
 
-const KEY: &str = "290c3c5d812a4ba7ce33adf09598a462692a615beb6c80fdafb3f9e3bbef8bc6";
+/// How long the page views of a site are kept by default
+pub const DEFAULT_RETENTION: Timestamp = 2 * WEEK_IN_SECONDS;
+pub const MAX_RETENTION: Timestamp = 12 * WEEK_IN_SECONDS;
+/// How far the page views can be ahead of the chain time, for the clocks of the clients
+pub const MAX_CLOCK_SKEW: Timestamp = 5 * MINUTE_IN_SECONDS;
+
+/// A field of the page views and the statistics
+///
+/// The fields of a site registered as `encrypted` are sealed with the key of the site, so the
+/// tracking server and its database only hold ciphers. The fields of the other sites are plain.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum Field {
+    Plain(String),
+    Sealed(Cipher),
+}
+
+impl Default for Field {
+    fn default() -> Self {
+        Field::Plain(String::new())
+    }
+}
+
+/// A value sealed by AES-256-GCM with a key of its site
+///
+/// The sid and the key version are authenticated along with the value, so a cipher can't be
+/// opened on another site or with another key.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Cipher {
+    /// The version of the site key, increased by every `RotateKey`
+    pub key_version: u32,
+    pub iv_b64: String,
+    pub data_b64: String,
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PageView {
@@ -32,43 +66,43 @@ pub struct PageView {
     cid: String,
     uid: String,
     host: String,
-    path: String,
+    path: Field,
     referrer: String,
-    ip: String,
-    user_agent: String,
+    ip: Field,
+    user_agent: Field,
     created_at: Timestamp,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct OnlineUser {
     sid: Sid,
-    cid_count: String,
-    ip_count: String,
+    cid_count: Field,
+    ip_count: Field,
     timestamp: u32,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct HourlyPageViewStat {
     sid: Sid,
-    pv_count: String,
-    cid_count: String,
-    avg_duration: String,
+    pv_count: Field,
+    cid_count: Field,
+    avg_duration: Field,
     timestamp: u32,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct WeeklySite {
     sid: Sid,
-    path: String,
-    count: String,
+    path: Field,
+    count: Field,
     timestamp: u32,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct WeeklyDevice {
     sid: Sid,
-    device: String,
-    count: String,
+    device: Field,
+    count: Field,
     timestamp: u32,
 }
 
@@ -121,10 +155,173 @@ impl DailyStat {
     }
 }
 
+/// A site registered by its owner, whose page views and statistics are only visible to the owner
+/// and its API keys
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Site {
+    owner: AccountIdWrapper,
+    /// The accounts ingesting the page views on behalf of the owner
+    api_keys: BTreeSet<AccountIdWrapper>,
+    /// Whether the fields of the page views and the statistics are sealed with the site key
+    encrypted: bool,
+    /// The version of the site key sealing the new fields
+    key_version: u32,
+    /// How long the page views are kept, back from the chain time
+    retention: Timestamp,
+    /// The oldest first
+    page_views: Vec<PageView>,
+    /// The ids of `page_views`, to drop the duplicates
+    #[serde(default)]
+    page_view_ids: BTreeSet<String>,
+}
+
+impl Site {
+    fn is_member(&self, account: &AccountIdWrapper) -> bool {
+        &self.owner == account || self.api_keys.contains(account)
+    }
+
+    /// Adds a page view, unless the one with the same id is kept
+    fn add(&mut self, page_view: PageView) {
+        if self.page_view_ids.insert(page_view.id.clone()) {
+            self.page_views.push(page_view);
+        }
+    }
+
+    /// Drops the page views older than the retention back from `now`. Returns whether any is
+    /// dropped.
+    fn prune(&mut self, now: Timestamp) -> bool {
+        let oldest = now.saturating_sub(self.retention);
+        let expired = self
+            .page_views
+            .iter()
+            .take_while(|pv| pv.created_at < oldest)
+            .count();
+        for pv in self.page_views.drain(..expired) {
+            self.page_view_ids.remove(&pv.id);
+        }
+        expired > 0
+    }
+}
+
+/// Seals and opens the fields of a site
+///
+/// The site keys are derived from the contract key, the sid and the key version, so they never
+/// have to be stored, and the older versions stay available after a rotation.
+struct Sealer<'a> {
+    contract_key: &'a [u8],
+    sid: &'a Sid,
+    key_version: u32,
+    encrypted: bool,
+}
+
+impl<'a> Sealer<'a> {
+    fn new(contract_key: Option<&'a [u8]>, sid: &'a Sid, site: &Site) -> Result<Self> {
+        let contract_key = match contract_key {
+            Some(key) => key,
+            None if site.encrypted => return Err(anyhow::Error::msg(Error::NoContractKey)),
+            None => &[],
+        };
+        Ok(Sealer {
+            contract_key,
+            sid,
+            key_version: site.key_version,
+            encrypted: site.encrypted,
+        })
+    }
+
+    fn key(&self, key_version: u32) -> [u8; 32] {
+        let mut material = b"phala/web3analytics/site_key".to_vec();
+        material.extend_from_slice(self.contract_key);
+        material.extend(self.sid.encode());
+        material.extend(key_version.encode());
+        blake2_256(&material)
+    }
+
+    fn aad(&self, key_version: u32) -> Vec<u8> {
+        let mut aad = self.sid.encode();
+        aad.extend(key_version.encode());
+        aad
+    }
+
+    /// Seals the value with the current key if the site is encrypted
+    ///
+    /// The IV is derived from the value, so every pRuntime seals the stored page views into the
+    /// same ciphers.
+    fn seal(&self, value: String) -> Field {
+        if !self.encrypted {
+            return Field::Plain(value);
+        }
+        let key = self.key(self.key_version);
+        let mut data = value.into_bytes();
+        let iv = aead::derive_iv(&key, &data);
+        aead::encrypt_with_aad(&iv, &key, &self.aad(self.key_version), &mut data);
+        Field::Sealed(Cipher {
+            key_version: self.key_version,
+            iv_b64: base64::encode(&iv),
+            data_b64: base64::encode(&data),
+        })
+    }
+
+    /// Opens a field, which must be sealed if and only if the site is encrypted
+    fn open(&self, field: &Field) -> Result<String> {
+        let cipher = match (field, self.encrypted) {
+            (Field::Plain(value), false) => return Ok(value.clone()),
+            (Field::Sealed(cipher), true) => cipher,
+            _ => return Err(anyhow::Error::msg(Error::BadField)),
+        };
+        if cipher.key_version > self.key_version {
+            return Err(anyhow::Error::msg(Error::BadCipher));
+        }
+        let iv =
+            base64::decode(&cipher.iv_b64).map_err(|_| anyhow::Error::msg(Error::BadCipher))?;
+        let mut data =
+            base64::decode(&cipher.data_b64).map_err(|_| anyhow::Error::msg(Error::BadCipher))?;
+        if iv.len() != aead::IV_BYTES {
+            return Err(anyhow::Error::msg(Error::BadCipher));
+        }
+        let mut iv_arr = [0u8; aead::IV_BYTES];
+        iv_arr.copy_from_slice(&iv);
+        let key = self.key(cipher.key_version);
+        let value = aead::decrypt_with_aad(&iv_arr, &key, &self.aad(cipher.key_version), &mut data)
+            .map_err(|_| anyhow::Error::msg(Error::BadCipher))?;
+        String::from_utf8(value.to_vec()).map_err(|_| anyhow::Error::msg(Error::BadField))
+    }
+
+    /// Opens a field holding a number
+    fn count(&self, field: &Field) -> Result<u32> {
+        self.open(field)?
+            .parse::<u32>()
+            .map_err(|_| anyhow::Error::msg(Error::BadField))
+    }
+
+    /// Checks the fields of a page view open
+    fn check(&self, page_view: &PageView) -> Result<()> {
+        self.open(&page_view.path)?;
+        self.open(&page_view.ip)?;
+        self.open(&page_view.user_agent)?;
+        Ok(())
+    }
+
+    /// Seals the fields of a page view again with the key of `to`
+    fn reseal(&self, page_view: &mut PageView, to: &Sealer) -> Result<()> {
+        page_view.path = to.seal(self.open(&page_view.path)?);
+        page_view.ip = to.seal(self.open(&page_view.ip)?);
+        page_view.user_agent = to.seal(self.open(&page_view.user_agent)?);
+        Ok(())
+    }
+}
+
 // contract
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Error {
     NotAuthorized,
+    /// The contract key isn't set by the genesis, so the encrypted sites can't be served
+    NoContractKey,
+    NotEncrypted,
+    /// A field is plain on an encrypted site, sealed on a plain site, or not a number
+    BadField,
+    /// A cipher is malformed, tampered, or sealed with another key
+    BadCipher,
     Other(String),
 }
 
@@ -132,6 +329,10 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::NotAuthorized => write!(f, "not authorized"),
+            Error::NoContractKey => write!(f, "no contract key"),
+            Error::NotEncrypted => write!(f, "the site isn't encrypted"),
+            Error::BadField => write!(f, "bad field"),
+            Error::BadCipher => write!(f, "bad cipher"),
             Error::Other(e) => write!(f, "{}", e),
         }
     }
@@ -139,78 +340,126 @@ impl fmt::Display for Error {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Command {
-    SetConfiguration { skip_stat: bool },
+    SetConfiguration {
+        skip_stat: bool,
+    },
+    /// Registers a site owned by the origin
+    RegisterSite {
+        sid: Sid,
+        encrypted: bool,
+    },
+    /// Allows an account to ingest the page views of a site. Only by the owner.
+    AddApiKey {
+        sid: Sid,
+        api_key: AccountIdWrapper,
+    },
+    /// Only by the owner
+    RemoveApiKey {
+        sid: Sid,
+        api_key: AccountIdWrapper,
+    },
+    /// Keeps the page views of a site for `retention` seconds, up to `MAX_RETENTION`. Only by the
+    /// owner.
+    SetRetention {
+        sid: Sid,
+        retention: Timestamp,
+    },
+    /// Adds the page views of a site, by the owner or an API key. The fields of the page views
+    /// must open on the site.
+    IngestPageViews {
+        sid: Sid,
+        page_views: Vec<PageView>,
+    },
+    /// Moves an encrypted site to a new key and seals the stored page views with it. The fields
+    /// sealed with the older keys still open. Only by the owner.
+    RotateKey {
+        sid: Sid,
+    },
 }
 
+/// Only the owner and the API keys of a site can query its statistics
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Request {
-    SetPageView {
-        page_views: Vec<PageView>,
-        encrypted: bool,
+    GetSite {
+        sid: Sid,
     },
-    ClearPageView {
-        timestamp: Timestamp,
+    /// The current key of an encrypted site, to seal the page views before the ingestion
+    GetSiteKey {
+        sid: Sid,
     },
     GetOnlineUsers {
+        sid: Sid,
         start: Timestamp,
         end: Timestamp,
     },
     GetHourlyStats {
+        sid: Sid,
         start: Timestamp,
         end: Timestamp,
         start_of_week: Timestamp,
     },
     GetDailyStats {
+        sid: Sid,
         daily_stat: DailyStat,
     },
     GetWeeklySites {
+        sid: Sid,
         weekly_sites_in_db: Vec<WeeklySite>,
         weekly_sites_new: Vec<WeeklySite>,
     },
     GetWeeklyDevices {
+        sid: Sid,
         weekly_devices_in_db: Vec<WeeklyDevice>,
         weekly_devices_new: Vec<WeeklyDevice>,
     },
     GetTotalStat {
+        sid: Sid,
         total_stat: HourlyPageViewStat,
-        count: String,
+        count: Option<Field>,
     },
     GetConfiguration {
         account: AccountIdWrapper,
     },
 }
 
+impl Request {
+    /// Whether the request reveals a site key, so it must be encrypted to the worker
+    pub fn is_confidential(&self) -> bool {
+        matches!(self, Request::GetSiteKey { .. })
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Response {
-    SetPageView {
+    GetSite {
+        owner: AccountIdWrapper,
+        api_keys: Vec<AccountIdWrapper>,
+        retention: Timestamp,
         page_view_count: u32,
+        encrypted: bool,
+        key_version: u32,
     },
-    ClearPageView {
-        page_view_count: u32,
+    GetSiteKey {
+        key_version: u32,
+        key_b64: String,
     },
     GetOnlineUsers {
         online_users: Vec<OnlineUser>,
-        encrypted: bool,
     },
     GetHourlyStats {
         hourly_stat: HourlyStat,
-        encrypted: bool,
     },
     GetDailyStats {
         daily_stat: DailyStat,
-        encrypted: bool,
     },
     GetWeeklySites {
         weekly_sites: Vec<WeeklySite>,
-        encrypted: bool,
     },
     GetWeeklyDevices {
         weekly_devices: Vec<WeeklyDevice>,
-        encrypted: bool,
     },
     GetTotalStat {
         total_stat: HourlyPageViewStat,
-        encrypted: bool,
     },
     GetConfiguration {
         skip_stat: bool,
@@ -221,48 +470,140 @@ pub enum Response {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Web3Analytics {
-    encrypted: bool,
-    page_views: Vec<PageView>,
-    online_users: Vec<OnlineUser>,
-    hourly_stat: HourlyStat,
-    daily_stat: DailyStat,
-    weekly_sites: Vec<WeeklySite>,
-    weekly_devices: Vec<WeeklyDevice>,
-    total_stat: HourlyPageViewStat,
+    sites: BTreeMap<Sid, Site>,
 
-    key: Vec<u8>,
+    /// The secret deriving the site keys, set by the genesis
+    key: Option<Vec<u8>>,
     #[serde(skip)]
     parser: woothee::parser::Parser,
 
     no_tracking: BTreeMap<AccountIdWrapper, bool>,
+
+    /// The chain time in seconds, read from `Timestamp::Now` at every block before its commands
+    #[serde(skip)]
+    now: Timestamp,
+}
+
+/// The state before the sites were added, whose page views had no owners
+#[derive(Deserialize)]
+struct Web3AnalyticsV1 {
+    no_tracking: BTreeMap<AccountIdWrapper, bool>,
+}
+
+/// The state before the site keys, whose fields were sealed with a constant key
+#[derive(Deserialize)]
+struct Web3AnalyticsV2 {
+    sites: BTreeMap<Sid, SiteV2>,
+    no_tracking: BTreeMap<AccountIdWrapper, bool>,
+}
+
+#[derive(Deserialize)]
+struct SiteV2 {
+    owner: AccountIdWrapper,
+    api_keys: BTreeSet<AccountIdWrapper>,
+    encrypted: bool,
+    retention: Timestamp,
+    page_views: Vec<PageViewV2>,
+}
+
+/// A page view before the fields were sealed with the site keys
+#[derive(Deserialize)]
+struct PageViewV2 {
+    id: String,
+    sid: Sid,
+    cid: String,
+    uid: String,
+    host: String,
+    path: String,
+    referrer: String,
+    ip: String,
+    user_agent: String,
+    created_at: Timestamp,
+}
+
+impl From<PageViewV2> for PageView {
+    fn from(pv: PageViewV2) -> Self {
+        PageView {
+            id: pv.id,
+            sid: pv.sid,
+            cid: pv.cid,
+            uid: pv.uid,
+            host: pv.host,
+            path: Field::Plain(pv.path),
+            referrer: pv.referrer,
+            ip: Field::Plain(pv.ip),
+            user_agent: Field::Plain(pv.user_agent),
+            created_at: pv.created_at,
+        }
+    }
 }
 
 impl Web3Analytics {
     pub fn new() -> Self {
         Self {
-            encrypted: false,
-            page_views: Vec::<PageView>::new(),
-            online_users: Vec::<OnlineUser>::new(),
-            hourly_stat: HourlyStat::new(),
-            daily_stat: DailyStat::new(),
-            weekly_sites: Vec::<WeeklySite>::new(),
-            weekly_devices: Vec::<WeeklyDevice>::new(),
-            total_stat: HourlyPageViewStat::default(),
+            sites: BTreeMap::new(),
 
-            key: hex::decode_hex(KEY),
+            key: None,
 
             parser: woothee::parser::Parser::new(),
 
             no_tracking: BTreeMap::<AccountIdWrapper, bool>::new(),
+
+            now: 0,
+        }
+    }
+
+    /// Sets the contract key, which can't be changed once the sites are sealed with it
+    pub fn set_key(&mut self, key: &[u8]) -> Result<()> {
+        if self.key.is_some() {
+            return Err(anyhow::Error::msg("The contract key is already set"));
+        }
+        if key.len() != 32 {
+            return Err(anyhow::Error::msg("The contract key must be 32 bytes"));
+        }
+        self.key = Some(key.to_vec());
+        Ok(())
+    }
+
+    fn sealer<'a>(&'a self, sid: &'a Sid, site: &Site) -> Result<Sealer<'a>> {
+        Sealer::new(self.key.as_deref(), sid, site)
+    }
+
+    /// The site queried by `origin`, if it's a member
+    fn site(&self, sid: &Sid, origin: Option<&chain::AccountId>) -> Result<&Site> {
+        let origin = origin.map(|o| AccountIdWrapper(o.clone()));
+        self.sites
+            .get(sid)
+            .filter(|site| origin.map_or(false, |o| site.is_member(&o)))
+            .ok_or_else(|| anyhow::Error::msg(Error::NotAuthorized))
+    }
+
+    /// The site managed by `origin`, which must be its owner
+    fn owned_site(
+        &mut self,
+        sid: &Sid,
+        origin: &AccountIdWrapper,
+    ) -> Result<&mut Site, TransactionStatus> {
+        match self.sites.get_mut(sid) {
+            Some(site) if &site.owner == origin => Ok(site),
+            Some(_) => Err(TransactionStatus::NotSiteOwner),
+            None => Err(TransactionStatus::SiteNotFound),
         }
     }
 
-    pub fn update_online_users(&mut self, start: Timestamp, end: Timestamp) {
+    /// The clients and IPs of the site online in every minute between `start` and `end`
+    fn online_users(
+        &self,
+        site: &Site,
+        sealer: &Sealer,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Result<Vec<OnlineUser>> {
         let mut sids = Vec::<Sid>::new();
         let mut cid_map = HashMap::<(Sid, Timestamp), Vec<String>>::new();
         let mut ip_map = HashMap::<(Sid, Timestamp), Vec<String>>::new();
 
-        for pv in self.page_views.clone() {
+        for pv in site.page_views.clone() {
             if pv.created_at < start {
                 continue;
             }
@@ -288,11 +629,7 @@ impl Web3Analytics {
             }
             cid_map.insert((pv.sid.clone(), ca), cids);
 
-            let ip = if self.encrypted {
-                self.decrypt(pv.ip)
-            } else {
-                pv.ip
-            };
+            let ip = sealer.open(&pv.ip)?;
             let mut ips = Vec::<String>::new();
             if ip_map.contains_key(&(pv.sid.clone(), ca)) {
                 ips = ip_map.get(&(pv.sid.clone(), ca)).unwrap().clone();
@@ -305,7 +642,7 @@ impl Web3Analytics {
             ip_map.insert((pv.sid.clone(), ca), ips);
         }
 
-        self.online_users.clear();
+        let mut online_users = Vec::new();
 
         let mut index = start.clone();
         while index < end {
@@ -316,34 +653,31 @@ impl Web3Analytics {
 
                 let cids = cid_map.get(&(sid.clone(), index)).unwrap();
                 let ips = ip_map.get(&(sid.clone(), index)).unwrap();
-                let cid_count = if self.encrypted {
-                    self.encrypt(cids.len().to_string())
-                } else {
-                    cids.len().to_string()
-                };
-                let ip_count = if self.encrypted {
-                    self.encrypt(ips.len().to_string())
-                } else {
-                    ips.len().to_string()
-                };
+                let cid_count = sealer.seal(cids.len().to_string());
+                let ip_count = sealer.seal(ips.len().to_string());
                 let ou = OnlineUser {
                     sid,
                     cid_count,
                     ip_count,
                     timestamp: index,
                 };
-                self.online_users.push(ou);
+                online_users.push(ou);
             }
             index += MINUTE_IN_SECONDS;
         }
+        Ok(online_users)
     }
 
-    pub fn update_hourly_stats(
-        &mut self,
+    /// The statistics of the site by the hour between `start_s` and `end_s`, and by the week since
+    /// `start_of_week`
+    fn hourly_stats(
+        &self,
+        site: &Site,
+        sealer: &Sealer,
         start_s: Timestamp,
         end_s: Timestamp,
         start_of_week: Timestamp,
-    ) {
+    ) -> Result<HourlyStat> {
         let mut sids = Vec::<Sid>::new();
         let mut sid_map = HashMap::<Sid, Vec<String>>::new();
         let mut cid_weekly_map = HashMap::<(Sid, Timestamp), Vec<String>>::new();
@@ -359,7 +693,7 @@ impl Web3Analytics {
         let start = start_s / HOUR_IN_SECONDS * HOUR_IN_SECONDS;
         let end = end_s / HOUR_IN_SECONDS * HOUR_IN_SECONDS;
 
-        for pv in self.page_views.clone() {
+        for pv in site.page_views.clone() {
             if pv.created_at <= start {
                 continue;
             }
@@ -412,11 +746,7 @@ impl Web3Analytics {
             }
             sid_map.insert(pv.sid.clone(), cids);
 
-            let path = if self.encrypted {
-                self.decrypt(pv.path)
-            } else {
-                pv.path
-            };
+            let path = sealer.open(&pv.path)?;
             let mut paths = Vec::<String>::new();
             if path_map.contains_key(&pv.sid) {
                 paths = path_map.get(&pv.sid).unwrap().clone();
@@ -428,11 +758,7 @@ impl Web3Analytics {
             }
             path_map.insert(pv.sid.clone(), paths);
 
-            let user_agent = if self.encrypted {
-                self.decrypt(pv.user_agent)
-            } else {
-                pv.user_agent
-            };
+            let user_agent = sealer.open(&pv.user_agent)?;
             let device = match self.parser.parse(&user_agent) {
                 Some(wr) => wr.name.to_string(),
                 None => "Unknown".to_string(),
@@ -485,7 +811,7 @@ impl Web3Analytics {
             }
         }
 
-        self.hourly_stat = HourlyStat::new();
+        let mut hourly_stat = HourlyStat::new();
 
         let mut hpv = Vec::<HourlyPageViewStat>::new();
         let mut index = start;
@@ -513,21 +839,9 @@ impl Web3Analytics {
                 let avg_duration_str = (total_duration / (cids.len() as u32)).to_string();
 
                 let pc = pv_count_map.get(&(sid.clone(), index)).unwrap();
-                let cid_count = if self.encrypted {
-                    self.encrypt(cids.len().to_string())
-                } else {
-                    cids.len().to_string()
-                };
-                let pv_count = if self.encrypted {
-                    self.encrypt((*pc).to_string())
-                } else {
-                    (*pc).to_string()
-                };
-                let avg_duration = if self.encrypted {
-                    self.encrypt(avg_duration_str)
-                } else {
-                    avg_duration_str
-                };
+                let cid_count = sealer.seal(cids.len().to_string());
+                let pv_count = sealer.seal((*pc).to_string());
+                let avg_duration = sealer.seal(avg_duration_str);
                 let hs = HourlyPageViewStat {
                     sid,
                     cid_count,
@@ -539,7 +853,7 @@ impl Web3Analytics {
             }
             index += HOUR_IN_SECONDS;
         }
-        self.hourly_stat.hourly_page_view_stats = hpv;
+        hourly_stat.hourly_page_view_stats = hpv;
 
         let mut site_clients = Vec::<SiteClient>::new();
         for sid in sids.clone() {
@@ -548,7 +862,7 @@ impl Web3Analytics {
 
             site_clients.push(sc);
         }
-        self.hourly_stat.site_clients = site_clients;
+        hourly_stat.site_clients = site_clients;
 
         let mut wcs = Vec::<WeeklyClient>::new();
         let mut index = start_of_week.clone();
@@ -568,7 +882,7 @@ impl Web3Analytics {
             }
             index += WEEK_IN_SECONDS;
         }
-        self.hourly_stat.weekly_clients = wcs;
+        hourly_stat.weekly_clients = wcs;
 
         let mut wss = Vec::<WeeklySite>::new();
         index = start_of_week.clone();
@@ -583,16 +897,8 @@ impl Web3Analytics {
                     let count = path_weekly_map
                         .get(&(sid.clone(), p.clone(), index))
                         .unwrap();
-                    let path = if self.encrypted {
-                        self.encrypt(p.clone())
-                    } else {
-                        p.clone()
-                    };
-                    let count = if self.encrypted {
-                        self.encrypt((*count).to_string())
-                    } else {
-                        (*count).to_string()
-                    };
+                    let path = sealer.seal(p.clone());
+                    let count = sealer.seal((*count).to_string());
                     let ws = WeeklySite {
                         sid: sid.clone(),
                         path,
@@ -604,7 +910,7 @@ impl Web3Analytics {
             }
             index += WEEK_IN_SECONDS;
         }
-        self.hourly_stat.weekly_sites = wss;
+        hourly_stat.weekly_sites = wss;
 
         let mut wds = Vec::<WeeklyDevice>::new();
         index = start_of_week.clone();
@@ -619,16 +925,8 @@ impl Web3Analytics {
                     let count = device_weekly_map
                         .get(&(sid.clone(), dev.clone(), index))
                         .unwrap();
-                    let device = if self.encrypted {
-                        self.encrypt(dev.clone())
-                    } else {
-                        dev.clone()
-                    };
-                    let count = if self.encrypted {
-                        self.encrypt((*count).to_string())
-                    } else {
-                        (*count).to_string()
-                    };
+                    let device = sealer.seal(dev.clone());
+                    let count = sealer.seal((*count).to_string());
                     let wd = WeeklyDevice {
                         sid: sid.clone(),
                         device,
@@ -640,10 +938,12 @@ impl Web3Analytics {
             }
             index += WEEK_IN_SECONDS;
         }
-        self.hourly_stat.weekly_devices = wds;
+        hourly_stat.weekly_devices = wds;
+        Ok(hourly_stat)
     }
 
-    fn update_daily_stats(&mut self, daily_stat: DailyStat) {
+    /// Sums up the hourly statistics of the site by the day
+    fn daily_stats(&self, sealer: &Sealer, daily_stat: DailyStat) -> Result<DailyStat> {
         let mut sids = Vec::<Sid>::new();
         let mut daily_map = HashMap::<(Sid, Timestamp), (u32, u32, u32)>::new();
         let mut first_date: Timestamp = 0;
@@ -661,24 +961,9 @@ impl Web3Analytics {
                 sids.push(sid.clone());
             }
 
-            let pv_count = if self.encrypted {
-                self.decrypt(hourly_stat.pv_count)
-            } else {
-                hourly_stat.pv_count
-            };
-            let cid_count = if self.encrypted {
-                self.decrypt(hourly_stat.cid_count)
-            } else {
-                hourly_stat.cid_count
-            };
-            let avg_duration = if self.encrypted {
-                self.decrypt(hourly_stat.avg_duration)
-            } else {
-                hourly_stat.avg_duration
-            };
-            let mut hourly_stat_pv_count = pv_count.parse::<u32>().unwrap();
-            let mut hourly_stat_cid_count = cid_count.parse::<u32>().unwrap();
-            let mut hourly_stat_avg_duration = avg_duration.parse::<u32>().unwrap();
+            let mut hourly_stat_pv_count = sealer.count(&hourly_stat.pv_count)?;
+            let mut hourly_stat_cid_count = sealer.count(&hourly_stat.cid_count)?;
+            let mut hourly_stat_avg_duration = sealer.count(&hourly_stat.avg_duration)?;
             if daily_map.contains_key(&(sid.clone(), ts)) {
                 let (pv_count0, cid_count0, avg_duration0) =
                     daily_map.get(&(sid.clone(), ts)).unwrap().clone();
@@ -696,12 +981,11 @@ impl Web3Analytics {
             );
         }
 
+        let mut result = DailyStat::new();
         if first_date == 0 {
-            return;
+            return Ok(result);
         }
 
-        self.daily_stat = DailyStat::new();
-
         let mut dss = Vec::<HourlyPageViewStat>::new();
         while first_date <= last_date {
             for sid in sids.clone() {
@@ -712,21 +996,9 @@ impl Web3Analytics {
                         .clone();
                     let ds = HourlyPageViewStat {
                         sid,
-                        pv_count: if self.encrypted {
-                            self.encrypt(pv_count.to_string())
-                        } else {
-                            pv_count.to_string()
-                        },
-                        cid_count: if self.encrypted {
-                            self.encrypt(cid_count.to_string())
-                        } else {
-                            cid_count.to_string()
-                        },
-                        avg_duration: if self.encrypted {
-                            self.encrypt(avg_duration.to_string())
-                        } else {
-                            avg_duration.to_string()
-                        },
+                        pv_count: sealer.seal(pv_count.to_string()),
+                        cid_count: sealer.seal(cid_count.to_string()),
+                        avg_duration: sealer.seal(avg_duration.to_string()),
                         timestamp: first_date.clone(),
                     };
 
@@ -736,164 +1008,173 @@ impl Web3Analytics {
             first_date += DAY_IN_SECONDS;
         }
 
-        self.daily_stat.stats = dss;
+        result.stats = dss;
+        Ok(result)
     }
 
-    fn update_weekly_sites(
-        &mut self,
+    /// Adds up the new weekly counts of the paths of the site to the stored ones
+    fn weekly_sites(
+        &self,
+        sealer: &Sealer,
         weekly_sites_in_db: Vec<WeeklySite>,
         weekly_sites_new: Vec<WeeklySite>,
-    ) {
-        self.weekly_sites.clear();
+    ) -> Result<Vec<WeeklySite>> {
+        let mut weekly_sites = Vec::new();
 
         for ws in weekly_sites_new {
-            let path = if self.encrypted {
-                self.decrypt(ws.path.clone())
-            } else {
-                ws.path.clone()
-            };
+            let path = sealer.open(&ws.path)?;
             let mut matched = false;
             for ws_db in weekly_sites_in_db.clone() {
-                let path_db = if self.encrypted {
-                    self.decrypt(ws_db.path.clone())
-                } else {
-                    ws_db.path.clone()
-                };
+                let path_db = sealer.open(&ws_db.path)?;
                 if ws.sid == ws_db.sid && ws.timestamp == ws_db.timestamp && path == path_db {
                     matched = true;
-                    let count = if self.encrypted {
-                        self.decrypt(ws.count.clone()).parse::<u32>().unwrap()
-                    } else {
-                        ws.count.clone().parse::<u32>().unwrap()
-                    };
-                    let count_db = if self.encrypted {
-                        self.decrypt(ws_db.count).parse::<u32>().unwrap()
-                    } else {
-                        ws_db.count.parse::<u32>().unwrap()
-                    };
-                    let total = if self.encrypted {
-                        self.encrypt((count + count_db).to_string())
-                    } else {
-                        (count + count_db).to_string()
-                    };
+                    let count = sealer.count(&ws.count)?;
+                    let count_db = sealer.count(&ws_db.count)?;
+                    let total = sealer.seal((count + count_db).to_string());
                     let w = WeeklySite {
                         sid: ws.sid.clone(),
                         count: total,
                         path: ws.path.clone(),
                         timestamp: ws.timestamp,
                     };
-                    self.weekly_sites.push(w);
+                    weekly_sites.push(w);
 
                     break;
                 }
             }
 
             if !matched {
-                self.weekly_sites.push(ws);
+                weekly_sites.push(ws);
             }
         }
+        Ok(weekly_sites)
     }
 
-    fn update_weekly_devices(
-        &mut self,
+    /// Adds up the new weekly counts of the devices of the site to the stored ones
+    fn weekly_devices(
+        &self,
+        sealer: &Sealer,
         weekly_devices_in_db: Vec<WeeklyDevice>,
         weekly_devices_new: Vec<WeeklyDevice>,
-    ) {
-        self.weekly_devices.clear();
+    ) -> Result<Vec<WeeklyDevice>> {
+        let mut weekly_devices = Vec::new();
 
         for wd in weekly_devices_new {
-            let device = if self.encrypted {
-                self.decrypt(wd.device.clone())
-            } else {
-                wd.device.clone()
-            };
+            let device = sealer.open(&wd.device)?;
             let mut matched = false;
             for wd_db in weekly_devices_in_db.clone() {
-                let device_db = if self.encrypted {
-                    self.decrypt(wd_db.device.clone())
-                } else {
-                    wd_db.device.clone()
-                };
+                let device_db = sealer.open(&wd_db.device)?;
                 if wd.sid == wd_db.sid && wd.timestamp == wd_db.timestamp && device == device_db {
                     matched = true;
-                    let count = if self.encrypted {
-                        self.decrypt(wd.count.clone()).parse::<u32>().unwrap()
-                    } else {
-                        wd.count.clone().parse::<u32>().unwrap()
-                    };
-                    let count_db = if self.encrypted {
-                        self.decrypt(wd_db.count).parse::<u32>().unwrap()
-                    } else {
-                        wd_db.count.parse::<u32>().unwrap()
-                    };
-                    let total = if self.encrypted {
-                        self.encrypt((count + count_db).to_string())
-                    } else {
-                        (count + count_db).to_string()
-                    };
+                    let count = sealer.count(&wd.count)?;
+                    let count_db = sealer.count(&wd_db.count)?;
+                    let total = sealer.seal((count + count_db).to_string());
                     let w = WeeklyDevice {
                         sid: wd.sid.clone(),
                         count: total,
                         device: wd.device.clone(),
                         timestamp: wd.timestamp,
                     };
-                    self.weekly_devices.push(w);
+                    weekly_devices.push(w);
 
                     break;
                 }
             }
 
             if !matched {
-                self.weekly_devices.push(wd);
+                weekly_devices.push(wd);
             }
         }
+        Ok(weekly_devices)
     }
 
-    fn update_total_stat(&mut self, total_stat: HourlyPageViewStat, count_str: String) {
-        self.total_stat = HourlyPageViewStat::default();
-
-        if !self.encrypted {
-            return;
+    /// Adds `count` page views to the total statistics of the site, only when it's encrypted
+    fn total_stat(
+        &self,
+        sealer: &Sealer,
+        total_stat: HourlyPageViewStat,
+        count: Option<Field>,
+    ) -> Result<HourlyPageViewStat> {
+        if !sealer.encrypted {
+            return Ok(HourlyPageViewStat::default());
         }
 
-        let mut count = self.decrypt(total_stat.pv_count).parse::<u32>().unwrap();
-        if !count_str.is_empty() {
-            count += self.decrypt(count_str).parse::<u32>().unwrap();
+        let mut pv_count = sealer.count(&total_stat.pv_count)?;
+        if let Some(count) = count {
+            pv_count += sealer.count(&count)?;
         }
+        let avg_duration = sealer.count(&total_stat.avg_duration)? / 2;
 
-        let avg_duration = self
-            .decrypt(total_stat.avg_duration)
-            .parse::<u32>()
-            .unwrap()
-            / 2;
-
-        self.total_stat = HourlyPageViewStat {
+        Ok(HourlyPageViewStat {
             sid: total_stat.sid,
             cid_count: total_stat.cid_count,
-            pv_count: self.encrypt(count.to_string()),
-            avg_duration: self.encrypt(avg_duration.to_string()),
+            pv_count: sealer.seal(pv_count.to_string()),
+            avg_duration: sealer.seal(avg_duration.to_string()),
             timestamp: total_stat.timestamp,
-        }
-    }
-
-    fn encrypt(&mut self, data: String) -> String {
-        let mut msg = data.as_bytes().to_vec();
-        let iv = aead::generate_iv();
-        aead::encrypt(&iv, &self.key, &mut msg);
-
-        format!("{:}|{:}", base64::encode(&iv), base64::encode(&msg))
+        })
     }
+}
 
-    fn decrypt(&mut self, data: String) -> String {
-        let v: Vec<&str> = data.split("|").collect();
-        let iv_b64 = v[0];
-        let cipher_b64 = v[1];
-
-        let iv = base64::decode(&iv_b64).unwrap();
-        let mut cipher_data = base64::decode(&cipher_b64).unwrap();
-
-        let cid = aead::decrypt(&iv, &*self.key, &mut cipher_data);
-        String::from_utf8(cid.to_vec()).unwrap()
+impl contracts::ContractState for Web3Analytics {
+    const SCHEMA_VERSION: u32 = 4;
+
+    // The constant key of the older versions is public, so it's dropped along with the page views
+    // sealed by it. The page views of the plain sites are kept. The encrypted sites need a contract
+    // key from the genesis.
+    fn migrate(version: u32, data: &[u8]) -> Result<Self> {
+        match version {
+            1 => {
+                let old: Web3AnalyticsV1 =
+                    serde_cbor::from_slice(data).map_err(|e| anyhow::Error::msg(e.to_string()))?;
+                Ok(Web3Analytics {
+                    no_tracking: old.no_tracking,
+                    ..Web3Analytics::new()
+                })
+            }
+            2 => {
+                let old: Web3AnalyticsV2 =
+                    serde_cbor::from_slice(data).map_err(|e| anyhow::Error::msg(e.to_string()))?;
+                let sites = old
+                    .sites
+                    .into_iter()
+                    .map(|(sid, site)| {
+                        let page_views: Vec<PageView> = if site.encrypted {
+                            Vec::new()
+                        } else {
+                            site.page_views.into_iter().map(PageView::from).collect()
+                        };
+                        let site = Site {
+                            owner: site.owner,
+                            api_keys: site.api_keys,
+                            encrypted: site.encrypted,
+                            key_version: 0,
+                            retention: site.retention,
+                            page_view_ids: page_views.iter().map(|pv| pv.id.clone()).collect(),
+                            page_views,
+                        };
+                        (sid, site)
+                    })
+                    .collect();
+                Ok(Web3Analytics {
+                    sites,
+                    no_tracking: old.no_tracking,
+                    ..Web3Analytics::new()
+                })
+            }
+            // The ids of the page views are indexed
+            3 => {
+                let mut state: Web3Analytics =
+                    serde_cbor::from_slice(data).map_err(|e| anyhow::Error::msg(e.to_string()))?;
+                for site in state.sites.values_mut() {
+                    site.page_view_ids = site.page_views.iter().map(|pv| pv.id.clone()).collect();
+                }
+                Ok(state)
+            }
+            _ => Err(anyhow::Error::msg(format!(
+                "Can't migrate from schema version {}",
+                version
+            ))),
+        }
     }
 }
 
@@ -908,9 +1189,9 @@ impl contracts::Contract<Command, Request, Response> for Web3Analytics {
         _txref: &TxRef,
         cmd: Command,
     ) -> TransactionStatus {
-        let status = match cmd {
+        let o = AccountIdWrapper(origin.clone());
+        match cmd {
             Command::SetConfiguration { skip_stat } => {
-                let o = AccountIdWrapper(origin.clone());
                 log::info!("SetConfiguration: [{}] -> {}", o.to_string(), skip_stat);
 
                 if skip_stat {
@@ -921,98 +1202,216 @@ impl contracts::Contract<Command, Request, Response> for Web3Analytics {
 
                 TransactionStatus::Ok
             }
-        };
-
-        status
+            Command::RegisterSite { sid, encrypted } => {
+                if self.sites.contains_key(&sid) {
+                    return TransactionStatus::SiteExist;
+                }
+                if encrypted && self.key.is_none() {
+                    return TransactionStatus::NoContractKey;
+                }
+                log::info!("RegisterSite: [{}] -> {}", o.to_string(), sid);
+                let site = Site {
+                    owner: o,
+                    api_keys: BTreeSet::new(),
+                    encrypted,
+                    key_version: 0,
+                    retention: DEFAULT_RETENTION,
+                    page_views: Vec::new(),
+                    page_view_ids: BTreeSet::new(),
+                };
+                self.sites.insert(sid, site);
+                TransactionStatus::Ok
+            }
+            Command::AddApiKey { sid, api_key } => match self.owned_site(&sid, &o) {
+                Ok(site) => {
+                    site.api_keys.insert(api_key);
+                    TransactionStatus::Ok
+                }
+                Err(status) => status,
+            },
+            Command::RemoveApiKey { sid, api_key } => match self.owned_site(&sid, &o) {
+                Ok(site) => {
+                    site.api_keys.remove(&api_key);
+                    TransactionStatus::Ok
+                }
+                Err(status) => status,
+            },
+            Command::SetRetention { sid, retention } => {
+                let now = self.now;
+                match self.owned_site(&sid, &o) {
+                    Ok(site) => {
+                        site.retention = retention.max(HOUR_IN_SECONDS).min(MAX_RETENTION);
+                        site.prune(now);
+                        TransactionStatus::Ok
+                    }
+                    Err(status) => status,
+                }
+            }
+            Command::IngestPageViews { sid, page_views } => {
+                let site = match self.sites.get_mut(&sid) {
+                    Some(site) => site,
+                    None => return TransactionStatus::SiteNotFound,
+                };
+                if !site.is_member(&o) {
+                    return TransactionStatus::NotSiteOwner;
+                }
+                if page_views.iter().any(|pv| pv.sid != sid) {
+                    return TransactionStatus::BadCommand;
+                }
+                let sealer = match Sealer::new(self.key.as_deref(), &sid, site) {
+                    Ok(sealer) => sealer,
+                    Err(_) => return TransactionStatus::NoContractKey,
+                };
+                if page_views.iter().any(|pv| sealer.check(pv).is_err()) {
+                    return TransactionStatus::BadCommand;
+                }
+                for page_view in page_views {
+                    if page_view.uid.len() == 64
+                        && self
+                            .no_tracking
+                            .contains_key(&AccountIdWrapper::from_hex(&page_view.uid))
+                    {
+                        continue;
+                    }
+                    // Or a single page view could expire all the others
+                    if page_view.created_at > self.now.saturating_add(MAX_CLOCK_SKEW) {
+                        continue;
+                    }
+                    site.add(page_view);
+                }
+                site.page_views.sort_by_key(|pv| pv.created_at);
+                site.prune(self.now);
+                TransactionStatus::Ok
+            }
+            Command::RotateKey { sid } => {
+                let site = match self.sites.get_mut(&sid) {
+                    Some(site) => site,
+                    None => return TransactionStatus::SiteNotFound,
+                };
+                if site.owner != o {
+                    return TransactionStatus::NotSiteOwner;
+                }
+                if !site.encrypted {
+                    return TransactionStatus::BadCommand;
+                }
+                let old = match Sealer::new(self.key.as_deref(), &sid, site) {
+                    Ok(sealer) => sealer,
+                    Err(_) => return TransactionStatus::NoContractKey,
+                };
+                let new = Sealer {
+                    key_version: old.key_version + 1,
+                    ..old
+                };
+                let mut page_views = site.page_views.clone();
+                for page_view in page_views.iter_mut() {
+                    if old.reseal(page_view, &new).is_err() {
+                        return TransactionStatus::BadCommand;
+                    }
+                }
+                log::info!("RotateKey: {} -> {}", sid, new.key_version);
+                site.page_views = page_views;
+                site.key_version = new.key_version;
+                TransactionStatus::Ok
+            }
+        }
     }
 
     fn handle_query(&mut self, origin: Option<&chain::AccountId>, req: Request) -> Response {
         let inner = || -> Result<Response> {
             match req {
-                Request::SetPageView {
-                    page_views,
-                    encrypted,
-                } => {
-                    for page_view in page_views {
-                        if page_view.uid.len() == 64
-                            && self
-                                .no_tracking
-                                .contains_key(&AccountIdWrapper::from_hex(&page_view.uid))
-                        {
-                            continue;
-                        }
-                        let b = self
-                            .page_views
-                            .clone()
-                            .into_iter()
-                            .any(|x| x.id == page_view.id);
-                        if !b {
-                            self.page_views.push(page_view);
-                        }
-                    }
-
-                    self.encrypted = encrypted;
-
-                    Ok(Response::SetPageView {
-                        page_view_count: self.page_views.len() as u32,
+                Request::GetSite { sid } => {
+                    let site = self.site(&sid, origin)?;
+                    Ok(Response::GetSite {
+                        owner: site.owner.clone(),
+                        api_keys: site.api_keys.iter().cloned().collect(),
+                        retention: site.retention,
+                        page_view_count: site.page_views.len() as u32,
+                        encrypted: site.encrypted,
+                        key_version: site.key_version,
                     })
                 }
-                Request::ClearPageView { timestamp: _ } => {
-                    self.page_views.clear();
-                    Ok(Response::ClearPageView {
-                        page_view_count: self.page_views.len() as u32,
+                Request::GetSiteKey { sid } => {
+                    let site = self.site(&sid, origin)?;
+                    if !site.encrypted {
+                        return Err(anyhow::Error::msg(Error::NotEncrypted));
+                    }
+                    let sealer = self.sealer(&sid, site)?;
+                    Ok(Response::GetSiteKey {
+                        key_version: sealer.key_version,
+                        key_b64: base64::encode(&sealer.key(sealer.key_version)),
                     })
                 }
-                Request::GetOnlineUsers { start, end } => {
-                    self.update_online_users(start, end);
+                Request::GetOnlineUsers { sid, start, end } => {
+                    let site = self.site(&sid, origin)?;
+                    let sealer = self.sealer(&sid, site)?;
                     Ok(Response::GetOnlineUsers {
-                        online_users: self.online_users.clone(),
-                        encrypted: self.encrypted,
+                        online_users: self.online_users(site, &sealer, start, end)?,
                     })
                 }
                 Request::GetHourlyStats {
+                    sid,
                     start,
                     end,
                     start_of_week,
                 } => {
-                    self.update_hourly_stats(start, end, start_of_week);
+                    let site = self.site(&sid, origin)?;
+                    let sealer = self.sealer(&sid, site)?;
                     Ok(Response::GetHourlyStats {
-                        hourly_stat: self.hourly_stat.clone(),
-                        encrypted: self.encrypted,
+                        hourly_stat: self.hourly_stats(site, &sealer, start, end, start_of_week)?,
                     })
                 }
-                Request::GetDailyStats { daily_stat } => {
-                    self.update_daily_stats(daily_stat);
+                Request::GetDailyStats { sid, daily_stat } => {
+                    let site = self.site(&sid, origin)?;
+                    check_sid(&sid, daily_stat.stats.iter().map(|s| &s.sid))?;
+                    let sealer = self.sealer(&sid, site)?;
                     Ok(Response::GetDailyStats {
-                        daily_stat: self.daily_stat.clone(),
-                        encrypted: self.encrypted,
+                        daily_stat: self.daily_stats(&sealer, daily_stat)?,
                     })
                 }
                 Request::GetWeeklySites {
+                    sid,
                     weekly_sites_in_db,
                     weekly_sites_new,
                 } => {
-                    self.update_weekly_sites(weekly_sites_in_db, weekly_sites_new);
+                    let site = self.site(&sid, origin)?;
+                    let all = weekly_sites_in_db.iter().chain(weekly_sites_new.iter());
+                    check_sid(&sid, all.map(|ws| &ws.sid))?;
+                    let sealer = self.sealer(&sid, site)?;
                     Ok(Response::GetWeeklySites {
-                        weekly_sites: self.weekly_sites.clone(),
-                        encrypted: self.encrypted,
+                        weekly_sites: self.weekly_sites(
+                            &sealer,
+                            weekly_sites_in_db,
+                            weekly_sites_new,
+                        )?,
                     })
                 }
                 Request::GetWeeklyDevices {
+                    sid,
                     weekly_devices_in_db,
                     weekly_devices_new,
                 } => {
-                    self.update_weekly_devices(weekly_devices_in_db, weekly_devices_new);
+                    let site = self.site(&sid, origin)?;
+                    let all = weekly_devices_in_db.iter().chain(weekly_devices_new.iter());
+                    check_sid(&sid, all.map(|wd| &wd.sid))?;
+                    let sealer = self.sealer(&sid, site)?;
                     Ok(Response::GetWeeklyDevices {
-                        weekly_devices: self.weekly_devices.clone(),
-                        encrypted: self.encrypted,
+                        weekly_devices: self.weekly_devices(
+                            &sealer,
+                            weekly_devices_in_db,
+                            weekly_devices_new,
+                        )?,
                     })
                 }
-                Request::GetTotalStat { total_stat, count } => {
-                    self.update_total_stat(total_stat, count);
+                Request::GetTotalStat {
+                    sid,
+                    total_stat,
+                    count,
+                } => {
+                    let site = self.site(&sid, origin)?;
+                    check_sid(&sid, Some(&total_stat.sid).into_iter())?;
+                    let sealer = self.sealer(&sid, site)?;
                     Ok(Response::GetTotalStat {
-                        total_stat: self.total_stat.clone(),
-                        encrypted: self.encrypted,
+                        total_stat: self.total_stat(&sealer, total_stat, count)?,
                     })
                 }
                 Request::GetConfiguration { account } => {
@@ -1033,4 +1432,716 @@ impl contracts::Contract<Command, Request, Response> for Web3Analytics {
             Ok(resp) => resp,
         }
     }
+
+    fn storage_keys(&self) -> Vec<Vec<u8>> {
+        vec![timestamp_now_key()]
+    }
+
+    fn handle_storage_reads(
+        &mut self,
+        _blocknum: chain::BlockNumber,
+        reads: &[(Vec<u8>, Option<Vec<u8>>)],
+    ) -> bool {
+        let key = timestamp_now_key();
+        let millis = reads
+            .iter()
+            .find(|(k, _)| k == &key)
+            .and_then(|(_, v)| v.as_ref())
+            .and_then(|v| u64::decode(&mut &v[..]).ok());
+        if let Some(millis) = millis {
+            self.now = (millis / 1000) as Timestamp;
+        }
+        let now = self.now;
+        let mut changed = false;
+        for site in self.sites.values_mut() {
+            changed |= site.prune(now);
+        }
+        changed
+    }
+}
+
+/// The storage key of `Timestamp::Now`, the time of the block in milliseconds
+fn timestamp_now_key() -> Vec<u8> {
+    crate::light_validation::utils::storage_prefix("Timestamp", "Now")
+}
+
+/// Checks the statistics passed in a query are of the queried site, so they can't be decrypted
+/// by the others
+fn check_sid<'a>(sid: &Sid, sids: impl Iterator<Item = &'a Sid>) -> Result<()> {
+    for s in sids {
+        if s != sid {
+            return Err(anyhow::Error::msg(Error::NotAuthorized));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::contracts::test_helpers::{account, txref};
+    use crate::contracts::{Contract, ContractState};
+
+    const OWNER: u8 = 1;
+    const API_KEY: u8 = 2;
+    const OTHER: u8 = 3;
+    /// The chain time, after the page views of the tests
+    const NOW: Timestamp = 1600822800;
+    const CHROME: &str = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/85.0.4183.102 Safari/537.36";
+
+    fn page_view(id: &str, path: &str, created_at: Timestamp) -> PageView {
+        PageView {
+            id: String::from(id),
+            sid: String::from("1"),
+            cid: String::from("d540041d837820e4a5a868c9c45d40ad"),
+            uid: String::new(),
+            host: String::from("http://localhost:9000"),
+            path: plain(path),
+            referrer: String::new(),
+            ip: plain("::1"),
+            user_agent: plain(CHROME),
+            created_at,
+        }
+    }
+
+    fn plain(value: &str) -> Field {
+        Field::Plain(String::from(value))
+    }
+
+    fn command(w3a: &mut Web3Analytics, origin: u8, cmd: Command) -> TransactionStatus {
+        w3a.handle_command(&account(origin), &txref(0), cmd)
+    }
+
+    fn ingest(w3a: &mut Web3Analytics, origin: u8, page_views: Vec<PageView>) -> TransactionStatus {
+        let sid = String::from("1");
+        command(w3a, origin, Command::IngestPageViews { sid, page_views })
+    }
+
+    /// Sets the chain time by the storage reads of a block
+    fn tick(w3a: &mut Web3Analytics, now: Timestamp) -> bool {
+        let millis = now as u64 * 1000;
+        w3a.handle_storage_reads(1, &[(timestamp_now_key(), Some(millis.encode()))])
+    }
+
+    fn registered(encrypted: bool) -> Web3Analytics {
+        let mut w3a = Web3Analytics::new();
+        w3a.set_key(&[7u8; 32]).unwrap();
+        tick(&mut w3a, NOW);
+        let sid = String::from("1");
+        let register = Command::RegisterSite {
+            sid: sid.clone(),
+            encrypted,
+        };
+        assert!(matches!(
+            command(&mut w3a, OWNER, register),
+            TransactionStatus::Ok
+        ));
+        let api_key = AccountIdWrapper(account(API_KEY));
+        let add = Command::AddApiKey { sid, api_key };
+        assert!(matches!(
+            command(&mut w3a, OWNER, add),
+            TransactionStatus::Ok
+        ));
+        w3a
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut w3a = registered(false);
+        let page_views = vec![
+            page_view("a", "/page2.html", 1600822028),
+            page_view("b", "/index.html", 1600822045),
+            page_view("c", "/page1.html", 1600822081),
+        ];
+        assert!(matches!(
+            ingest(&mut w3a, API_KEY, page_views.clone()),
+            TransactionStatus::Ok
+        ));
+        // Ingested once
+        ingest(&mut w3a, API_KEY, page_views);
+
+        let sid = String::from("1");
+        let req = Request::GetHourlyStats {
+            sid: sid.clone(),
+            start: 1600822020,
+            end: 1600822800,
+            start_of_week: 1600646400,
+        };
+        let hourly_stat = match w3a.handle_query(Some(&account(OWNER)), req) {
+            Response::GetHourlyStats { hourly_stat, .. } => hourly_stat,
+            _ => panic!("Unexpected response"),
+        };
+        let stat = &hourly_stat.hourly_page_view_stats;
+        assert_eq!(stat.len(), 1);
+        assert_eq!(
+            (&stat[0].cid_count, &stat[0].pv_count),
+            (&plain("1"), &plain("3"))
+        );
+        assert_eq!(stat[0].avg_duration, plain("26"));
+        assert_eq!(hourly_stat.weekly_devices.len(), 1);
+        assert_eq!(hourly_stat.weekly_devices[0].device, plain("Chrome"));
+        assert_eq!(hourly_stat.weekly_devices[0].count, plain("3"));
+        let paths: Vec<_> = hourly_stat
+            .weekly_sites
+            .iter()
+            .map(|ws| ws.path.clone())
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                plain("/page2.html"),
+                plain("/index.html"),
+                plain("/page1.html")
+            ]
+        );
+
+        let req = Request::GetOnlineUsers {
+            sid: sid.clone(),
+            start: 1600822020,
+            end: 1600822140,
+        };
+        match w3a.handle_query(Some(&account(API_KEY)), req) {
+            Response::GetOnlineUsers { online_users, .. } => {
+                assert_eq!(online_users.len(), 2);
+                assert_eq!(online_users[0].cid_count, plain("1"));
+                assert_eq!(online_users[0].ip_count, plain("1"));
+            }
+            _ => panic!("Unexpected response"),
+        }
+
+        let stat = |pv_count: &str, cid_count: &str, avg_duration: &str| HourlyPageViewStat {
+            sid: sid.clone(),
+            pv_count: plain(pv_count),
+            cid_count: plain(cid_count),
+            avg_duration: plain(avg_duration),
+            timestamp: 1600819200,
+        };
+        let daily_stat = DailyStat {
+            stats: vec![stat("3", "1", "26"), stat("2", "1", "60")],
+        };
+        let req = Request::GetDailyStats {
+            sid: sid.clone(),
+            daily_stat,
+        };
+        match w3a.handle_query(Some(&account(OWNER)), req) {
+            Response::GetDailyStats { daily_stat, .. } => {
+                let stats = &daily_stat.stats;
+                assert_eq!(stats.len(), 1);
+                assert_eq!(
+                    (&stats[0].pv_count, &stats[0].cid_count),
+                    (&plain("5"), &plain("2"))
+                );
+                assert_eq!(stats[0].avg_duration, plain("86"));
+            }
+            _ => panic!("Unexpected response"),
+        }
+    }
+
+    #[test]
+    fn test_access() {
+        let mut w3a = registered(false);
+        let sid = String::from("1");
+        let register = Command::RegisterSite {
+            sid: sid.clone(),
+            encrypted: false,
+        };
+        assert!(matches!(
+            command(&mut w3a, OTHER, register),
+            TransactionStatus::SiteExist
+        ));
+        // Only the owner manages the site
+        let api_key = AccountIdWrapper(account(OTHER));
+        let add = Command::AddApiKey {
+            sid: sid.clone(),
+            api_key: api_key.clone(),
+        };
+        assert!(matches!(
+            command(&mut w3a, API_KEY, add),
+            TransactionStatus::NotSiteOwner
+        ));
+        let page_views = vec![page_view("a", "/index.html", 1600822028)];
+        assert!(matches!(
+            ingest(&mut w3a, OTHER, page_views.clone()),
+            TransactionStatus::NotSiteOwner
+        ));
+        let mut other_site = page_views.clone();
+        other_site[0].sid = String::from("2");
+        assert!(matches!(
+            ingest(&mut w3a, OWNER, other_site),
+            TransactionStatus::BadCommand
+        ));
+        assert!(matches!(
+            ingest(&mut w3a, API_KEY, page_views),
+            TransactionStatus::Ok
+        ));
+
+        let get_site = Request::GetSite { sid: sid.clone() };
+        for origin in [None, Some(account(OTHER))].iter() {
+            let resp = w3a.handle_query(origin.as_ref(), get_site.clone());
+            assert!(matches!(resp, Response::Error(_)));
+        }
+        // The statistics of the other sites can't be decrypted
+        let daily_stat = DailyStat {
+            stats: vec![HourlyPageViewStat {
+                sid: String::from("2"),
+                ..Default::default()
+            }],
+        };
+        let req = Request::GetDailyStats { sid, daily_stat };
+        let resp = w3a.handle_query(Some(&account(OWNER)), req);
+        assert!(matches!(resp, Response::Error(_)));
+    }
+
+    #[test]
+    fn test_retention() {
+        let mut w3a = registered(false);
+        let sid = String::from("1");
+        let page_view_count = |w3a: &mut Web3Analytics| {
+            let req = Request::GetSite { sid: sid.clone() };
+            match w3a.handle_query(Some(&account(OWNER)), req) {
+                Response::GetSite {
+                    page_view_count, ..
+                } => page_view_count,
+                _ => panic!("Unexpected response"),
+            }
+        };
+        let start = 1600822028;
+        tick(&mut w3a, start);
+        ingest(&mut w3a, OWNER, vec![page_view("a", "/", start)]);
+        // Ahead of the chain time
+        let page_views = vec![page_view("b", "/", start + DAY_IN_SECONDS)];
+        ingest(&mut w3a, OWNER, page_views.clone());
+        assert_eq!(page_view_count(&mut w3a), 1);
+        tick(&mut w3a, start + DAY_IN_SECONDS);
+        ingest(&mut w3a, OWNER, page_views.clone());
+        // The duplicates are dropped
+        ingest(&mut w3a, OWNER, page_views);
+        ingest(&mut w3a, OWNER, vec![page_view("a", "/", start)]);
+        assert_eq!(page_view_count(&mut w3a), 2);
+
+        // Expired as the chain time goes
+        assert!(!tick(&mut w3a, start + DEFAULT_RETENTION));
+        assert!(tick(&mut w3a, start + DEFAULT_RETENTION + 1));
+        assert_eq!(page_view_count(&mut w3a), 1);
+        // Not by a page view far ahead
+        let now = start + DEFAULT_RETENTION + 1;
+        let page_views = vec![
+            page_view("c", "/", now),
+            page_view("d", "/", Timestamp::max_value()),
+        ];
+        ingest(&mut w3a, OWNER, page_views);
+        assert_eq!(page_view_count(&mut w3a), 2);
+
+        let retention = Command::SetRetention {
+            sid: sid.clone(),
+            retention: HOUR_IN_SECONDS,
+        };
+        assert!(matches!(
+            command(&mut w3a, OWNER, retention),
+            TransactionStatus::Ok
+        ));
+        assert_eq!(page_view_count(&mut w3a), 1);
+    }
+
+    #[test]
+    fn test_encrypted() {
+        let sid = String::from("1");
+        let register = Command::RegisterSite {
+            sid: sid.clone(),
+            encrypted: true,
+        };
+        assert!(matches!(
+            command(&mut Web3Analytics::new(), OWNER, register),
+            TransactionStatus::NoContractKey
+        ));
+
+        let mut w3a = registered(true);
+        let key = w3a.key.clone().unwrap();
+        let site = w3a.sites.get(&sid).unwrap().clone();
+        let sealer = Sealer::new(Some(&key[..]), &sid, &site).unwrap();
+        let sealed = |id: &str, created_at: Timestamp| {
+            let mut pv = page_view(id, "/index.html", created_at);
+            pv.path = sealer.seal(String::from("/index.html"));
+            pv.ip = sealer.seal(String::from("::1"));
+            pv.user_agent = sealer.seal(String::from(CHROME));
+            pv
+        };
+        // The plain page views are refused
+        let page_views = vec![page_view("a", "/index.html", 1600822028)];
+        assert!(matches!(
+            ingest(&mut w3a, OWNER, page_views),
+            TransactionStatus::BadCommand
+        ));
+        let page_views = vec![sealed("a", 1600822028), sealed("b", 1600822045)];
+        assert!(matches!(
+            ingest(&mut w3a, OWNER, page_views),
+            TransactionStatus::Ok
+        ));
+
+        // The site key is only revealed to the members, and matches the sealer
+        let req = Request::GetSiteKey { sid: sid.clone() };
+        assert!(matches!(
+            w3a.handle_query(Some(&account(OTHER)), req.clone()),
+            Response::Error(_)
+        ));
+        match w3a.handle_query(Some(&account(API_KEY)), req) {
+            Response::GetSiteKey {
+                key_version,
+                key_b64,
+            } => {
+                assert_eq!(key_version, 0);
+                assert_eq!(key_b64, base64::encode(&sealer.key(0)));
+            }
+            _ => panic!("Unexpected response"),
+        }
+
+        let hourly_stats = |w3a: &mut Web3Analytics| {
+            let req = Request::GetHourlyStats {
+                sid: sid.clone(),
+                start: 1600822020,
+                end: 1600822800,
+                start_of_week: 1600646400,
+            };
+            match w3a.handle_query(Some(&account(OWNER)), req) {
+                Response::GetHourlyStats { hourly_stat, .. } => hourly_stat,
+                _ => panic!("Unexpected response"),
+            }
+        };
+        let stat = hourly_stats(&mut w3a).hourly_page_view_stats[0].clone();
+        assert!(matches!(&stat.pv_count, Field::Sealed(c) if c.key_version == 0));
+        assert_eq!(sealer.count(&stat.pv_count).unwrap(), 2);
+
+        // The stored page views are sealed again with the new key, and the old ciphers still open
+        let rotate = Command::RotateKey { sid: sid.clone() };
+        assert!(matches!(
+            command(&mut w3a, API_KEY, rotate),
+            TransactionStatus::NotSiteOwner
+        ));
+        let rotate = Command::RotateKey { sid: sid.clone() };
+        assert!(matches!(
+            command(&mut w3a, OWNER, rotate),
+            TransactionStatus::Ok
+        ));
+        let site = w3a.sites.get(&sid).unwrap().clone();
+        let new_sealer = Sealer::new(Some(&key[..]), &sid, &site).unwrap();
+        assert_eq!(new_sealer.key_version, 1);
+        assert_ne!(new_sealer.key(1), sealer.key(0));
+        assert!(sealer.check(&site.page_views[0]).is_err());
+        assert!(new_sealer.check(&site.page_views[0]).is_ok());
+        let new_stat = hourly_stats(&mut w3a).hourly_page_view_stats[0].clone();
+        assert!(matches!(&new_stat.pv_count, Field::Sealed(c) if c.key_version == 1));
+        let req = Request::GetDailyStats {
+            sid: sid.clone(),
+            daily_stat: DailyStat {
+                stats: vec![stat.clone(), new_stat],
+            },
+        };
+        match w3a.handle_query(Some(&account(OWNER)), req) {
+            Response::GetDailyStats { daily_stat, .. } => {
+                assert_eq!(new_sealer.count(&daily_stat.stats[0].pv_count).unwrap(), 4);
+            }
+            _ => panic!("Unexpected response"),
+        }
+
+        // A tampered cipher is refused gracefully
+        let mut tampered = stat;
+        if let Field::Sealed(cipher) = &mut tampered.pv_count {
+            cipher.data_b64 = base64::encode(b"tampered");
+        }
+        let req = Request::GetDailyStats {
+            sid,
+            daily_stat: DailyStat {
+                stats: vec![tampered],
+            },
+        };
+        assert!(matches!(
+            w3a.handle_query(Some(&account(OWNER)), req),
+            Response::Error(_)
+        ));
+    }
+
+    /// The page views recorded by the tracker for the `test_w3a_*` tests
+    const DAY_VIEWS: [(&str, &str, Timestamp); 2] = [
+        (
+            "2cfcb3eb-a38e-494f-b6c0-a38c9cd2c267",
+            "/index.html",
+            1600675693,
+        ),
+        (
+            "80aad35c-7d58-494d-ba95-be95c011eb2c",
+            "/index.html",
+            1600730399,
+        ),
+    ];
+    const HOUR_VIEWS: [(&str, &str, Timestamp); 3] = [
+        (
+            "47ca3f6f-d296-447e-90e8-5ef10e4b713e",
+            "/page2.html",
+            1600822028,
+        ),
+        (
+            "81bee3d3-3b3c-4366-ba08-1ee9884e29ee",
+            "/index.html",
+            1600822045,
+        ),
+        (
+            "5effeb8f-b62e-43ae-a7ab-69fc49efb8ab",
+            "/page1.html",
+            1600822081,
+        ),
+    ];
+
+    /// Registers a site and ingests the page views by its API key, sealed if it's encrypted
+    fn ingested(encrypted: bool, page_views: &[(&str, &str, Timestamp)]) -> Web3Analytics {
+        let mut w3a = registered(encrypted);
+        let sid = String::from("1");
+        let page_views: Vec<_> = {
+            let sealer = w3a.sealer(&sid, &w3a.sites[&sid]).unwrap();
+            page_views
+                .iter()
+                .map(|(id, path, created_at)| {
+                    let mut pv = page_view(id, path, *created_at);
+                    pv.path = sealer.seal(String::from(*path));
+                    pv.ip = sealer.seal(String::from("::1"));
+                    pv.user_agent = sealer.seal(String::from(CHROME));
+                    pv
+                })
+                .collect()
+        };
+        assert!(matches!(
+            ingest(&mut w3a, API_KEY, page_views),
+            TransactionStatus::Ok
+        ));
+        w3a
+    }
+
+    /// Seals a value for site 1
+    fn seal(w3a: &Web3Analytics, value: &str) -> Field {
+        let sid = String::from("1");
+        let sealer = w3a.sealer(&sid, &w3a.sites[&sid]).unwrap();
+        sealer.seal(String::from(value))
+    }
+
+    /// Opens a field of site 1, which must be sealed if and only if the site is encrypted
+    fn reveal(w3a: &Web3Analytics, field: &Field) -> String {
+        let sid = String::from("1");
+        let sealer = w3a.sealer(&sid, &w3a.sites[&sid]).unwrap();
+        sealer.open(field).unwrap()
+    }
+
+    fn page_view_count(w3a: &mut Web3Analytics) -> u32 {
+        let req = Request::GetSite {
+            sid: String::from("1"),
+        };
+        match w3a.handle_query(Some(&account(OWNER)), req) {
+            Response::GetSite {
+                page_view_count, ..
+            } => page_view_count,
+            _ => panic!("Unexpected response"),
+        }
+    }
+
+    fn check_online_users(encrypted: bool) {
+        let mut w3a = ingested(encrypted, &DAY_VIEWS);
+        let req = Request::GetOnlineUsers {
+            sid: String::from("1"),
+            start: 1600675680,
+            end: 1600742280,
+        };
+        let online_users = match w3a.handle_query(Some(&account(API_KEY)), req) {
+            Response::GetOnlineUsers { online_users } => online_users,
+            _ => panic!("Unexpected response"),
+        };
+        assert_eq!(online_users.len(), 2);
+        for user in online_users.iter() {
+            assert_eq!(reveal(&w3a, &user.cid_count), "1");
+            assert_eq!(reveal(&w3a, &user.ip_count), "1");
+        }
+    }
+
+    fn check_hourly_stats(encrypted: bool) {
+        let mut w3a = ingested(encrypted, &HOUR_VIEWS);
+        let req = Request::GetHourlyStats {
+            sid: String::from("1"),
+            start: 1600822020,
+            end: 1600822800,
+            start_of_week: 1600646400,
+        };
+        let hourly_stat = match w3a.handle_query(Some(&account(OWNER)), req) {
+            Response::GetHourlyStats { hourly_stat } => hourly_stat,
+            _ => panic!("Unexpected response"),
+        };
+        let stats = &hourly_stat.hourly_page_view_stats;
+        assert_eq!(stats.len(), 1);
+        assert_eq!(reveal(&w3a, &stats[0].cid_count), "1");
+        assert_eq!(reveal(&w3a, &stats[0].pv_count), "3");
+        assert_eq!(reveal(&w3a, &stats[0].avg_duration), "26");
+
+        assert_eq!(hourly_stat.site_clients.len(), 1);
+        assert_eq!(hourly_stat.site_clients[0].cids.len(), 1);
+
+        let devices = &hourly_stat.weekly_devices;
+        assert_eq!(devices.len(), 1);
+        assert_eq!(reveal(&w3a, &devices[0].count), "3");
+        assert_eq!(reveal(&w3a, &devices[0].device), "Chrome");
+
+        let sites: Vec<_> = hourly_stat
+            .weekly_sites
+            .iter()
+            .map(|ws| (reveal(&w3a, &ws.path), reveal(&w3a, &ws.count)))
+            .collect();
+        let expected: Vec<_> = ["/page2.html", "/index.html", "/page1.html"]
+            .iter()
+            .map(|path| (String::from(*path), String::from("1")))
+            .collect();
+        assert_eq!(sites, expected);
+    }
+
+    fn check_daily_stats(encrypted: bool) {
+        let mut w3a = registered(encrypted);
+        let stat = |pv_count: &str, cid_count: &str, avg_duration: &str| HourlyPageViewStat {
+            sid: String::from("1"),
+            pv_count: seal(&w3a, pv_count),
+            cid_count: seal(&w3a, cid_count),
+            avg_duration: seal(&w3a, avg_duration),
+            timestamp: 1600819200,
+        };
+        let daily_stat = DailyStat {
+            stats: vec![stat("3", "1", "26"), stat("2", "1", "60")],
+        };
+        let req = Request::GetDailyStats {
+            sid: String::from("1"),
+            daily_stat,
+        };
+        let stats = match w3a.handle_query(Some(&account(OWNER)), req) {
+            Response::GetDailyStats { daily_stat } => daily_stat.stats,
+            _ => panic!("Unexpected response"),
+        };
+        assert_eq!(stats.len(), 1);
+        assert_eq!(reveal(&w3a, &stats[0].cid_count), "2");
+        assert_eq!(reveal(&w3a, &stats[0].pv_count), "5");
+        assert_eq!(reveal(&w3a, &stats[0].avg_duration), "86");
+    }
+
+    #[test]
+    fn test_w3a_setpageview() {
+        let mut w3a = ingested(false, &DAY_VIEWS);
+        assert_eq!(page_view_count(&mut w3a), 2);
+    }
+
+    #[test]
+    fn test_w3a_getonlineusers() {
+        check_online_users(false);
+    }
+
+    #[test]
+    fn test_w3a_gethourlystats() {
+        check_hourly_stats(false);
+    }
+
+    #[test]
+    fn test_w3a_getdailystats() {
+        check_daily_stats(false);
+    }
+
+    #[test]
+    fn test_w3a_clearpageview() {
+        let mut w3a = ingested(false, &HOUR_VIEWS);
+        assert_eq!(page_view_count(&mut w3a), 3);
+        // The page views are cleared by the retention instead of a request
+        let retention = Command::SetRetention {
+            sid: String::from("1"),
+            retention: HOUR_IN_SECONDS,
+        };
+        assert!(matches!(
+            command(&mut w3a, OWNER, retention),
+            TransactionStatus::Ok
+        ));
+        assert_eq!(page_view_count(&mut w3a), 3);
+        assert!(tick(&mut w3a, 1600826400));
+        assert_eq!(page_view_count(&mut w3a), 0);
+    }
+
+    #[test]
+    fn test_w3a_encrypted_getonlineusers() {
+        check_online_users(true);
+    }
+
+    #[test]
+    fn test_w3a_encrypted_gethourlystats() {
+        check_hourly_stats(true);
+    }
+
+    #[test]
+    fn test_w3a_encrypted_getdailystats() {
+        check_daily_stats(true);
+    }
+
+    #[test]
+    fn test_migrate_v2() {
+        #[derive(Serialize)]
+        struct PageViewV2 {
+            id: String,
+            sid: Sid,
+            cid: String,
+            uid: String,
+            host: String,
+            path: String,
+            referrer: String,
+            ip: String,
+            user_agent: String,
+            created_at: Timestamp,
+        }
+        #[derive(Serialize)]
+        struct SiteV2 {
+            owner: AccountIdWrapper,
+            api_keys: BTreeSet<AccountIdWrapper>,
+            encrypted: bool,
+            retention: Timestamp,
+            page_views: Vec<PageViewV2>,
+        }
+        #[derive(Serialize)]
+        struct StateV2 {
+            sites: BTreeMap<Sid, SiteV2>,
+            no_tracking: BTreeMap<AccountIdWrapper, bool>,
+        }
+        let site = |encrypted: bool, path: &str| SiteV2 {
+            owner: AccountIdWrapper(account(OWNER)),
+            api_keys: BTreeSet::new(),
+            encrypted,
+            retention: DEFAULT_RETENTION,
+            page_views: vec![PageViewV2 {
+                id: String::from("a"),
+                sid: String::from("1"),
+                cid: String::new(),
+                uid: String::new(),
+                host: String::new(),
+                path: String::from(path),
+                referrer: String::new(),
+                ip: String::from("::1"),
+                user_agent: String::from(CHROME),
+                created_at: 1600822028,
+            }],
+        };
+        let mut state = StateV2 {
+            sites: BTreeMap::new(),
+            no_tracking: BTreeMap::new(),
+        };
+        state
+            .sites
+            .insert(String::from("1"), site(false, "/index.html"));
+        state
+            .sites
+            .insert(String::from("2"), site(true, "c2VhbGVk"));
+        let data = serde_cbor::to_vec(&state).unwrap();
+
+        // Only the page views sealed by the constant key are dropped
+        let w3a = Web3Analytics::migrate(2, &data).unwrap();
+        let kept = &w3a.sites["1"];
+        assert_eq!(kept.page_views.len(), 1);
+        assert_eq!(kept.page_views[0].path, plain("/index.html"));
+        assert!(kept.page_view_ids.contains("a"));
+        let dropped = &w3a.sites["2"];
+        assert!(dropped.page_views.is_empty() && dropped.page_view_ids.is_empty());
+    }
 }
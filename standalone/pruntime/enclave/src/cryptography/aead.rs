@@ -1,4 +1,5 @@
 use crate::std::vec::Vec;
+use anyhow::Result;
 
 use ring::rand::SecureRandom;
 
@@ -12,6 +13,20 @@ pub fn generate_iv() -> IV {
     nonce_vec
 }
 
+/// Derives the IV from the key and the message, for deterministic encryption
+///
+/// Only for the ciphers that must be reproducible across pRuntimes (e.g. in the contract states).
+/// The same message always gets the same cipher, so the equality of messages is revealed, but an
+/// IV is never reused for different messages.
+pub fn derive_iv(secret: &[u8], msg: &[u8]) -> IV {
+    let mut material = secret.to_vec();
+    material.extend_from_slice(msg);
+    let hash = sp_core::hashing::blake2_256(&material);
+    let mut iv = [0u8; IV_BYTES];
+    iv.copy_from_slice(&hash[..IV_BYTES]);
+    iv
+}
+
 fn load_key(raw: &[u8]) -> ring::aead::LessSafeKey {
     let unbound_key = ring::aead::UnboundKey::new(&ring::aead::AES_256_GCM, raw)
         .expect("Failed to load the secret key");
@@ -28,14 +43,45 @@ pub fn encrypt(iv: &IV, secret: &[u8], in_out: &mut Vec<u8>) {
 }
 
 // Decrypts the cipher (with 128 auth tag appended) in-place and returns the message as a slice.
-pub fn decrypt<'in_out>(iv: &[u8], secret: &[u8], in_out: &'in_out mut [u8]) -> &'in_out mut [u8] {
+// Fails if the IV is malformed or the cipher was tampered.
+pub fn decrypt<'in_out>(
+    iv: &[u8],
+    secret: &[u8],
+    in_out: &'in_out mut [u8],
+) -> Result<&'in_out mut [u8]> {
+    if iv.len() != IV_BYTES {
+        return Err(anyhow::Error::msg("bad iv"));
+    }
     let mut iv_arr = [0u8; IV_BYTES];
-    iv_arr.copy_from_slice(&iv[..IV_BYTES]);
+    iv_arr.copy_from_slice(iv);
     let key = load_key(secret);
     let nonce = ring::aead::Nonce::assume_unique_for_key(iv_arr);
 
     key.open_in_place(nonce, ring::aead::Aad::empty(), in_out)
-        .expect("open_in_place failed")
+        .map_err(|_| anyhow::Error::msg("open_in_place failed"))
 }
 
-// TODO: handle error
+// Encrypts the data in-place and appends a 128bit auth tag. The additional data `aad` is
+// authenticated but not encrypted.
+pub fn encrypt_with_aad(iv: &IV, secret: &[u8], aad: &[u8], in_out: &mut Vec<u8>) {
+    let nonce = ring::aead::Nonce::assume_unique_for_key(iv.clone());
+    let key = load_key(secret);
+
+    key.seal_in_place_append_tag(nonce, ring::aead::Aad::from(aad), in_out)
+        .expect("seal_in_place_separate_tag failed");
+}
+
+// Decrypts the cipher (with 128 auth tag appended) in-place and returns the message as a slice.
+// Fails if either the cipher or `aad` was tampered.
+pub fn decrypt_with_aad<'in_out>(
+    iv: &IV,
+    secret: &[u8],
+    aad: &[u8],
+    in_out: &'in_out mut [u8],
+) -> Result<&'in_out mut [u8]> {
+    let nonce = ring::aead::Nonce::assume_unique_for_key(iv.clone());
+    let key = load_key(secret);
+
+    key.open_in_place(nonce, ring::aead::Aad::from(aad), in_out)
+        .map_err(|_| anyhow::Error::msg("open_in_place failed"))
+}
@@ -58,12 +58,12 @@ where
     ring::agreement::agree_ephemeral(sk, peer_public_key, error_value, kdf)
 }
 
-// Derives a secret key for symmetric encryption without a KDF
-pub fn agree(sk: &EphemeralPrivateKey, pubkey: &[u8]) -> Vec<u8> {
+// Derives a secret key for symmetric encryption without a KDF. Fails if `pubkey` is invalid.
+pub fn agree(sk: &EphemeralPrivateKey, pubkey: &[u8]) -> Result<Vec<u8>> {
     let unparsed_pk = ring::agreement::UnparsedPublicKey::new(&ring::agreement::ECDH_P256, pubkey);
 
     agree_longlived(sk, &unparsed_pk, ring::error::Unspecified, |key_material| {
         Ok(key_material.to_vec())
     })
-    .expect("ecdh failed")
+    .map_err(|_| anyhow::Error::msg("ecdh failed"))
 }
@@ -4,6 +4,7 @@ use anyhow::Result;
 use core::fmt;
 use serde::{Deserialize, Serialize};
 
+use phala_types::rpc::{EncryptedSecret, QueryOrigin, SignatureType as RpcSignatureType};
 use sp_core::crypto::Pair;
 
 pub mod aead;
@@ -33,15 +34,42 @@ pub fn decrypt(
     let iv = base64::decode(&cipher.iv_b64)
         .map_err(|_| anyhow::Error::msg(Error::BadInput("iv_b64")))?;
     // ECDH derived secret
-    let secret = ecdh::agree(privkey, &pubkey);
+    let secret = ecdh::agree(privkey, &pubkey)?;
     log::info!("Agreed SK: {:?}", crate::hex::encode_hex_compact(&secret));
-    let msg = aead::decrypt(iv.as_slice(), secret.as_slice(), &mut data);
+    let msg = aead::decrypt(iv.as_slice(), secret.as_slice(), &mut data)?;
     Ok(DecryptOutput {
         msg: msg.to_vec(),
         secret,
     })
 }
 
+/// Decrypts a secret encrypted to the worker ECDH key
+pub fn decrypt_secret(
+    secret: &EncryptedSecret,
+    privkey: &ring::agreement::EphemeralPrivateKey,
+) -> Result<Vec<u8>> {
+    let key = ecdh::agree(privkey, &secret.pubkey)?;
+    let mut data = secret.cipher.clone();
+    let msg = aead::decrypt(&secret.iv, &key, &mut data)?;
+    Ok(msg.to_vec())
+}
+
+/// Encrypts a secret to a worker ECDH public key, as the owner of the secret does
+#[cfg(test)]
+pub fn encrypt_secret(worker_pubkey: &[u8], msg: &[u8]) -> EncryptedSecret {
+    let sk = ecdh::generate_key();
+    let pubkey = sk.compute_public_key().expect("can't compute pubkey");
+    let key = ecdh::agree(&sk, worker_pubkey).expect("ecdh failed");
+    let iv = aead::generate_iv();
+    let mut cipher = msg.to_vec();
+    aead::encrypt(&iv, &key, &mut cipher);
+    EncryptedSecret {
+        pubkey: pubkey.as_ref().to_vec(),
+        iv: iv.to_vec(),
+        cipher,
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Origin {
     pub origin: String,
@@ -60,21 +88,48 @@ pub enum SignatureType {
 }
 
 impl Origin {
-    pub fn verify(&self, msg: &[u8]) -> Result<bool> {
-        let sig = base64::decode(&self.sig_b64)
+    /// Decodes the hex public key and the base64 signature
+    pub fn decode(&self) -> Result<QueryOrigin> {
+        let pubkey = crate::hex::try_decode_hex(&self.origin)
+            .ok_or_else(|| anyhow::Error::msg(Error::BadInput("origin")))?;
+        let signature = base64::decode(&self.sig_b64)
             .map_err(|_| anyhow::Error::msg(Error::BadInput("sig_b64")))?;
-        let pubkey = crate::hex::decode_hex(&self.origin);
-        // .map_err(|_| Error::BadInput("origin"))?;   TODO: handle error
-
-        let result = match self.sig_type {
-            SignatureType::Ed25519 => verify::<sp_core::ed25519::Pair>(&sig, msg, &pubkey),
-            SignatureType::Sr25519 => verify::<sp_core::sr25519::Pair>(&sig, msg, &pubkey),
-            SignatureType::Ecdsa => verify::<sp_core::ecdsa::Pair>(&sig, msg, &pubkey),
+        let sig_type = match self.sig_type {
+            SignatureType::Ed25519 => RpcSignatureType::Ed25519,
+            SignatureType::Sr25519 => RpcSignatureType::Sr25519,
+            SignatureType::Ecdsa => RpcSignatureType::Ecdsa,
         };
-        Ok(result)
+        Ok(QueryOrigin {
+            pubkey,
+            signature,
+            sig_type,
+        })
+    }
+}
+
+/// Verifies the signature of `msg` by the origin
+pub fn verify_origin(origin: &QueryOrigin, msg: &[u8]) -> bool {
+    let (sig, pubkey) = (&origin.signature, &origin.pubkey);
+    match origin.sig_type {
+        RpcSignatureType::Ed25519 => verify::<sp_core::ed25519::Pair>(sig, msg, pubkey),
+        RpcSignatureType::Sr25519 => verify::<sp_core::sr25519::Pair>(sig, msg, pubkey),
+        RpcSignatureType::Ecdsa => verify::<sp_core::ecdsa::Pair>(sig, msg, pubkey),
     }
 }
 
+/// The raw account id of the origin
+///
+/// An ECDSA public key is 33 bytes, so it's mapped to the blake2_256 hash of the key, as
+/// Substrate does.
+pub fn origin_account_id(origin: &QueryOrigin) -> Result<[u8; 32]> {
+    let raw = match origin.sig_type {
+        RpcSignatureType::Ecdsa => sp_core::hashing::blake2_256(&origin.pubkey),
+        _ => core::convert::TryInto::try_into(origin.pubkey.as_slice())
+            .map_err(|_| anyhow::Error::msg(Error::BadInput("origin")))?,
+    };
+    Ok(raw)
+}
+
 fn verify<T>(sig: &[u8], msg: &[u8], pubkey: &[u8]) -> bool
 where
     T: Pair,
@@ -0,0 +1,1980 @@
+//! Platform independent part of pRuntime.
+//!
+//! The runtime states and the request handlers here don't touch any SGX API, so they are shared
+//! by the enclave and by `pruntime-sim`, which includes this file directly. The including crate
+//! must provide `SgxMutex` at its root, along with the other enclave modules.
+
+use crate::std::collections::{BTreeMap, BTreeSet};
+use crate::std::prelude::v1::*;
+use crate::std::string::String;
+use crate::std::vec::Vec;
+use log::{debug, error, info, warn};
+use parity_scale_codec::{Decode, Encode, FullCodec};
+use secp256k1::{PublicKey, SecretKey};
+use serde_json::Value;
+use sp_core::crypto::Pair;
+use sp_core::hashing::blake2_256;
+use sp_core::H256 as Hash;
+
+use phala_types::pruntime::{
+    BlockHeaderWithEvents as GenericBlockHeaderWithEvents, HeaderToSync as GenericHeaderToSync,
+    SignedResponseMessage, StorageKV, StorageReadRequest,
+};
+use phala_types::rpc::{
+    AddBridgeArgs, BridgeStatus, Call, CheckpointInfo, ContractsGenesis, EgressInfo, ErrorCode,
+    Info, IngestAck, IngestFrame, InitRuntimeArgs, QueryOrigin, Reply, RpcError, RpcRequest,
+    RpcResponse, RuntimeInfo, TestArgs,
+};
+use phala_types::{ChainMessage, VersionedMessage};
+
+use crate::chain;
+use crate::checkpoint;
+use crate::contracts::{
+    self, AccountIdWrapper, Contract, ContractId, ContractState, EncodedState, ASSETS, BALANCES,
+    DATA_PLAZA, DIEM, SYSTEM, WEB3_ANALYTICS,
+};
+use crate::cryptography::{self, aead, ecdh};
+use crate::hex;
+use crate::light_validation::{self, AuthoritySetChange, BridgeId};
+use crate::merkle;
+use crate::msg_channel::MsgChannel;
+use crate::rpc_types::*;
+use crate::system::{self, CommandIndex, TransactionReceipt, TransactionStatus};
+use crate::types::{self, TxRef};
+use crate::SgxMutex;
+
+pub type HeaderToSync = GenericHeaderToSync<chain::BlockNumber, chain::Hashing>;
+pub type BlockHeaderWithEvents =
+    GenericBlockHeaderWithEvents<chain::BlockNumber, chain::Hashing, chain::Balance>;
+pub type OnlineWorkerSnapshot =
+    phala_types::pruntime::OnlineWorkerSnapshot<chain::BlockNumber, chain::Balance>;
+
+pub type ChainLightValidation = light_validation::LightValidation<chain::Runtime>;
+pub type EcdhKey = ring::agreement::EphemeralPrivateKey;
+
+#[derive(Debug)]
+pub struct RuntimeState {
+    pub contract1: contracts::data_plaza::DataPlaza,
+    pub contract2: contracts::balances::Balances,
+    pub contract3: contracts::assets::Assets,
+    pub contract4: contracts::web3analytics::Web3Analytics,
+    pub contract5: contracts::diem::Diem,
+    pub light_client: ChainLightValidation,
+    pub main_bridge: u64,
+    pub commitment: StateCommitment,
+}
+
+/// The contracts committed in the state root, in the order of the Merkle leaves
+pub const COMMITTED_CONTRACTS: [ContractId; 5] =
+    [DATA_PLAZA, BALANCES, ASSETS, WEB3_ANALYTICS, DIEM];
+
+/// Caches the Merkle leaves of the contract states, so only the touched contracts are re-encoded
+/// when the state root is updated
+#[derive(Debug)]
+pub struct StateCommitment {
+    leaves: BTreeMap<ContractId, Hash>,
+    dirty: BTreeSet<ContractId>,
+}
+
+impl StateCommitment {
+    pub fn new() -> Self {
+        StateCommitment {
+            leaves: BTreeMap::new(),
+            dirty: COMMITTED_CONTRACTS.iter().cloned().collect(),
+        }
+    }
+
+    /// Marks the state of a contract as changed. The contracts out of the state root are ignored.
+    pub fn touch(&mut self, id: ContractId) {
+        if COMMITTED_CONTRACTS.contains(&id) {
+            self.dirty.insert(id);
+        }
+    }
+}
+
+/// The sync progress of a chain added by `add_bridge`, as `LocalState` of the main chain
+#[derive(Encode, Decode, Debug, Clone)]
+pub struct BridgeCursor {
+    pub headernum: u32,
+    pub blocknum: u32,
+    pub block_hashes: Vec<Hash>,
+}
+
+pub struct LocalState {
+    pub initialized: bool,
+    pub public_key: Box<PublicKey>,
+    pub private_key: Box<SecretKey>,
+    pub headernum: u32, // the height of synced block
+    pub blocknum: u32,  // the height of dispatched block
+    pub block_hashes: Vec<Hash>,
+    pub ecdh_private_key: Option<EcdhKey>,
+    pub ecdh_public_key: Option<ring::agreement::PublicKey>,
+    pub machine_id: [u8; 16],
+    pub dev_mode: bool,
+    pub runtime_info: Option<InitRuntimeResp>,
+    /// The Merkle root over the contract states after the last dispatched block
+    pub state_root: Hash,
+    /// Take a checkpoint every `checkpoint_interval` dispatched blocks. 0 to disable.
+    pub checkpoint_interval: u32,
+    /// The `blocknum` of the last checkpoint
+    pub last_checkpoint: u32,
+    /// Keep the transaction receipts of the last `receipt_retention` blocks. 0 to keep them until
+    /// the store is full.
+    pub receipt_retention: u32,
+    /// The chains tracked besides the main one
+    pub bridges: BTreeMap<BridgeId, BridgeCursor>,
+}
+
+impl RuntimeState {
+    pub fn new() -> Self {
+        RuntimeState {
+            contract1: contracts::data_plaza::DataPlaza::new(),
+            contract2: contracts::balances::Balances::new(None),
+            contract3: contracts::assets::Assets::new(),
+            contract4: contracts::web3analytics::Web3Analytics::new(),
+            contract5: contracts::diem::Diem::new(),
+            light_client: ChainLightValidation::new(),
+            main_bridge: 0,
+            commitment: StateCommitment::new(),
+        }
+    }
+
+    fn encode_contract(&self, id: ContractId) -> anyhow::Result<EncodedState> {
+        match id {
+            DATA_PLAZA => self.contract1.encode_state(id),
+            BALANCES => self.contract2.encode_state(id),
+            ASSETS => self.contract3.encode_state(id),
+            WEB3_ANALYTICS => self.contract4.encode_state(id),
+            DIEM => self.contract5.encode_state(id),
+            _ => Err(anyhow::Error::msg(format!("Unknown contract {}", id))),
+        }
+    }
+
+    /// Feeds an event of an added chain to the contracts
+    fn handle_bridge_event(&mut self, bridge_id: BridgeId, event: &chain::ChainMessage) {
+        let changed = [
+            (
+                DATA_PLAZA,
+                self.contract1.handle_bridge_event(bridge_id, event),
+            ),
+            (
+                BALANCES,
+                self.contract2.handle_bridge_event(bridge_id, event),
+            ),
+            (ASSETS, self.contract3.handle_bridge_event(bridge_id, event)),
+            (
+                WEB3_ANALYTICS,
+                self.contract4.handle_bridge_event(bridge_id, event),
+            ),
+            (DIEM, self.contract5.handle_bridge_event(bridge_id, event)),
+        ];
+        for (id, changed) in changed.iter() {
+            if *changed {
+                self.commitment.touch(*id);
+            }
+        }
+    }
+
+    /// The storage keys requested by each contract
+    fn storage_keys(&self) -> Vec<(ContractId, Vec<Vec<u8>>)> {
+        vec![
+            (DATA_PLAZA, self.contract1.storage_keys()),
+            (BALANCES, self.contract2.storage_keys()),
+            (ASSETS, self.contract3.storage_keys()),
+            (WEB3_ANALYTICS, self.contract4.storage_keys()),
+            (DIEM, self.contract5.storage_keys()),
+        ]
+    }
+
+    /// Feeds the values of its storage keys at a block to a contract
+    fn handle_storage_reads(
+        &mut self,
+        id: ContractId,
+        blocknum: chain::BlockNumber,
+        reads: &[(Vec<u8>, Option<Vec<u8>>)],
+    ) {
+        let changed = match id {
+            DATA_PLAZA => self.contract1.handle_storage_reads(blocknum, reads),
+            BALANCES => self.contract2.handle_storage_reads(blocknum, reads),
+            ASSETS => self.contract3.handle_storage_reads(blocknum, reads),
+            WEB3_ANALYTICS => self.contract4.handle_storage_reads(blocknum, reads),
+            DIEM => self.contract5.handle_storage_reads(blocknum, reads),
+            _ => false,
+        };
+        if changed {
+            self.commitment.touch(id);
+        }
+    }
+
+    /// Encodes the states in the compact binary form. The contract states are encoded in CBOR
+    /// with their schema versions, while the rest is in SCALE.
+    pub fn encode_state(&self) -> anyhow::Result<Vec<u8>> {
+        let contracts = COMMITTED_CONTRACTS
+            .iter()
+            .map(|id| self.encode_contract(*id))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok((contracts, &self.light_client, self.main_bridge).encode())
+    }
+
+    /// Computes the Merkle root over the contract states. The leaves are the hashes of the
+    /// encoded states, ordered as `COMMITTED_CONTRACTS`.
+    pub fn state_root(&mut self) -> anyhow::Result<Hash> {
+        // A contract stays dirty until its leaf is updated, so a failure doesn't lose any change
+        let dirty: Vec<ContractId> = self.commitment.dirty.iter().cloned().collect();
+        for id in dirty {
+            let leaf = Hash(blake2_256(&self.encode_contract(id)?.encode()));
+            self.commitment.leaves.insert(id, leaf);
+            self.commitment.dirty.remove(&id);
+        }
+        let leaves: Vec<Hash> = COMMITTED_CONTRACTS
+            .iter()
+            .map(|id| self.commitment.leaves[id])
+            .collect();
+        Ok(merkle::merkle_root(&leaves))
+    }
+
+    /// Decodes the states produced by `encode_state`. The states are migrated if they are encoded
+    /// with older schema versions. A contract missing in the data starts from its initial state.
+    pub fn decode_state(data: &[u8]) -> anyhow::Result<Self> {
+        let (contracts, light_client, main_bridge): (Vec<EncodedState>, ChainLightValidation, u64) =
+            Decode::decode(&mut &data[..])
+                .map_err(|_| anyhow::Error::msg("Bad runtime state encoding"))?;
+
+        fn load<T: ContractState>(
+            contracts: &[EncodedState],
+            id: ContractId,
+            init: impl FnOnce() -> T,
+        ) -> anyhow::Result<T> {
+            match contracts.iter().find(|c| c.id == id) {
+                Some(encoded) => T::decode_state(encoded),
+                None => {
+                    warn!("Contract {} not found in the state, initializing it", id);
+                    Ok(init())
+                }
+            }
+        }
+
+        Ok(RuntimeState {
+            contract1: load(
+                &contracts,
+                DATA_PLAZA,
+                contracts::data_plaza::DataPlaza::new,
+            )?,
+            contract2: load(&contracts, BALANCES, || {
+                contracts::balances::Balances::new(None)
+            })?,
+            contract3: load(&contracts, ASSETS, contracts::assets::Assets::new)?,
+            contract4: load(
+                &contracts,
+                WEB3_ANALYTICS,
+                contracts::web3analytics::Web3Analytics::new,
+            )?,
+            contract5: load(&contracts, DIEM, contracts::diem::Diem::new)?,
+            light_client,
+            main_bridge,
+            commitment: StateCommitment::new(),
+        })
+    }
+}
+
+lazy_static! {
+    pub static ref STATE: SgxMutex<RuntimeState> = SgxMutex::new(RuntimeState::new());
+
+    pub static ref LOCAL_STATE: SgxMutex<LocalState> = {
+        // Give it an uninitialized default. Will be reset when initialig pRuntime. x
+        let raw_pk = hex::decode_hex("0000000000000000000000000000000000000000000000000000000000000001");
+        let sk = SecretKey::parse_slice(raw_pk.as_slice()).unwrap();
+        let pk = PublicKey::from_secret_key(&sk);
+
+        SgxMutex::new(
+            LocalState {
+                initialized: false,
+                public_key: Box::new(pk),
+                private_key: Box::new(sk),
+                headernum: 0,
+                blocknum: 0,
+                block_hashes: Vec::new(),
+                ecdh_private_key: None,
+                ecdh_public_key: None,
+                machine_id: [0; 16],
+                dev_mode: false,
+                runtime_info: None,
+                state_root: Hash::zero(),
+                checkpoint_interval: 0,
+                last_checkpoint: 0,
+                receipt_retention: 0,
+                bridges: BTreeMap::new(),
+            }
+        )
+    };
+
+    pub static ref SYSTEM_STATE: SgxMutex<system::System> = {
+        SgxMutex::new(system::System::new())
+    };
+
+    /// The headers of the ingestion stream waiting for a justified header
+    static ref PENDING_HEADERS: SgxMutex<Vec<HeaderToSync>> = SgxMutex::new(Vec::new());
+}
+
+/// The most headers buffered by the ingestion stream without a justification
+const MAX_PENDING_HEADERS: usize = 10_000;
+
+/// The calls implemented differently by the enclave and by the simulator
+pub trait Platform {
+    fn init_runtime(&self, args: InitRuntimeArgs) -> Result<InitRuntimeResp, RpcError>;
+    fn test(&self, args: TestArgs) -> Result<(), RpcError>;
+    fn test_ink(&self) -> Result<(), RpcError>;
+    /// Saves a checkpoint taken at `blocknum` to the host
+    fn save_checkpoint(&self, blocknum: u32, data: &[u8]) -> anyhow::Result<()>;
+}
+
+/// Handles a call. Both the binary and the JSON API end up here.
+pub fn dispatch(platform: &impl Platform, call: Call) -> Result<Reply, RpcError> {
+    let save = |blocknum: u32, data: &[u8]| platform.save_checkpoint(blocknum, data);
+    match call {
+        Call::Test(args) => platform.test(args).map(|_| Reply::Test),
+        Call::InitRuntime(args) => platform
+            .init_runtime(args)
+            .and_then(|resp| runtime_info_to_rpc(&resp))
+            .map(Reply::InitRuntime),
+        Call::GetInfo => Ok(Reply::GetInfo(get_info())),
+        Call::DumpStates => dump_states(save),
+        Call::LoadStates { data } => load_states(&data),
+        Call::SyncHeader {
+            headers,
+            authority_set_change,
+        } => sync_header(headers, authority_set_change),
+        Call::Query {
+            query_payload,
+            origin,
+        } => query(query_payload, origin),
+        Call::DispatchBlock { blocks } => {
+            let reply = dispatch_block(blocks)?;
+            checkpoint_if_due(save);
+            Ok(reply)
+        }
+        Call::GetRuntimeInfo => get_runtime_info(),
+        Call::AddBridge(args) => add_bridge(args),
+        Call::SyncBridgeHeader {
+            bridge_id,
+            headers,
+            authority_set_change,
+            head_proof,
+        } => sync_bridge_header(bridge_id, headers, authority_set_change, head_proof),
+        Call::DispatchBridgeBlock { bridge_id, blocks } => dispatch_bridge_block(bridge_id, blocks),
+        Call::GetBridges => get_bridges(),
+        Call::GetStorageKeys => get_storage_keys(),
+        Call::Set { path, data } => set(path, data),
+        Call::Get { path } => get(path),
+        Call::TestInk => platform.test_ink().map(|_| Reply::TestInk),
+    }
+}
+
+/// Handles an encoded `RpcRequest` and returns the encoded `RpcResponse`
+pub fn handle_rpc(platform: &impl Platform, request: &[u8]) -> Vec<u8> {
+    let result = RpcRequest::decode(&mut &request[..])
+        .map_err(|_| RpcError::new(ErrorCode::BadRequest, "Malformed request"))
+        .and_then(|request| {
+            info!("RPC call: {}", request.call.action());
+            dispatch(platform, request.call)
+        });
+    if let Err(e) = &result {
+        warn!("RPC call failed: {}", e);
+    }
+    let (ok, payload) = match result {
+        Ok(reply) => (true, reply.encode()),
+        Err(err) => (false, err.encode()),
+    };
+    let (message, signature) = sign_response(blake2_256(request), ok, payload);
+    RpcResponse {
+        message,
+        signature: signature.to_vec(),
+    }
+    .encode()
+}
+
+/// Handles a frame of the ingestion stream and returns the encoded `IngestAck`
+///
+/// `seq` 0 starts a new stream, dropping the headers left pending by the previous one.
+pub fn handle_ingest_frame(platform: &impl Platform, seq: u32, frame: &[u8]) -> Vec<u8> {
+    let result = IngestFrame::decode(&mut &frame[..])
+        .map_err(|_| RpcError::new(ErrorCode::BadRequest, "Malformed frame"))
+        .and_then(|frame| ingest_frame(platform, seq, frame));
+    if let Err(e) = &result {
+        warn!("Ingest frame {} failed: {}", seq, e);
+    }
+    let local_state = LOCAL_STATE.lock().unwrap();
+    IngestAck {
+        seq,
+        headernum: local_state.headernum,
+        blocknum: local_state.blocknum,
+        code: match result {
+            Ok(()) => 0,
+            Err(e) => e.code as u8,
+        },
+    }
+    .encode()
+}
+
+fn ingest_frame(platform: &impl Platform, seq: u32, frame: IngestFrame) -> Result<(), RpcError> {
+    let mut pending = PENDING_HEADERS.lock().unwrap();
+    if seq == 0 {
+        pending.clear();
+    }
+    match frame {
+        IngestFrame::Header {
+            header,
+            authority_set_change,
+        } => {
+            let header = HeaderToSync::decode(&mut &header[..])
+                .map_err(|_| RpcError::new(ErrorCode::BadRequest, "Invalid header"))?;
+            if header.justification.is_none() {
+                if authority_set_change.is_some() {
+                    return Err(RpcError::new(
+                        ErrorCode::BadRequest,
+                        "Authority set change without justification",
+                    ));
+                }
+                if pending.len() >= MAX_PENDING_HEADERS {
+                    pending.clear();
+                    return Err(RpcError::new(
+                        ErrorCode::BadRequest,
+                        "Too many headers without justification",
+                    ));
+                }
+                pending.push(header);
+                return Ok(());
+            }
+            let mut headers = crate::std::mem::take(&mut *pending);
+            headers.push(header);
+            let authority_set_change = decode_authority_set_change(authority_set_change)?;
+            sync_headers(headers, authority_set_change)?;
+        }
+        IngestFrame::Block(block) => {
+            drop(pending);
+            dispatch_block(vec![block])?;
+            checkpoint_if_due(|blocknum, data| platform.save_checkpoint(blocknum, data));
+        }
+    }
+    Ok(())
+}
+
+/// Converts the runtime info to its binary form
+pub fn runtime_info_to_rpc(resp: &InitRuntimeResp) -> Result<RuntimeInfo, RpcError> {
+    let decode = |h: &str| {
+        hex::try_decode_hex(h).ok_or_else(|| RpcError::new(ErrorCode::Internal, "Bad runtime info"))
+    };
+    Ok(RuntimeInfo {
+        encoded_runtime_info: resp.encoded_runtime_info.clone(),
+        public_key: decode(&resp.public_key)?,
+        ecdh_public_key: decode(&resp.ecdh_public_key)?,
+        attestation: resp
+            .attestation
+            .as_ref()
+            .map(|attn| phala_types::rpc::Attestation {
+                version: attn.version,
+                provider: attn.provider.clone(),
+                report: attn.payload.report.clone(),
+                signature: attn.payload.signature.clone(),
+                signing_cert: attn.payload.signing_cert.clone(),
+            }),
+    })
+}
+
+#[derive(Encode, Decode)]
+struct CheckpointBody {
+    block_hashes: Vec<Hash>,
+    bridges: BTreeMap<BridgeId, BridgeCursor>,
+    egress: MsgChannel,
+    receipts: system::ReceiptStore,
+    /// `Option<InitRuntimeResp>` in JSON
+    runtime_info: Vec<u8>,
+    /// `RuntimeState` in its binary encoding
+    state: Vec<u8>,
+}
+
+/// Takes an encrypted checkpoint of the runtime
+pub fn create_checkpoint() -> Result<Vec<u8>, RpcError> {
+    let local_state = LOCAL_STATE.lock().unwrap();
+    if !local_state.initialized {
+        return Err(RpcError::new(
+            ErrorCode::NotInitialized,
+            "Runtime not initialized",
+        ));
+    }
+    let mut state = STATE.lock().unwrap();
+    let system_state = SYSTEM_STATE.lock().unwrap();
+
+    let state_root = state.state_root().map_err(|e| {
+        RpcError::new(
+            ErrorCode::Internal,
+            format!("Failed to compute the state root: {}", e),
+        )
+    })?;
+    let encoded_state = state.encode_state().map_err(|e| {
+        RpcError::new(
+            ErrorCode::Internal,
+            format!("Failed to encode the state: {}", e),
+        )
+    })?;
+    let body = CheckpointBody {
+        block_hashes: local_state.block_hashes.clone(),
+        bridges: local_state.bridges.clone(),
+        egress: system_state.egress.clone(),
+        receipts: system_state.receipts.clone(),
+        runtime_info: serde_json::to_vec(&local_state.runtime_info).unwrap(),
+        state: encoded_state,
+    };
+    let encoded_body = body.encode();
+    info!("Checkpoint body size {}", encoded_body.len());
+
+    let key = checkpoint::derive_key(&local_state.private_key);
+    Ok(checkpoint::seal(
+        &key,
+        local_state.headernum,
+        local_state.blocknum,
+        state_root,
+        &encoded_body,
+    ))
+}
+
+/// Restores the runtime from a checkpoint taken by the same worker
+pub fn restore_checkpoint(data: &[u8]) -> Result<checkpoint::CheckpointHeader, RpcError> {
+    let bad_checkpoint = |msg: String| RpcError::new(ErrorCode::BadCheckpoint, msg);
+    let mut local_state = LOCAL_STATE.lock().unwrap();
+    let key = checkpoint::derive_key(&local_state.private_key);
+    let (header, raw_body) = checkpoint::open(&key, data)
+        .map_err(|e| bad_checkpoint(format!("Bad checkpoint: {}", e)))?;
+    let body = CheckpointBody::decode(&mut raw_body.as_slice())
+        .map_err(|_| bad_checkpoint("Bad checkpoint body".into()))?;
+    let runtime_info: Option<InitRuntimeResp> = serde_json::from_slice(&body.runtime_info)
+        .map_err(|_| bad_checkpoint("Bad checkpoint runtime info".into()))?;
+    let mut new_state = RuntimeState::decode_state(&body.state)
+        .map_err(|e| bad_checkpoint(format!("Bad checkpoint state: {}", e)))?;
+    let state_root = new_state
+        .state_root()
+        .map_err(|e| bad_checkpoint(format!("Bad checkpoint state: {}", e)))?;
+    checkpoint::check_state_root(&header, state_root)
+        .map_err(|e| bad_checkpoint(format!("Bad checkpoint: {}", e)))?;
+
+    // The identity key is not a part of the checkpoint
+    let id_pair = identity_pair(&local_state);
+    new_state.contract2.set_id(id_pair.clone());
+    new_state.contract3.set_id(id_pair.clone());
+    let mut state = STATE.lock().unwrap();
+    let mut system_state = SYSTEM_STATE.lock().unwrap();
+    system_state.set_id(&id_pair);
+    system_state.set_machine_id(local_state.machine_id.to_vec());
+    system_state.egress = body.egress;
+    system_state.receipts = body.receipts;
+    *state = new_state;
+
+    local_state.headernum = header.headernum;
+    local_state.blocknum = header.blocknum;
+    local_state.block_hashes = body.block_hashes;
+    local_state.bridges = body.bridges;
+    local_state.state_root = state_root;
+    local_state.runtime_info = runtime_info;
+    local_state.initialized = true;
+    local_state.last_checkpoint = header.blocknum;
+    info!(
+        "Restored checkpoint at header {} block {}",
+        header.headernum, header.blocknum
+    );
+    Ok(header)
+}
+
+/// Takes a checkpoint and hands it over to `save` along with its `blocknum`
+fn save_checkpoint_with(
+    save: impl FnOnce(u32, &[u8]) -> anyhow::Result<()>,
+) -> Result<(checkpoint::CheckpointHeader, Vec<u8>), RpcError> {
+    let data = create_checkpoint()?;
+    let header = checkpoint::peek_header(&data).expect("Just created; qed");
+    save(header.blocknum, &data).map_err(|e| {
+        RpcError::new(
+            ErrorCode::BadCheckpoint,
+            format!("Failed to save checkpoint: {}", e),
+        )
+    })?;
+    LOCAL_STATE.lock().unwrap().last_checkpoint = header.blocknum;
+    Ok((header, data))
+}
+
+/// Takes a checkpoint if `checkpoint_interval` blocks have been dispatched since the last one.
+/// Called after `dispatch_block`. The blocks are already dispatched, so failures are only logged.
+pub fn checkpoint_if_due(save: impl FnOnce(u32, &[u8]) -> anyhow::Result<()>) {
+    {
+        let local_state = LOCAL_STATE.lock().unwrap();
+        let interval = local_state.checkpoint_interval;
+        if interval == 0 || local_state.blocknum < local_state.last_checkpoint + interval {
+            return;
+        }
+    }
+    match save_checkpoint_with(save) {
+        Ok((header, data)) => info!(
+            "Checkpoint taken at block {} ({} bytes)",
+            header.blocknum,
+            data.len()
+        ),
+        Err(e) => error!("Failed to take checkpoint: {}", e),
+    }
+}
+
+/// Resumes from the newest checkpoint that can be restored. `load(i)` reads the `i`-th newest
+/// checkpoint, or returns `None` if there are no more.
+pub fn resume_from_checkpoint(load: impl Fn(usize) -> anyhow::Result<Option<Vec<u8>>>) {
+    for index in 0.. {
+        match load(index) {
+            Ok(Some(data)) => match restore_checkpoint(&data) {
+                Ok(_) => return,
+                Err(e) => warn!("Failed to restore checkpoint #{}: {}", index, e),
+            },
+            Ok(None) => break,
+            Err(e) => {
+                warn!("Failed to load checkpoint #{}: {:?}", index, e);
+                break;
+            }
+        }
+    }
+    info!("No checkpoint to resume from");
+}
+
+/// Takes a checkpoint, hands it over to `save`, and returns it to the caller as a backup
+pub fn dump_states(save: impl FnOnce(u32, &[u8]) -> anyhow::Result<()>) -> Result<Reply, RpcError> {
+    let (header, data) = save_checkpoint_with(save)?;
+    Ok(Reply::DumpStates {
+        data,
+        checkpoint: checkpoint_info(&header),
+    })
+}
+
+pub fn load_states(data: &[u8]) -> Result<Reply, RpcError> {
+    let header = restore_checkpoint(data)?;
+    Ok(Reply::LoadStates(checkpoint_info(&header)))
+}
+
+fn checkpoint_info(header: &checkpoint::CheckpointHeader) -> CheckpointInfo {
+    CheckpointInfo {
+        headernum: header.headernum,
+        blocknum: header.blocknum,
+        state_root: header.state_root.0,
+        body_hash: header.body_hash.0,
+    }
+}
+
+fn identity_pair(local_state: &LocalState) -> sp_core::ecdsa::Pair {
+    let ecdsa_seed = local_state.private_key.serialize();
+    sp_core::ecdsa::Pair::from_seed_slice(&ecdsa_seed).expect("Unexpected ecdsa key error")
+}
+
+/// Sets up the main bridge, the states bound to the identity key and the contract genesis.
+///
+/// Called by `init_runtime` once the identity keys are loaded into `local_state`.
+pub fn init_runtime_states(
+    local_state: &mut LocalState,
+    bridge_genesis_info: &[u8],
+    contracts_genesis: &ContractsGenesis,
+) -> Result<(), RpcError> {
+    contracts::genesis::check(contracts_genesis, local_state.dev_mode)
+        .map_err(|e| RpcError::new(ErrorCode::Forbidden, e.to_string()))?;
+    // Initialize bridge
+    let genesis =
+        light_validation::BridgeInitInfo::<chain::Runtime>::decode(&mut &bridge_genesis_info[..])
+            .map_err(|_| RpcError::new(ErrorCode::BadRequest, "Can't decode bridge_genesis_info"))?;
+    // Set up the bridge in local state
+    let mut state = STATE.lock().unwrap();
+    let bridge_id = state
+        .light_client
+        .initialize_bridge(
+            genesis.block_header,
+            genesis.validator_set,
+            genesis.validator_set_proof,
+        )
+        .expect("Bridge initialize failed");
+    state.main_bridge = bridge_id;
+    let id_pair = identity_pair(local_state);
+    // Re-init some contracts because they require the identity key
+    let mut system_state = SYSTEM_STATE.lock().unwrap();
+    system_state.set_id(&id_pair);
+    system_state.set_machine_id(local_state.machine_id.to_vec());
+    state.contract2 = contracts::balances::Balances::new(Some(id_pair.clone()));
+    state.contract3 = contracts::assets::Assets::new();
+    state.contract3.set_id(id_pair);
+    state.contract5 = contracts::diem::Diem::new();
+    let state = &mut *state;
+    let ecdh_key = local_state
+        .ecdh_private_key
+        .as_ref()
+        .ok_or_else(|| RpcError::new(ErrorCode::Internal, "ECDH key not initialized"))?;
+    contracts::genesis::apply(
+        contracts_genesis,
+        ecdh_key,
+        &mut state.contract2,
+        &mut state.contract3,
+        &mut state.contract5,
+        &mut state.contract4,
+    )
+    .map_err(|e| RpcError::new(ErrorCode::BadRequest, e.to_string()))?;
+    state.commitment.touch(BALANCES);
+    state.commitment.touch(ASSETS);
+    state.commitment.touch(DIEM);
+    state.commitment.touch(WEB3_ANALYTICS);
+    // Initialize other states
+    local_state.headernum = 1;
+    local_state.blocknum = 1;
+    local_state.bridges.clear();
+    Ok(())
+}
+
+/// Hashes a request as covered by the signature of its response
+///
+/// `request` is the whole `{input, nonce}` object. `Value` keeps the object keys sorted, so the
+/// compact encoding doesn't depend on how the request was encoded by the client or the host.
+pub fn request_hash(request: &Value) -> [u8; 32] {
+    blake2_256(&serde_json::to_vec(request).expect("Value is always serializable; qed."))
+}
+
+/// Wraps the result of an action into the signed `{status, payload, signature}` response
+///
+/// The signature covers the `SignedResponseMessage` binding the payload to the request and the
+/// current chain heights, which are returned along with it.
+pub fn sign_result(request_hash: [u8; 32], result: Result<Value, Value>) -> Value {
+    let (ok, payload) = match result {
+        Ok(payload) => (true, payload),
+        Err(payload) => (false, payload),
+    };
+    let s_payload = payload.to_string();
+    let (signed, signature) = sign_response(request_hash, ok, s_payload.as_bytes().to_vec());
+
+    json!({
+        "status": if ok { "ok" } else { "error" },
+        "payload": s_payload,
+        "signature": hex::encode_hex_compact(signature.as_ref()),
+        "headernum": signed.headernum,
+        "blocknum": signed.blocknum,
+    })
+}
+
+/// Signs the payload of a response with the identity key, along with the current chain heights
+fn sign_response(
+    request_hash: [u8; 32],
+    ok: bool,
+    payload: Vec<u8>,
+) -> (SignedResponseMessage, [u8; 64]) {
+    let local_state = LOCAL_STATE.lock().unwrap();
+    let signed = SignedResponseMessage {
+        request_hash,
+        ok,
+        payload,
+        headernum: local_state.headernum,
+        blocknum: local_state.blocknum,
+    };
+    let digest = sp_core::hashing::sha2_256(&signed.encode());
+    let message = secp256k1::Message::parse_slice(&digest).unwrap();
+    let (signature, _recovery_id) = secp256k1::sign(&message, &local_state.private_key);
+    (signed, signature.serialize())
+}
+
+fn handle_execution(
+    system: &mut system::System,
+    state: &mut RuntimeState,
+    pos: &TxRef,
+    origin: chain::AccountId,
+    contract_id: ContractId,
+    payload: &Vec<u8>,
+    command_index: CommandIndex,
+    ecdh_privkey: &EcdhKey,
+) {
+    // The payload comes from the chain, so a malformed one fails the command instead of the
+    // worker, or it would fail again at every replay
+    let (inner_data, status) = match decode_payload(payload, ecdh_privkey) {
+        Ok((inner_data, encrypted)) => {
+            info!(
+                "handle_execution: incominng cmd: {}",
+                String::from_utf8_lossy(&inner_data)
+            );
+            let status = execute_command(state, pos, &origin, contract_id, &inner_data, encrypted);
+            (inner_data, status)
+        }
+        Err(status) => {
+            warn!("handle_execution: Bad payload: {:?}", status);
+            (Vec::new(), status)
+        }
+    };
+
+    system.add_receipt(
+        command_index,
+        TransactionReceipt {
+            account: AccountIdWrapper(origin),
+            block_num: pos.blocknum,
+            contract_id,
+            command: String::from_utf8_lossy(&inner_data).to_string(),
+            status,
+        },
+    );
+}
+
+/// Decodes the payload of a command, and decrypts it if it's encrypted. Returns the command along
+/// with whether it was encrypted.
+fn decode_payload(
+    payload: &[u8],
+    ecdh_privkey: &EcdhKey,
+) -> Result<(Vec<u8>, bool), TransactionStatus> {
+    let payload: types::Payload =
+        serde_json::from_slice(payload).map_err(|_| TransactionStatus::BadCommand)?;
+    match payload {
+        types::Payload::Plain(data) => Ok((data.into_bytes(), false)),
+        types::Payload::Cipher(cipher) => cryptography::decrypt(&cipher, ecdh_privkey)
+            .map(|output| (output.msg, true))
+            .map_err(|_| TransactionStatus::DecryptFailed),
+    }
+}
+
+fn execute_command(
+    state: &mut RuntimeState,
+    pos: &TxRef,
+    origin: &chain::AccountId,
+    contract_id: ContractId,
+    inner_data: &[u8],
+    encrypted: bool,
+) -> TransactionStatus {
+    info!("handle_execution: about to call handle_command");
+    match contract_id {
+        DATA_PLAZA => match serde_json::from_slice::<contracts::data_plaza::Command>(inner_data) {
+            Ok(cmd) if cmd.is_confidential() && !encrypted => TransactionStatus::NotEncrypted,
+            Ok(cmd) => {
+                let status = state.contract1.handle_command(origin, pos, cmd);
+                // Escrows the budget of a new order, and delivers the orders ready. It's only
+                // done by the commands, so all the workers settle at the same blocks.
+                state.contract1.settle(&mut state.contract2);
+                state.commitment.touch(BALANCES);
+                status
+            }
+            _ => TransactionStatus::BadCommand,
+        },
+        BALANCES => match serde_json::from_slice(inner_data) {
+            Ok(cmd) => state.contract2.handle_command(origin, pos, cmd),
+            _ => TransactionStatus::BadCommand,
+        },
+        ASSETS => match serde_json::from_slice(inner_data) {
+            Ok(cmd) => state.contract3.handle_command(origin, pos, cmd),
+            _ => TransactionStatus::BadCommand,
+        },
+        WEB3_ANALYTICS => match serde_json::from_slice(inner_data) {
+            Ok(cmd) => state.contract4.handle_command(origin, pos, cmd),
+            _ => TransactionStatus::BadCommand,
+        },
+        DIEM => match serde_json::from_slice(inner_data) {
+            Ok(cmd) => state.contract5.handle_command(origin, pos, cmd),
+            _ => TransactionStatus::BadCommand,
+        },
+        _ => {
+            warn!(
+                "handle_execution: Skipped unknown contract: {}",
+                contract_id
+            );
+            TransactionStatus::BadContractId
+        }
+    }
+}
+
+pub fn sync_header(
+    headers_data: Vec<Vec<u8>>,
+    authority_set_change: Option<Vec<u8>>,
+) -> Result<Reply, RpcError> {
+    // Parse data to headers
+    let parsed_headers: Result<Vec<HeaderToSync>, _> = headers_data
+        .iter()
+        .map(|d| Decode::decode(&mut &d[..]))
+        .collect();
+    let headers =
+        parsed_headers.map_err(|_| RpcError::new(ErrorCode::BadRequest, "Invalid header"))?;
+    let authority_set_change = decode_authority_set_change(authority_set_change)?;
+    sync_headers(headers, authority_set_change)
+}
+
+fn decode_authority_set_change(
+    data: Option<Vec<u8>>,
+) -> Result<Option<AuthoritySetChange>, RpcError> {
+    data.map(|data| {
+        AuthoritySetChange::decode(&mut &data[..])
+            .map_err(|_| RpcError::new(ErrorCode::BadRequest, "cannot decode authority_set_change"))
+    })
+    .transpose()
+}
+
+fn sync_headers(
+    headers: Vec<HeaderToSync>,
+    authority_set_change: Option<AuthoritySetChange>,
+) -> Result<Reply, RpcError> {
+    let invalid_header = |msg: &str| RpcError::new(ErrorCode::InvalidHeader, msg);
+    // Light validation when possible
+    let (last_header, ancestry_proof) = split_headers(&headers)?;
+    {
+        // 1. the last header must has justification
+        let justification = last_header
+            .justification
+            .as_ref()
+            .ok_or_else(|| invalid_header("Missing justification"))?
+            .clone();
+        // 2. submit to light client
+        let mut state = STATE.lock().unwrap();
+        let bridge_id = state.main_bridge;
+        state
+            .light_client
+            .submit_finalized_headers(
+                bridge_id,
+                last_header.header.clone(),
+                ancestry_proof,
+                justification,
+                authority_set_change,
+            )
+            .map_err(|e| invalid_header(&format!("Light validation failed {:?}", e)))?
+    }
+    // Passed the validation
+    let mut local_state = LOCAL_STATE.lock().unwrap();
+    let local_state = &mut *local_state;
+    let synced_to = advance_headers(
+        &mut local_state.headernum,
+        &mut local_state.block_hashes,
+        &headers,
+    )?;
+
+    Ok(Reply::SyncHeader { synced_to })
+}
+
+/// Checks the order of the headers to sync, and splits them into the last header and the ancestry
+/// proof of it (from high to low)
+fn split_headers(
+    headers: &[HeaderToSync],
+) -> Result<(&HeaderToSync, Vec<chain::Header>), RpcError> {
+    let (last_header, ancestors) = headers
+        .split_last()
+        .ok_or_else(|| RpcError::new(ErrorCode::BadRequest, "No header in the request"))?;
+    for (i, header) in headers.iter().enumerate() {
+        if i > 0 && headers[i - 1].header.hash() != header.header.parent_hash {
+            return Err(RpcError::new(
+                ErrorCode::InvalidHeader,
+                "Incorrect header order",
+            ));
+        }
+    }
+    let ancestry_proof = ancestors.iter().rev().map(|h| h.header.clone()).collect();
+    Ok((last_header, ancestry_proof))
+}
+
+/// Moves the header cursor of a chain forward over the validated headers, and saves their hashes
+/// for the blocks to dispatch. Returns the last synced header.
+fn advance_headers(
+    headernum: &mut u32,
+    block_hashes: &mut Vec<Hash>,
+    headers: &[HeaderToSync],
+) -> Result<u32, RpcError> {
+    for (i, header) in headers.iter().enumerate() {
+        if header.header.number != *headernum + i as u32 {
+            return Err(RpcError::new(ErrorCode::InvalidHeader, "Unexpected header"));
+        }
+    }
+    let last_header = *headernum + headers.len() as u32 - 1;
+    *headernum = last_header + 1;
+    block_hashes.extend(headers.iter().map(|h| h.header.hash()));
+    Ok(last_header)
+}
+
+/// Starts tracking a chain besides the main one, with its own header and block cursors
+pub fn add_bridge(args: AddBridgeArgs) -> Result<Reply, RpcError> {
+    let bad_request = |msg: &str| RpcError::new(ErrorCode::BadRequest, msg);
+    let invalid_header = |e: anyhow::Error| {
+        RpcError::new(
+            ErrorCode::InvalidHeader,
+            format!("Light validation failed {:?}", e),
+        )
+    };
+    let mut local_state = LOCAL_STATE.lock().unwrap();
+    if !local_state.initialized {
+        return Err(RpcError::new(
+            ErrorCode::NotInitialized,
+            "Runtime not initialized",
+        ));
+    }
+    let mut state = STATE.lock().unwrap();
+    let (bridge_id, first_header) = match args {
+        AddBridgeArgs::Grandpa { genesis_info } => {
+            let genesis =
+                light_validation::BridgeInitInfo::<chain::Runtime>::decode(&mut &genesis_info[..])
+                    .map_err(|_| bad_request("Can't decode genesis_info"))?;
+            let number = genesis.block_header.number;
+            let bridge_id = state
+                .light_client
+                .initialize_bridge(
+                    genesis.block_header,
+                    genesis.validator_set,
+                    genesis.validator_set_proof,
+                )
+                .map_err(invalid_header)?;
+            (bridge_id, number)
+        }
+        AddBridgeArgs::Parachain {
+            relay_bridge,
+            para_id,
+            header,
+            head_proof,
+        } => {
+            let header = chain::Header::decode(&mut &header[..])
+                .map_err(|_| bad_request("Invalid header"))?;
+            let number = header.number;
+            let bridge_id = state
+                .light_client
+                .initialize_parachain(relay_bridge, para_id, header, head_proof)
+                .map_err(invalid_header)?;
+            (bridge_id, number)
+        }
+    };
+    local_state.bridges.insert(
+        bridge_id,
+        BridgeCursor {
+            headernum: first_header + 1,
+            blocknum: first_header + 1,
+            block_hashes: Vec::new(),
+        },
+    );
+    info!("Added bridge {} from header {}", bridge_id, first_header);
+    Ok(Reply::AddBridge { bridge_id })
+}
+
+fn unknown_bridge() -> RpcError {
+    RpcError::new(ErrorCode::NotFound, "Unknown bridge")
+}
+
+/// Syncs the headers of an added chain
+///
+/// The headers of a GRANDPA chain are validated as the main chain. A parachain has no finality
+/// of its own, so its last header must be proven as its head in the relay chain instead.
+pub fn sync_bridge_header(
+    bridge_id: BridgeId,
+    headers_data: Vec<Vec<u8>>,
+    authority_set_change: Option<Vec<u8>>,
+    head_proof: Option<Vec<Vec<u8>>>,
+) -> Result<Reply, RpcError> {
+    let bad_request = |msg: &str| RpcError::new(ErrorCode::BadRequest, msg);
+    let invalid_header = |msg: &str| RpcError::new(ErrorCode::InvalidHeader, msg);
+    let headers: Vec<HeaderToSync> = headers_data
+        .iter()
+        .map(|d| Decode::decode(&mut &d[..]))
+        .collect::<Result<_, _>>()
+        .map_err(|_| bad_request("Invalid header"))?;
+    let authority_set_change = decode_authority_set_change(authority_set_change)?;
+    let (last_header, ancestry_proof) = split_headers(&headers)?;
+
+    let mut local_state = LOCAL_STATE.lock().unwrap();
+    let cursor = local_state
+        .bridges
+        .get_mut(&bridge_id)
+        .ok_or_else(unknown_bridge)?;
+    {
+        let mut state = STATE.lock().unwrap();
+        let light_client = &mut state.light_client;
+        let result = if light_client.parachain(bridge_id).is_some() {
+            if authority_set_change.is_some() {
+                return Err(bad_request("Authority set change of a parachain"));
+            }
+            let head_proof = head_proof.ok_or_else(|| bad_request("Missing head_proof"))?;
+            light_client.submit_parachain_headers(
+                bridge_id,
+                last_header.header.clone(),
+                ancestry_proof,
+                head_proof,
+            )
+        } else {
+            let justification = last_header
+                .justification
+                .clone()
+                .ok_or_else(|| invalid_header("Missing justification"))?;
+            light_client.submit_finalized_headers(
+                bridge_id,
+                last_header.header.clone(),
+                ancestry_proof,
+                justification,
+                authority_set_change,
+            )
+        };
+        result.map_err(|e| invalid_header(&format!("Light validation failed {:?}", e)))?;
+    }
+    let synced_to = advance_headers(&mut cursor.headernum, &mut cursor.block_hashes, &headers)?;
+
+    Ok(Reply::SyncBridgeHeader {
+        bridge_id,
+        synced_to,
+    })
+}
+
+/// Dispatches the blocks of an added chain, feeding their messages to the contracts
+pub fn dispatch_bridge_block(
+    bridge_id: BridgeId,
+    blocks_data: Vec<Vec<u8>>,
+) -> Result<Reply, RpcError> {
+    let all_blocks: Vec<BlockHeaderWithEvents> = blocks_data
+        .iter()
+        .map(|d| Decode::decode(&mut &d[..]))
+        .collect::<Result<_, _>>()
+        .map_err(|_| RpcError::new(ErrorCode::BadRequest, "Invalid block"))?;
+
+    let mut local_state = LOCAL_STATE.lock().unwrap();
+    let local_state = &mut *local_state;
+    let cursor = local_state
+        .bridges
+        .get_mut(&bridge_id)
+        .ok_or_else(unknown_bridge)?;
+    let blocks = unprocessed_blocks(
+        &all_blocks,
+        cursor.blocknum,
+        cursor.headernum,
+        &cursor.block_hashes,
+    )?;
+
+    let mut state = STATE.lock().unwrap();
+    let mut last_block = 0;
+    for block in blocks {
+        let messages = verify_messages(&state.light_client, block)?;
+        for message in messages.iter() {
+            state.handle_bridge_event(bridge_id, message);
+        }
+        last_block = block.block_header.number;
+        cursor.block_hashes.remove(0);
+        cursor.blocknum = last_block + 1;
+    }
+    local_state.state_root = state.state_root().map_err(|e| {
+        RpcError::new(
+            ErrorCode::Internal,
+            format!("Failed to commit the state: {}", e),
+        )
+    })?;
+
+    Ok(Reply::DispatchBridgeBlock {
+        bridge_id,
+        dispatched_to: last_block,
+        state_root: local_state.state_root.0,
+    })
+}
+
+/// The cursors of the chains added by `add_bridge`
+pub fn get_bridges() -> Result<Reply, RpcError> {
+    let local_state = LOCAL_STATE.lock().unwrap();
+    let state = STATE.lock().unwrap();
+    let bridges = local_state
+        .bridges
+        .iter()
+        .map(|(bridge_id, cursor)| BridgeStatus {
+            bridge_id: *bridge_id,
+            parachain: state.light_client.parachain(*bridge_id),
+            headernum: cursor.headernum,
+            blocknum: cursor.blocknum,
+        })
+        .collect();
+    Ok(Reply::GetBridges(bridges))
+}
+
+/// The storage keys the contracts request at every block, to be attached by the host as
+/// `BlockHeaderWithEvents::storage_reads`
+pub fn get_storage_keys() -> Result<Reply, RpcError> {
+    let state = STATE.lock().unwrap();
+    let keys: BTreeSet<Vec<u8>> = state
+        .storage_keys()
+        .into_iter()
+        .flat_map(|(_, keys)| keys)
+        .collect();
+    Ok(Reply::GetStorageKeys {
+        keys: keys.into_iter().collect(),
+    })
+}
+
+pub fn dispatch_block(blocks_data: Vec<Vec<u8>>) -> Result<Reply, RpcError> {
+    // Parse data to blocks
+    let parsed_blocks: Result<Vec<BlockHeaderWithEvents>, _> = blocks_data
+        .iter()
+        .map(|d| Decode::decode(&mut &d[..]))
+        .collect();
+    let all_blocks =
+        parsed_blocks.map_err(|_| RpcError::new(ErrorCode::BadRequest, "Invalid block"))?;
+
+    let mut local_state = LOCAL_STATE.lock().unwrap();
+    let blocks = unprocessed_blocks(
+        &all_blocks,
+        local_state.blocknum,
+        local_state.headernum,
+        &local_state.block_hashes,
+    )?;
+
+    let ecdh_privkey = ecdh::clone_key(
+        local_state
+            .ecdh_private_key
+            .as_ref()
+            .expect("ECDH not initizlied"),
+    );
+    let mut last_block = 0;
+    for block in blocks.iter() {
+        handle_events(&block, &ecdh_privkey, local_state.dev_mode)?;
+        SYSTEM_STATE
+            .lock()
+            .unwrap()
+            .receipts
+            .prune(block.block_header.number, local_state.receipt_retention);
+
+        last_block = block.block_header.number;
+        local_state.block_hashes.remove(0);
+        local_state.blocknum = last_block + 1;
+        local_state.state_root = STATE.lock().unwrap().state_root().map_err(|e| {
+            RpcError::new(
+                ErrorCode::Internal,
+                format!("Failed to commit the state: {}", e),
+            )
+        })?;
+    }
+
+    Ok(Reply::DispatchBlock {
+        dispatched_to: last_block,
+        state_root: local_state.state_root.0,
+    })
+}
+
+/// The blocks not dispatched yet, checked against the synced headers of their chain
+fn unprocessed_blocks<'a>(
+    all_blocks: &'a [BlockHeaderWithEvents],
+    blocknum: u32,
+    headernum: u32,
+    block_hashes: &[Hash],
+) -> Result<Vec<&'a BlockHeaderWithEvents>, RpcError> {
+    let invalid_block = |msg: &str| RpcError::new(ErrorCode::InvalidBlock, msg);
+    // Ignore processed blocks
+    let blocks: Vec<_> = all_blocks
+        .iter()
+        .filter(|b| b.block_header.number >= blocknum)
+        .collect();
+    // Validate blocks
+    let first_block = &blocks
+        .first()
+        .ok_or_else(|| RpcError::new(ErrorCode::BadRequest, "No block in the request"))?;
+    let last_block = &blocks
+        .last()
+        .ok_or_else(|| RpcError::new(ErrorCode::BadRequest, "No block in the request"))?;
+    if first_block.block_header.number != blocknum {
+        return Err(invalid_block("Unexpected block"));
+    }
+    if last_block.block_header.number >= headernum {
+        return Err(invalid_block("Unsynced block"));
+    }
+    for (i, block) in blocks.iter().enumerate() {
+        let expected_hash = &block_hashes[i];
+        if block.block_header.hash() != *expected_hash {
+            return Err(invalid_block("Unexpected block hash"));
+        }
+    }
+    Ok(blocks)
+}
+
+/// Reads the `Phala::WorkerMessages` of a block from its storage proof. The messages of the
+/// versions or kinds unknown to this release are skipped, so runtime upgrades don't break it.
+fn verify_messages(
+    light_client: &ChainLightValidation,
+    block: &BlockHeaderWithEvents,
+) -> Result<Vec<chain::ChainMessage>, RpcError> {
+    let invalid_block = |msg: &str| RpcError::new(ErrorCode::InvalidBlock, msg);
+    let key = light_validation::utils::storage_prefix("Phala", "WorkerMessages");
+    let data = light_client
+        .read_storage_proof(
+            block.block_header.state_root,
+            block.messages_proof.clone(),
+            &[key.as_slice()],
+        )
+        .map_err(|_| invalid_block("Bad storage proof for messages"))?
+        .remove(0);
+    let messages = match data {
+        Some(data) => Vec::<VersionedMessage>::decode(&mut data.as_slice())
+            .map_err(|_| invalid_block("Decode messages error"))?,
+        None => Vec::new(),
+    };
+    Ok(messages
+        .iter()
+        .filter_map(|message| {
+            let decoded = message.decode_message();
+            if decoded.is_none() {
+                warn!("Skipped unknown message (version {})", message.version);
+            }
+            decoded
+        })
+        .collect())
+}
+
+fn handle_events(
+    block_with_events: &BlockHeaderWithEvents,
+    ecdh_privkey: &EcdhKey,
+    dev_mode: bool,
+) -> Result<(), RpcError> {
+    let ref mut state = STATE.lock().unwrap();
+    let invalid_block = |msg: &str| RpcError::new(ErrorCode::InvalidBlock, msg);
+    let messages = verify_messages(&state.light_client, block_with_events)?;
+    // Validate worker snapshot (if applicable)
+    let state_root = block_with_events.block_header.state_root;
+    if let Some(worker_snapshot) = block_with_events.worker_snapshot.as_ref() {
+        if !validate_worker_snapshot(&state.light_client, state_root, worker_snapshot) {
+            return Err(invalid_block("Invalid worker_snapshot storage proof"));
+        }
+    }
+    apply_storage_reads(state, block_with_events)?;
+    // Dispatch messages
+    let system = &mut SYSTEM_STATE.lock().unwrap();
+    let mut event_handler = system.feed_event();
+    for message in &messages {
+        // Dispatch to system contract anyway
+        event_handler
+            .feed(block_with_events, message)
+            .map_err(|e| RpcError::new(ErrorCode::InvalidBlock, format!("Event error {:?}", e)))?;
+        // Otherwise we only dispatch the messages for dev_mode pRuntime (not miners)
+        if !dev_mode {
+            info!("handle_events: skipped for miners");
+            continue;
+        }
+        match message {
+            ChainMessage::CommandPushed {
+                origin,
+                contract_id,
+                payload,
+                num,
+            } => {
+                info!(
+                    "push_command(contract_id: {}, payload: data[{}])",
+                    contract_id,
+                    payload.len()
+                );
+                let blocknum = block_with_events.block_header.number;
+                let pos = TxRef {
+                    blocknum,
+                    index: *num,
+                };
+                state.commitment.touch(*contract_id);
+                handle_execution(
+                    event_handler.system,
+                    state,
+                    &pos,
+                    origin.clone(),
+                    *contract_id,
+                    payload,
+                    *num,
+                    ecdh_privkey,
+                );
+            }
+            ChainMessage::AssetTransferToTee { .. } | ChainMessage::AssetTransferToChain { .. } => {
+                state.commitment.touch(ASSETS);
+                state.contract3.handle_event(message);
+            }
+            _ => {
+                state.commitment.touch(BALANCES);
+                state.contract2.handle_event(message);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Feeds each contract the values of the storage keys it requested at the block
+fn apply_storage_reads(
+    state: &mut RuntimeState,
+    block: &BlockHeaderWithEvents,
+) -> Result<(), RpcError> {
+    let requested = state.storage_keys();
+    let reads = read_requested_storage(&state.light_client, block, requested)?;
+    let blocknum = block.block_header.number;
+    for (id, reads) in reads {
+        state.handle_storage_reads(id, blocknum, &reads);
+    }
+    Ok(())
+}
+
+/// The values of the storage keys requested by the contracts at a block
+type ContractStorageReads = Vec<(ContractId, Vec<(Vec<u8>, Option<Vec<u8>>)>)>;
+
+/// Verifies the storage reads of a block against its state root and returns the values of the
+/// keys `requested` by each contract. Every requested key must be present, so that all the workers
+/// see the same values.
+fn read_requested_storage(
+    light_client: &ChainLightValidation,
+    block: &BlockHeaderWithEvents,
+    requested: Vec<(ContractId, Vec<Vec<u8>>)>,
+) -> Result<ContractStorageReads, RpcError> {
+    let invalid_block = |msg: &str| RpcError::new(ErrorCode::InvalidBlock, msg);
+    if requested.iter().all(|(_, keys)| keys.is_empty()) {
+        return Ok(Vec::new());
+    }
+    let StorageReadRequest { items, proof } = block
+        .storage_reads
+        .as_ref()
+        .ok_or_else(|| invalid_block("Missing storage reads"))?;
+    let keys: Vec<&[u8]> = items.iter().map(|(k, _)| k.as_slice()).collect();
+    let values = light_client
+        .read_storage_proof(block.block_header.state_root, proof.clone(), &keys)
+        .map_err(|_| invalid_block("Bad storage proof for storage reads"))?;
+    if items
+        .iter()
+        .zip(values.iter())
+        .any(|((_, v), proven)| v != proven)
+    {
+        return Err(invalid_block("Storage read value mismatch"));
+    }
+    let proven: BTreeMap<&[u8], &Option<Vec<u8>>> =
+        items.iter().map(|(k, v)| (k.as_slice(), v)).collect();
+    requested
+        .into_iter()
+        .filter(|(_, keys)| !keys.is_empty())
+        .map(|(id, keys)| {
+            let reads = keys
+                .into_iter()
+                .map(|k| {
+                    let value = proven
+                        .get(k.as_slice())
+                        .map(|v| (*v).clone())
+                        .ok_or_else(|| invalid_block("Missing storage read"))?;
+                    Ok((k, value))
+                })
+                .collect::<Result<Vec<_>, RpcError>>()?;
+            Ok::<_, RpcError>((id, reads))
+        })
+        .collect()
+}
+
+fn validate_worker_snapshot(
+    light_client: &ChainLightValidation,
+    state_root: Hash,
+    snapshot: &OnlineWorkerSnapshot,
+) -> bool {
+    info!("validate_worker_snapshot()");
+    use light_validation::utils::storage_prefix;
+    use phala_types::WorkerStateEnum;
+    let prefix_onlineworkers = storage_prefix("Phala", "OnlineWorkers");
+    let prefix_computeworkers = storage_prefix("Phala", "ComputeWorkers");
+    let prefix_workerstate = storage_prefix("Phala", "WorkerState");
+    let prefix_stakereceived = storage_prefix("MiningStaking", "StakeReceived");
+
+    let cond = [
+        // Check keys
+        snapshot.online_workers_kv.key() == prefix_onlineworkers.as_slice(),
+        snapshot.compute_workers_kv.key() == prefix_computeworkers.as_slice(),
+        snapshot
+            .worker_state_kv
+            .iter()
+            .all(|kv| kv.key().starts_with(prefix_workerstate.as_slice())),
+        // WorkerState key is real
+        snapshot
+            .stake_received_kv
+            .iter()
+            .all(|kv| kv.key().starts_with(prefix_stakereceived.as_slice())),
+        // There's no missing entry in WorkerState
+        snapshot.online_workers_kv.value() == &(snapshot.worker_state_kv.len() as u32),
+        // Compute worker is enabled
+        snapshot.compute_workers_kv.value() != &0,
+        // All the workers are online
+        snapshot
+            .worker_state_kv
+            .iter()
+            .all(|kv| match kv.value().state {
+                WorkerStateEnum::Mining(_) => true,
+                _ => false,
+            }),
+    ];
+    if !cond.iter().all(|x| *x) {
+        info!("Checks: {:?}", cond);
+        info!(
+            "stake_received key: {}",
+            hex::encode_hex_compact(snapshot.stake_received_kv[0].key())
+        );
+        info!("snapshot: {:?}", snapshot);
+        return false;
+    }
+
+    // Validate the storage proof
+    fn raw_kv<'a, T: FullCodec + Clone>(kv: &'a StorageKV<T>) -> (&'a [u8], Vec<u8>) {
+        (&kv.0, kv.1.encode())
+    }
+    let mut raw_items = Vec::<(&[u8], Vec<u8>)>::new();
+    raw_items.extend(snapshot.worker_state_kv.iter().map(raw_kv));
+    raw_items.extend(snapshot.stake_received_kv.iter().map(raw_kv));
+    raw_items.push(raw_kv(&snapshot.online_workers_kv));
+    raw_items.push(raw_kv(&snapshot.compute_workers_kv));
+    let raw_items_ref: Vec<_> = raw_items.iter().map(|(k, v)| (*k, v.as_slice())).collect();
+    let r = light_client.validate_storage_proof(
+        state_root,
+        snapshot.proof.clone(),
+        raw_items_ref.as_slice(),
+    );
+    if r.is_err() {
+        error!("Snapshot light validation: {:?}", r);
+        return false;
+    }
+    true
+}
+
+pub fn get_info() -> Info {
+    let local_state = LOCAL_STATE.lock().unwrap();
+    let ecdh_public_key = match &local_state.ecdh_public_key {
+        Some(ecdh_public_key) => ecdh_public_key.as_ref().to_vec(),
+        None => Vec::new(),
+    };
+    let system_state = SYSTEM_STATE.lock().unwrap();
+
+    Info {
+        initialized: local_state.initialized,
+        public_key: local_state.public_key.serialize_compressed().to_vec(),
+        ecdh_public_key,
+        headernum: local_state.headernum,
+        blocknum: local_state.blocknum,
+        machine_id: local_state.machine_id,
+        dev_mode: local_state.dev_mode,
+        state_root: local_state.state_root.0,
+        system_egress: EgressInfo {
+            sequence: system_state.egress.sequence,
+            len: system_state.egress.queue.len() as u32,
+        },
+        min_spec_version: chain::MIN_SPEC_VERSION,
+        max_spec_version: chain::MAX_SPEC_VERSION,
+    }
+}
+
+pub fn get_runtime_info() -> Result<Reply, RpcError> {
+    let local_state = LOCAL_STATE.lock().unwrap();
+    let resp = local_state
+        .runtime_info
+        .as_ref()
+        .ok_or_else(|| RpcError::new(ErrorCode::NotInitialized, "Uninitiated runtime info"))?;
+    runtime_info_to_rpc(resp).map(Reply::GetRuntimeInfo)
+}
+
+pub fn query(query_payload: String, origin: Option<QueryOrigin>) -> Result<Reply, RpcError> {
+    let bad_request = |msg: &str| RpcError::new(ErrorCode::BadRequest, msg);
+    let payload_data = query_payload.as_bytes();
+    // Validate signature
+    if let Some(origin) = &origin {
+        if !cryptography::verify_origin(origin, payload_data) {
+            return Err(RpcError::new(
+                ErrorCode::BadSignature,
+                "Verifying signature failed",
+            ));
+        }
+        info!("Verifying signature passed!");
+    }
+    // Load and decrypt if necessary
+    let payload: types::Payload =
+        serde_json::from_slice(payload_data).map_err(|_| bad_request("Malformed payload"))?;
+    let (msg, secret, pubkey) = {
+        let local_state = LOCAL_STATE.lock().unwrap();
+        match payload {
+            types::Payload::Plain(data) => (data.into_bytes(), None, None),
+            types::Payload::Cipher(cipher) => {
+                info!("cipher: {:?}", cipher);
+                let ecdh_privkey = local_state
+                    .ecdh_private_key
+                    .as_ref()
+                    .expect("ECDH not initizlied");
+                let result = cryptography::decrypt(&cipher, ecdh_privkey)
+                    .map_err(|_| bad_request("Decrypt failed"))?;
+                (
+                    result.msg,
+                    Some(result.secret),
+                    local_state.ecdh_public_key.clone(),
+                )
+            }
+        }
+    };
+    debug!("msg: {}", String::from_utf8_lossy(&msg));
+    let opaque_query: types::OpaqueQuery =
+        serde_json::from_slice(&msg).map_err(|_| bad_request("Malformed request (Query)"))?;
+    // Origin
+    let accid_origin = match origin.as_ref() {
+        Some(o) => {
+            let accid =
+                cryptography::origin_account_id(o).map_err(|_| bad_request("Bad origin"))?;
+            Some(chain::AccountId::from(accid))
+        }
+        None => None,
+    };
+    // Dispatch
+    let mut state = STATE.lock().unwrap();
+    let ref_origin = accid_origin.as_ref();
+    let res = match opaque_query.contract_id {
+        DATA_PLAZA => {
+            let request: contracts::data_plaza::Request = types::deopaque_query(opaque_query)
+                .map_err(|_| bad_request("Malformed request (data_plaza::Request)"))?
+                .request;
+            if request.is_confidential() && secret.is_none() {
+                return Err(bad_request("Unencrypted dataset request"));
+            }
+            serde_json::to_value(state.contract1.handle_query(ref_origin, request)).unwrap()
+        }
+        BALANCES => serde_json::to_value(
+            state.contract2.handle_query(
+                ref_origin,
+                types::deopaque_query(opaque_query)
+                    .map_err(|_| bad_request("Malformed request (balances::Request)"))?
+                    .request,
+            ),
+        )
+        .unwrap(),
+        ASSETS => serde_json::to_value(
+            state.contract3.handle_query(
+                ref_origin,
+                types::deopaque_query(opaque_query)
+                    .map_err(|_| bad_request("Malformed request (assets::Request)"))?
+                    .request,
+            ),
+        )
+        .unwrap(),
+        WEB3_ANALYTICS => {
+            let request: contracts::web3analytics::Request = types::deopaque_query(opaque_query)
+                .map_err(|_| bad_request("Malformed request (w3a::Request)"))?
+                .request;
+            if request.is_confidential() && secret.is_none() {
+                return Err(bad_request("Unencrypted site key request"));
+            }
+            serde_json::to_value(state.contract4.handle_query(ref_origin, request)).unwrap()
+        }
+        DIEM => serde_json::to_value(
+            state.contract5.handle_query(
+                ref_origin,
+                types::deopaque_query(opaque_query)
+                    .map_err(|_| bad_request("Malformed request (diem::Request)"))?
+                    .request,
+            ),
+        )
+        .unwrap(),
+        SYSTEM => {
+            let mut system_state = SYSTEM_STATE.lock().unwrap();
+            serde_json::to_value(
+                system_state.handle_query(
+                    ref_origin,
+                    types::deopaque_query(opaque_query)
+                        .map_err(|_| bad_request("Malformed request (system::Request)"))?
+                        .request,
+                ),
+            )
+            .unwrap()
+        }
+        _ => return Err(RpcError::new(ErrorCode::NotFound, "Unknown contract")),
+    };
+    // Encrypt response if necessary
+    let res_json = res.to_string();
+    let res_payload = if let (Some(sk), Some(pk)) = (secret, pubkey) {
+        let iv = aead::generate_iv();
+        let mut msg = res_json.as_bytes().to_vec();
+        aead::encrypt(&iv, &sk, &mut msg);
+        types::Payload::Cipher(cryptography::AeadCipher {
+            iv_b64: base64::encode(&iv),
+            cipher_b64: base64::encode(&msg),
+            pubkey_b64: base64::encode(&pk),
+        })
+    } else {
+        types::Payload::Plain(res_json)
+    };
+
+    Ok(Reply::Query {
+        payload: serde_json::to_string(&res_payload).unwrap(),
+    })
+}
+
+fn check_dev_mode() -> Result<(), RpcError> {
+    if !LOCAL_STATE.lock().unwrap().dev_mode {
+        return Err(RpcError::new(
+            ErrorCode::Forbidden,
+            "Only available in dev mode",
+        ));
+    }
+    Ok(())
+}
+
+/// Reads any DataPlaza dataset or result. Only for the dev mode, since it bypasses the access
+/// control of the `GetResult` query.
+pub fn get(path: String) -> Result<Reply, RpcError> {
+    check_dev_mode()?;
+    let state = STATE.lock().unwrap();
+    let data = state
+        .contract1
+        .get(&path)
+        .ok_or_else(|| RpcError::new(ErrorCode::NotFound, "Data doesn't exist"))?;
+    Ok(Reply::Get {
+        data: data.clone(),
+        path,
+    })
+}
+
+/// Writes any DataPlaza dataset. Only for the dev mode, since it bypasses the access control of
+/// the `Upload` command.
+pub fn set(path: String, data: Vec<u8>) -> Result<Reply, RpcError> {
+    check_dev_mode()?;
+    let mut state = STATE.lock().unwrap();
+    state.contract1.set(path.clone(), data);
+    state.commitment.touch(DATA_PLAZA);
+    Ok(Reply::Set { path })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    type Tx = (ContractId, chain::AccountId, Vec<u8>);
+
+    fn command(contract_id: ContractId, origin: u8, cmd: Value) -> Tx {
+        let payload = types::Payload::Plain(cmd.to_string());
+        (
+            contract_id,
+            chain::AccountId::from([origin; 32]),
+            serde_json::to_vec(&payload).unwrap(),
+        )
+    }
+
+    fn block_stream() -> Vec<Vec<Tx>> {
+        let dest = hex::encode_hex_compact(&[2; 32]);
+        vec![
+            vec![
+                command(
+                    ASSETS,
+                    1,
+                    json!({"Issue": {"symbol": "PHA", "total": "1000000"}}),
+                ),
+                command(
+                    WEB3_ANALYTICS,
+                    2,
+                    json!({"SetConfiguration": {"skip_stat": true}}),
+                ),
+            ],
+            vec![command(DIEM, 3, json!({"NewAccount": {"seq_number": 0}}))],
+            vec![
+                command(
+                    ASSETS,
+                    1,
+                    json!({"Transfer": {"id": 0, "dest": dest, "value": "10"}}),
+                ),
+                command(DIEM, 4, json!({"NewAccount": {"seq_number": 1}})),
+            ],
+        ]
+    }
+
+    /// Runs a fresh runtime over the blocks and returns the state root after each block
+    fn run(blocks: &[Vec<Tx>]) -> Vec<Hash> {
+        let mut state = RuntimeState::new();
+        // `NewAccount` needs a chain id, which is normally set by `SetTrustedState`
+        let mut diem = serde_json::to_value(&state.contract5).unwrap();
+        diem["chain_id"] = json!(2);
+        state.contract5 = serde_json::from_value(diem).unwrap();
+
+        let mut system = system::System::new();
+        let ecdh_key = ecdh::generate_key();
+        blocks
+            .iter()
+            .enumerate()
+            .map(|(i, block)| {
+                for (index, (contract_id, origin, payload)) in block.iter().enumerate() {
+                    let pos = TxRef {
+                        blocknum: i as chain::BlockNumber + 1,
+                        index: index as u64,
+                    };
+                    state.commitment.touch(*contract_id);
+                    handle_execution(
+                        &mut system,
+                        &mut state,
+                        &pos,
+                        origin.clone(),
+                        *contract_id,
+                        payload,
+                        index as CommandIndex,
+                        &ecdh_key,
+                    );
+                }
+                state.state_root().unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_deterministic_state_root() {
+        let blocks = block_stream();
+        let roots = run(&blocks);
+        // Another instance over the same blocks ends up with the same states
+        assert_eq!(run(&blocks), roots);
+        assert!(roots.windows(2).all(|w| w[0] != w[1]));
+        // The state root changes once the blocks differ
+        let mut other_blocks = block_stream();
+        other_blocks[2].pop();
+        let other_roots = run(&other_blocks);
+        assert_eq!(other_roots[..2], roots[..2]);
+        assert_ne!(other_roots[2], roots[2]);
+    }
+
+    #[test]
+    fn test_unknown_contract_not_committed() {
+        let blocks = block_stream();
+        let roots = run(&blocks);
+        // Commands to the system or an unknown contract don't break nor change the state root
+        let mut other_blocks = block_stream();
+        other_blocks[1].push(command(99, 5, json!({"Foo": {}})));
+        other_blocks[2].push(command(SYSTEM, 5, json!({"Foo": {}})));
+        assert_eq!(run(&other_blocks), roots);
+    }
+
+    #[test]
+    fn test_bad_payload() {
+        let mut state = RuntimeState::new();
+        let mut system = system::System::new();
+        let ecdh_key = ecdh::generate_key();
+        let mut execute = |index: CommandIndex, payload: &[u8]| {
+            let pos = TxRef {
+                blocknum: 1,
+                index: index as u64,
+            };
+            handle_execution(
+                &mut system,
+                &mut state,
+                &pos,
+                chain::AccountId::from([1; 32]),
+                ASSETS,
+                &payload.to_vec(),
+                index,
+                &ecdh_key,
+            );
+        };
+        let cipher = |pubkey: &[u8]| {
+            serde_json::to_vec(&types::Payload::Cipher(cryptography::AeadCipher {
+                iv_b64: base64::encode(&[0u8; 12]),
+                cipher_b64: base64::encode(b"garbage"),
+                pubkey_b64: base64::encode(pubkey),
+            }))
+            .unwrap()
+        };
+
+        // Neither the garbage on the chain nor the bad ciphers bring the worker down
+        execute(0, b"garbage");
+        execute(1, &cipher(&[4u8; 65]));
+        let valid_pubkey = ecdh::generate_key().compute_public_key().unwrap();
+        execute(2, &cipher(valid_pubkey.as_ref()));
+        let status = |index| system.receipts.get(index).unwrap().status.clone();
+        assert!(matches!(status(0), TransactionStatus::BadCommand));
+        assert!(matches!(status(1), TransactionStatus::DecryptFailed));
+        assert!(matches!(status(2), TransactionStatus::DecryptFailed));
+    }
+
+    #[test]
+    fn test_storage_reads() {
+        use crate::light_validation::storage_proof::tests::craft_proof;
+
+        let light_client = ChainLightValidation::new();
+        let (state_root, proof) = craft_proof::<chain::Hashing>(&[
+            (&b"key1"[..], &b"value1"[..]),
+            (&b"key2"[..], &b"value2"[..]),
+        ]);
+        let read =
+            |k: &str, v: Option<&str>| (k.as_bytes().to_vec(), v.map(|v| v.as_bytes().to_vec()));
+        let block = |items: Vec<(Vec<u8>, Option<Vec<u8>>)>| BlockHeaderWithEvents {
+            block_header: chain::Header {
+                parent_hash: Default::default(),
+                number: 1,
+                state_root,
+                extrinsics_root: Default::default(),
+                digest: Default::default(),
+            },
+            messages_proof: Default::default(),
+            worker_snapshot: None,
+            storage_reads: Some(StorageReadRequest {
+                items,
+                proof: proof.clone(),
+            }),
+        };
+        let requested = || {
+            vec![
+                (BALANCES, vec![b"key1".to_vec(), b"key3".to_vec()]),
+                (ASSETS, vec![]),
+                (DIEM, vec![b"key2".to_vec()]),
+            ]
+        };
+        let error = |block: &BlockHeaderWithEvents, requested| {
+            read_requested_storage(&light_client, block, requested)
+                .unwrap_err()
+                .message
+        };
+
+        // Each contract gets its keys, including the proven absent ones
+        let good = block(vec![
+            read("key1", Some("value1")),
+            read("key2", Some("value2")),
+            read("key3", None),
+        ]);
+        assert_eq!(
+            read_requested_storage(&light_client, &good, requested()).unwrap(),
+            vec![
+                (
+                    BALANCES,
+                    vec![read("key1", Some("value1")), read("key3", None)]
+                ),
+                (DIEM, vec![read("key2", Some("value2"))]),
+            ]
+        );
+        // The reads are only required when any key is requested
+        let mut no_reads = good.clone();
+        no_reads.storage_reads = None;
+        assert!(
+            read_requested_storage(&light_client, &no_reads, vec![(BALANCES, vec![])])
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(error(&no_reads, requested()), "Missing storage reads");
+        // The host can neither forge nor omit a value
+        let forged = block(vec![
+            read("key1", Some("value2")),
+            read("key2", Some("value2")),
+            read("key3", None),
+        ]);
+        assert_eq!(error(&forged, requested()), "Storage read value mismatch");
+        let omitted = block(vec![
+            read("key1", Some("value1")),
+            read("key2", Some("value2")),
+        ]);
+        assert_eq!(error(&omitted, requested()), "Missing storage read");
+    }
+
+    #[test]
+    fn test_signed_response() {
+        let request: Value =
+            serde_json::from_str(r#"{"nonce": {"value": 7}, "input": {"b": 1, "a": [2, 3]}}"#)
+                .unwrap();
+        let reordered: Value =
+            serde_json::from_str(r#"{"input":{"a":[2,3],"b":1},"nonce":{"value":7}}"#).unwrap();
+        let req_hash = request_hash(&request);
+        assert_eq!(request_hash(&reordered), req_hash);
+
+        let resp = sign_result(req_hash, Ok(json!({"answer": 42})));
+        let pk = LOCAL_STATE.lock().unwrap().public_key.clone();
+        let signed = SignedResponseMessage {
+            request_hash: req_hash,
+            ok: true,
+            payload: resp["payload"].as_str().unwrap().as_bytes().to_vec(),
+            headernum: resp["headernum"].as_u64().unwrap() as u32,
+            blocknum: resp["blocknum"].as_u64().unwrap() as u32,
+        };
+        let signature = hex::decode_hex(resp["signature"].as_str().unwrap());
+        let signature = secp256k1::Signature::parse_slice(&signature).unwrap();
+        let verify = |signed: &SignedResponseMessage| {
+            let digest = sp_core::hashing::sha2_256(&signed.encode());
+            let message = secp256k1::Message::parse_slice(&digest).unwrap();
+            secp256k1::verify(&message, &signature, &pk)
+        };
+        assert!(verify(&signed));
+        // Bound to the request and the payload
+        assert!(!verify(&SignedResponseMessage {
+            request_hash: request_hash(&json!({"input": {}, "nonce": {"value": 7}})),
+            ..signed.clone()
+        }));
+        assert!(!verify(&SignedResponseMessage {
+            payload: br#"{"answer":43}"#.to_vec(),
+            ..signed.clone()
+        }));
+        assert!(!verify(&SignedResponseMessage {
+            ok: false,
+            ..signed
+        }));
+    }
+}
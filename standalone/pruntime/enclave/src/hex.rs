@@ -2,51 +2,36 @@
 
 use crate::std::char;
 use crate::std::prelude::v1::*;
-use sgx_types::*;
 
-fn decode_hex_digit(digit: char) -> u8 {
+fn decode_hex_digit(digit: char) -> Option<u8> {
     match digit {
-        '0'..='9' => digit as u8 - '0' as u8,
-        'a'..='f' => digit as u8 - 'a' as u8 + 10,
-        'A'..='F' => digit as u8 - 'A' as u8 + 10,
-        _ => panic!(),
+        '0'..='9' => Some(digit as u8 - '0' as u8),
+        'a'..='f' => Some(digit as u8 - 'a' as u8 + 10),
+        'A'..='F' => Some(digit as u8 - 'A' as u8 + 10),
+        _ => None,
     }
 }
 
-pub fn decode_spid(hex: &str) -> sgx_spid_t {
-    let mut spid = sgx_spid_t::default();
-    let hex = hex.trim();
-
-    if hex.len() < 16 * 2 {
-        log::warn!("Input spid file len ({}) is incorrect!", hex.len());
-        return spid;
-    }
-
-    let decoded_vec = decode_hex(hex);
-
-    spid.id.copy_from_slice(&decoded_vec[..16]);
-
-    spid
+pub fn decode_hex(hex: &str) -> Vec<u8> {
+    try_decode_hex(hex).expect("Invalid hex")
 }
 
-pub fn decode_hex(hex: &str) -> Vec<u8> {
+/// Decodes `hex`, or returns `None` if it's not valid hex
+pub fn try_decode_hex(hex: &str) -> Option<Vec<u8>> {
     let mut r: Vec<u8> = Vec::new();
-    let mut chars = hex.chars().enumerate();
+    let mut chars = hex.chars();
     loop {
-        let (pos, first) = match chars.next() {
+        let first = match chars.next() {
             None => break,
             Some(elt) => elt,
         };
         if first == ' ' {
             continue;
         }
-        let (_, second) = match chars.next() {
-            None => panic!("pos = {}d", pos),
-            Some(elt) => elt,
-        };
-        r.push((decode_hex_digit(first) << 4) | decode_hex_digit(second));
+        let second = chars.next()?;
+        r.push((decode_hex_digit(first)? << 4) | decode_hex_digit(second)?);
     }
-    r
+    Some(r)
 }
 
 #[allow(unused)]
@@ -85,6 +70,7 @@ mod test {
 
     use super::decode_hex;
     use super::encode_hex;
+    use super::try_decode_hex;
 
     #[test]
     fn test_decode_hex() {
@@ -93,6 +79,8 @@ mod test {
         assert_eq!(decode_hex("ff"), [0xffu8].to_vec());
         assert_eq!(decode_hex("AB"), [0xabu8].to_vec());
         assert_eq!(decode_hex("fa 19"), [0xfau8, 0x19].to_vec());
+        assert_eq!(try_decode_hex("0g"), None);
+        assert_eq!(try_decode_hex("abc"), None);
     }
 
     #[test]
@@ -0,0 +1,329 @@
+//! The JSON API of pRuntime, kept during the transition to the binary API
+//!
+//! A JSON request (`{input, nonce}` with an action code) is translated to a `Call` and handled by
+//! `handlers::dispatch`, and the `Reply` is translated back to the JSON response the per-action
+//! handlers used to return. The errors are `{message, code}`, where `code` is the `ErrorCode`.
+
+use crate::std::prelude::v1::*;
+use crate::std::string::String;
+use crate::std::vec::Vec;
+use parity_scale_codec::Decode;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use phala_types::rpc::*;
+
+use crate::handlers::{dispatch, request_hash, sign_result, Platform};
+use crate::hex;
+use crate::rpc_types::*;
+use crate::types;
+
+/// Handles a JSON request and returns the signed JSON response
+///
+/// `request` is the whole `{input, nonce}` object sent by the client.
+pub fn handle_json(platform: &impl Platform, action: u8, request: Value) -> Value {
+    let request_hash = request_hash(&request);
+    let input = request.get("input").cloned().unwrap_or(Value::Null);
+    let result = parse_call(action, input).and_then(|call| dispatch(platform, call));
+    if let Err(e) = &result {
+        warn!("Action {} failed: {}", action, e);
+    }
+    sign_result(
+        request_hash,
+        result.map(reply_to_json).map_err(|e| error_to_json(&e)),
+    )
+}
+
+fn bad_request(msg: String) -> RpcError {
+    RpcError::new(ErrorCode::BadRequest, msg)
+}
+
+fn load_param<T: DeserializeOwned>(input: Value) -> Result<T, RpcError> {
+    serde_json::from_value(input).map_err(|e| bad_request(format!("Malformed input: {}", e)))
+}
+
+fn decode_b64(data: &str, field: &str) -> Result<Vec<u8>, RpcError> {
+    base64::decode(data).map_err(|_| bad_request(format!("Failed to decode base64 {}", field)))
+}
+
+fn decode_b64_list(list: &[String], field: &str) -> Result<Vec<Vec<u8>>, RpcError> {
+    list.iter().map(|b64| decode_b64(b64, field)).collect()
+}
+
+fn decode_hex(data: &str, field: &str) -> Result<Vec<u8>, RpcError> {
+    hex::try_decode_hex(data).ok_or_else(|| bad_request(format!("Failed to decode hex {}", field)))
+}
+
+/// Translates the input of a JSON request to the call
+pub fn parse_call(action: u8, input: Value) -> Result<Call, RpcError> {
+    let call = match action {
+        ACTION_TEST => {
+            let req: TestReq = load_param(input)?;
+            let test_ecdh = match req.test_ecdh {
+                Some(param) => Some(TestEcdhArgs {
+                    pubkey: param
+                        .pubkey_hex
+                        .map(|h| decode_hex(&h, "pubkey_hex"))
+                        .transpose()?,
+                    message: param
+                        .message_b64
+                        .map(|b64| decode_b64(&b64, "message_b64"))
+                        .transpose()?,
+                }),
+                None => None,
+            };
+            Call::Test(TestArgs {
+                test_parse_block: req.test_parse_block == Some(true),
+                test_bridge: req.test_bridge == Some(true),
+                test_ecdh,
+            })
+        }
+        ACTION_INIT_RUNTIME => {
+            let req: InitRuntimeReq = load_param(input)?;
+            Call::InitRuntime(InitRuntimeArgs {
+                skip_ra: req.skip_ra,
+                bridge_genesis_info: decode_b64(
+                    &req.bridge_genesis_info_b64,
+                    "bridge_genesis_info_b64",
+                )?,
+                debug_set_key: req
+                    .debug_set_key
+                    .map(|key| decode_hex(&key, "debug_set_key"))
+                    .transpose()?,
+                contracts_genesis: match req.contracts_genesis_b64 {
+                    Some(b64) => {
+                        let raw = decode_b64(&b64, "contracts_genesis_b64")?;
+                        ContractsGenesis::decode(&mut &raw[..]).map_err(|_| {
+                            bad_request("Can't decode contracts_genesis_b64".to_string())
+                        })?
+                    }
+                    None => Default::default(),
+                },
+            })
+        }
+        ACTION_GET_INFO => Call::GetInfo,
+        ACTION_DUMP_STATES => Call::DumpStates,
+        ACTION_LOAD_STATES => {
+            let req: LoadStatesReq = load_param(input)?;
+            Call::LoadStates {
+                data: decode_hex(&req.data, "data")?,
+            }
+        }
+        ACTION_SYNC_HEADER => {
+            let req: SyncHeaderReq = load_param(input)?;
+            Call::SyncHeader {
+                headers: req
+                    .headers_b64
+                    .iter()
+                    .map(|b64| decode_b64(b64, "header"))
+                    .collect::<Result<_, _>>()?,
+                authority_set_change: req
+                    .authority_set_change_b64
+                    .map(|b64| decode_b64(&b64, "authority_set_change_b64"))
+                    .transpose()?,
+            }
+        }
+        ACTION_QUERY => {
+            let req: types::SignedQuery = load_param(input)?;
+            let origin = req
+                .origin
+                .map(|o| {
+                    o.decode()
+                        .map_err(|e| bad_request(format!("Bad origin: {}", e)))
+                })
+                .transpose()?;
+            Call::Query {
+                query_payload: req.query_payload,
+                origin,
+            }
+        }
+        ACTION_DISPATCH_BLOCK => {
+            let req: DispatchBlockReq = load_param(input)?;
+            Call::DispatchBlock {
+                blocks: req
+                    .blocks_b64
+                    .iter()
+                    .map(|b64| decode_b64(b64, "block"))
+                    .collect::<Result<_, _>>()?,
+            }
+        }
+        ACTION_GET_RUNTIME_INFO => Call::GetRuntimeInfo,
+        ACTION_ADD_BRIDGE => {
+            let req: AddBridgeReq = load_param(input)?;
+            let args = match req.genesis_info_b64 {
+                Some(b64) => AddBridgeArgs::Grandpa {
+                    genesis_info: decode_b64(&b64, "genesis_info_b64")?,
+                },
+                None => {
+                    let missing = |field: &str| bad_request(format!("Missing {}", field));
+                    AddBridgeArgs::Parachain {
+                        relay_bridge: req.relay_bridge.ok_or_else(|| missing("relay_bridge"))?,
+                        para_id: req.para_id.ok_or_else(|| missing("para_id"))?,
+                        header: decode_b64(
+                            &req.header_b64.ok_or_else(|| missing("header_b64"))?,
+                            "header_b64",
+                        )?,
+                        head_proof: decode_b64_list(
+                            &req.head_proof_b64
+                                .ok_or_else(|| missing("head_proof_b64"))?,
+                            "head_proof_b64",
+                        )?,
+                    }
+                }
+            };
+            Call::AddBridge(args)
+        }
+        ACTION_SYNC_BRIDGE_HEADER => {
+            let req: SyncBridgeHeaderReq = load_param(input)?;
+            Call::SyncBridgeHeader {
+                bridge_id: req.bridge_id,
+                headers: decode_b64_list(&req.headers_b64, "header")?,
+                authority_set_change: req
+                    .authority_set_change_b64
+                    .map(|b64| decode_b64(&b64, "authority_set_change_b64"))
+                    .transpose()?,
+                head_proof: req
+                    .head_proof_b64
+                    .map(|list| decode_b64_list(&list, "head_proof_b64"))
+                    .transpose()?,
+            }
+        }
+        ACTION_DISPATCH_BRIDGE_BLOCK => {
+            let req: DispatchBridgeBlockReq = load_param(input)?;
+            Call::DispatchBridgeBlock {
+                bridge_id: req.bridge_id,
+                blocks: decode_b64_list(&req.blocks_b64, "block")?,
+            }
+        }
+        ACTION_GET_BRIDGES => Call::GetBridges,
+        ACTION_GET_STORAGE_KEYS => Call::GetStorageKeys,
+        ACTION_SET => {
+            let req: SetReq = load_param(input)?;
+            Call::Set {
+                data: decode_b64(&req.data, "data")?,
+                path: req.path,
+            }
+        }
+        ACTION_GET => {
+            let req: GetReq = load_param(input)?;
+            Call::Get { path: req.path }
+        }
+        ACTION_TEST_INK => Call::TestInk,
+        _ => return Err(RpcError::new(ErrorCode::UnknownCall, "Unknown action")),
+    };
+    Ok(call)
+}
+
+/// Translates the reply to the JSON payload of the response
+pub fn reply_to_json(reply: Reply) -> Value {
+    match reply {
+        Reply::Test | Reply::TestInk => json!({}),
+        Reply::InitRuntime(info) | Reply::GetRuntimeInfo(info) => {
+            serde_json::to_value(runtime_info_from_rpc(info)).unwrap()
+        }
+        Reply::GetInfo(info) => json!({
+            "initialized": info.initialized,
+            "public_key": hex::encode_hex_compact(&info.public_key),
+            "ecdh_public_key": hex::encode_hex_compact(&info.ecdh_public_key),
+            "headernum": info.headernum,
+            "blocknum": info.blocknum,
+            "machine_id": info.machine_id,
+            "dev_mode": info.dev_mode,
+            "state_root": hex::encode_hex_compact(&info.state_root),
+            "system_egress": {
+                "sequence": info.system_egress.sequence,
+                "len": info.system_egress.len,
+            },
+            "min_spec_version": info.min_spec_version,
+            "max_spec_version": info.max_spec_version,
+        }),
+        Reply::DumpStates { data, checkpoint } => json!({
+            "data": hex::encode_hex_compact(&data),
+            "headernum": checkpoint.headernum,
+            "blocknum": checkpoint.blocknum,
+            "state_root": hex::encode_hex_compact(&checkpoint.state_root),
+            "body_hash": hex::encode_hex_compact(&checkpoint.body_hash),
+        }),
+        Reply::LoadStates(checkpoint) => json!({
+            "headernum": checkpoint.headernum,
+            "blocknum": checkpoint.blocknum,
+            "state_root": hex::encode_hex_compact(&checkpoint.state_root),
+            "body_hash": hex::encode_hex_compact(&checkpoint.body_hash),
+        }),
+        Reply::SyncHeader { synced_to } => json!({ "synced_to": synced_to }),
+        Reply::Query { payload } => {
+            serde_json::from_str(&payload).expect("Encoded by ourselves; qed.")
+        }
+        Reply::DispatchBlock {
+            dispatched_to,
+            state_root,
+        } => json!({
+            "dispatched_to": dispatched_to,
+            "state_root": hex::encode_hex_compact(&state_root),
+        }),
+        Reply::AddBridge { bridge_id } => json!({ "bridge_id": bridge_id }),
+        Reply::SyncBridgeHeader {
+            bridge_id,
+            synced_to,
+        } => json!({ "bridge_id": bridge_id, "synced_to": synced_to }),
+        Reply::DispatchBridgeBlock {
+            bridge_id,
+            dispatched_to,
+            state_root,
+        } => json!({
+            "bridge_id": bridge_id,
+            "dispatched_to": dispatched_to,
+            "state_root": hex::encode_hex_compact(&state_root),
+        }),
+        Reply::GetBridges(bridges) => {
+            let bridges: Vec<_> = bridges
+                .iter()
+                .map(|bridge| {
+                    json!({
+                        "bridge_id": bridge.bridge_id,
+                        "parachain": bridge.parachain.map(|(relay_bridge, para_id)| json!({
+                            "relay_bridge": relay_bridge,
+                            "para_id": para_id,
+                        })),
+                        "headernum": bridge.headernum,
+                        "blocknum": bridge.blocknum,
+                    })
+                })
+                .collect();
+            json!({ "bridges": bridges })
+        }
+        Reply::GetStorageKeys { keys } => {
+            let keys: Vec<_> = keys.iter().map(|k| hex::encode_hex_compact(k)).collect();
+            json!({ "keys": keys })
+        }
+        Reply::Set { path } => json!({ "path": path }),
+        Reply::Get { path, data } => json!({
+            "path": path,
+            "value": base64::encode(&data),
+        }),
+    }
+}
+
+pub fn error_to_json(err: &RpcError) -> Value {
+    json!({
+        "message": err.message,
+        "code": err.code as u8,
+    })
+}
+
+fn runtime_info_from_rpc(info: RuntimeInfo) -> InitRuntimeResp {
+    InitRuntimeResp {
+        encoded_runtime_info: info.encoded_runtime_info,
+        public_key: hex::encode_hex_compact(&info.public_key),
+        ecdh_public_key: hex::encode_hex_compact(&info.ecdh_public_key),
+        attestation: info.attestation.map(|attn| InitRespAttestation {
+            version: attn.version,
+            provider: attn.provider,
+            payload: AttestationReport {
+                report: attn.report,
+                signature: attn.signature,
+                signing_cert: attn.signing_cert,
+            },
+        }),
+    }
+}
@@ -16,8 +16,6 @@ extern crate lazy_static;
 #[macro_use]
 extern crate log;
 
-extern crate runtime as chain;
-
 use sgx_rand::*;
 use sgx_tcrypto::*;
 use sgx_tse::*;
@@ -35,60 +33,43 @@ use crate::std::sync::SgxMutex;
 use crate::std::vec::Vec;
 use anyhow::Result;
 use core::convert::TryInto;
-use frame_system::EventRecord;
 use itertools::Itertools;
 use log::{debug, error, info, warn};
-use parity_scale_codec::{Decode, Encode, FullCodec};
+use parity_scale_codec::{Decode, Encode};
 use secp256k1::{PublicKey, SecretKey};
-use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde::{Deserialize, Serialize};
 use serde_cbor;
-use serde_json::{Map, Value};
-use sp_core::crypto::Pair;
-use sp_core::H256 as Hash;
 
 use http_req::request::{Method, Request};
 use std::time::Duration;
 
 use pink::InkModule;
 
-extern crate pallet_phala as phala;
-use phala_types::{
-    pruntime::{
-        BlockHeaderWithEvents as GenericBlockHeaderWithEvents, HeaderToSync as GenericHeaderToSync,
-        StorageKV,
-    },
-    PRuntimeInfo,
+use phala_types::rpc::{
+    ErrorCode, InitRuntimeArgs, RpcError, TestArgs, TestEcdhArgs, INGEST_ACK_LEN,
 };
+use phala_types::PRuntimeInfo;
 
 mod cert;
+mod chain;
+mod checkpoint;
 mod contracts;
 mod cryptography;
+mod handlers;
 mod hex;
+mod json_api;
 mod light_validation;
+mod merkle;
 mod msg_channel;
 mod rpc_types;
 mod system;
 mod types;
 
-use contracts::{
-    AccountIdWrapper, Contract, ContractId, ASSETS, BALANCES, DATA_PLAZA, DIEM, SYSTEM,
-    WEB3_ANALYTICS,
-};
 use cryptography::{aead, ecdh};
-use light_validation::AuthoritySetChange;
+use handlers::*;
 use rpc_types::*;
-use system::{CommandIndex, TransactionReceipt, TransactionStatus};
-use types::{Error, TxRef};
-
-type HeaderToSync =
-    GenericHeaderToSync<chain::BlockNumber, <chain::Runtime as frame_system::Config>::Hashing>;
-type BlockHeaderWithEvents = GenericBlockHeaderWithEvents<
-    chain::BlockNumber,
-    <chain::Runtime as frame_system::Config>::Hashing,
-    chain::Balance,
->;
-type OnlineWorkerSnapshot =
-    phala_types::pruntime::OnlineWorkerSnapshot<chain::BlockNumber, chain::Balance>;
+use system::TransactionStatus;
+use types::Error;
 
 extern "C" {
     pub fn ocall_load_ias_spid(
@@ -127,6 +108,14 @@ extern "C" {
 
     pub fn ocall_dump_state(
         ret_val: *mut sgx_status_t,
+        input_ptr: *const u8,
+        input_len: usize,
+        blocknum: u32,
+    ) -> sgx_status_t;
+
+    pub fn ocall_load_state(
+        ret_val: *mut sgx_status_t,
+        index: usize,
         output_ptr: *mut u8,
         output_len_ptr: *mut usize,
         output_buf_len: usize,
@@ -151,35 +140,6 @@ pub const IAS_HOST: &'static str = env!("IAS_HOST");
 pub const IAS_SIGRL_ENDPOINT: &'static str = env!("IAS_SIGRL_ENDPOINT");
 pub const IAS_REPORT_ENDPOINT: &'static str = env!("IAS_REPORT_ENDPOINT");
 
-type ChainLightValidation = light_validation::LightValidation<chain::Runtime>;
-type EcdhKey = ring::agreement::EphemeralPrivateKey;
-
-#[derive(Serialize, Deserialize, Debug)]
-struct RuntimeState {
-    contract1: contracts::data_plaza::DataPlaza,
-    contract2: contracts::balances::Balances,
-    contract3: contracts::assets::Assets,
-    contract4: contracts::web3analytics::Web3Analytics,
-    contract5: contracts::diem::Diem,
-    #[serde(serialize_with = "se_to_b64", deserialize_with = "de_from_b64")]
-    light_client: ChainLightValidation,
-    main_bridge: u64,
-}
-
-struct LocalState {
-    initialized: bool,
-    public_key: Box<PublicKey>,
-    private_key: Box<SecretKey>,
-    headernum: u32, // the height of synced block
-    blocknum: u32,  // the height of dispatched block
-    block_hashes: Vec<Hash>,
-    ecdh_private_key: Option<EcdhKey>,
-    ecdh_public_key: Option<ring::agreement::PublicKey>,
-    machine_id: [u8; 16],
-    dev_mode: bool,
-    runtime_info: Option<InitRuntimeResp>,
-}
-
 struct TestContract {
     name: String,
     code: Vec<u8>,
@@ -187,24 +147,6 @@ struct TestContract {
     txs: Vec<Vec<u8>>,
 }
 
-fn se_to_b64<S>(value: &ChainLightValidation, serializer: S) -> Result<S::Ok, S::Error>
-where
-    S: Serializer,
-{
-    let data = value.encode();
-    let s = base64::encode(data.as_slice());
-    String::serialize(&s, serializer)
-}
-
-fn de_from_b64<'de, D>(deserializer: D) -> Result<ChainLightValidation, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let s = String::deserialize(deserializer)?;
-    let data = base64::decode(&s).map_err(de::Error::custom)?;
-    ChainLightValidation::decode(&mut data.as_slice()).map_err(|_| de::Error::custom("bad data"))
-}
-
 fn to_sealed_log_for_slice<T: Copy + ContiguousMemory>(
     sealed_data: &SgxSealedData<[T]>,
     sealed_log: *mut u8,
@@ -227,45 +169,20 @@ fn from_sealed_log_for_slice<'a, T: Copy + ContiguousMemory>(
     }
 }
 
-lazy_static! {
-    static ref STATE: SgxMutex<RuntimeState> = {
-        SgxMutex::new(RuntimeState {
-            contract1: contracts::data_plaza::DataPlaza::new(),
-            contract2: contracts::balances::Balances::new(None),
-            contract3: contracts::assets::Assets::new(),
-            contract4: contracts::web3analytics::Web3Analytics::new(),
-            contract5: contracts::diem::Diem::new(),
-            light_client: ChainLightValidation::new(),
-            main_bridge: 0
-        })
-    };
+fn decode_spid(hex: &str) -> sgx_spid_t {
+    let mut spid = sgx_spid_t::default();
+    let hex = hex.trim();
 
-    static ref LOCAL_STATE: SgxMutex<LocalState> = {
-        // Give it an uninitialized default. Will be reset when initialig pRuntime. x
-        let raw_pk = hex::decode_hex("0000000000000000000000000000000000000000000000000000000000000001");
-        let sk = SecretKey::parse_slice(raw_pk.as_slice()).unwrap();
-        let pk = PublicKey::from_secret_key(&sk);
-
-        SgxMutex::new(
-            LocalState {
-                initialized: false,
-                public_key: Box::new(pk),
-                private_key: Box::new(sk),
-                headernum: 0,
-                blocknum: 0,
-                block_hashes: Vec::new(),
-                ecdh_private_key: None,
-                ecdh_public_key: None,
-                machine_id: [0; 16],
-                dev_mode: false,
-                runtime_info: None,
-            }
-        )
-    };
+    if hex.len() < 16 * 2 {
+        log::warn!("Input spid file len ({}) is incorrect!", hex.len());
+        return spid;
+    }
 
-    static ref SYSTEM_STATE: SgxMutex<system::System> = {
-        SgxMutex::new(system::System::new())
-    };
+    let decoded_vec = hex::decode_hex(hex);
+
+    spid.id.copy_from_slice(&decoded_vec[..16]);
+
+    spid
 }
 
 fn ias_spid() -> sgx_spid_t {
@@ -286,7 +203,7 @@ fn ias_spid() -> sgx_spid_t {
     let key_str = str::from_utf8(key_slice).unwrap();
     // println!("IAS SPID: {}", key_str.to_owned());
 
-    hex::decode_spid(&key_str[..key_len])
+    decode_spid(&key_str[..key_len])
 }
 
 fn ias_key() -> String {
@@ -646,20 +563,6 @@ fn generate_seal_key() -> [u8; 16] {
     seal_key.key
 }
 
-const ACTION_TEST: u8 = 0;
-const ACTION_INIT_RUNTIME: u8 = 1;
-const ACTION_GET_INFO: u8 = 2;
-const ACTION_DUMP_STATES: u8 = 3;
-const ACTION_LOAD_STATES: u8 = 4;
-const ACTION_SYNC_HEADER: u8 = 5;
-const ACTION_QUERY: u8 = 6;
-const ACTION_DISPATCH_BLOCK: u8 = 7;
-// Reserved: 8, 9
-const ACTION_GET_RUNTIME_INFO: u8 = 10;
-const ACTION_SET: u8 = 21;
-const ACTION_GET: u8 = 22;
-const ACTION_TEST_INK: u8 = 100;
-
 #[no_mangle]
 pub extern "C" fn ecall_set_state(input_ptr: *const u8, input_len: usize) -> sgx_status_t {
     let input_slice = unsafe { std::slice::from_raw_parts(input_ptr, input_len) };
@@ -669,6 +572,52 @@ pub extern "C" fn ecall_set_state(input_ptr: *const u8, input_len: usize) -> sgx
     sgx_status_t::SGX_SUCCESS
 }
 
+/// The enclave implementation of the platform dependent calls
+struct Enclave;
+
+impl Platform for Enclave {
+    fn init_runtime(&self, args: InitRuntimeArgs) -> Result<InitRuntimeResp, RpcError> {
+        init_runtime(args)
+    }
+
+    fn test(&self, args: TestArgs) -> Result<(), RpcError> {
+        test(args)
+    }
+
+    fn test_ink(&self) -> Result<(), RpcError> {
+        test_ink()
+    }
+
+    fn save_checkpoint(&self, blocknum: u32, data: &[u8]) -> Result<()> {
+        save_checkpoint(blocknum, data)
+    }
+}
+
+/// Copies the output of an ECALL to the buffer of the host
+///
+/// The full length is always reported, so the host can tell if the buffer is too small.
+fn write_output(
+    output: &[u8],
+    output_ptr: *mut u8,
+    output_len_ptr: *mut usize,
+    output_buf_len: usize,
+) {
+    let output_len = output.len();
+    unsafe {
+        if output_len <= output_buf_len {
+            ptr::copy_nonoverlapping(output.as_ptr(), output_ptr, output_len);
+        } else {
+            warn!("Too much output. Buffer overflow.");
+        }
+        ptr::copy_nonoverlapping(
+            &output_len as *const usize,
+            output_len_ptr,
+            std::mem::size_of_val(&output_len),
+        );
+    }
+}
+
+/// Handles a request to the JSON API
 #[no_mangle]
 pub extern "C" fn ecall_handle(
     action: u8,
@@ -679,84 +628,51 @@ pub extern "C" fn ecall_handle(
     output_buf_len: usize,
 ) -> sgx_status_t {
     let input_slice = unsafe { std::slice::from_raw_parts(input_ptr, input_len) };
-    let input: serde_json::value::Value = serde_json::from_slice(input_slice).unwrap();
-    let input_value = input.get("input").unwrap().clone();
-
-    // Strong typed
-    fn load_param<T: de::DeserializeOwned>(input_value: serde_json::value::Value) -> T {
-        serde_json::from_value(input_value).unwrap()
-    }
-
-    let result = match action {
-        ACTION_INIT_RUNTIME => init_runtime(load_param(input_value)),
-        ACTION_TEST => test(load_param(input_value)),
-        ACTION_QUERY => query(load_param(input_value)),
-        ACTION_SYNC_HEADER => sync_header(load_param(input_value)),
-        ACTION_DISPATCH_BLOCK => dispatch_block(load_param(input_value)),
-        _ => {
-            let payload = input_value.as_object().unwrap();
-            match action {
-                ACTION_GET_INFO => get_info(payload),
-                ACTION_DUMP_STATES => dump_states(payload),
-                ACTION_LOAD_STATES => load_states(payload),
-                ACTION_GET => get(payload),
-                ACTION_SET => set(payload),
-                ACTION_GET_RUNTIME_INFO => get_runtime_info(payload),
-                ACTION_TEST_INK => test_ink(payload),
-                _ => unknown(),
-            }
-        }
-    };
-
-    let local_state = LOCAL_STATE.lock().unwrap();
-
-    let output_json = match result {
-        Ok(payload) => {
-            let s_payload = payload.to_string();
-
-            let hash_payload = rsgx_sha256_slice(&s_payload.as_bytes()).unwrap();
-            let message = secp256k1::Message::parse_slice(&hash_payload[..32]).unwrap();
-            let (signature, _recovery_id) = secp256k1::sign(&message, &local_state.private_key);
-
-            json!({
-                "status": "ok",
-                "payload": s_payload,
-                "signature": hex::encode_hex_compact(signature.serialize().as_ref()),
-            })
-        }
-        Err(payload) => {
-            let s_payload = payload.to_string();
-
-            let hash_payload = rsgx_sha256_slice(&s_payload.as_bytes()).unwrap();
-            let message = secp256k1::Message::parse_slice(&hash_payload[..32]).unwrap();
-            let (signature, _recovery_id) = secp256k1::sign(&message, &local_state.private_key);
-
-            json!({
-                "status": "error",
-                "payload": s_payload,
-                "signature": hex::encode_hex_compact(signature.serialize().as_ref()),
-            })
+    let output_json = match serde_json::from_slice(input_slice) {
+        Ok(request) => json_api::handle_json(&Enclave, action, request),
+        Err(_) => {
+            error!("Malformed JSON request");
+            return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
         }
     };
     info!("{}", output_json.to_string());
 
-    let output_json_vec = serde_json::to_vec(&output_json).unwrap();
-    let output_json_vec_len = output_json_vec.len();
-    let output_json_vec_len_ptr = &output_json_vec_len as *const usize;
+    let output = serde_json::to_vec(&output_json).unwrap();
+    write_output(&output, output_ptr, output_len_ptr, output_buf_len);
+    sgx_status_t::SGX_SUCCESS
+}
 
-    unsafe {
-        if output_json_vec_len <= output_buf_len {
-            ptr::copy_nonoverlapping(output_json_vec.as_ptr(), output_ptr, output_json_vec_len);
-        } else {
-            warn!("Too much output. Buffer overflow.");
-        }
-        ptr::copy_nonoverlapping(
-            output_json_vec_len_ptr,
-            output_len_ptr,
-            std::mem::size_of_val(&output_json_vec_len),
-        );
-    }
+/// Handles a request to the binary API (an encoded `RpcRequest`)
+#[no_mangle]
+pub extern "C" fn ecall_rpc(
+    input_ptr: *const u8,
+    input_len: usize,
+    output_ptr: *mut u8,
+    output_len_ptr: *mut usize,
+    output_buf_len: usize,
+) -> sgx_status_t {
+    let input_slice = unsafe { std::slice::from_raw_parts(input_ptr, input_len) };
+    let output = handle_rpc(&Enclave, input_slice);
+    write_output(&output, output_ptr, output_len_ptr, output_buf_len);
+    sgx_status_t::SGX_SUCCESS
+}
 
+/// Handles a frame of the ingestion stream, writing its `IngestAck` to `ack_ptr`
+#[no_mangle]
+pub extern "C" fn ecall_ingest(
+    seq: u32,
+    frame_ptr: *const u8,
+    frame_len: usize,
+    ack_ptr: *mut u8,
+    ack_len: usize,
+) -> sgx_status_t {
+    if ack_len != INGEST_ACK_LEN {
+        return sgx_status_t::SGX_ERROR_INVALID_PARAMETER;
+    }
+    let frame = unsafe { std::slice::from_raw_parts(frame_ptr, frame_len) };
+    let ack = handle_ingest_frame(&Enclave, seq, frame);
+    let ack_slice = unsafe { std::slice::from_raw_parts_mut(ack_ptr, ack_len) };
+    ack_slice.copy_from_slice(&ack);
     sgx_status_t::SGX_SUCCESS
 }
 
@@ -918,94 +834,99 @@ fn init_secret_keys(
     Ok(data)
 }
 
-#[no_mangle]
-pub extern "C" fn ecall_init() -> sgx_status_t {
-    let mut local_state = LOCAL_STATE.lock().unwrap();
-    match init_secret_keys(&mut local_state, None) {
-        Err(e) if e.is::<sgx_status_t>() => e.downcast::<sgx_status_t>().unwrap(),
-        _ => sgx_status_t::SGX_SUCCESS,
+/// Saves the checkpoint to the host. It's safe to hand it out because it's encrypted by a key
+/// derived from the sealed identity key.
+fn save_checkpoint(blocknum: u32, data: &[u8]) -> Result<()> {
+    let mut retval = sgx_status_t::SGX_SUCCESS;
+    let result = unsafe { ocall_dump_state(&mut retval, data.as_ptr(), data.len(), blocknum) };
+    if result != sgx_status_t::SGX_SUCCESS {
+        return Err(anyhow::Error::msg(result));
     }
+    if retval != sgx_status_t::SGX_SUCCESS {
+        return Err(anyhow::Error::msg(retval));
+    }
+    Ok(())
 }
 
-// --------------------------------
-
-fn error_msg(msg: &str) -> Value {
-    json!({ "message": msg })
-}
-
-fn unknown() -> Result<Value, Value> {
-    Err(json!({
-        "message": "Unknown action"
-    }))
+const CHECKPOINT_BUF_INIT_LEN: usize = 1024 * 1024;
+
+/// Loads the `index`-th newest checkpoint from the host. Returns `None` if there isn't any.
+fn load_checkpoint(index: usize) -> Result<Option<Vec<u8>>> {
+    let mut buf_len = CHECKPOINT_BUF_INIT_LEN;
+    loop {
+        let mut buf = vec![0u8; buf_len];
+        let mut data_len: usize = 0;
+        let mut retval = sgx_status_t::SGX_SUCCESS;
+        let result = unsafe {
+            ocall_load_state(&mut retval, index, buf.as_mut_ptr(), &mut data_len, buf_len)
+        };
+        if result != sgx_status_t::SGX_SUCCESS {
+            return Err(anyhow::Error::msg(result));
+        }
+        if retval != sgx_status_t::SGX_SUCCESS {
+            return Err(anyhow::Error::msg(retval));
+        }
+        if data_len == 0 {
+            return Ok(None);
+        }
+        // The host always reports the full length. Retry with a larger buffer if it doesn't fit.
+        if data_len > buf_len {
+            buf_len = data_len;
+            continue;
+        }
+        buf.truncate(data_len);
+        return Ok(Some(buf));
+    }
 }
 
-const SECRET: &[u8; 32] = b"24e3e78e1f15150cdbad02f3205f6dd0";
-
-fn dump_states(_input: &Map<String, Value>) -> Result<Value, Value> {
-    let sessions = STATE.lock().unwrap();
-    let serialized = serde_json::to_string(&*sessions).unwrap();
-
-    // Your private data
-    let content = serialized.as_bytes().to_vec();
-    info!("Content to encrypt's size {}", content.len());
-    debug!("{}", serialized);
-
-    // Ring uses the same input variable as output
-    let mut in_out = content.clone();
-    info!("in_out len {}", in_out.len());
-
-    // Random data must be used only once per encryption
-    let iv = aead::generate_iv();
-    aead::encrypt(&iv, SECRET, &mut in_out);
+#[no_mangle]
+pub extern "C" fn ecall_init(checkpoint_interval: u32, receipt_retention: u32) -> sgx_status_t {
+    env_logger::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
-    Ok(json!({
-        "data": hex::encode_hex_compact(in_out.as_ref()),
-        "nonce": hex::encode_hex_compact(&iv)
-    }))
+    let mut local_state = LOCAL_STATE.lock().unwrap();
+    match init_secret_keys(&mut local_state, None) {
+        Err(e) if e.is::<sgx_status_t>() => return e.downcast::<sgx_status_t>().unwrap(),
+        _ => (),
+    }
+    local_state.checkpoint_interval = checkpoint_interval;
+    local_state.receipt_retention = receipt_retention;
+    // `restore_checkpoint` takes the lock by itself
+    drop(local_state);
+    resume_from_checkpoint(load_checkpoint);
+    sgx_status_t::SGX_SUCCESS
 }
 
-fn load_states(input: &Map<String, Value>) -> Result<Value, Value> {
-    let nonce_vec = hex::decode_hex(input.get("nonce").unwrap().as_str().unwrap());
-    let mut in_out = hex::decode_hex(input.get("data").unwrap().as_str().unwrap());
-    debug!("{}", input.get("data").unwrap().as_str().unwrap());
-
-    let decrypted_data = aead::decrypt(&nonce_vec, &*SECRET, &mut in_out);
-    debug!("{}", String::from_utf8(decrypted_data.to_vec()).unwrap());
-
-    let deserialized: RuntimeState = serde_json::from_slice(decrypted_data).unwrap();
-
-    debug!("{}", serde_json::to_string_pretty(&deserialized).unwrap());
-
-    let mut sessions = STATE.lock().unwrap();
-    let _ = std::mem::replace(&mut *sessions, deserialized);
-
-    Ok(json!({}))
-}
+// --------------------------------
 
-fn init_runtime(input: InitRuntimeReq) -> Result<Value, Value> {
-    // TODO: Guard only initialize once
+fn init_runtime(input: InitRuntimeArgs) -> Result<InitRuntimeResp, RpcError> {
     let mut local_state = LOCAL_STATE.lock().unwrap();
     if local_state.initialized {
-        return Err(json!({"message": "Already initialized"}));
+        return Err(RpcError::new(
+            ErrorCode::AlreadyInitialized,
+            "Already initialized",
+        ));
     }
 
-    env_logger::from_env(env_logger::Env::default().default_filter_or("info")).init();
-
     // load identity
-    if let Some(key) = input.debug_set_key {
+    if let Some(raw_key) = input.debug_set_key {
         if input.skip_ra == false {
-            return Err(error_msg("RA is disallowed when debug_set_key is enabled"));
+            return Err(RpcError::new(
+                ErrorCode::Forbidden,
+                "RA is disallowed when debug_set_key is enabled",
+            ));
         }
-        let raw_key = hex::decode_hex(&key);
         let ecdsa_key = SecretKey::parse_slice(raw_key.as_slice())
-            .map_err(|_| error_msg("can't parse private key"))?;
-        let ecdh_key =
-            ecdh::create_key(raw_key.as_slice()).map_err(|_| error_msg("can't create ecdh key"))?;
+            .map_err(|_| RpcError::new(ErrorCode::BadRequest, "can't parse private key"))?;
+        let ecdh_key = ecdh::create_key(raw_key.as_slice())
+            .map_err(|_| RpcError::new(ErrorCode::BadRequest, "can't create ecdh key"))?;
         init_secret_keys(&mut local_state, Some((ecdsa_key, ecdh_key)))
-            .map_err(|_| error_msg("failed to update secret key"))?;
+            .map_err(|_| RpcError::new(ErrorCode::Internal, "failed to update secret key"))?;
     }
     if !input.skip_ra && local_state.dev_mode {
-        return Err(error_msg("RA is disallowed when debug_set_key is enabled"));
+        return Err(RpcError::new(
+            ErrorCode::Forbidden,
+            "RA is disallowed when debug_set_key is enabled",
+        ));
     }
 
     let ecdsa_pk = &local_state.public_key;
@@ -1058,7 +979,10 @@ fn init_runtime(input: InitRuntimeReq) -> Result<Value, Value> {
             Ok(r) => r,
             Err(e) => {
                 error!("Error in create_attestation_report: {:?}", e);
-                return Err(json!({"message": "Error while connecting to IAS"}));
+                return Err(RpcError::new(
+                    ErrorCode::AttestationFailed,
+                    "Error while connecting to IAS",
+                ));
             }
         };
 
@@ -1073,34 +997,11 @@ fn init_runtime(input: InitRuntimeReq) -> Result<Value, Value> {
         });
     }
 
-    // Initialize bridge
-    let raw_genesis =
-        base64::decode(&input.bridge_genesis_info_b64).expect("Bad bridge_genesis_info_b64");
-    let genesis =
-        light_validation::BridgeInitInfo::<chain::Runtime>::decode(&mut raw_genesis.as_slice())
-            .expect("Can't decode bridge_genesis_info_b64");
-    // Set up the bridge in local state
-    let mut state = STATE.lock().unwrap();
-    let bridge_id = state
-        .light_client
-        .initialize_bridge(
-            genesis.block_header,
-            genesis.validator_set,
-            genesis.validator_set_proof,
-        )
-        .expect("Bridge initialize failed");
-    state.main_bridge = bridge_id;
-    let ecdsa_seed = local_state.private_key.serialize();
-    let id_pair = sp_core::ecdsa::Pair::from_seed_slice(&ecdsa_seed)
-        .expect("Unexpected ecdsa key error in init_runtime");
-    // Re-init some contracts because they require the identity key
-    let mut system_state = SYSTEM_STATE.lock().unwrap();
-    system_state.set_id(&id_pair);
-    system_state.set_machine_id(local_state.machine_id.to_vec());
-    state.contract2 = contracts::balances::Balances::new(Some(id_pair));
-    // Initialize other states
-    local_state.headernum = 1;
-    local_state.blocknum = 1;
+    init_runtime_states(
+        &mut local_state,
+        &input.bridge_genesis_info,
+        &input.contracts_genesis,
+    )?;
     // Response
     let resp = InitRuntimeResp {
         encoded_runtime_info,
@@ -1110,459 +1011,10 @@ fn init_runtime(input: InitRuntimeReq) -> Result<Value, Value> {
     };
     local_state.runtime_info = Some(resp.clone());
     local_state.initialized = true;
-    Ok(serde_json::to_value(resp).unwrap())
-}
-
-fn fmt_call(call: &chain::Call) -> String {
-    match call {
-        chain::Call::Timestamp(chain::TimestampCall::set(t)) => format!("Timestamp::set({})", t),
-        chain::Call::Balances(chain::BalancesCall::transfer(to, amount)) => {
-            format!("Balance::transfer({:?}, {:?})", to, amount)
-        }
-        _ => String::from("<Unparsed>"),
-    }
-}
-
-fn print_block(signed_block: &chain::SignedBlock) {
-    let header: &chain::Header = &signed_block.block.header;
-    let extrinsics: &Vec<chain::UncheckedExtrinsic> = &signed_block.block.extrinsics;
-
-    debug!("SignedBlock {{");
-    debug!("  block {{");
-    debug!("    header {{");
-    debug!("      number: {}", header.number);
-    debug!("      extrinsics_root: {}", header.extrinsics_root);
-    debug!("      state_root: {}", header.state_root);
-    debug!("      parent_hash: {}", header.parent_hash);
-    debug!("      digest: logs[{}]", header.digest.logs.len());
-    debug!("  extrinsics: [");
-    for extrinsic in extrinsics {
-        debug!("    UncheckedExtrinsic {{");
-        debug!("      function: {}", fmt_call(&extrinsic.function));
-        debug!("      signature: {:?}", extrinsic.signature);
-        debug!("    }}");
-    }
-    debug!("  ]");
-    debug!("  justification: <skipped...>");
-    debug!("}}");
-}
-
-fn parse_block(data: &Vec<u8>) -> Result<chain::SignedBlock> {
-    chain::SignedBlock::decode(&mut data.as_slice()).map_err(anyhow::Error::msg)
-}
-
-fn format_address(addr: &chain::Address) -> String {
-    match addr {
-        chain::Address::Id(id) => hex::encode_hex_compact(id.as_ref()),
-        chain::Address::Index(index) => format!("index:{:?}", index),
-        // TODO: Verify these
-        chain::Address::Raw(address) => hex::encode_hex_compact(address.as_ref()),
-        chain::Address::Address32(address) => hex::encode_hex_compact(address.as_ref()),
-        chain::Address::Address20(address) => hex::encode_hex_compact(address.as_ref()),
-    }
-}
-
-fn handle_execution(
-    system: &mut system::System,
-    state: &mut RuntimeState,
-    pos: &TxRef,
-    origin: chain::AccountId,
-    contract_id: ContractId,
-    payload: &Vec<u8>,
-    command_index: CommandIndex,
-    ecdh_privkey: &EcdhKey,
-) {
-    let payload: types::Payload =
-        serde_json::from_slice(payload.as_slice()).expect("Failed to decode payload");
-    let inner_data = match payload {
-        types::Payload::Plain(data) => data.into_bytes(),
-        types::Payload::Cipher(cipher) => {
-            cryptography::decrypt(&cipher, ecdh_privkey)
-                .expect("Decrypt failed")
-                .msg
-        }
-    };
-
-    let inner_data_string = String::from_utf8_lossy(&inner_data);
-    info!("handle_execution: incominng cmd: {}", inner_data_string);
-
-    info!("handle_execution: about to call handle_command");
-    let status = match contract_id {
-        DATA_PLAZA => match serde_json::from_slice(inner_data.as_slice()) {
-            Ok(cmd) => state.contract1.handle_command(&origin, pos, cmd),
-            _ => TransactionStatus::BadCommand,
-        },
-        BALANCES => match serde_json::from_slice(inner_data.as_slice()) {
-            Ok(cmd) => state.contract2.handle_command(&origin, pos, cmd),
-            _ => TransactionStatus::BadCommand,
-        },
-        ASSETS => match serde_json::from_slice(inner_data.as_slice()) {
-            Ok(cmd) => state.contract3.handle_command(&origin, pos, cmd),
-            _ => TransactionStatus::BadCommand,
-        },
-        WEB3_ANALYTICS => match serde_json::from_slice(inner_data.as_slice()) {
-            Ok(cmd) => state.contract4.handle_command(&origin, pos, cmd),
-            _ => TransactionStatus::BadCommand,
-        },
-        DIEM => match serde_json::from_slice(inner_data.as_slice()) {
-            Ok(cmd) => state.contract5.handle_command(&origin, pos, cmd),
-            _ => TransactionStatus::BadCommand,
-        },
-        _ => {
-            warn!(
-                "handle_execution: Skipped unknown contract: {}",
-                contract_id
-            );
-            TransactionStatus::BadContractId
-        }
-    };
-
-    system.add_receipt(
-        command_index,
-        TransactionReceipt {
-            account: AccountIdWrapper(origin),
-            block_num: pos.blocknum,
-            contract_id,
-            command: inner_data_string.to_string(),
-            status,
-        },
-    );
-}
-
-fn sync_header(input: SyncHeaderReq) -> Result<Value, Value> {
-    // Parse base64 to data
-    let parsed_data: Result<Vec<_>, _> = (&input.headers_b64).iter().map(base64::decode).collect();
-    let headers_data = parsed_data.map_err(|_| error_msg("Failed to parse base64 header"))?;
-    // Parse data to headers
-    let parsed_headers: Result<Vec<HeaderToSync>, _> = headers_data
-        .iter()
-        .map(|d| Decode::decode(&mut &d[..]))
-        .collect();
-    let headers = parsed_headers.map_err(|_| error_msg("Invalid header"))?;
-    // Light validation when possible
-    let last_header = headers
-        .last()
-        .ok_or_else(|| error_msg("No header in the request"))?;
-    {
-        // 1. the last header must has justification
-        let justification = last_header
-            .justification
-            .as_ref()
-            .ok_or_else(|| error_msg("Missing justification"))?
-            .clone();
-        let last_header = last_header.header.clone();
-        // 2. check header sequence
-        for (i, header) in headers.iter().enumerate() {
-            if i > 0 && headers[i - 1].header.hash() != header.header.parent_hash {
-                return Err(error_msg("Incorrect header order"));
-            }
-        }
-        // 3. generate accenstor proof
-        let mut accenstor_proof: Vec<_> = headers[0..headers.len() - 1]
-            .iter()
-            .map(|h| h.header.clone())
-            .collect();
-        accenstor_proof.reverse(); // from high to low
-                                   // 4. submit to light client
-        let mut state = STATE.lock().unwrap();
-        let bridge_id = state.main_bridge;
-        let authority_set_change = input
-            .authority_set_change_b64
-            .map(|b64| parse_authority_set_change(b64))
-            .transpose()?;
-        state
-            .light_client
-            .submit_finalized_headers(
-                bridge_id,
-                last_header,
-                accenstor_proof,
-                justification,
-                authority_set_change,
-            )
-            .map_err(|e| error_msg(format!("Light validation failed {:?}", e).as_str()))?
-    }
-    // Passed the validation
-    let mut local_state = LOCAL_STATE.lock().unwrap();
-    let mut last_header = 0;
-    for header_with_events in headers.iter() {
-        let header = &header_with_events.header;
-        if header.number != local_state.headernum {
-            return Err(error_msg("Unexpected header"));
-        }
-
-        // move forward
-        last_header = header.number;
-        local_state.headernum = last_header + 1;
-    }
-
-    // Save the block hashes for future dispatch
-    for header in headers.iter() {
-        local_state.block_hashes.push(header.header.hash());
-    }
-
-    Ok(json!({ "synced_to": last_header }))
-}
-
-fn dispatch_block(input: DispatchBlockReq) -> Result<Value, Value> {
-    // Parse base64 to data
-    let parsed_data: Result<Vec<_>, _> = (&input.blocks_b64).iter().map(base64::decode).collect();
-    let blocks_data = parsed_data.map_err(|_| error_msg("Failed to parse base64 block"))?;
-    // Parse data to blocks
-    let parsed_blocks: Result<Vec<BlockHeaderWithEvents>, _> = blocks_data
-        .iter()
-        .map(|d| Decode::decode(&mut &d[..]))
-        .collect();
-    let all_blocks = parsed_blocks.map_err(|_| error_msg("Invalid block"))?;
-
-    let mut local_state = LOCAL_STATE.lock().unwrap();
-    // Ignore processed blocks
-    let blocks: Vec<_> = all_blocks
-        .iter()
-        .filter(|b| b.block_header.number >= local_state.blocknum)
-        .collect();
-    // Validate blocks
-    let first_block = &blocks
-        .first()
-        .ok_or_else(|| error_msg("No block in the request"))?;
-    let last_block = &blocks
-        .last()
-        .ok_or_else(|| error_msg("No block in the request"))?;
-    if first_block.block_header.number != local_state.blocknum {
-        return Err(error_msg("Unexpected block"));
-    }
-    if last_block.block_header.number >= local_state.headernum {
-        return Err(error_msg("Unsynced block"));
-    }
-    for (i, block) in blocks.iter().enumerate() {
-        let expected_hash = &local_state.block_hashes[i];
-        if block.block_header.hash() != *expected_hash {
-            return Err(error_msg("Unexpected block hash"));
-        }
-    }
-
-    let ecdh_privkey = ecdh::clone_key(
-        local_state
-            .ecdh_private_key
-            .as_ref()
-            .expect("ECDH not initizlied"),
-    );
-    let mut last_block = 0;
-    for block in blocks.iter() {
-        if block.events.is_none() {
-            return Err(error_msg("Event was required"));
-        }
-
-        handle_events(&block, &ecdh_privkey, local_state.dev_mode)?;
-
-        last_block = block.block_header.number;
-        local_state.block_hashes.remove(0);
-        local_state.blocknum = last_block + 1;
-    }
-
-    Ok(json!({ "dispatched_to": last_block }))
-}
-
-fn parse_authority_set_change(data_b64: String) -> Result<AuthoritySetChange, Value> {
-    let data = base64::decode(&data_b64)
-        .map_err(|_| error_msg("cannot decode authority_set_change_b64"))?;
-    AuthoritySetChange::decode(&mut &data[..])
-        .map_err(|_| error_msg("cannot decode authority_set_change"))
-}
-
-fn handle_events(
-    block_with_events: &BlockHeaderWithEvents,
-    ecdh_privkey: &EcdhKey,
-    dev_mode: bool,
-) -> Result<(), Value> {
-    let ref mut state = STATE.lock().unwrap();
-    let missing_field = error_msg("Missing field");
-    // Validate sotrage proof for events
-    let events = block_with_events
-        .events
-        .as_ref()
-        .ok_or(missing_field.clone())?;
-    let proof = block_with_events
-        .proof
-        .as_ref()
-        .ok_or(missing_field.clone())?;
-    let event_storage_key = light_validation::utils::storage_prefix("System", "Events");
-    let state_root = block_with_events.block_header.state_root;
-    state
-        .light_client
-        .validate_storage_proof(
-            state_root,
-            proof.clone(),
-            &[(event_storage_key.as_slice(), events.as_slice())],
-        )
-        .map_err(|_| error_msg("Bad storage proof for events"))?;
-    // Validate worker snapshot (if applicable)
-    if let Some(worker_snapshot) = block_with_events.worker_snapshot.as_ref() {
-        if !validate_worker_snapshot(&state.light_client, state_root, worker_snapshot) {
-            return Err(error_msg("Invalid worker_snapshot storage proof"));
-        }
-    }
-    // Dispatch events
-    let events = Vec::<EventRecord<chain::Event, Hash>>::decode(&mut events.as_slice())
-        .map_err(|_| error_msg("Decode events error"))?;
-    let system = &mut SYSTEM_STATE.lock().unwrap();
-    let mut event_handler = system.feed_event();
-    for evt in &events {
-        if let chain::Event::pallet_phala(pe) = &evt.event {
-            // Dispatch to system contract anyway
-            event_handler
-                .feed(block_with_events, &pe)
-                .map_err(|e| error_msg(format!("Event error {:?}", e).as_str()))?;
-            // Otherwise we only dispatch the events for dev_mode pRuntime (not miners)
-            if !dev_mode {
-                info!("handle_events: skipped for miners");
-                continue;
-            }
-            match pe {
-                phala::RawEvent::CommandPushed(who, contract_id, payload, num) => {
-                    info!(
-                        "push_command(contract_id: {}, payload: data[{}])",
-                        contract_id,
-                        payload.len()
-                    );
-                    let blocknum = block_with_events.block_header.number;
-                    let pos = TxRef {
-                        blocknum,
-                        index: *num,
-                    };
-                    handle_execution(
-                        event_handler.system,
-                        state,
-                        &pos,
-                        who.clone(),
-                        *contract_id,
-                        payload,
-                        *num,
-                        ecdh_privkey,
-                    );
-                }
-                _ => {
-                    state.contract2.handle_event(evt.event.clone());
-                }
-            }
-        }
-    }
-    Ok(())
+    Ok(resp)
 }
 
-fn validate_worker_snapshot(
-    light_client: &ChainLightValidation,
-    state_root: Hash,
-    snapshot: &OnlineWorkerSnapshot,
-) -> bool {
-    info!("validate_worker_snapshot()");
-    use light_validation::utils::storage_prefix;
-    use phala_types::WorkerStateEnum;
-    let prefix_onlineworkers = storage_prefix("Phala", "OnlineWorkers");
-    let prefix_computeworkers = storage_prefix("Phala", "ComputeWorkers");
-    let prefix_workerstate = storage_prefix("Phala", "WorkerState");
-    let prefix_stakereceived = storage_prefix("MiningStaking", "StakeReceived");
-
-    let cond = [
-        // Check keys
-        snapshot.online_workers_kv.key() == prefix_onlineworkers.as_slice(),
-        snapshot.compute_workers_kv.key() == prefix_computeworkers.as_slice(),
-        snapshot
-            .worker_state_kv
-            .iter()
-            .all(|kv| kv.key().starts_with(prefix_workerstate.as_slice())),
-        // WorkerState key is real
-        snapshot
-            .stake_received_kv
-            .iter()
-            .all(|kv| kv.key().starts_with(prefix_stakereceived.as_slice())),
-        // There's no missing entry in WorkerState
-        snapshot.online_workers_kv.value() == &(snapshot.worker_state_kv.len() as u32),
-        // Compute worker is enabled
-        snapshot.compute_workers_kv.value() != &0,
-        // All the workers are online
-        snapshot
-            .worker_state_kv
-            .iter()
-            .all(|kv| match kv.value().state {
-                WorkerStateEnum::Mining(_) => true,
-                _ => false,
-            }),
-    ];
-    if !cond.iter().all(|x| *x) {
-        info!("Checks: {:?}", cond);
-        info!(
-            "stake_received key: {}",
-            hex::encode_hex_compact(snapshot.stake_received_kv[0].key())
-        );
-        info!("snapshot: {:?}", snapshot);
-        return false;
-    }
-
-    // Validate the storage proof
-    fn raw_kv<'a, T: FullCodec + Clone>(kv: &'a StorageKV<T>) -> (&'a [u8], Vec<u8>) {
-        (&kv.0, kv.1.encode())
-    }
-    let mut raw_items = Vec::<(&[u8], Vec<u8>)>::new();
-    raw_items.extend(snapshot.worker_state_kv.iter().map(raw_kv));
-    raw_items.extend(snapshot.stake_received_kv.iter().map(raw_kv));
-    raw_items.push(raw_kv(&snapshot.online_workers_kv));
-    raw_items.push(raw_kv(&snapshot.compute_workers_kv));
-    let raw_items_ref: Vec<_> = raw_items.iter().map(|(k, v)| (*k, v.as_slice())).collect();
-    let r = light_client.validate_storage_proof(
-        state_root,
-        snapshot.proof.clone(),
-        raw_items_ref.as_slice(),
-    );
-    if r.is_err() {
-        error!("Snapshot light validation: {:?}", r);
-        return false;
-    }
-    true
-}
-
-fn get_info(_input: &Map<String, Value>) -> Result<Value, Value> {
-    let local_state = LOCAL_STATE.lock().unwrap();
-
-    let initialized = local_state.initialized;
-    let pk = &local_state.public_key;
-    let s_pk = hex::encode_hex_compact(pk.serialize_compressed().as_ref());
-    let s_ecdh_pk = match &local_state.ecdh_public_key {
-        Some(ecdh_public_key) => hex::encode_hex_compact(ecdh_public_key.as_ref()),
-        None => "".to_string(),
-    };
-    let headernum = local_state.headernum;
-    let blocknum = local_state.blocknum;
-    let machine_id = local_state.machine_id;
-
-    let system_state = SYSTEM_STATE.lock().unwrap();
-    let sys_seq_start = system_state.egress.sequence;
-    let sys_len = system_state.egress.queue.len();
-
-    Ok(json!({
-        "initialized": initialized,
-        "public_key": s_pk,
-        "ecdh_public_key": s_ecdh_pk,
-        "headernum": headernum,
-        "blocknum": blocknum,
-        "machine_id": machine_id,
-        "dev_mode": local_state.dev_mode,
-        "system_egress": {
-            "sequence": sys_seq_start,
-            "len": sys_len,
-        }
-    }))
-}
-
-fn get_runtime_info(_input: &Map<String, Value>) -> Result<Value, Value> {
-    let local_state = LOCAL_STATE.lock().unwrap();
-    let resp = local_state
-        .runtime_info
-        .as_ref()
-        .ok_or_else(|| error_msg("Uninitiated runtime info"))?;
-    Ok(serde_json::to_value(resp).unwrap())
-}
-
-fn test_ink(_input: &Map<String, Value>) -> Result<Value, Value> {
+fn test_ink() -> Result<(), RpcError> {
     info!("=======Begin Ink Contract Test=======");
 
     let mut testcases = Vec::new();
@@ -1611,166 +1063,7 @@ fn test_ink(_input: &Map<String, Value>) -> Result<Value, Value> {
         }
     }
 
-    Ok(json!({}))
-}
-
-fn query(q: types::SignedQuery) -> Result<Value, Value> {
-    let payload_data = q.query_payload.as_bytes();
-    // Validate signature
-    if let Some(origin) = &q.origin {
-        if !origin
-            .verify(payload_data)
-            .map_err(|_| error_msg("Bad signature or origin"))?
-        {
-            return Err(error_msg("Verifying signature failed"));
-        }
-        info!("Verifying signature passed!");
-    }
-    // Load and decrypt if necessary
-    let payload: types::Payload =
-        serde_json::from_slice(payload_data).expect("Failed to decode payload");
-    let (msg, secret, pubkey) = {
-        let local_state = LOCAL_STATE.lock().unwrap();
-        match payload {
-            types::Payload::Plain(data) => (data.into_bytes(), None, None),
-            types::Payload::Cipher(cipher) => {
-                info!("cipher: {:?}", cipher);
-                let ecdh_privkey = local_state
-                    .ecdh_private_key
-                    .as_ref()
-                    .expect("ECDH not initizlied");
-                let result = cryptography::decrypt(&cipher, ecdh_privkey).expect("Decrypt failed");
-                (
-                    result.msg,
-                    Some(result.secret),
-                    local_state.ecdh_public_key.clone(),
-                )
-            }
-        }
-    };
-    debug!("msg: {}", String::from_utf8_lossy(&msg));
-    let opaque_query: types::OpaqueQuery =
-        serde_json::from_slice(&msg).map_err(|_| error_msg("Malformed request (Query)"))?;
-    // Origin
-    let accid_origin = match q.origin.as_ref() {
-        Some(o) => {
-            let accid =
-                contracts::account_id_from_hex(&o.origin).map_err(|_| error_msg("Bad origin"))?;
-            Some(accid)
-        }
-        None => None,
-    };
-    // Dispatch
-    let mut state = STATE.lock().unwrap();
-    let ref_origin = accid_origin.as_ref();
-    let res = match opaque_query.contract_id {
-        DATA_PLAZA => serde_json::to_value(
-            state.contract1.handle_query(
-                ref_origin,
-                types::deopaque_query(opaque_query)
-                    .map_err(|_| error_msg("Malformed request (data_plaza::Request)"))?
-                    .request,
-            ),
-        )
-        .unwrap(),
-        BALANCES => serde_json::to_value(
-            state.contract2.handle_query(
-                ref_origin,
-                types::deopaque_query(opaque_query)
-                    .map_err(|_| error_msg("Malformed request (balances::Request)"))?
-                    .request,
-            ),
-        )
-        .unwrap(),
-        ASSETS => serde_json::to_value(
-            state.contract3.handle_query(
-                ref_origin,
-                types::deopaque_query(opaque_query)
-                    .map_err(|_| error_msg("Malformed request (assets::Request)"))?
-                    .request,
-            ),
-        )
-        .unwrap(),
-        WEB3_ANALYTICS => serde_json::to_value(
-            state.contract4.handle_query(
-                ref_origin,
-                types::deopaque_query(opaque_query)
-                    .map_err(|_| error_msg("Malformed request (w3a::Request)"))?
-                    .request,
-            ),
-        )
-        .unwrap(),
-        DIEM => serde_json::to_value(
-            state.contract5.handle_query(
-                ref_origin,
-                types::deopaque_query(opaque_query)
-                    .map_err(|_| error_msg("Malformed request (diem::Request)"))?
-                    .request,
-            ),
-        )
-        .unwrap(),
-        SYSTEM => {
-            let mut system_state = SYSTEM_STATE.lock().unwrap();
-            serde_json::to_value(
-                system_state.handle_query(
-                    ref_origin,
-                    types::deopaque_query(opaque_query)
-                        .map_err(|_| error_msg("Malformed request (system::Request)"))?
-                        .request,
-                ),
-            )
-            .unwrap()
-        }
-        _ => return Err(Value::Null),
-    };
-    // Encrypt response if necessary
-    let res_json = res.to_string();
-    let res_payload = if let (Some(sk), Some(pk)) = (secret, pubkey) {
-        let iv = aead::generate_iv();
-        let mut msg = res_json.as_bytes().to_vec();
-        aead::encrypt(&iv, &sk, &mut msg);
-        types::Payload::Cipher(cryptography::AeadCipher {
-            iv_b64: base64::encode(&iv),
-            cipher_b64: base64::encode(&msg),
-            pubkey_b64: base64::encode(&pk),
-        })
-    } else {
-        types::Payload::Plain(res_json)
-    };
-
-    let res_value = serde_json::to_value(res_payload).unwrap();
-    Ok(res_value)
-}
-
-fn get(input: &Map<String, Value>) -> Result<Value, Value> {
-    let state = STATE.lock().unwrap();
-    let path = input.get("path").unwrap().as_str().unwrap();
-
-    let data = match state.contract1.get(&path.to_string()) {
-        Some(d) => d,
-        None => return Err(error_msg("Data doesn't exist")),
-    };
-
-    let data_b64 = base64::encode(data);
-
-    Ok(json!({
-        "path": path.to_string(),
-        "value": data_b64
-    }))
-}
-
-fn set(input: &Map<String, Value>) -> Result<Value, Value> {
-    let mut state = STATE.lock().unwrap();
-    let path = input.get("path").unwrap().as_str().unwrap();
-    let data_b64 = input.get("data").unwrap().as_str().unwrap();
-
-    let data = base64::decode(data_b64).map_err(|_| error_msg("Failed to decode base64 data"))?;
-    state.contract1.set(path.to_string(), data);
-
-    Ok(json!({
-        "path": path.to_string(),
-        "data": data_b64.to_string()
-    }))
+    Ok(())
 }
 
 fn test_bridge() {
@@ -1804,39 +1097,7 @@ fn test_bridge() {
         .expect("Submit first block failed; qed");
 }
 
-fn test_parse_block() {
-    let raw_block: Vec<u8> = base64::decode("iAKMDRPbdbAZ0eev9OZ1QgaAkoEnazAp6JzH2GeRFYdsR+pFUBbOaAW0+k5K+jPtsEr/P/JKJQDSobnB98Qhf8ug8HkDygkapC5T++CNvzYORIFimatwYSu/U53t66xzpQgGYXVyYSCGvagPAAAAAAVhdXJhAQEuXZ5zy2+qk+60y+/m1r0oZv/+LEiDCxMotfkvjP9aebuUVxBTmd2LCpu645AAjpRUNhqOmVuiKreUoV1aMpWLCCgEAQALoPTZAm8BQQKE/9Q1k8cV/dMcYRQavQSpn9aCLIVYhUzN45pWhOelbaJ9AU5gayhZiGwAEAthrYW6Ucm+acGAR3whdfUk17jp4NMearo4+NxR2w0VsVkEF0gQ/U6AHggnM+BZmvrhhMdSygqlAQAABAD/jq8EFRaHc2Mmyf6hfiX8UodhNpPJEpCcsiaqR5TyakgHABCl1OgA")
-        .unwrap();
-    debug!("SignedBlock data[{}]", raw_block.len());
-    let block = match parse_block(&raw_block) {
-        Ok(b) => b,
-        Err(err) => {
-            error!("test_parse_block: Failed to parse block ({:?})", err);
-            return;
-        }
-    };
-    print_block(&block);
-
-    // test parse address
-    let ref_sig = block.block.extrinsics[1].signature.as_ref().unwrap();
-    let ref_addr = &ref_sig.0;
-    debug!("test_parse_block: addr = {}", format_address(ref_addr));
-
-    let cmd_json = serde_json::to_string_pretty(&contracts::data_plaza::Command::List(
-        contracts::data_plaza::ItemDetails {
-            name: "nname".to_owned(),
-            category: "ccategory".to_owned(),
-            description: "ddesc".to_owned(),
-            price: contracts::data_plaza::PricePolicy::PerRow { price: 100_u128 },
-            dataset_link: "llink".to_owned(),
-            dataset_preview: "pprev".to_owned(),
-        },
-    ))
-    .expect("jah");
-    debug!("sample command: {}", cmd_json);
-}
-
-fn test_ecdh(params: TestEcdhParam) {
+fn test_ecdh(params: TestEcdhArgs) {
     let bob_pub: [u8; 65] = [
         0x04, 0xb8, 0xd1, 0x8e, 0x7d, 0xe4, 0xc1, 0x10, 0x69, 0x48, 0x7b, 0x5c, 0x1e, 0x6e, 0xa5,
         0xdf, 0x04, 0x51, 0xf7, 0xe1, 0xa8, 0x46, 0x17, 0x5b, 0xf6, 0xfd, 0xf8, 0xe8, 0xea, 0x5c,
@@ -1845,8 +1106,7 @@ fn test_ecdh(params: TestEcdhParam) {
         0xbf, 0x33, 0x78, 0x32, 0x96,
     ];
 
-    let pubkey_data = params.pubkey_hex.map(|h| hex::decode_hex(&h));
-    let pk = match pubkey_data.as_ref() {
+    let pk = match params.pubkey.as_ref() {
         Some(d) => d.as_slice(),
         None => bob_pub.as_ref(),
     };
@@ -1856,11 +1116,10 @@ fn test_ecdh(params: TestEcdhParam) {
         .ecdh_private_key
         .as_ref()
         .expect("ECDH private key not initialized");
-    let key = ecdh::agree(alice_priv, pk);
+    let key = ecdh::agree(alice_priv, pk).expect("ecdh failed");
     debug!("ECDH derived secret key: {}", hex::encode_hex_compact(&key));
 
-    if let Some(msg_b64) = params.message_b64 {
-        let mut msg = base64::decode(&msg_b64).expect("Failed to decode msg_b64");
+    if let Some(mut msg) = params.message {
         let iv = aead::generate_iv();
         aead::encrypt(&iv, &key, &mut msg);
 
@@ -1869,12 +1128,12 @@ fn test_ecdh(params: TestEcdhParam) {
     }
 }
 
-fn test(param: TestReq) -> Result<Value, Value> {
-    if param.test_bridge == Some(true) {
+fn test(param: TestArgs) -> Result<(), RpcError> {
+    if param.test_bridge {
         test_bridge();
     }
     if let Some(p) = param.test_ecdh {
         test_ecdh(p);
     }
-    Ok(json!({}))
+    Ok(())
 }
@@ -39,6 +39,7 @@ mod justification;
 pub mod storage_proof;
 mod types;
 
+use crate::chain;
 use crate::std::collections::BTreeMap;
 use crate::std::fmt;
 use crate::std::vec::Vec;
@@ -53,11 +54,14 @@ use finality_grandpa::voter_set::VoterSet;
 use num::AsPrimitive;
 use parity_scale_codec::{Decode, Encode};
 use sp_core::H256;
-use sp_finality_grandpa::{AuthorityId, AuthorityList, AuthorityWeight};
-use sp_runtime::traits::{Block as BlockT, Header, NumberFor};
+use sp_finality_grandpa::{
+    AuthorityId, AuthorityList, AuthorityWeight, ConsensusLog, GRANDPA_ENGINE_ID,
+};
+use sp_runtime::generic::OpaqueDigestItemId;
+use sp_runtime::traits::{Block as BlockT, Hash as HashT, Header, NumberFor};
 use sp_runtime::EncodedJustification;
 
-pub use types::{AuthoritySet, AuthoritySetChange};
+pub use types::{AuthoritySet, AuthoritySetChange, PendingChange};
 
 #[derive(Encode, Decode, Clone, PartialEq)]
 pub struct BridgeInitInfo<T: Config> {
@@ -70,6 +74,7 @@ pub struct BridgeInitInfo<T: Config> {
 pub struct BridgeInfo<T: Config> {
     last_finalized_block_header: T::Header,
     current_set: AuthoritySet,
+    pending_change: Option<PendingChange<NumberFor<T::Block>>>,
 }
 
 impl<T: Config> BridgeInfo<T> {
@@ -80,17 +85,31 @@ impl<T: Config> BridgeInfo<T> {
                 authority_set: validator_set,
                 set_id: 0,
             },
+            pending_change: None,
         }
     }
 }
 
-type BridgeId = u64;
+pub type BridgeId = u64;
+pub type ParaId = u32;
 
-pub trait Config: frame_system::Config<Hash = H256> {
+/// A parachain, whose headers are proven by the heads registered in its relay chain
+#[derive(Encode, Decode, Clone, PartialEq)]
+pub struct ParachainInfo<T: Config> {
+    relay_bridge: BridgeId,
+    para_id: ParaId,
+    last_header: T::Header,
+}
+
+pub trait Config {
+    type Hashing: HashT<Output = H256>;
+    type Header: Header<Hash = H256, Hashing = Self::Hashing>;
     type Block: BlockT<Hash = H256, Header = Self::Header>;
 }
 
 impl Config for chain::Runtime {
+    type Hashing = chain::Hashing;
+    type Header = chain::Header;
     type Block = chain::Block;
 }
 
@@ -98,6 +117,8 @@ impl Config for chain::Runtime {
 pub struct LightValidation<T: Config> {
     num_bridges: BridgeId,
     tracked_bridges: BTreeMap<BridgeId, BridgeInfo<T>>,
+    /// The parachains share the ids with the GRANDPA bridges
+    tracked_parachains: BTreeMap<BridgeId, ParachainInfo<T>>,
 }
 
 impl<T: Config> LightValidation<T>
@@ -108,9 +129,17 @@ where
         LightValidation {
             num_bridges: 0,
             tracked_bridges: BTreeMap::new(),
+            tracked_parachains: BTreeMap::new(),
         }
     }
 
+    /// The relay chain and the id of a parachain, or `None` if the bridge is not a parachain
+    pub fn parachain(&self, bridge_id: BridgeId) -> Option<(BridgeId, ParaId)> {
+        self.tracked_parachains
+            .get(&bridge_id)
+            .map(|para| (para.relay_bridge, para.para_id))
+    }
+
     pub fn initialize_bridge(
         &mut self,
         block_header: T::Header,
@@ -132,12 +161,81 @@ where
         Ok(new_bridge_id)
     }
 
+    /// Starts tracking a parachain of the GRANDPA bridge `relay_bridge` from `header`
+    ///
+    /// `header` must be the head of the parachain registered in the relay chain storage at the last
+    /// finalized relay header, as proven by `head_proof`.
+    pub fn initialize_parachain(
+        &mut self,
+        relay_bridge: BridgeId,
+        para_id: ParaId,
+        header: T::Header,
+        head_proof: StorageProof,
+    ) -> Result<BridgeId> {
+        self.check_para_head(relay_bridge, para_id, &header, head_proof)?;
+
+        let new_bridge_id = self.num_bridges + 1;
+        self.tracked_parachains.insert(
+            new_bridge_id,
+            ParachainInfo {
+                relay_bridge,
+                para_id,
+                last_header: header,
+            },
+        );
+        self.num_bridges = new_bridge_id;
+
+        Ok(new_bridge_id)
+    }
+
+    /// Submits a sequence of parachain headers ending with `header`
+    ///
+    /// A parachain has no finality of its own. `header` must be the head of the parachain in the
+    /// relay chain storage at the last finalized relay header, as proven by `head_proof`, so the
+    /// relay chain headers must be synced first. The ancestry proof links it to the last submitted
+    /// parachain header, as `submit_finalized_headers`.
+    pub fn submit_parachain_headers(
+        &mut self,
+        bridge_id: BridgeId,
+        header: T::Header,
+        ancestry_proof: Vec<T::Header>,
+        head_proof: StorageProof,
+    ) -> Result<()> {
+        let para = self
+            .tracked_parachains
+            .get(&bridge_id)
+            .ok_or_else(|| anyhow::Error::msg(Error::NoSuchBridgeExists))?;
+        verify_ancestry(ancestry_proof, para.last_header.hash(), &header)?;
+        self.check_para_head(para.relay_bridge, para.para_id, &header, head_proof)?;
+
+        let para = self
+            .tracked_parachains
+            .get_mut(&bridge_id)
+            .expect("We succesfully got this parachain earlier, therefore it exists; qed");
+        para.last_header = header;
+        Ok(())
+    }
+
     /// Submits a sequence of block headers to the light client to validate
     ///
-    /// The light client accepts a sequence of block headers, optionally with an authority set change
-    /// in the last block. Without the authority set change, it assumes the authority set and the set
-    /// id remains the same after submitting the blocks. One submission can have at most one authortiy
-    /// set change (change.set_id == last_set_id + 1).
+    /// The light client accepts a sequence of block headers, where only the last one is justified.
+    /// The authority set changes are derived from the GRANDPA `ScheduledChange` and `ForcedChange`
+    /// digests of the submitted headers once they are finalized:
+    ///
+    /// - A scheduled change is enacted when its enactment block is finalized by the current set, so
+    ///   the headers can't go beyond the enactment block before it's submitted as the last header.
+    /// - A forced change is enacted when the chain reaches its enactment block, and the headers from
+    ///   there are finalized by the new set.
+    ///
+    /// A change is only trusted once the header announcing it is finalized, so the host can't make
+    /// up one, with one exception: a forced change issued to recover a stalled finality. The old set
+    /// never finalizes the header announcing it, so when the last header isn't finalized by the
+    /// current set, the forced change announced in the submitted headers is enacted at the
+    /// announcing block plus its delay, as GRANDPA does. The new set votes from the
+    /// `median_last_finalized` block of the change, so it must finalize a header beyond both that
+    /// block and the announcing one, after the enactment block. The optional
+    /// `auhtority_set_change` given by the host is checked against the derived change enacted by
+    /// the submission (and the storage of the last header), but never overrides it.
     pub fn submit_finalized_headers(
         &mut self,
         bridge_id: BridgeId,
@@ -153,47 +251,120 @@ where
 
         // Check that the new header is a decendent of the old header
         let last_header = &bridge.last_finalized_block_header;
-        verify_ancestry(ancestry_proof, last_header.hash(), &header)?;
+        let route = verify_ancestry(ancestry_proof, last_header.hash(), &header)?;
 
         let block_hash = header.hash();
         let block_num = *header.number();
 
-        // Check that the header has been finalized
-        let voters = &bridge.current_set;
-        let voter_set = VoterSet::new(voters.authority_set.clone()).unwrap();
-        let voter_set_id = voters.set_id;
-        verify_grandpa_proof::<T::Block>(
-            grandpa_proof,
-            block_hash,
-            block_num,
-            voter_set_id,
-            &voter_set,
-        )?;
-
-        match self.tracked_bridges.get_mut(&bridge_id) {
-            Some(bridge_info) => {
-                bridge_info.last_finalized_block_header = header;
-                if let Some(change) = auhtority_set_change {
-                    // Check the validator set increment
-                    if change.authority_set.set_id != voter_set_id + 1 {
-                        return Err(anyhow::Error::msg(Error::UnexpectedValidatorSetId));
-                    }
-                    // Check validator set change proof
-                    let state_root = bridge_info.last_finalized_block_header.state_root();
-                    Self::check_validator_set_proof(
-                        state_root,
-                        change.authority_proof,
-                        &change.authority_set.authority_set,
-                    )?;
-                    // Commit
-                    bridge_info.current_set = AuthoritySet {
-                        authority_set: change.authority_set.authority_set,
-                        set_id: change.authority_set.set_id,
-                    }
+        // Decide the set finalizing the header
+        let mut voters = bridge.current_set.clone();
+        let mut pending_change = bridge.pending_change.clone();
+        let mut enacted = false;
+        if let Some(change) = pending_change.clone() {
+            if change.forced && block_num >= change.enacted_at {
+                voters = enact(&voters, change);
+                pending_change = None;
+                enacted = true;
+            } else if !change.forced && block_num > change.enacted_at {
+                return Err(anyhow::Error::msg(Error::SetChangeNotFinalized));
+            }
+        }
+
+        // Check that the header has been finalized, by the current set or, if the finality stalled,
+        // by the set forced in the submitted headers
+        let mut forced_at = None;
+        if let Err(err) =
+            verify_grandpa_proof::<T::Block>(grandpa_proof.clone(), block_hash, block_num, &voters)
+        {
+            let StalledForcedChange {
+                announced_at,
+                median_last_finalized,
+                change,
+            } = match stalled_forced_change::<T::Block>(&route, &header)? {
+                Some(forced) => forced,
+                None => return Err(err),
+            };
+            if pending_change.is_some() {
+                return Err(anyhow::Error::msg(Error::ConflictingSetChange));
+            }
+            if change.enacted_at > block_num
+                || announced_at >= block_num
+                || median_last_finalized >= block_num
+            {
+                return Err(err);
+            }
+            let forced_voters = enact(&voters, change);
+            verify_grandpa_proof::<T::Block>(grandpa_proof, block_hash, block_num, &forced_voters)?;
+            info!(
+                "Finality recovered by the forced change announced at block {:?}",
+                announced_at
+            );
+            voters = forced_voters;
+            enacted = true;
+            forced_at = Some(announced_at);
+        }
+
+        // A scheduled change is enacted by finalizing its enactment block
+        if let Some(change) = pending_change.clone() {
+            if change.enacted_at == block_num {
+                voters = enact(&voters, change);
+                pending_change = None;
+                enacted = true;
+            }
+        }
+
+        // The changes announced by the headers just finalized, after the forced change enacted
+        for h in route.iter().chain(Some(&header)) {
+            if forced_at.map_or(false, |forced_at| *h.number() <= forced_at) {
+                continue;
+            }
+            for change in grandpa_changes::<T::Block>(h) {
+                if pending_change.is_some() {
+                    return Err(anyhow::Error::msg(Error::ConflictingSetChange));
                 }
+                pending_change = Some(change);
             }
-            _ => panic!("We succesfully got this bridge earlier, therefore it exists; qed"),
-        };
+        }
+        if let Some(change) = pending_change.clone() {
+            if change.enacted_at < block_num {
+                // The set must have stopped at the enactment block
+                return Err(anyhow::Error::msg(Error::SetChangeNotFinalized));
+            }
+            if change.enacted_at == block_num {
+                voters = enact(&voters, change);
+                pending_change = None;
+                enacted = true;
+            }
+        }
+
+        if let Some(change) = auhtority_set_change {
+            if !enacted || change.authority_set.set_id != voters.set_id {
+                return Err(anyhow::Error::msg(Error::UnexpectedValidatorSetId));
+            }
+            if change.authority_set.authority_set != voters.authority_set {
+                return Err(anyhow::Error::msg(Error::ValidatorSetMismatch));
+            }
+            Self::check_validator_set_proof(
+                header.state_root(),
+                change.authority_proof,
+                &change.authority_set.authority_set,
+            )?;
+        }
+
+        // Commit
+        let bridge_info = self
+            .tracked_bridges
+            .get_mut(&bridge_id)
+            .expect("We succesfully got this bridge earlier, therefore it exists; qed");
+        if enacted {
+            info!(
+                "Authority set changed to #{} at block {:?}",
+                voters.set_id, block_num
+            );
+        }
+        bridge_info.last_finalized_block_header = header;
+        bridge_info.current_set = voters;
+        bridge_info.pending_change = pending_change;
 
         Ok(())
     }
@@ -217,7 +388,7 @@ where
 
     pub fn validate_storage_proof(
         &self,
-        state_root: T::Hash,
+        state_root: H256,
         proof: StorageProof,
         items: &[(&[u8], &[u8])], // &[(key, value)]
     ) -> Result<()> {
@@ -232,6 +403,17 @@ where
         }
         Ok(())
     }
+
+    /// Reads the values of `keys` from a storage proof, `None` for the proven absent ones
+    pub fn read_storage_proof(
+        &self,
+        state_root: H256,
+        proof: StorageProof,
+        keys: &[&[u8]],
+    ) -> Result<Vec<Option<Vec<u8>>>> {
+        let checker = StorageProofChecker::<T::Hashing>::new(state_root, proof)?;
+        keys.iter().map(|k| checker.read_value(k)).collect()
+    }
 }
 
 #[derive(Debug)]
@@ -248,6 +430,9 @@ pub enum Error {
     HeaderAncestryMismatch,
     UnexpectedValidatorSetId,
     StorageValueMismatch,
+    ConflictingSetChange,
+    SetChangeNotFinalized,
+    ParachainHeadMismatch,
 }
 
 impl fmt::Display for Error {
@@ -262,6 +447,9 @@ impl fmt::Display for Error {
             Error::HeaderAncestryMismatch => write!(f, "header ancestry mismatch"),
             Error::UnexpectedValidatorSetId => write!(f, "unexpected validator set id"),
             Error::StorageValueMismatch => write!(f, "storage value mismatch"),
+            Error::ConflictingSetChange => write!(f, "conflicting authority set change"),
+            Error::SetChangeNotFinalized => write!(f, "authority set change not finalized"),
+            Error::ParachainHeadMismatch => write!(f, "parachain head mismatch"),
         }
     }
 }
@@ -285,8 +473,32 @@ impl<T: Config> LightValidation<T>
 where
     NumberFor<T::Block>: AsPrimitive<usize>,
 {
+    /// Checks that `header` is the head of the parachain at the last finalized relay header
+    fn check_para_head(
+        &self,
+        relay_bridge: BridgeId,
+        para_id: ParaId,
+        header: &T::Header,
+        head_proof: StorageProof,
+    ) -> Result<()> {
+        let relay = self
+            .tracked_bridges
+            .get(&relay_bridge)
+            .ok_or_else(|| anyhow::Error::msg(Error::NoSuchBridgeExists))?;
+        let state_root = *relay.last_finalized_block_header.state_root();
+        // `HeadData` is the encoded header as bytes
+        let head_data = header.encode().encode();
+        let key = utils::para_head_key(para_id);
+        self.validate_storage_proof(
+            state_root,
+            head_proof,
+            &[(key.as_slice(), head_data.as_slice())],
+        )
+        .map_err(|_| anyhow::Error::msg(Error::ParachainHeadMismatch))
+    }
+
     fn check_validator_set_proof(
-        state_root: &T::Hash,
+        state_root: &H256,
         proof: StorageProof,
         validator_set: &Vec<(AuthorityId, AuthorityWeight)>,
     ) -> Result<()> {
@@ -313,7 +525,9 @@ where
 // is a chain of headers between (but not including) the `child`
 // and `ancestor`. This could be updated to use something like
 // Log2 Ancestors (#2053) in the future.
-fn verify_ancestry<H>(proof: Vec<H>, ancestor_hash: H::Hash, child: &H) -> Result<()>
+//
+// Returns the headers between them, from low to high.
+fn verify_ancestry<H>(proof: Vec<H>, ancestor_hash: H::Hash, child: &H) -> Result<Vec<H>>
 where
     H: Header<Hash = H256>,
 {
@@ -335,44 +549,129 @@ where
         );
     }
 
-    let mut parent_hash = child.parent_hash();
-    if *parent_hash == ancestor_hash {
-        return Ok(());
+    let mut parent_hash = *child.parent_hash();
+    let mut route = Vec::new();
+    if parent_hash == ancestor_hash {
+        return Ok(route);
     }
 
     // If we find that the header's parent hash matches our ancestor's hash we're done
-    for header in proof.iter() {
+    for header in proof.into_iter() {
         // Need to check that blocks are actually related
-        if header.hash() != *parent_hash {
+        if header.hash() != parent_hash {
             break;
         }
 
-        parent_hash = header.parent_hash();
-        if *parent_hash == ancestor_hash {
-            return Ok(());
+        parent_hash = *header.parent_hash();
+        route.push(header);
+        if parent_hash == ancestor_hash {
+            route.reverse();
+            return Ok(route);
         }
     }
 
     Err(anyhow::Error::msg(Error::InvalidAncestryProof))
 }
 
+/// The authority set changes announced in the GRANDPA digests of a header
+fn grandpa_changes<B>(header: &B::Header) -> Vec<PendingChange<NumberFor<B>>>
+where
+    B: BlockT<Hash = H256>,
+{
+    let id = OpaqueDigestItemId::Consensus(&GRANDPA_ENGINE_ID);
+    let number = *header.number();
+    header
+        .digest()
+        .logs()
+        .iter()
+        .filter_map(|log| log.try_to::<ConsensusLog<NumberFor<B>>>(id))
+        .filter_map(|log| match log {
+            ConsensusLog::ScheduledChange(change) => Some(PendingChange {
+                next_authorities: change.next_authorities,
+                enacted_at: number + change.delay,
+                forced: false,
+            }),
+            ConsensusLog::ForcedChange(_, change) => Some(PendingChange {
+                next_authorities: change.next_authorities,
+                enacted_at: number + change.delay,
+                forced: true,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// A forced change announced in the headers not finalized yet
+struct StalledForcedChange<N> {
+    announced_at: N,
+    /// The block the new set votes from
+    median_last_finalized: N,
+    change: PendingChange<N>,
+}
+
+/// The forced change announced in the submitted headers. It fails if there are other changes
+/// announced before it.
+fn stalled_forced_change<B>(
+    route: &[B::Header],
+    header: &B::Header,
+) -> Result<Option<StalledForcedChange<NumberFor<B>>>>
+where
+    B: BlockT<Hash = H256>,
+{
+    let id = OpaqueDigestItemId::Consensus(&GRANDPA_ENGINE_ID);
+    let mut other_changes = false;
+    for h in route.iter().chain(Some(header)) {
+        for log in h.digest().logs() {
+            match log.try_to::<ConsensusLog<NumberFor<B>>>(id) {
+                Some(ConsensusLog::ForcedChange(median_last_finalized, change)) => {
+                    if other_changes {
+                        return Err(anyhow::Error::msg(Error::ConflictingSetChange));
+                    }
+                    let announced_at = *h.number();
+                    return Ok(Some(StalledForcedChange {
+                        announced_at,
+                        median_last_finalized,
+                        change: PendingChange {
+                            next_authorities: change.next_authorities,
+                            enacted_at: announced_at + change.delay,
+                            forced: true,
+                        },
+                    }));
+                }
+                Some(ConsensusLog::ScheduledChange(_)) => other_changes = true,
+                _ => (),
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// The set succeeding `set` after `change`
+fn enact<N>(set: &AuthoritySet, change: PendingChange<N>) -> AuthoritySet {
+    AuthoritySet {
+        authority_set: change.next_authorities,
+        set_id: set.set_id + 1,
+    }
+}
+
 fn verify_grandpa_proof<B>(
     justification: EncodedJustification,
     hash: B::Hash,
     number: NumberFor<B>,
-    set_id: u64,
-    voters: &VoterSet<AuthorityId>,
+    set: &AuthoritySet,
 ) -> Result<()>
 where
     B: BlockT<Hash = H256>,
     NumberFor<B>: finality_grandpa::BlockNumberOps,
 {
+    let voters = VoterSet::new(set.authority_set.clone())
+        .ok_or_else(|| anyhow::Error::msg(Error::InvalidFinalityProof))?;
     // We don't really care about the justification, as long as it's valid
     let _ = GrandpaJustification::<B>::decode_and_verify_finalizes(
         &justification,
         (hash, number),
-        set_id,
-        voters,
+        set.set_id,
+        &voters,
     )
     .map_err(anyhow::Error::msg)?;
 
@@ -383,16 +682,26 @@ impl<T: Config> fmt::Debug for LightValidation<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "LightValidationTest {{ num_bridges: {}, tracked_bridges: {:?} }}",
-            self.num_bridges, self.tracked_bridges
+            "LightValidationTest {{ num_bridges: {}, tracked_bridges: {:?}, tracked_parachains: {:?} }}",
+            self.num_bridges, self.tracked_bridges, self.tracked_parachains
+        )
+    }
+}
+
+impl<T: Config> fmt::Debug for ParachainInfo<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ParachainInfo {{ relay_bridge: {}, para_id: {}, last_header: {:?} }}",
+            self.relay_bridge, self.para_id, self.last_header
         )
     }
 }
 
 impl<T: Config> fmt::Debug for BridgeInfo<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "BridgeInfo {{ last_finalized_block_header: {:?}, current_validator_set: {:?}, current_validator_set_id: {} }}",
-			self.last_finalized_block_header, self.current_set.authority_set, self.current_set.set_id)
+        write!(f, "BridgeInfo {{ last_finalized_block_header: {:?}, current_validator_set: {:?}, current_validator_set_id: {}, pending_change: {:?} }}",
+			self.last_finalized_block_header, self.current_set.authority_set, self.current_set.set_id, self.pending_change)
     }
 }
 
@@ -408,6 +717,7 @@ impl<T: Config> fmt::Debug for BridgeInitInfo<T> {
 
 pub mod utils {
     use crate::std::vec::Vec;
+    use parity_scale_codec::Encode;
 
     /// Gets the prefix of a storage item
     pub fn storage_prefix(module: &str, storage: &str) -> Vec<u8> {
@@ -426,6 +736,15 @@ pub mod utils {
         bytes
     }
 
+    /// The key of the head of a parachain in the relay chain storage (`Paras::Heads`)
+    pub fn para_head_key(para_id: u32) -> Vec<u8> {
+        let id = para_id.encode();
+        let mut bytes = storage_prefix("Paras", "Heads");
+        bytes.extend(&sp_core::twox_64(&id));
+        bytes.extend(&id);
+        bytes
+    }
+
     /// Gets the last 32 bytes as the account key (`storage_key` must be longer than that)
     pub fn extract_account_id_key_unsafe<'a>(storage_key: &'a [u8]) -> &'a [u8] {
         if storage_key.len() < 32 {
@@ -434,3 +753,411 @@ pub mod utils {
         &storage_key[storage_key.len() - 32..]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use finality_grandpa::{Message, Precommit, SignedPrecommit};
+    use sp_core::Pair;
+    use sp_finality_grandpa::{AuthorityPair, ScheduledChange};
+    use sp_runtime::generic::{Digest, DigestItem};
+    use storage_proof::tests::craft_proof;
+
+    type LightClient = LightValidation<chain::Runtime>;
+    type Hashing = chain::Hashing;
+    type Log = ConsensusLog<chain::BlockNumber>;
+
+    const BRIDGE: BridgeId = 1;
+
+    fn authorities(seeds: &[u8]) -> Vec<AuthorityPair> {
+        seeds
+            .iter()
+            .map(|seed| AuthorityPair::from_seed(&[*seed; 32]))
+            .collect()
+    }
+
+    fn authority_list(authorities: &[AuthorityPair]) -> AuthorityList {
+        authorities.iter().map(|pair| (pair.public(), 1)).collect()
+    }
+
+    fn scheduled(
+        next: &[AuthorityPair],
+        delay: chain::BlockNumber,
+    ) -> ScheduledChange<chain::BlockNumber> {
+        ScheduledChange {
+            next_authorities: authority_list(next),
+            delay,
+        }
+    }
+
+    fn genesis() -> chain::Header {
+        chain::Header::new(
+            0,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        )
+    }
+
+    fn child(parent: &chain::Header, logs: &[Log], state_root: H256) -> chain::Header {
+        let digest = Digest {
+            logs: logs
+                .iter()
+                .map(|log| DigestItem::Consensus(GRANDPA_ENGINE_ID, log.encode()))
+                .collect(),
+        };
+        chain::Header::new(
+            parent.number + 1,
+            Default::default(),
+            state_root,
+            parent.hash(),
+            digest,
+        )
+    }
+
+    /// Extends the chain with `n` headers without digests
+    fn extend(headers: &mut Vec<chain::Header>, n: usize) {
+        for _ in 0..n {
+            let header = child(headers.last().unwrap(), &[], Default::default());
+            headers.push(header);
+        }
+    }
+
+    /// A justification of `header` signed by all the `signers` in the first round
+    fn justify(header: &chain::Header, set_id: u64, signers: &[AuthorityPair]) -> Vec<u8> {
+        let round = 1u64;
+        let precommit = Precommit {
+            target_hash: header.hash(),
+            target_number: header.number,
+        };
+        let message = Message::Precommit(precommit.clone());
+        let precommits: Vec<_> = signers
+            .iter()
+            .map(|pair| SignedPrecommit {
+                precommit: precommit.clone(),
+                signature: pair.sign(&(&message, round, set_id).encode()),
+                id: pair.public(),
+            })
+            .collect();
+        let commit = finality_grandpa::Commit {
+            target_hash: header.hash(),
+            target_number: header.number,
+            precommits,
+        };
+        (round, commit, Vec::<chain::Header>::new()).encode()
+    }
+
+    fn new_client(genesis: &chain::Header, authorities: &[AuthorityPair]) -> LightClient {
+        let mut client = LightClient::new();
+        client.tracked_bridges.insert(
+            BRIDGE,
+            BridgeInfo::new(genesis.clone(), authority_list(authorities)),
+        );
+        client.num_bridges = BRIDGE;
+        client
+    }
+
+    /// Submits `headers`, with the last one justified by `signers`
+    fn submit(
+        client: &mut LightClient,
+        headers: &[chain::Header],
+        set_id: u64,
+        signers: &[AuthorityPair],
+        change: Option<AuthoritySetChange>,
+    ) -> Result<()> {
+        let (last, ancestry) = headers.split_last().unwrap();
+        let ancestry_proof = ancestry.iter().rev().cloned().collect();
+        let justification = justify(last, set_id, signers);
+        client.submit_finalized_headers(BRIDGE, last.clone(), ancestry_proof, justification, change)
+    }
+
+    fn current_set(client: &LightClient) -> AuthoritySet {
+        client.tracked_bridges[&BRIDGE].current_set.clone()
+    }
+
+    fn assert_error(result: Result<()>, error: Error) {
+        assert_eq!(result.unwrap_err().to_string(), error.to_string());
+    }
+
+    #[test]
+    fn test_scheduled_change() {
+        let (set_a, set_b) = (authorities(&[1, 2, 3]), authorities(&[4, 5, 6]));
+        let mut headers = vec![genesis()];
+        // Announced at #1, enacted at #3
+        let header = child(
+            &headers[0],
+            &[Log::ScheduledChange(scheduled(&set_b, 2))],
+            Default::default(),
+        );
+        headers.push(header);
+        extend(&mut headers, 3);
+        let mut client = new_client(&headers[0], &set_a);
+
+        submit(&mut client, &headers[1..3], 0, &set_a, None).unwrap();
+        assert_eq!(current_set(&client).set_id, 0);
+        // Can't skip the enactment block
+        assert_error(
+            submit(&mut client, &headers[3..5], 0, &set_a, None),
+            Error::SetChangeNotFinalized,
+        );
+        // The enactment block is finalized by the old set
+        assert!(submit(&mut client, &headers[3..4], 1, &set_b, None).is_err());
+        submit(&mut client, &headers[3..4], 0, &set_a, None).unwrap();
+        let set = current_set(&client);
+        assert_eq!(set.set_id, 1);
+        assert_eq!(set.authority_set, authority_list(&set_b));
+        // And the following blocks by the new set
+        assert!(submit(&mut client, &headers[4..5], 0, &set_a, None).is_err());
+        submit(&mut client, &headers[4..5], 1, &set_b, None).unwrap();
+    }
+
+    #[test]
+    fn test_forced_change() {
+        let (set_a, set_b) = (authorities(&[1, 2, 3]), authorities(&[4, 5, 6]));
+        let mut headers = vec![genesis()];
+        // Announced at #1, enacted at #2 no matter if it's finalized
+        let header = child(
+            &headers[0],
+            &[Log::ForcedChange(0, scheduled(&set_b, 1))],
+            Default::default(),
+        );
+        headers.push(header);
+        extend(&mut headers, 2);
+        let mut client = new_client(&headers[0], &set_a);
+
+        submit(&mut client, &headers[1..2], 0, &set_a, None).unwrap();
+        assert!(submit(&mut client.clone(), &headers[2..4], 0, &set_a, None).is_err());
+        submit(&mut client, &headers[2..4], 1, &set_b, None).unwrap();
+        assert_eq!(current_set(&client).set_id, 1);
+        assert!(client.tracked_bridges[&BRIDGE].pending_change.is_none());
+    }
+
+    #[test]
+    fn test_stalled_forced_change() {
+        let (set_a, set_b) = (authorities(&[1, 2, 3]), authorities(&[4, 5, 6]));
+        let set_c = authorities(&[7, 8, 9]);
+        let mut headers = vec![genesis()];
+        extend(&mut headers, 2);
+        // The old set stalls after #0, so #3 announcing the change is never finalized by it. The
+        // new set votes from #1 and takes over at #5.
+        let header = child(
+            &headers[2],
+            &[Log::ForcedChange(1, scheduled(&set_b, 2))],
+            Default::default(),
+        );
+        headers.push(header);
+        extend(&mut headers, 4);
+        let mut client = new_client(&headers[0], &set_a);
+
+        // Not before the enactment block
+        assert!(submit(&mut client, &headers[1..5], 1, &set_b, None).is_err());
+        // Nor by another set
+        assert!(submit(&mut client, &headers[1..6], 1, &set_c, None).is_err());
+        assert_eq!(current_set(&client).set_id, 0);
+        submit(&mut client, &headers[1..6], 1, &set_b, None).unwrap();
+        let set = current_set(&client);
+        assert_eq!(set.set_id, 1);
+        assert_eq!(set.authority_set, authority_list(&set_b));
+        assert!(client.tracked_bridges[&BRIDGE].pending_change.is_none());
+        // The new set goes on finalizing
+        assert!(submit(&mut client, &headers[6..8], 0, &set_a, None).is_err());
+        submit(&mut client, &headers[6..8], 1, &set_b, None).unwrap();
+    }
+
+    #[test]
+    fn test_stalled_forced_change_median() {
+        let (set_a, set_b) = (authorities(&[1, 2, 3]), authorities(&[4, 5, 6]));
+        let mut headers = vec![genesis()];
+        // The new set only votes from #3, and takes over at #1
+        let header = child(
+            &headers[0],
+            &[Log::ForcedChange(3, scheduled(&set_b, 0))],
+            Default::default(),
+        );
+        headers.push(header);
+        extend(&mut headers, 3);
+        let mut client = new_client(&headers[0], &set_a);
+
+        // So it can't finalize up to #3
+        assert!(submit(&mut client.clone(), &headers[1..4], 1, &set_b, None).is_err());
+        submit(&mut client, &headers[1..5], 1, &set_b, None).unwrap();
+        assert_eq!(current_set(&client).set_id, 1);
+
+        // And a scheduled change announced before the forced one conflicts with it
+        let mut headers = vec![genesis()];
+        let header = child(
+            &headers[0],
+            &[Log::ScheduledChange(scheduled(&set_b, 10))],
+            Default::default(),
+        );
+        headers.push(header);
+        let header = child(
+            &headers[1],
+            &[Log::ForcedChange(0, scheduled(&set_b, 0))],
+            Default::default(),
+        );
+        headers.push(header);
+        extend(&mut headers, 1);
+        let mut client = new_client(&headers[0], &set_a);
+        assert_error(
+            submit(&mut client, &headers[1..4], 1, &set_b, None),
+            Error::ConflictingSetChange,
+        );
+    }
+
+    #[test]
+    fn test_unfinalized_change_not_trusted() {
+        let (set_a, set_evil) = (authorities(&[1, 2, 3]), authorities(&[7, 8, 9]));
+        let genesis = genesis();
+        let mut client = new_client(&genesis, &set_a);
+
+        // A made up change can't justify the header announcing it
+        for log in vec![
+            Log::ScheduledChange(scheduled(&set_evil, 0)),
+            Log::ForcedChange(0, scheduled(&set_evil, 0)),
+        ] {
+            let header = child(&genesis, &[log], Default::default());
+            assert!(submit(&mut client, &[header], 1, &set_evil, None).is_err());
+        }
+        assert_eq!(current_set(&client).set_id, 0);
+
+        // Nor can the host claim a change without the digest
+        let header = child(&genesis, &[], Default::default());
+        let change = AuthoritySetChange {
+            authority_set: AuthoritySet {
+                authority_set: authority_list(&set_evil),
+                set_id: 1,
+            },
+            authority_proof: vec![],
+        };
+        assert_error(
+            submit(&mut client, &[header], 0, &set_a, Some(change)),
+            Error::UnexpectedValidatorSetId,
+        );
+    }
+
+    #[test]
+    fn test_host_change_checked() {
+        let set_a = authorities(&[1, 2, 3]);
+        let set_b = authorities(&[4, 5, 6]);
+        let set_evil = authorities(&[7, 8, 9]);
+        let genesis = genesis();
+        let mut client = new_client(&genesis, &set_a);
+
+        // Enacted immediately, with the new set in the storage
+        let mut authorities_value = vec![1u8];
+        authorities_value.extend(authority_list(&set_b).encode());
+        let (state_root, proof) =
+            craft_proof::<Hashing>(&[(&b":grandpa_authorities"[..], &authorities_value[..])]);
+        let header = child(
+            &genesis,
+            &[Log::ScheduledChange(scheduled(&set_b, 0))],
+            state_root,
+        );
+        let change = |set: &[AuthorityPair]| AuthoritySetChange {
+            authority_set: AuthoritySet {
+                authority_set: authority_list(set),
+                set_id: 1,
+            },
+            authority_proof: proof.clone(),
+        };
+
+        assert_error(
+            submit(
+                &mut client,
+                &[header.clone()],
+                0,
+                &set_a,
+                Some(change(&set_evil)),
+            ),
+            Error::ValidatorSetMismatch,
+        );
+        submit(&mut client, &[header], 0, &set_a, Some(change(&set_b))).unwrap();
+        assert_eq!(current_set(&client).set_id, 1);
+    }
+
+    #[test]
+    fn test_conflicting_changes() {
+        let (set_a, set_b) = (authorities(&[1, 2, 3]), authorities(&[4, 5, 6]));
+        let genesis = genesis();
+        let h1 = child(
+            &genesis,
+            &[Log::ScheduledChange(scheduled(&set_b, 5))],
+            Default::default(),
+        );
+        let h2 = child(
+            &h1,
+            &[Log::ForcedChange(0, scheduled(&set_a, 5))],
+            Default::default(),
+        );
+        let mut client = new_client(&genesis, &set_a);
+        assert_error(
+            submit(&mut client, &[h1, h2], 0, &set_a, None),
+            Error::ConflictingSetChange,
+        );
+    }
+
+    #[test]
+    fn test_parachain() {
+        const PARA_ID: ParaId = 2000;
+        let set_a = authorities(&[1, 2, 3]);
+        let relay_genesis = genesis();
+        let mut client = new_client(&relay_genesis, &set_a);
+        let key = utils::para_head_key(PARA_ID);
+        // A relay header with `head` registered as the parachain head
+        let relay_child = |parent: &chain::Header, head: &chain::Header| {
+            let head_data = head.encode().encode();
+            let (state_root, proof) =
+                craft_proof::<Hashing>(&[(key.as_slice(), head_data.as_slice())]);
+            (child(parent, &[], state_root), proof)
+        };
+
+        let mut para_headers = vec![genesis()];
+        extend(&mut para_headers, 2);
+        let (relay_1, proof_1) = relay_child(&relay_genesis, &para_headers[0]);
+        // Not proven until the relay header is finalized
+        assert!(client
+            .initialize_parachain(BRIDGE, PARA_ID, para_headers[0].clone(), proof_1.clone())
+            .is_err());
+        submit(&mut client, &[relay_1.clone()], 0, &set_a, None).unwrap();
+        let para = client
+            .initialize_parachain(BRIDGE, PARA_ID, para_headers[0].clone(), proof_1)
+            .unwrap();
+        assert_eq!(client.parachain(para), Some((BRIDGE, PARA_ID)));
+        assert_eq!(client.parachain(BRIDGE), None);
+
+        let (relay_2, proof_2) = relay_child(&relay_1, &para_headers[2]);
+        submit(&mut client, &[relay_2], 0, &set_a, None).unwrap();
+        // Only the registered head is accepted
+        assert_error(
+            client.submit_parachain_headers(para, para_headers[1].clone(), vec![], proof_2.clone()),
+            Error::ParachainHeadMismatch,
+        );
+        client
+            .submit_parachain_headers(
+                para,
+                para_headers[2].clone(),
+                vec![para_headers[1].clone()],
+                proof_2,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_read_storage_proof() {
+        let client = LightClient::new();
+        let (root, proof) = craft_proof::<Hashing>(&[
+            (&b"key1"[..], &b"value1"[..]),
+            (&b"key2"[..], &b"value2"[..]),
+        ]);
+        let values = client
+            .read_storage_proof(root, proof.clone(), &[&b"key2"[..], &b"absent"[..]])
+            .unwrap();
+        assert_eq!(values, vec![Some(b"value2".to_vec()), None]);
+        // The proof doesn't match another state root
+        assert!(client
+            .read_storage_proof(Default::default(), proof, &[&b"key1"[..]])
+            .is_err());
+    }
+}
@@ -70,45 +70,67 @@ where
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
 
-    use primitives::{Blake2Hasher, H256};
-    use state_machine::{
-        backend::{Backend, InMemory},
-        prove_read,
-    };
+    use sp_core::{Blake2Hasher, H256};
+    use trie::{trie_types::TrieDBMut, TrieMut};
+
+    /// Builds a trie of `items`, and returns its root and all its nodes as the proof
+    pub(crate) fn craft_proof<H: Hasher>(items: &[(&[u8], &[u8])]) -> (H::Out, StorageProof) {
+        let mut db = MemoryDB::<H>::default();
+        let mut root = Default::default();
+        {
+            let mut trie = TrieDBMut::<H>::new(&mut db, &mut root);
+            for (key, value) in items {
+                trie.insert(key, value).unwrap();
+            }
+        }
+        let proof = db.drain().into_iter().map(|(_, (node, _))| node).collect();
+        (root, proof)
+    }
 
     #[test]
     fn storage_proof_check() {
         // construct storage proof
-        let backend = <InMemory<Blake2Hasher>>::from(vec![
-            (None, b"key1".to_vec(), Some(b"value1".to_vec())),
-            (None, b"key2".to_vec(), Some(b"value2".to_vec())),
-            (None, b"key3".to_vec(), Some(b"value3".to_vec())),
+        let (root, proof) = craft_proof::<Blake2Hasher>(&[
+            (&b"key1"[..], &b"value1"[..]),
+            (&b"key2"[..], &b"value2"[..]),
+            (&b"key3"[..], &b"value3"[..]),
             // Value is too big to fit in a branch node
-            (None, b"key11".to_vec(), Some(vec![0u8; 32])),
+            (&b"key11"[..], &[0u8; 32][..]),
         ]);
-        let root = backend.storage_root(std::iter::empty()).0;
-        let proof: StorageProof = prove_read(backend, &[&b"key1"[..], &b"key2"[..], &b"key22"[..]])
-            .unwrap()
-            .iter_nodes()
-            .collect();
 
         // check proof in runtime
         let checker = <StorageProofChecker<Blake2Hasher>>::new(root, proof.clone()).unwrap();
-        assert_eq!(checker.read_value(b"key1"), Ok(Some(b"value1".to_vec())));
-        assert_eq!(checker.read_value(b"key2"), Ok(Some(b"value2".to_vec())));
         assert_eq!(
-            checker.read_value(b"key11111"),
-            Err(Error::StorageValueUnavailable)
+            checker.read_value(b"key1").unwrap(),
+            Some(b"value1".to_vec())
+        );
+        assert_eq!(
+            checker.read_value(b"key2").unwrap(),
+            Some(b"value2".to_vec())
+        );
+        assert_eq!(checker.read_value(b"key22").unwrap(), None);
+
+        // the values out of the proof are unavailable
+        let root_only: StorageProof = proof
+            .iter()
+            .filter(|node| Blake2Hasher::hash(node) == root)
+            .cloned()
+            .collect();
+        let checker = <StorageProofChecker<Blake2Hasher>>::new(root, root_only).unwrap();
+        assert_eq!(
+            checker.read_value(b"key11").unwrap_err().to_string(),
+            Error::StorageValueUnavailable.to_string()
         );
-        assert_eq!(checker.read_value(b"key22"), Ok(None));
 
         // checking proof against invalid commitment fails
         assert_eq!(
-            <StorageProofChecker<Blake2Hasher>>::new(H256::random(), proof).err(),
-            Some(Error::StorageRootMismatch)
+            <StorageProofChecker<Blake2Hasher>>::new(H256::repeat_byte(1), proof)
+                .err()
+                .map(|e| e.to_string()),
+            Some(Error::StorageRootMismatch.to_string())
         );
     }
 }
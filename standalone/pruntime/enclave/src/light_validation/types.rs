@@ -13,3 +13,14 @@ pub struct AuthoritySetChange {
     pub authority_set: AuthoritySet,
     pub authority_proof: StorageProof,
 }
+
+/// An authority set change announced in a finalized header, but not enacted yet
+#[derive(Encode, Decode, Clone, PartialEq, Debug)]
+pub struct PendingChange<N> {
+    pub next_authorities: AuthorityList,
+    /// The block where the new set takes over
+    pub enacted_at: N,
+    /// Whether it's a `ForcedChange`, enacted as soon as the chain reaches `enacted_at` instead of
+    /// when `enacted_at` is finalized
+    pub forced: bool,
+}
@@ -0,0 +1,66 @@
+//! Binary Merkle tree over blake2_256 hashes
+
+use crate::std::vec::Vec;
+use sp_core::hashing::blake2_256;
+use sp_core::H256;
+
+/// Computes the Merkle root of `leaves`
+///
+/// Each level hashes the concatenation of the adjacent pairs. An odd node at the end of a level is
+/// promoted to the next level as is. The root of no leaf is zero.
+pub fn merkle_root(leaves: &[H256]) -> H256 {
+    if leaves.is_empty() {
+        return H256::zero();
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => {
+                    let mut data = [0u8; 64];
+                    data[..32].copy_from_slice(left.as_bytes());
+                    data[32..].copy_from_slice(right.as_bytes());
+                    H256(blake2_256(&data))
+                }
+                [single] => *single,
+                _ => unreachable!("chunks(2) yields one or two items; qed."),
+            })
+            .collect::<Vec<_>>();
+    }
+    level[0]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn leaf(n: u8) -> H256 {
+        H256::repeat_byte(n)
+    }
+
+    fn hash_pair(left: H256, right: H256) -> H256 {
+        let mut data = left.as_bytes().to_vec();
+        data.extend_from_slice(right.as_bytes());
+        H256(blake2_256(&data))
+    }
+
+    #[test]
+    fn test_merkle_root() {
+        assert_eq!(merkle_root(&[]), H256::zero());
+        assert_eq!(merkle_root(&[leaf(1)]), leaf(1));
+        assert_eq!(
+            merkle_root(&[leaf(1), leaf(2)]),
+            hash_pair(leaf(1), leaf(2))
+        );
+        assert_eq!(
+            merkle_root(&[leaf(1), leaf(2), leaf(3)]),
+            hash_pair(hash_pair(leaf(1), leaf(2)), leaf(3))
+        );
+        // Sensitive to the order
+        assert_ne!(
+            merkle_root(&[leaf(1), leaf(2)]),
+            merkle_root(&[leaf(2), leaf(1)])
+        );
+    }
+}
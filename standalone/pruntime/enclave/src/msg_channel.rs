@@ -1,10 +1,11 @@
 use crate::std::vec::Vec;
-use parity_scale_codec::Encode;
+use parity_scale_codec::{Decode, Encode};
 use phala_types::{SignedWorkerMessage, WorkerMessage, WorkerMessagePayload};
 use sp_core::ecdsa;
 use sp_core::Pair;
 
 /// An one-way async message channel
+#[derive(Encode, Decode, Clone)]
 pub struct MsgChannel {
     pub sequence: u64,
     pub queue: Vec<SignedWorkerMessage>,
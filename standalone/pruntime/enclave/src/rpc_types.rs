@@ -6,6 +6,9 @@ pub struct InitRuntimeReq {
     pub skip_ra: bool,
     pub bridge_genesis_info_b64: String,
     pub debug_set_key: Option<String>,
+    /// SCALE encoded `ContractsGenesis`. The contracts start empty if absent.
+    #[serde(default)]
+    pub contracts_genesis_b64: Option<String>,
 }
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct InitRuntimeResp {
@@ -49,3 +52,43 @@ pub struct SyncHeaderReq {
 pub struct DispatchBlockReq {
     pub blocks_b64: Vec<String>,
 }
+
+/// `add_bridge` of a GRANDPA chain with `genesis_info_b64`, or of a parachain otherwise
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AddBridgeReq {
+    pub genesis_info_b64: Option<String>,
+    pub relay_bridge: Option<u64>,
+    pub para_id: Option<u32>,
+    pub header_b64: Option<String>,
+    pub head_proof_b64: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SyncBridgeHeaderReq {
+    pub bridge_id: u64,
+    pub headers_b64: Vec<String>,
+    pub authority_set_change_b64: Option<String>,
+    pub head_proof_b64: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DispatchBridgeBlockReq {
+    pub bridge_id: u64,
+    pub blocks_b64: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LoadStatesReq {
+    pub data: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SetReq {
+    pub path: String,
+    pub data: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetReq {
+    pub path: String,
+}
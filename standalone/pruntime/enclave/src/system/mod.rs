@@ -3,23 +3,26 @@ use anyhow::Result;
 use core::fmt;
 use log::info;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
 
-use parity_scale_codec::Encode;
-use phala_types::{BlockRewardInfo, SignedWorkerMessage, WorkerMessagePayload};
+use parity_scale_codec::{Decode, Encode};
+use phala_types::{BlockRewardInfo, ChainMessage, SignedWorkerMessage, WorkerMessagePayload};
 use sp_core::ecdsa;
 use sp_core::hashing::blake2_256;
 use sp_core::U256;
 
+use crate::chain;
 use crate::contracts::AccountIdWrapper;
 use crate::msg_channel::MsgChannel;
 
 mod comp_election;
+mod receipts;
+
+pub use receipts::{IndexedReceipt, ReceiptStore};
 
 pub type CommandIndex = u64;
-type PhalaEvent = phala::RawEvent<sp_runtime::AccountId32, u128>;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// The receipts in the checkpoints refer to the variants by their index, so append the new ones
+#[derive(Serialize, Deserialize, Encode, Decode, Debug, Clone)]
 pub enum TransactionStatus {
     Ok,
     InsufficientBalance,
@@ -48,9 +51,23 @@ pub enum TransactionStatus {
     FailedToCalculateBalance,
     BadChainId,
     TransferringNotAllowed,
+    InsufficientAllowance,
+    MemoTooLong,
+    AccountFrozen,
+    Overflow,
+    AssetNotBridged,
+    ItemNotFound,
+    PathNotAllowed,
+    SiteExist,
+    SiteNotFound,
+    NotSiteOwner,
+    NoContractKey,
+    NotEncrypted,
+    DecryptFailed,
+    DatasetTooLarge,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Encode, Decode, Debug, Clone)]
 pub struct TransactionReceipt {
     pub account: AccountIdWrapper,
     pub block_num: chain::BlockNumber,
@@ -69,7 +86,7 @@ pub struct System {
     // Computation task electino
     pub comp_elected: bool,
     // Transaction
-    pub receipts: BTreeMap<CommandIndex, TransactionReceipt>,
+    pub receipts: ReceiptStore,
     // Messageing
     pub egress: MsgChannel,
 }
@@ -98,7 +115,7 @@ impl System {
     }
 
     pub fn get_receipt(&self, command_index: CommandIndex) -> Option<&TransactionReceipt> {
-        self.receipts.get(&command_index)
+        self.receipts.get(command_index)
     }
 
     pub fn handle_query(
@@ -106,6 +123,11 @@ impl System {
         accid_origin: Option<&chain::AccountId>,
         req: Request,
     ) -> Response {
+        let origin = || {
+            accid_origin
+                .map(|origin| AccountIdWrapper(origin.clone()))
+                .ok_or_else(|| anyhow::Error::msg(Error::NotAuthorized))
+        };
         let inner = || -> Result<Response> {
             match req {
                 Request::QueryReceipt { command_index } => match self.get_receipt(command_index) {
@@ -124,6 +146,23 @@ impl System {
                         "Transaction hash not found",
                     )))),
                 },
+                Request::QueryReceiptsByAccount { before, limit } => {
+                    let (receipts, next) = self.receipts.by_account(&origin()?, before, limit);
+                    Ok(Response::Receipts { receipts, next })
+                }
+                Request::QueryReceiptsByBlock { block_num } => Ok(Response::Receipts {
+                    receipts: self.receipts.by_block(&origin()?, block_num),
+                    next: None,
+                }),
+                Request::WatchReceipts { after, limit } => {
+                    let (receipts, missed) = self.receipts.feed(&origin()?, after, limit);
+                    let cursor = receipts.last().map(|r| r.command_index).or(after);
+                    Ok(Response::ReceiptFeed {
+                        receipts,
+                        cursor,
+                        missed,
+                    })
+                }
                 Request::GetWorkerEgress { start_sequence } => {
                     let pending_msgs: Vec<SignedWorkerMessage> = self
                         .egress
@@ -216,17 +255,17 @@ impl<'a> EventHandler<'a> {
     pub fn feed(
         &mut self,
         block_context: &'a super::BlockHeaderWithEvents,
-        event: &PhalaEvent,
+        message: &chain::ChainMessage,
     ) -> Result<()> {
-        match event {
+        match message {
             // Reset the egress queue once we detected myself is re-registered
-            phala::RawEvent::WorkerRegistered(_stash, pubkey, _machine_id) => {
+            ChainMessage::WorkerRegistered { pubkey, .. } => {
                 if pubkey == &self.system.id_pubkey {
                     info!("System::handle_event: Reset MsgChannel due to WorkerRegistered");
                     self.system.egress = Default::default();
                 }
             }
-            phala::RawEvent::WorkerRenewed(_stash, machine_id) => {
+            ChainMessage::WorkerRenewed { machine_id, .. } => {
                 // Not perfect because we only have machine_id but not pubkey here.
                 if machine_id == &self.system.machine_id {
                     info!("System::handle_event: Reset MsgChannel due to WorkerRenewed");
@@ -234,19 +273,19 @@ impl<'a> EventHandler<'a> {
                 }
             }
             // Handle other events
-            phala::RawEvent::WorkerMessageReceived(_stash, pubkey, seq) => {
+            ChainMessage::WorkerMessageReceived { pubkey, seq, .. } => {
                 info!("System::handle_event: Message confirmed (seq={})", seq);
                 // Advance the egress queue messages
                 if pubkey == &self.system.id_pubkey {
                     self.system.egress.received(*seq);
                 }
             }
-            phala::RawEvent::RewardSeed(reward_info) => {
+            ChainMessage::RewardSeed(reward_info) => {
                 let blocknum = block_context.block_header.number;
                 self.seed = Some(reward_info.seed);
                 self.system.handle_reward_seed(blocknum, &reward_info)?;
             }
-            phala::RawEvent::NewMiningRound(round) => {
+            ChainMessage::NewMiningRound(round) => {
                 info!("System::handle_event: new mining round ({})", round);
                 // Save the snapshot for later use
                 self.snapshot = block_context.worker_snapshot.as_ref();
@@ -287,8 +326,27 @@ impl fmt::Display for Error {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum Request {
-    QueryReceipt { command_index: CommandIndex },
-    GetWorkerEgress { start_sequence: u64 },
+    QueryReceipt {
+        command_index: CommandIndex,
+    },
+    GetWorkerEgress {
+        start_sequence: u64,
+    },
+    /// The receipts of the origin older than `before`, the newest first
+    QueryReceiptsByAccount {
+        before: Option<CommandIndex>,
+        limit: u32,
+    },
+    /// The receipts of the origin in the block
+    QueryReceiptsByBlock {
+        block_num: chain::BlockNumber,
+    },
+    /// The receipts of the origin newer than `after`, the oldest first. Poll it with the returned
+    /// `cursor` to watch the commands complete.
+    WatchReceipts {
+        after: Option<CommandIndex>,
+        limit: u32,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -300,6 +358,18 @@ pub enum Response {
         length: usize,
         encoded_egress_b64: String,
     },
+    Receipts {
+        receipts: Vec<IndexedReceipt>,
+        /// The `before` of the next page, if any
+        next: Option<CommandIndex>,
+    },
+    ReceiptFeed {
+        receipts: Vec<IndexedReceipt>,
+        /// The `after` of the next poll
+        cursor: Option<CommandIndex>,
+        /// Some receipts after `after` were dropped by the retention
+        missed: bool,
+    },
     Error(#[serde(with = "serde_anyhow")] anyhow::Error),
 }
 
@@ -0,0 +1,276 @@
+//! The bounded store of the transaction receipts
+//!
+//! A receipt is kept until `retention` blocks have passed since the block it was produced in, and
+//! the store never holds more than `MAX_RECEIPTS`, dropping the oldest first. The store is a part of
+//! the checkpoints, so the receipts outlive the process. Only the receipts are encoded; the indexes
+//! by account and by block are rebuilt when decoded.
+
+use crate::std::collections::{BTreeMap, BTreeSet};
+use crate::std::prelude::v1::*;
+use parity_scale_codec::{Decode, Encode, Error as CodecError, Input, Output};
+use serde::{Deserialize, Serialize};
+
+use super::{CommandIndex, TransactionReceipt};
+use crate::chain;
+use crate::contracts::AccountIdWrapper;
+
+/// The most receipts kept regardless of the retention
+pub const MAX_RECEIPTS: usize = 100_000;
+/// The most receipts returned in a page
+pub const MAX_PAGE_SIZE: u32 = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexedReceipt {
+    pub command_index: CommandIndex,
+    pub receipt: TransactionReceipt,
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct ReceiptStore {
+    receipts: BTreeMap<CommandIndex, TransactionReceipt>,
+    /// The highest command index ever dropped
+    pruned_to: Option<CommandIndex>,
+    by_account: BTreeMap<AccountIdWrapper, BTreeSet<CommandIndex>>,
+    by_block: BTreeMap<chain::BlockNumber, BTreeSet<CommandIndex>>,
+}
+
+impl ReceiptStore {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.receipts.len()
+    }
+
+    pub fn insert(&mut self, command_index: CommandIndex, receipt: TransactionReceipt) {
+        if let Some(old) = self.receipts.remove(&command_index) {
+            self.unindex(command_index, &old);
+        }
+        self.index(command_index, &receipt);
+        self.receipts.insert(command_index, receipt);
+        while self.receipts.len() > MAX_RECEIPTS {
+            let oldest = *self.receipts.keys().next().expect("Not empty; qed.");
+            self.remove(oldest);
+        }
+    }
+
+    pub fn get(&self, command_index: CommandIndex) -> Option<&TransactionReceipt> {
+        self.receipts.get(&command_index)
+    }
+
+    /// Drops the receipts produced `retention` or more blocks before `blocknum`. 0 to keep them
+    /// all (up to `MAX_RECEIPTS`).
+    pub fn prune(&mut self, blocknum: chain::BlockNumber, retention: u32) {
+        if retention == 0 || blocknum < retention {
+            return;
+        }
+        let kept = self.by_block.split_off(&(blocknum - retention + 1));
+        let expired = crate::std::mem::replace(&mut self.by_block, kept);
+        for index in expired.into_iter().flat_map(|(_, indexes)| indexes) {
+            self.remove(index);
+        }
+    }
+
+    /// The receipts of `account` older than `before`, the newest first. Returns the page and the
+    /// cursor of the next one.
+    pub fn by_account(
+        &self,
+        account: &AccountIdWrapper,
+        before: Option<CommandIndex>,
+        limit: u32,
+    ) -> (Vec<IndexedReceipt>, Option<CommandIndex>) {
+        let limit = limit.min(MAX_PAGE_SIZE) as usize;
+        let indexes = match self.by_account.get(account) {
+            Some(indexes) => indexes,
+            None => return (Vec::new(), None),
+        };
+        let page: Vec<_> = indexes
+            .iter()
+            .rev()
+            .filter(|i| before.map_or(true, |before| **i < before))
+            .take(limit)
+            .map(|i| self.indexed(*i))
+            .collect();
+        let next = match page.last() {
+            Some(last) if page.len() == limit => Some(last.command_index),
+            _ => None,
+        };
+        (page, next)
+    }
+
+    /// The receipts of `account` in the block
+    pub fn by_block(
+        &self,
+        account: &AccountIdWrapper,
+        blocknum: chain::BlockNumber,
+    ) -> Vec<IndexedReceipt> {
+        self.by_block
+            .get(&blocknum)
+            .into_iter()
+            .flatten()
+            .filter(|i| &self.receipts[*i].account == account)
+            .map(|i| self.indexed(*i))
+            .collect()
+    }
+
+    /// The receipts of `account` newer than `after`, the oldest first
+    ///
+    /// Returns the receipts and whether some receipts after `after` may have been dropped.
+    pub fn feed(
+        &self,
+        account: &AccountIdWrapper,
+        after: Option<CommandIndex>,
+        limit: u32,
+    ) -> (Vec<IndexedReceipt>, bool) {
+        let limit = limit.min(MAX_PAGE_SIZE) as usize;
+        let missed = match (after, self.pruned_to) {
+            (_, None) => false,
+            (None, Some(_)) => true,
+            (Some(after), Some(pruned_to)) => after < pruned_to,
+        };
+        let receipts = self
+            .by_account
+            .get(account)
+            .into_iter()
+            .flatten()
+            .filter(|i| after.map_or(true, |after| **i > after))
+            .take(limit)
+            .map(|i| self.indexed(*i))
+            .collect();
+        (receipts, missed)
+    }
+
+    fn indexed(&self, command_index: CommandIndex) -> IndexedReceipt {
+        IndexedReceipt {
+            command_index,
+            receipt: self.receipts[&command_index].clone(),
+        }
+    }
+
+    fn index(&mut self, command_index: CommandIndex, receipt: &TransactionReceipt) {
+        self.by_account
+            .entry(receipt.account.clone())
+            .or_default()
+            .insert(command_index);
+        self.by_block
+            .entry(receipt.block_num)
+            .or_default()
+            .insert(command_index);
+    }
+
+    fn unindex(&mut self, command_index: CommandIndex, receipt: &TransactionReceipt) {
+        if let Some(indexes) = self.by_account.get_mut(&receipt.account) {
+            indexes.remove(&command_index);
+            if indexes.is_empty() {
+                self.by_account.remove(&receipt.account);
+            }
+        }
+        if let Some(indexes) = self.by_block.get_mut(&receipt.block_num) {
+            indexes.remove(&command_index);
+            if indexes.is_empty() {
+                self.by_block.remove(&receipt.block_num);
+            }
+        }
+    }
+
+    fn remove(&mut self, command_index: CommandIndex) {
+        if let Some(receipt) = self.receipts.remove(&command_index) {
+            self.unindex(command_index, &receipt);
+            self.pruned_to = Some(self.pruned_to.map_or(command_index, |i| i.max(command_index)));
+        }
+    }
+}
+
+impl Encode for ReceiptStore {
+    fn encode_to<T: Output + ?Sized>(&self, dest: &mut T) {
+        self.receipts.encode_to(dest);
+        self.pruned_to.encode_to(dest);
+    }
+}
+
+impl Decode for ReceiptStore {
+    fn decode<I: Input>(input: &mut I) -> Result<Self, CodecError> {
+        let receipts = BTreeMap::<CommandIndex, TransactionReceipt>::decode(input)?;
+        let pruned_to = Option::<CommandIndex>::decode(input)?;
+        let mut store = ReceiptStore {
+            pruned_to,
+            ..Default::default()
+        };
+        for (command_index, receipt) in receipts {
+            store.index(command_index, &receipt);
+            store.receipts.insert(command_index, receipt);
+        }
+        Ok(store)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::contracts::test_helpers;
+    use crate::system::TransactionStatus;
+
+    fn account(n: u8) -> AccountIdWrapper {
+        AccountIdWrapper(test_helpers::account(n))
+    }
+
+    fn receipt(n: u8, block_num: chain::BlockNumber) -> TransactionReceipt {
+        TransactionReceipt {
+            account: account(n),
+            block_num,
+            contract_id: 2,
+            command: "{}".to_string(),
+            status: TransactionStatus::Ok,
+        }
+    }
+
+    fn indexes(receipts: &[IndexedReceipt]) -> Vec<CommandIndex> {
+        receipts.iter().map(|r| r.command_index).collect()
+    }
+
+    #[test]
+    fn test_queries() {
+        let mut store = ReceiptStore::new();
+        for i in 0..10 {
+            store.insert(i, receipt((i % 2) as u8, (i / 3) as u32));
+        }
+
+        let (page, next) = store.by_account(&account(0), None, 3);
+        assert_eq!(indexes(&page), vec![8, 6, 4]);
+        let (page, next) = store.by_account(&account(0), next, 3);
+        assert_eq!(indexes(&page), vec![2, 0]);
+        assert_eq!(next, None);
+
+        assert_eq!(indexes(&store.by_block(&account(1), 1)), vec![3, 5]);
+        assert_eq!(indexes(&store.by_block(&account(1), 9)), vec![]);
+
+        let (feed, missed) = store.feed(&account(1), Some(3), 2);
+        assert_eq!(indexes(&feed), vec![5, 7]);
+        assert!(!missed);
+    }
+
+    #[test]
+    fn test_retention() {
+        let mut store = ReceiptStore::new();
+        for i in 0..10 {
+            store.insert(i, receipt(0, i as u32));
+        }
+        // Keeps the blocks 7, 8 and 9
+        store.prune(9, 3);
+        assert_eq!(store.len(), 3);
+        assert!(store.get(6).is_none());
+        assert!(store.get(7).is_some());
+        let (feed, missed) = store.feed(&account(0), Some(2), 10);
+        assert_eq!(indexes(&feed), vec![7, 8, 9]);
+        assert!(missed);
+        let (_, missed) = store.feed(&account(0), Some(6), 10);
+        assert!(!missed);
+
+        // Survives the checkpoints
+        let decoded = ReceiptStore::decode(&mut &store.encode()[..]).unwrap();
+        assert_eq!(decoded.encode(), store.encode());
+        assert_eq!(indexes(&decoded.by_block(&account(0), 8)), vec![8]);
+        assert!(decoded.feed(&account(0), Some(2), 10).1);
+    }
+}
@@ -4,10 +4,9 @@ use anyhow::Result;
 use core::fmt;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
+use crate::chain;
 use crate::cryptography::{AeadCipher, Origin};
 
-extern crate runtime as chain;
-
 // supportive
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
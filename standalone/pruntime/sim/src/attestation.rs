@@ -0,0 +1,45 @@
+//! Mock attestation provider
+//!
+//! The reports produced here are NOT signed by Intel IAS. They only keep the shape of a real IAS
+//! report so that the tooling around pRuntime can be exercised without SGX. A chain verifying
+//! attestations must reject any report with the `SIM` provider.
+
+use crate::rpc_types::{AttestationReport, InitRespAttestation};
+
+pub const PROVIDER: &str = "SIM";
+
+/// Length of `sgx_quote_t` without the signature
+const QUOTE_BODY_LEN: usize = 432;
+/// Offset of `report_body.report_data` in `sgx_quote_t`
+const REPORT_DATA_OFFSET: usize = 368;
+/// Length of `report_data`
+const REPORT_DATA_LEN: usize = 64;
+
+/// Creates a fake attestation report committing to `report_data`
+///
+/// The fake quote body puts `report_data` at the same offset as a real quote, so consumers
+/// extracting the committed runtime info hash from `isvEnclaveQuoteBody` keep working.
+pub fn create_report(report_data: &[u8]) -> InitRespAttestation {
+    let data_len = report_data.len().min(REPORT_DATA_LEN);
+    let mut quote_body = vec![0u8; QUOTE_BODY_LEN];
+    quote_body[REPORT_DATA_OFFSET..REPORT_DATA_OFFSET + data_len]
+        .copy_from_slice(&report_data[..data_len]);
+
+    let report = json!({
+        "id": "SIMULATED",
+        "version": 4,
+        "isvEnclaveQuoteStatus": "SIMULATED",
+        "isvEnclaveQuoteBody": base64::encode(&quote_body),
+        "simulated": true,
+    });
+
+    InitRespAttestation {
+        version: 1,
+        provider: PROVIDER.to_string(),
+        payload: AttestationReport {
+            report: report.to_string(),
+            signature: String::new(),
+            signing_cert: String::new(),
+        },
+    }
+}
@@ -0,0 +1,152 @@
+//! Benchmarks of the runtime state encoding
+//!
+//! Compares the binary codec used by the checkpoints with the JSON encoding it replaced, on a
+//! large synthetic state. Run with `cargo bench`; `cargo test -- --nocapture` prints the sizes.
+
+extern crate test;
+
+use serde_json::Value;
+use test::Bencher;
+
+use crate::contracts::assets::Assets;
+use crate::contracts::balances::Balances;
+use crate::contracts::data_plaza::DataPlaza;
+use crate::contracts::diem::Diem;
+use crate::contracts::web3analytics::Web3Analytics;
+use crate::handlers::{ChainLightValidation, RuntimeState, StateCommitment};
+use crate::hex;
+
+const PAGE_VIEWS: usize = 10_000;
+const ACCOUNTS: usize = 1_000;
+
+fn account_hex(i: usize) -> String {
+    hex::encode_hex_compact(&sp_core::hashing::blake2_256(&i.to_le_bytes()))
+}
+
+fn page_view(i: usize) -> Value {
+    json!({
+        "id": account_hex(i),
+        "sid": "1",
+        "cid": format!("client-{}", i % 100),
+        "uid": "",
+        "host": "phala.network",
+        "path": { "Plain": format!("/blog/{}", i % 20) },
+        "referrer": "https://www.google.com/",
+        "ip": { "Plain": format!("10.0.{}.{}", i / 256 % 256, i % 256) },
+        "user_agent": { "Plain": "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) \
+            Chrome/88.0.4324.150 Safari/537.36" },
+        "created_at": 1_600_000_000 + i,
+    })
+}
+
+/// Fills the contracts through their serialized form, because the fields are private
+fn synthetic_state() -> RuntimeState {
+    let mut web3analytics = serde_json::to_value(Web3Analytics::new()).unwrap();
+    web3analytics["sites"] = json!({
+        "1": {
+            "owner": account_hex(0),
+            "api_keys": [],
+            "encrypted": false,
+            "key_version": 0,
+            "retention": 1_000_000,
+            "page_views": (0..PAGE_VIEWS).map(page_view).collect::<Vec<_>>(),
+        }
+    });
+
+    let mut balances = serde_json::to_value(Balances::new(None)).unwrap();
+    balances["accounts"] = (0..ACCOUNTS)
+        .map(|i| (account_hex(i), json!(i * 1_000_000)))
+        .collect::<serde_json::Map<_, _>>()
+        .into();
+
+    let mut diem = serde_json::to_value(Diem::new()).unwrap();
+    let account = diem["accounts"]
+        .as_object()
+        .unwrap()
+        .values()
+        .next()
+        .unwrap()
+        .clone();
+    diem["accounts"] = (0..ACCOUNTS)
+        .map(|i| (account_hex(i), account.clone()))
+        .collect::<serde_json::Map<_, _>>()
+        .into();
+
+    RuntimeState {
+        contract1: DataPlaza::new(),
+        contract2: serde_json::from_value(balances).unwrap(),
+        contract3: Assets::new(),
+        contract4: serde_json::from_value(web3analytics).unwrap(),
+        contract5: serde_json::from_value(diem).unwrap(),
+        light_client: ChainLightValidation::new(),
+        main_bridge: 0,
+        commitment: StateCommitment::new(),
+    }
+}
+
+/// The JSON encoding used by the checkpoints before the binary codec
+fn encode_json(state: &RuntimeState) -> Vec<u8> {
+    use parity_scale_codec::Encode;
+    serde_json::to_vec(&json!({
+        "contract1": state.contract1,
+        "contract2": state.contract2,
+        "contract3": state.contract3,
+        "contract4": state.contract4,
+        "contract5": state.contract5,
+        "light_client": base64::encode(&state.light_client.encode()),
+        "main_bridge": state.main_bridge,
+    }))
+    .unwrap()
+}
+
+fn decode_json(data: &[u8]) -> RuntimeState {
+    use parity_scale_codec::Decode;
+    let mut value: Value = serde_json::from_slice(data).unwrap();
+    let light_client = base64::decode(value["light_client"].as_str().unwrap()).unwrap();
+    RuntimeState {
+        contract1: serde_json::from_value(value["contract1"].take()).unwrap(),
+        contract2: serde_json::from_value(value["contract2"].take()).unwrap(),
+        contract3: serde_json::from_value(value["contract3"].take()).unwrap(),
+        contract4: serde_json::from_value(value["contract4"].take()).unwrap(),
+        contract5: serde_json::from_value(value["contract5"].take()).unwrap(),
+        light_client: ChainLightValidation::decode(&mut light_client.as_slice()).unwrap(),
+        main_bridge: value["main_bridge"].as_u64().unwrap(),
+    }
+}
+
+#[test]
+fn test_binary_encoding() {
+    let state = synthetic_state();
+    let binary = state.encode_state().unwrap();
+    let json = encode_json(&state);
+    println!("JSON: {} bytes, binary: {} bytes", json.len(), binary.len());
+    assert!(binary.len() < json.len());
+
+    let decoded = RuntimeState::decode_state(&binary).unwrap();
+    assert_eq!(decoded.encode_state().unwrap(), binary);
+    assert_eq!(encode_json(&decode_json(&json)), json);
+}
+
+#[bench]
+fn bench_encode_json(b: &mut Bencher) {
+    let state = synthetic_state();
+    b.iter(|| encode_json(&state));
+}
+
+#[bench]
+fn bench_decode_json(b: &mut Bencher) {
+    let data = encode_json(&synthetic_state());
+    b.iter(|| decode_json(&data));
+}
+
+#[bench]
+fn bench_encode_binary(b: &mut Bencher) {
+    let state = synthetic_state();
+    b.iter(|| state.encode_state().unwrap());
+}
+
+#[bench]
+fn bench_decode_binary(b: &mut Bencher) {
+    let data = synthetic_state().encode_state().unwrap();
+    b.iter(|| RuntimeState::decode_state(&data).unwrap());
+}
@@ -0,0 +1,107 @@
+//! Benchmarks of the block ingestion transport
+//!
+//! Compares the streaming ingestion (`/ingest`) with the JSON `dispatch_block` request it
+//! supersedes, from the request body to the encoded blocks handed to `dispatch_block`. The
+//! dispatching itself is the same for both. Run with `cargo bench`, which reports the throughput of
+//! the blocks; `cargo test -- --nocapture` prints the sizes.
+
+extern crate test;
+
+use std::io::{Cursor, Read};
+use std::sync::atomic::AtomicBool;
+
+use parity_scale_codec::{Decode, Encode};
+use phala_types::rpc::{encode_ingest_frame, Call, IngestAck, IngestFrame, ACTION_DISPATCH_BLOCK};
+use serde_json::Value;
+use test::Bencher;
+
+use crate::ingest_stream::IngestStream;
+use crate::json_api;
+
+const BLOCKS: usize = 1_000;
+/// About the size of a block with its events and their storage proof
+const BLOCK_LEN: usize = 4 * 1024;
+
+fn blocks() -> Vec<Vec<u8>> {
+    (0..BLOCKS)
+        .map(|i| {
+            let seed = sp_core::hashing::blake2_256(&i.to_le_bytes());
+            seed.iter().cycle().take(BLOCK_LEN).cloned().collect()
+        })
+        .collect()
+}
+
+fn json_body(blocks: &[Vec<u8>]) -> Vec<u8> {
+    let blocks_b64: Vec<_> = blocks.iter().map(base64::encode).collect();
+    serde_json::to_vec(&json!({
+        "input": { "blocks_b64": blocks_b64 },
+        "nonce": { "value": 1 },
+    }))
+    .unwrap()
+}
+
+fn decode_json(body: &[u8]) -> Vec<Vec<u8>> {
+    let mut request: Value = serde_json::from_slice(body).unwrap();
+    match json_api::parse_call(ACTION_DISPATCH_BLOCK, request["input"].take()).unwrap() {
+        Call::DispatchBlock { blocks } => blocks,
+        _ => unreachable!(),
+    }
+}
+
+fn ingest_body(blocks: &[Vec<u8>]) -> Vec<u8> {
+    blocks
+        .iter()
+        .flat_map(|block| encode_ingest_frame(&IngestFrame::Block(block.clone())))
+        .collect()
+}
+
+/// Reads the frames through the stream the `/ingest` route serves, acking every frame
+fn decode_ingest(active: &'static AtomicBool, body: &[u8]) -> Vec<Vec<u8>> {
+    let mut blocks = Vec::new();
+    let mut acks = Vec::new();
+    IngestStream::start(active, Cursor::new(body), |seq, frame: &[u8]| {
+        match IngestFrame::decode(&mut &frame[..]).unwrap() {
+            IngestFrame::Block(block) => blocks.push(block),
+            _ => unreachable!(),
+        }
+        let ack = IngestAck {
+            seq,
+            headernum: 0,
+            blocknum: seq + 1,
+            code: 0,
+        };
+        Ok(ack.encode())
+    })
+    .unwrap()
+    .read_to_end(&mut acks)
+    .unwrap();
+    blocks
+}
+
+#[test]
+fn test_ingest_encoding() {
+    static ACTIVE: AtomicBool = AtomicBool::new(false);
+    let blocks = blocks();
+    let json = json_body(&blocks);
+    let ingest = ingest_body(&blocks);
+    println!("JSON: {} bytes, ingest: {} bytes", json.len(), ingest.len());
+    assert!(ingest.len() < json.len());
+
+    assert_eq!(decode_json(&json), blocks);
+    assert_eq!(decode_ingest(&ACTIVE, &ingest), blocks);
+}
+
+#[bench]
+fn bench_ingest_json(b: &mut Bencher) {
+    let body = json_body(&blocks());
+    b.bytes = (BLOCKS * BLOCK_LEN) as u64;
+    b.iter(|| decode_json(&body));
+}
+
+#[bench]
+fn bench_ingest_stream(b: &mut Bencher) {
+    static ACTIVE: AtomicBool = AtomicBool::new(false);
+    let body = ingest_body(&blocks());
+    b.bytes = (BLOCKS * BLOCK_LEN) as u64;
+    b.iter(|| decode_ingest(&ACTIVE, &body));
+}
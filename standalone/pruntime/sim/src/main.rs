@@ -0,0 +1,224 @@
+//! pRuntime without SGX, for development only
+//!
+//! It runs the same contract and bridge logic as the enclave (the platform independent modules
+//! are included from `../enclave/src`) and serves the same RESTful API as `pruntime/app`, so
+//! pHost, the node and the e2e tests can run on any Linux box. There's no confidentiality and the
+//! attestation reports are fake (provider `SIM`).
+
+#![feature(decl_macro)]
+#![cfg_attr(test, feature(test))]
+
+#[macro_use]
+extern crate serde_json;
+#[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate rocket;
+
+#[path = "../../enclave/src/chain.rs"]
+mod chain;
+#[path = "../../enclave/src/checkpoint.rs"]
+mod checkpoint;
+#[path = "../../app/src/checkpoint_store.rs"]
+mod checkpoint_store;
+#[path = "../../enclave/src/contracts/mod.rs"]
+mod contracts;
+#[path = "../../enclave/src/cryptography/mod.rs"]
+mod cryptography;
+#[path = "../../enclave/src/handlers.rs"]
+mod handlers;
+#[path = "../../enclave/src/hex.rs"]
+mod hex;
+#[path = "../../app/src/ingest_stream.rs"]
+mod ingest_stream;
+#[path = "../../enclave/src/json_api.rs"]
+mod json_api;
+#[path = "../../enclave/src/light_validation/mod.rs"]
+mod light_validation;
+#[path = "../../enclave/src/merkle.rs"]
+mod merkle;
+#[path = "../../enclave/src/msg_channel.rs"]
+mod msg_channel;
+#[path = "../../enclave/src/rpc_types.rs"]
+mod rpc_types;
+#[path = "../../enclave/src/system/mod.rs"]
+mod system;
+#[path = "../../enclave/src/types.rs"]
+mod types;
+
+mod attestation;
+#[cfg(test)]
+mod bench;
+#[cfg(test)]
+mod ingest_bench;
+mod simulator;
+
+use std::env;
+use std::io::Read;
+use std::sync::atomic::AtomicBool;
+
+use rocket::http::{ContentType, Method, Status};
+use rocket::response::content::Content;
+use rocket::response::Stream;
+use rocket::Data;
+use rocket_contrib::json::{Json, JsonValue};
+use rocket_cors::{AllowedHeaders, AllowedMethods, AllowedOrigins, CorsOptions};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+// Items the shared modules expect at the crate root, as in the enclave
+use handlers::OnlineWorkerSnapshot;
+use ingest_stream::IngestStream;
+use std::sync::Mutex as SgxMutex;
+use system::TransactionStatus;
+
+/// The maximum size of a request to the binary API
+const RPC_REQUEST_LIMIT: u64 = 64 * 1024 * 1024;
+
+lazy_static! {
+    static ref ALLOW_CORS: bool = {
+        env::var("ALLOW_CORS").unwrap_or_else(|_| "".to_string()) != ""
+    };
+    static ref ENABLE_KICK_API: bool = {
+        env::var("ENABLE_KICK_API").unwrap_or_else(|_| "".to_string()) != ""
+    };
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ContractInput {
+    pub input: Map<String, Value>,
+    pub nonce: Map<String, Value>,
+}
+
+fn call_runtime(action: u8, contract_input: Json<ContractInput>) -> JsonValue {
+    debug!("{}", serde_json::to_string_pretty(&*contract_input).unwrap());
+
+    let request = serde_json::to_value(contract_input.into_inner()).unwrap();
+    JsonValue(simulator::handle(action, request))
+}
+
+/// The JSON API. `action` is the name of the call, e.g. `/get_info`.
+#[post("/<action>", format = "json", data = "<contract_input>")]
+fn json_rpc(action: String, contract_input: Json<ContractInput>) -> Option<JsonValue> {
+    let action = phala_types::rpc::action_by_name(&action)?;
+    Some(call_runtime(action, contract_input))
+}
+
+/// The binary API. The body is an encoded `RpcRequest`.
+#[post("/rpc", data = "<data>")]
+fn rpc(data: Data) -> Result<Content<Vec<u8>>, Status> {
+    let mut request = Vec::new();
+    data.open()
+        .take(RPC_REQUEST_LIMIT)
+        .read_to_end(&mut request)
+        .map_err(|_| Status::BadRequest)?;
+    let response = simulator::handle_binary(&request);
+    Ok(Content(ContentType::Binary, response))
+}
+
+/// Set while an ingestion stream is running
+static INGESTING: AtomicBool = AtomicBool::new(false);
+
+/// The streaming ingestion. The body is a stream of `IngestFrame`s, and the response streams
+/// back an `IngestAck` per frame.
+#[post("/ingest", data = "<data>")]
+fn ingest(data: Data) -> Result<Content<Stream<impl Read>>, Status> {
+    let stream = IngestStream::start(&INGESTING, data.open(), |seq, frame: &[u8]| {
+        Ok(simulator::handle_ingest_frame(seq, frame))
+    })
+    .ok_or(Status::Conflict)?;
+    Ok(Content(
+        ContentType::Binary,
+        Stream::chunked(stream, ingest_stream::ACK_LEN as u64),
+    ))
+}
+
+/// Lists the checkpoints kept on the disk, the newest first
+#[get("/checkpoints")]
+fn checkpoints() -> JsonValue {
+    match simulator::checkpoint_store().list() {
+        Ok(files) => {
+            let checkpoints: Vec<Value> = files
+                .iter()
+                .map(|f| {
+                    json!({
+                        "blocknum": f.blocknum,
+                        "file": f.path.display().to_string(),
+                        "size": f.size,
+                    })
+                })
+                .collect();
+            JsonValue(json!({
+                "interval": simulator::checkpoint_interval(),
+                "checkpoints": checkpoints,
+            }))
+        }
+        Err(err) => JsonValue(json!({
+            "status": "error",
+            "payload": format!("Failed to list checkpoints: {}", err),
+        })),
+    }
+}
+
+#[post("/kick")]
+fn kick() {
+    info!("Kick API received, exiting...");
+    std::process::exit(0);
+}
+
+fn cors_options() -> CorsOptions {
+    let allowed_origins = AllowedOrigins::all();
+    let allowed_methods: AllowedMethods = vec![Method::Get, Method::Post]
+        .into_iter()
+        .map(From::from)
+        .collect();
+
+    rocket_cors::CorsOptions {
+        allowed_origins,
+        allowed_methods,
+        allowed_headers: AllowedHeaders::all(),
+        allow_credentials: true,
+        ..Default::default()
+    }
+}
+
+fn rocket() -> rocket::Rocket {
+    let mut server = rocket::ignite().mount(
+        "/",
+        routes![json_rpc, rpc, ingest, checkpoints],
+    );
+
+    if *ENABLE_KICK_API {
+        info!("ENABLE `kick` API");
+
+        server = server.mount("/", routes![kick]);
+    }
+
+    if *ALLOW_CORS {
+        info!("Allow CORS");
+
+        server
+            .mount("/", rocket_cors::catch_all_options_routes())
+            .attach(cors_options().to_cors().expect("To not fail"))
+            .manage(cors_options().to_cors().expect("To not fail"))
+    } else {
+        server
+    }
+}
+
+fn main() {
+    env::set_var("RUST_BACKTRACE", "1");
+    env::set_var("ROCKET_ENV", "dev");
+
+    env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .parse_default_env()
+        .init();
+
+    warn!("Running pRuntime in software simulation. Never use it in production!");
+    simulator::init();
+
+    rocket().launch();
+}
@@ -0,0 +1,452 @@
+//! Software replacement of the enclave entry points (`ecall_init`, `ecall_handle` and `ecall_rpc`)
+//!
+//! There's no sealing. The identity keys are stored in plain text under `STATE_FILE_PATH`, next to
+//! the checkpoints, so a restarted `pruntime-sim` keeps its identity and resumes from the latest
+//! checkpoint.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use parity_scale_codec::Encode;
+use secp256k1::{PublicKey, SecretKey};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use phala_types::rpc::{ErrorCode, InitRuntimeArgs, RpcError, TestArgs};
+use phala_types::PRuntimeInfo;
+
+use crate::attestation;
+use crate::chain;
+use crate::checkpoint_store::CheckpointStore;
+use crate::cryptography::ecdh;
+use crate::handlers::*;
+use crate::hex;
+use crate::json_api;
+use crate::rpc_types::*;
+
+pub const VERSION: u32 = 1;
+
+const IDENTITY_FILE: &str = "pruntime-sim.identity";
+const CHECKPOINT_DIR: &str = "checkpoints";
+
+/// The counterpart of `PersistentRuntimeData` in the enclave, but not sealed
+#[derive(Serialize, Deserialize)]
+struct PersistentIdentity {
+    sk: String,
+    ecdh_sk: String,
+    dev_mode: bool,
+}
+
+fn state_file(name: &str) -> PathBuf {
+    let dir = env::var("STATE_FILE_PATH").unwrap_or_else(|_| "./".to_string());
+    PathBuf::from(dir).join(name)
+}
+
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Take a checkpoint every N dispatched blocks. 0 to disable.
+pub fn checkpoint_interval() -> u32 {
+    env_or("CHECKPOINT_INTERVAL", 1000)
+}
+
+/// Keep the transaction receipts of the last N blocks (about a week by default). 0 to keep them
+/// until the store is full.
+pub fn receipt_retention() -> u32 {
+    env_or("RECEIPT_RETENTION", 100_000)
+}
+
+pub fn checkpoint_store() -> CheckpointStore {
+    CheckpointStore::new(state_file(CHECKPOINT_DIR), env_or("CHECKPOINT_KEEP", 3))
+}
+
+/// Loads the identity and resumes from the checkpoint, like `ecall_init` does
+pub fn init() {
+    let mut local_state = LOCAL_STATE.lock().unwrap();
+    let (ecdsa_sk, ecdh_sk, dev_mode) = match load_identity() {
+        Ok(Some(identity)) => identity,
+        Ok(None) => {
+            warn!("Persistent identity not found.");
+            let ecdsa_sk = SecretKey::random(&mut rand::thread_rng());
+            let ecdh_sk = ecdh::generate_key();
+            save_identity(&ecdsa_sk, &ecdh_sk, false).expect("Failed to save the identity");
+            (ecdsa_sk, ecdh_sk, false)
+        }
+        Err(e) => panic!("Identity file corrupted, please delete it: {:?}", e),
+    };
+    set_identity(&mut local_state, ecdsa_sk, ecdh_sk, dev_mode)
+        .expect("Failed to set the identity");
+    local_state.checkpoint_interval = checkpoint_interval();
+    local_state.receipt_retention = receipt_retention();
+    // `restore_checkpoint` takes the lock by itself
+    drop(local_state);
+
+    let store = checkpoint_store();
+    resume_from_checkpoint(|index| Ok(store.load(index)?));
+}
+
+/// The simulated implementation of the platform dependent calls
+struct Simulator;
+
+impl Platform for Simulator {
+    fn init_runtime(&self, args: InitRuntimeArgs) -> Result<InitRuntimeResp, RpcError> {
+        init_runtime(args)
+    }
+
+    fn test(&self, _args: TestArgs) -> Result<(), RpcError> {
+        Ok(())
+    }
+
+    fn test_ink(&self) -> Result<(), RpcError> {
+        Err(RpcError::new(
+            ErrorCode::Forbidden,
+            "Ink contracts are not supported in the simulator",
+        ))
+    }
+
+    fn save_checkpoint(&self, blocknum: u32, data: &[u8]) -> Result<()> {
+        save_checkpoint(blocknum, data)
+    }
+}
+
+/// Handles a JSON request and returns the signed response, like `ecall_handle` does
+///
+/// `request` is the whole `{input, nonce}` object sent by the client.
+pub fn handle(action: u8, request: Value) -> Value {
+    let output_json = json_api::handle_json(&Simulator, action, request);
+    info!("{}", output_json.to_string());
+    output_json
+}
+
+/// Handles an encoded `RpcRequest` and returns the encoded response, like `ecall_rpc` does
+pub fn handle_binary(request: &[u8]) -> Vec<u8> {
+    handle_rpc(&Simulator, request)
+}
+
+/// Handles a frame of the ingestion stream and returns the encoded ack, like `ecall_ingest` does
+pub fn handle_ingest_frame(seq: u32, frame: &[u8]) -> Vec<u8> {
+    crate::handlers::handle_ingest_frame(&Simulator, seq, frame)
+}
+
+fn save_checkpoint(blocknum: u32, data: &[u8]) -> Result<()> {
+    let path = checkpoint_store().save(blocknum, data)?;
+    info!(
+        "Saved checkpoint ({} bytes) to {}",
+        data.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+fn save_identity(ecdsa_sk: &SecretKey, ecdh_sk: &EcdhKey, dev_mode: bool) -> Result<()> {
+    let identity = PersistentIdentity {
+        sk: hex::encode_hex_compact(ecdsa_sk.serialize().as_ref()),
+        ecdh_sk: hex::encode_hex_compact(ecdh::dump_key(ecdh_sk).as_ref()),
+        dev_mode,
+    };
+    fs::write(state_file(IDENTITY_FILE), serde_json::to_vec(&identity)?)?;
+    Ok(())
+}
+
+fn load_identity() -> Result<Option<(SecretKey, EcdhKey, bool)>> {
+    let data = match fs::read(state_file(IDENTITY_FILE)) {
+        Ok(data) => data,
+        Err(_) => return Ok(None),
+    };
+    let identity: PersistentIdentity = serde_json::from_slice(&data)?;
+    let ecdsa_sk = SecretKey::parse_slice(&hex::decode_hex(&identity.sk))
+        .map_err(|_| anyhow::Error::msg("can't parse private key"))?;
+    let ecdh_sk = ecdh::create_key(&hex::decode_hex(&identity.ecdh_sk))?;
+    Ok(Some((ecdsa_sk, ecdh_sk, identity.dev_mode)))
+}
+
+fn set_identity(
+    local_state: &mut LocalState,
+    ecdsa_sk: SecretKey,
+    ecdh_sk: EcdhKey,
+    dev_mode: bool,
+) -> Result<()> {
+    let ecdsa_pk = PublicKey::from_secret_key(&ecdsa_sk);
+    let ecdh_pk = ecdh_sk
+        .compute_public_key()
+        .map_err(|_| anyhow::Error::msg("can't compute pubkey"))?;
+    info!(
+        "Identity pubkey: {:?}",
+        hex::encode_hex_compact(ecdsa_pk.serialize_compressed().as_ref())
+    );
+    info!(
+        "ECDH pubkey: {:?}",
+        hex::encode_hex_compact(ecdh_pk.as_ref())
+    );
+
+    // There's no seal key to derive the machine id from. Bind it to the identity instead.
+    let machine_id = sp_core::hashing::blake2_128(&ecdsa_pk.serialize_compressed());
+    info!("Machine id: {:?}", hex::encode_hex_compact(&machine_id));
+
+    *local_state.public_key = ecdsa_pk;
+    *local_state.private_key = ecdsa_sk;
+    local_state.ecdh_private_key = Some(ecdh_sk);
+    local_state.ecdh_public_key = Some(ecdh_pk);
+    local_state.machine_id = machine_id;
+    local_state.dev_mode = dev_mode;
+    Ok(())
+}
+
+fn cpu_feature_level() -> u32 {
+    let mut cpu_feature_level: u32 = 1;
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        // Atom doesn't support AVX
+        if is_x86_feature_detected!("avx2") {
+            cpu_feature_level += 1;
+            // Customer-level Core doesn't support AVX512
+            if is_x86_feature_detected!("avx512f") {
+                cpu_feature_level += 1;
+            }
+        }
+    }
+    cpu_feature_level
+}
+
+fn init_runtime(input: InitRuntimeArgs) -> Result<InitRuntimeResp, RpcError> {
+    let mut local_state = LOCAL_STATE.lock().unwrap();
+    if local_state.initialized {
+        return Err(RpcError::new(
+            ErrorCode::AlreadyInitialized,
+            "Already initialized",
+        ));
+    }
+
+    // load identity
+    if let Some(raw_key) = input.debug_set_key {
+        if input.skip_ra == false {
+            return Err(RpcError::new(
+                ErrorCode::Forbidden,
+                "RA is disallowed when debug_set_key is enabled",
+            ));
+        }
+        let ecdsa_key = SecretKey::parse_slice(raw_key.as_slice())
+            .map_err(|_| RpcError::new(ErrorCode::BadRequest, "can't parse private key"))?;
+        let ecdh_key = ecdh::create_key(raw_key.as_slice())
+            .map_err(|_| RpcError::new(ErrorCode::BadRequest, "can't create ecdh key"))?;
+        save_identity(&ecdsa_key, &ecdh_key, true)
+            .map_err(|_| RpcError::new(ErrorCode::Internal, "failed to save secret key"))?;
+        set_identity(&mut local_state, ecdsa_key, ecdh_key, true)
+            .map_err(|_| RpcError::new(ErrorCode::Internal, "failed to update secret key"))?;
+    }
+    if !input.skip_ra && local_state.dev_mode {
+        return Err(RpcError::new(
+            ErrorCode::Forbidden,
+            "RA is disallowed when debug_set_key is enabled",
+        ));
+    }
+
+    let ecdsa_serialized_pk = local_state.public_key.serialize_compressed();
+    let ecdsa_hex_pk = hex::encode_hex_compact(ecdsa_serialized_pk.as_ref());
+    let ecdh_hex_pk = hex::encode_hex_compact(
+        local_state
+            .ecdh_public_key
+            .as_ref()
+            .expect("ECDH not initizlied")
+            .as_ref(),
+    );
+
+    // Build PRuntimeInfo
+    let runtime_info = PRuntimeInfo {
+        version: VERSION,
+        machine_id: local_state.machine_id.clone(),
+        pubkey: ecdsa_serialized_pk,
+        features: vec![num_cpus::get() as u32, cpu_feature_level()],
+    };
+    let encoded_runtime_info = runtime_info.encode();
+    let runtime_info_hash = sp_core::hashing::blake2_512(&encoded_runtime_info);
+
+    // Produce the mock attestation report
+    let attestation = if !input.skip_ra {
+        warn!("Producing a simulated attestation report. It's not trustworthy!");
+        Some(attestation::create_report(&runtime_info_hash))
+    } else {
+        None
+    };
+
+    init_runtime_states(
+        &mut local_state,
+        &input.bridge_genesis_info,
+        &input.contracts_genesis,
+    )?;
+    // Response
+    let resp = InitRuntimeResp {
+        encoded_runtime_info,
+        public_key: ecdsa_hex_pk,
+        ecdh_public_key: ecdh_hex_pk,
+        attestation,
+    };
+    local_state.runtime_info = Some(resp.clone());
+    local_state.initialized = true;
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use parity_scale_codec::Decode;
+    use phala_types::rpc::{Call, IngestAck, IngestFrame, Reply, RpcRequest, RpcResponse};
+    use std::sync::Mutex;
+
+    lazy_static! {
+        /// The tests share the global states
+        static ref SERIAL: Mutex<()> = Mutex::new(());
+    }
+
+    /// Resets the states as if the process was restarted
+    fn restart() {
+        {
+            let mut local_state = LOCAL_STATE.lock().unwrap();
+            local_state.initialized = false;
+            local_state.headernum = 0;
+            local_state.blocknum = 0;
+            local_state.block_hashes.clear();
+            local_state.last_checkpoint = 0;
+        }
+        init();
+    }
+
+    fn dispatch_to(headernum: u32, blocknum: u32) {
+        {
+            let mut local_state = LOCAL_STATE.lock().unwrap();
+            local_state.headernum = headernum;
+            local_state.blocknum = blocknum;
+        }
+        checkpoint_if_due(save_checkpoint);
+    }
+
+    #[test]
+    fn test_resume_from_latest_checkpoint() {
+        let _guard = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        let dir = env::temp_dir().join(format!("pruntime-sim-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        env::set_var("STATE_FILE_PATH", &dir);
+        env::set_var("CHECKPOINT_INTERVAL", "10");
+        env::set_var("CHECKPOINT_KEEP", "2");
+
+        init();
+        let pubkey = *LOCAL_STATE.lock().unwrap().public_key;
+        LOCAL_STATE.lock().unwrap().initialized = true;
+        dispatch_to(11, 5); // Not due
+        dispatch_to(21, 10);
+        dispatch_to(25, 15); // Not due
+        dispatch_to(31, 20);
+        dispatch_to(41, 30);
+        let blocks: Vec<_> = checkpoint_store()
+            .list()
+            .unwrap()
+            .iter()
+            .map(|f| f.blocknum)
+            .collect();
+        assert_eq!(blocks, vec![30, 20]);
+
+        restart();
+        {
+            let local_state = LOCAL_STATE.lock().unwrap();
+            assert!(local_state.initialized);
+            assert_eq!(*local_state.public_key, pubkey);
+            assert_eq!(local_state.headernum, 41);
+            assert_eq!(local_state.blocknum, 30);
+            assert_eq!(local_state.last_checkpoint, 30);
+        }
+
+        // Falls back to the previous checkpoint if the latest one is broken
+        let latest = checkpoint_store().list().unwrap()[0].path.clone();
+        fs::write(&latest, b"broken").unwrap();
+        restart();
+        assert_eq!(LOCAL_STATE.lock().unwrap().blocknum, 20);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_binary_rpc() {
+        let _guard = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        let pubkey = LOCAL_STATE.lock().unwrap().public_key.clone();
+
+        let request = RpcRequest {
+            call: Call::GetInfo,
+            nonce: 1,
+        }
+        .encode();
+        let resp = RpcResponse::decode(&mut &handle_binary(&request)[..]).unwrap();
+        assert!(resp.message.ok);
+        assert_eq!(resp.message.request_hash, sp_core::hashing::blake2_256(&request));
+        let digest = sp_core::hashing::sha2_256(&resp.message.encode());
+        assert!(secp256k1::verify(
+            &secp256k1::Message::parse(&digest),
+            &secp256k1::Signature::parse_slice(&resp.signature).unwrap(),
+            &pubkey,
+        ));
+        match Reply::decode(&mut &resp.message.payload[..]).unwrap() {
+            Reply::GetInfo(info) => {
+                assert_eq!(info.public_key, pubkey.serialize_compressed().to_vec())
+            }
+            reply => panic!("unexpected reply {:?}", reply),
+        }
+
+        // Malformed requests are answered with signed errors
+        let resp = RpcResponse::decode(&mut &handle_binary(&[0xff])[..]).unwrap();
+        assert!(!resp.message.ok);
+        let err = RpcError::decode(&mut &resp.message.payload[..]).unwrap();
+        assert_eq!(err.code, ErrorCode::BadRequest);
+
+        // So are the unknown actions in the JSON API
+        let resp = handle(42, serde_json::json!({"input": {}, "nonce": {}}));
+        assert_eq!(resp["status"], "error");
+        let payload: Value = serde_json::from_str(resp["payload"].as_str().unwrap()).unwrap();
+        assert_eq!(payload["code"], ErrorCode::UnknownCall as u8);
+    }
+
+    #[test]
+    fn test_ingest_frame() {
+        let _guard = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        let headernum = LOCAL_STATE.lock().unwrap().headernum;
+        let ingest = |seq, frame: &[u8]| {
+            IngestAck::decode(&mut &handle_ingest_frame(seq, frame)[..]).unwrap()
+        };
+
+        let ack = ingest(0, &[0xff]);
+        assert_eq!(ack.seq, 0);
+        assert_eq!(ack.error(), Some(ErrorCode::BadRequest));
+
+        // The headers wait for a justified one
+        let header = HeaderToSync {
+            header: chain::Header {
+                parent_hash: Default::default(),
+                number: headernum,
+                state_root: Default::default(),
+                extrinsics_root: Default::default(),
+                digest: Default::default(),
+            },
+            justification: None,
+        };
+        let frame = IngestFrame::Header {
+            header: header.encode(),
+            authority_set_change: None,
+        };
+        let ack = ingest(0, &frame.encode());
+        assert!(ack.is_ok());
+        assert_eq!(ack.headernum, headernum);
+
+        let frame = IngestFrame::Header {
+            header: header.encode(),
+            authority_set_change: Some(vec![]),
+        };
+        let ack = ingest(1, &frame.encode());
+        assert_eq!(ack.seq, 1);
+        assert_eq!(ack.error(), Some(ErrorCode::BadRequest));
+    }
+}
@@ -122,7 +122,7 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	// and set impl_version to 0. If only runtime
 	// implementation changes and behavior does not, then leave spec_version as
 	// is and increment impl_version.
-	spec_version: 32,
+	spec_version: 34,
 	impl_version: 0,
 	apis: RUNTIME_API_VERSIONS,
 	transaction_version: 2,
@@ -1090,6 +1090,7 @@ impl pallet_phala::Config for Runtime {
 	type Event = Event;
 	type Randomness = RandomnessCollectiveFlip;
 	type TEECurrency = Balances;
+	type Assets = Assets;
 	type UnixTime = Timestamp;
 	type Treasury = Treasury;
 	type OnRoundEnd = MiningStaking;